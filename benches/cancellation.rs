@@ -0,0 +1,56 @@
+// What cancellation costs: a million-iteration loop in each engine (compare
+// with a build from before cancellation existed), and how long a runaway loop
+// keeps going after its token is cancelled. Run with `cargo bench`.
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use flux_compiler::{Engine, RunError, Runtime};
+
+const ITERATIONS: usize = 1_000_000;
+const RUNS: usize = 5;
+
+fn counting_loop() -> String {
+    format!("mut i = 0\nwhile (i < {}) {{\n    i = i + 1\n}}\ni\n", ITERATIONS)
+}
+
+fn best_time(engine: Engine) -> Duration {
+    let source = counting_loop();
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut runtime = Runtime::new().with_engine(engine);
+        let start = Instant::now();
+        let count = runtime.run_source(&source).expect("benchmark program failed");
+        best = best.min(start.elapsed());
+        assert_eq!(count.to_string(), ITERATIONS.to_string());
+    }
+    best
+}
+
+// From cancel() to run_source returning
+fn stop_latency(engine: Engine) -> Duration {
+    let (token_tx, token_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        let mut runtime = Runtime::new().with_engine(engine);
+        token_tx.send(runtime.cancellation_token()).unwrap();
+        let result = runtime.run_source("mut i = 0\nwhile (true) { i = i + 1 }");
+        done_tx.send(Instant::now()).unwrap();
+        assert_eq!(result, Err(RunError::Cancelled));
+    });
+    let token = token_rx.recv().unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let cancelled_at = Instant::now();
+    token.cancel();
+    let stopped_at = done_rx.recv().unwrap();
+    worker.join().unwrap();
+    stopped_at - cancelled_at
+}
+
+fn main() {
+    for (name, engine) in [("interpreter", Engine::Interpreter), ("vm", Engine::Vm)] {
+        println!(
+            "cancellation ({}): {} iterations, best of {}: {:?}; stopped {:?} after cancel()",
+            name, ITERATIONS, RUNS, best_time(engine), stop_latency(engine)
+        );
+    }
+}
@@ -0,0 +1,53 @@
+// Parses and evaluates a generated 50k-line program that is mostly identifier
+// traffic (declarations, lookups, reassignments), reporting the wall time and
+// how many heap allocations it took. Run with `cargo bench`.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use flux_compiler::Runtime;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const LINES: usize = 50_000;
+const RUNS: usize = 5;
+
+fn program() -> String {
+    let mut source = String::from("mut total = 0;\nmut add = fn(a, b) { a + b };\n");
+    for i in 0..LINES {
+        source.push_str(&format!("mut v{} = add(total, {}); total = v{};\n", i % 500, i % 7, i % 500));
+    }
+    source.push_str("total\n");
+    source
+}
+
+fn main() {
+    let source = program();
+    let mut best = Duration::MAX;
+    let mut allocations = 0;
+    for _ in 0..RUNS {
+        let mut runtime = Runtime::new();
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        let result = runtime.run_source(&source).expect("benchmark program failed");
+        best = best.min(start.elapsed());
+        allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        assert_eq!(result.to_string(), "149997");
+    }
+    println!("identifiers: {} lines, best of {}: {:?}, {} allocations", LINES, RUNS, best, allocations);
+}
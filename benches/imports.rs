@@ -0,0 +1,65 @@
+// Runs a program that imports 50 small modules, served from memory by a
+// module resolver, and times the builtin scope each import starts from:
+// a fresh copy of every builtin (as imports used to) against the shared scope.
+// Run with `cargo bench`.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use flux_compiler::{builtins, set_module_resolver, Runtime};
+use flux_compiler::environment::Environment;
+
+const MODULES: usize = 50;
+const RUNS: usize = 20;
+
+fn program() -> String {
+    let mut source = String::from("mut total = 0\n");
+    for i in 0..MODULES {
+        source.push_str(&format!("total = total + import(\"m{}\")[\"value\"]()\n", i));
+    }
+    source.push_str("total\n");
+    source
+}
+
+fn install_modules() {
+    let modules: HashMap<String, String> = (0..MODULES)
+        .map(|i| (format!("m{}", i), format!("mut value = fn() {{ len(\"{}\") }}\n", "x".repeat(i))))
+        .collect();
+    set_module_resolver(Box::new(move |name, _importer| {
+        Ok(modules.get(name).cloned())
+    }));
+}
+
+fn best_of<F: FnMut()>(mut work: F) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        work();
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+fn main() {
+    install_modules();
+    let source = program();
+    let expected = (0..MODULES).sum::<usize>().to_string();
+    let run = best_of(|| {
+        let result = Runtime::new().run_source(&source).expect("benchmark program failed");
+        assert_eq!(result.to_string(), expected);
+    });
+
+    let copied = best_of(|| {
+        for _ in 0..MODULES {
+            let mut env = Environment::new();
+            for (name, tool) in builtins::new_environment() { env.set(name, tool); }
+        }
+    });
+    let shared = best_of(|| {
+        for _ in 0..MODULES {
+            Environment::new_enclosed(builtins::builtin_scope());
+        }
+    });
+    println!(
+        "imports: {} modules, best of {}: {:?} per run; builtin scopes {:?} copied, {:?} shared",
+        MODULES, RUNS, run, copied, shared
+    );
+}
@@ -0,0 +1,34 @@
+// Runs a VM loop that calls a one-line helper ten million times, with and
+// without --opt=inline, and checks both give the same result. Run with `cargo bench`.
+use std::time::{Duration, Instant};
+use flux_compiler::{Engine, Runtime};
+
+const CALLS: usize = 10_000_000;
+const RUNS: usize = 3;
+
+fn program() -> String {
+    format!(
+        "mut sq = fn(x) {{ x * x }}\nmut last = 0\nmut i = 0\nwhile (i < {}) {{\n    last = sq(i)\n    i = i + 1\n}}\nlast\n",
+        CALLS
+    )
+}
+
+fn best_of(source: &str, inline: bool) -> (Duration, String) {
+    let mut best = Duration::MAX;
+    let mut result = String::new();
+    for _ in 0..RUNS {
+        let mut runtime = Runtime::new().with_engine(Engine::Vm).with_inlining(inline);
+        let start = Instant::now();
+        result = runtime.run_source(source).expect("benchmark program failed").to_string();
+        best = best.min(start.elapsed());
+    }
+    (best, result)
+}
+
+fn main() {
+    let source = program();
+    let (calls, called) = best_of(&source, false);
+    let (inlined, inlined_result) = best_of(&source, true);
+    assert_eq!(called, inlined_result);
+    println!("inline: {} calls to sq(), best of {}: {:?} called, {:?} inlined", CALLS, RUNS, calls, inlined);
+}
@@ -0,0 +1,28 @@
+// Times the VM on a million-iteration counting loop that is nothing but
+// integer arithmetic, comparisons and variable traffic. Run with `cargo bench`.
+use std::time::{Duration, Instant};
+use flux_compiler::{Engine, Runtime};
+
+const ITERATIONS: usize = 1_000_000;
+const RUNS: usize = 5;
+
+fn program() -> String {
+    format!(
+        "mut total = 0\nmut i = 0\nwhile (i < {}) {{\n    total = total + i * 2 - 1\n    i = i + 1\n}}\ntotal\n",
+        ITERATIONS
+    )
+}
+
+fn main() {
+    let source = program();
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut runtime = Runtime::new().with_engine(Engine::Vm);
+        let start = Instant::now();
+        let total = runtime.run_source(&source).expect("benchmark program failed");
+        best = best.min(start.elapsed());
+        let n = ITERATIONS as i64;
+        assert_eq!(total.to_string(), (n * (n - 1) - n).to_string());
+    }
+    println!("vm_arithmetic: {} iterations, best of {}: {:?}", ITERATIONS, RUNS, best);
+}
@@ -0,0 +1,21 @@
+#!/bin/sh
+# Builds, lints and tests every supported combination of the engine features:
+# both (the default), the interpreter alone and the VM alone. A build with
+# neither is a compile error by design, so it is checked to fail.
+set -eu
+cd "$(dirname "$0")/.."
+
+for features in "eval,vm" "eval" "vm"; do
+    echo "== features: $features"
+    cargo build --workspace --no-default-features --features "$features"
+    cargo clippy --workspace --all-targets --no-default-features --features "$features" -- -D warnings
+    cargo test --workspace --no-default-features --features "$features"
+done
+
+echo "== features: none (must not build)"
+if cargo build --no-default-features 2>/dev/null; then
+    echo "a build without eval or vm should fail" >&2
+    exit 1
+fi
+
+echo "all feature combinations ok"
@@ -1,17 +1,82 @@
 use std::fmt;
+use crate::intern::Symbol;
+use crate::parser::ParseError;
+use crate::token::Span;
+
+// What parse_program makes of a source: the statements that parsed, and the
+// errors found in the rest. Past the parser's limit errors are only counted;
+// `summary` then says how many were left out ("... and 3,214 more errors").
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+    pub errors: Vec<ParseError>,
+    pub summary: Option<String>,
+}
+
+impl Program {
+    // Anything failed to parse, whether or not its error was kept
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty() || self.summary.is_some()
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct BlockStatement {
     pub statements: Vec<Statement>,
 }
 
+impl BlockStatement {
+    // A bare string literal as the first statement documents the function
+    pub fn docstring(&self) -> Option<&str> {
+        match self.statements.first() {
+            Some(Statement::Expression(Expression::StringLiteral(s))) => Some(s),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement {
-    Let { name: String, value: Expression },
-    Assign { name: String, value: Expression }, 
-    Return(Expression),
+    Let { name: Symbol, value: Expression },
+    // `mut [a, b] = value`: the first elements of an array, one name each
+    LetDestructure { names: Vec<Symbol>, value: Expression, span: Span },
+    // `name = value`, which needs a `mut name` in scope; the span is for that error
+    Assign { name: Symbol, value: Expression, span: Span },
+    // `name op= value`, with `operator` the arithmetic part ("+" for `+=`)
+    CompoundAssign { name: Symbol, operator: String, value: Expression, span: Span },
+    // left[index] = value (left may itself be an index expression: grid[0][1] = 5);
+    // the span covers the target, where a bad index or container is reported
+    IndexAssign { left: Expression, index: Expression, value: Expression, span: Span },
+    // `return` or `return value` (a bare return gives null)
+    Return(Option<Expression>),
+    // `break` or `break value` (the value becomes the loop's result)
+    Break(Option<Expression>),
+    Continue,
+    // `defer { ... }`: runs when the enclosing function (or script) finishes
+    Defer(BlockStatement),
     Expression(Expression),
-    Function { name: String, parameters: Vec<String>, body: BlockStatement },
+    // `fn name(...) {...}`, lowered to a `mut` binding (see ast_lower)
+    Function { name: Symbol, parameters: Vec<Symbol>, variadic: bool, body: BlockStatement },
+    // `material Steel { density: 7850, yield: 250 }` (or `context`): binds
+    // `Steel` to a record of those fields, in the order written
+    Record { kind: RecordKind, name: Symbol, fields: Vec<(Symbol, Expression)> },
+}
+
+// The keywords that declare a record. The kind tags the record, so a material
+// and a context with the same fields are still different values.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecordKind {
+    Material,
+    Context,
+}
+
+impl RecordKind {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            RecordKind::Material => "material",
+            RecordKind::Context => "context",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -19,42 +84,595 @@ pub struct HashLiteral {
     pub pairs: Vec<(Expression, Expression)>, 
 }
 
+// One arm of a match expression: `pattern [if guard] => body`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: BlockStatement,
+}
+
+// The shapes a match arm can test for. Names in a pattern bind in the arm's scope.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Wildcard,
+    Binding(Symbol),
+    Literal(Expression),
+    // [a, b, rest...]
+    Array { elements: Vec<Pattern>, rest: Option<Symbol> },
+    // {"type": "click", "x": x} (other keys are allowed)
+    Hash(Vec<(Expression, Pattern)>),
+}
+
+// Clippy objects to IndexExpression repeating the enum's name, but the name is
+// also the node's "type" in the --ast json output, which tools read
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
-    Identifier(String),
-    IntegerLiteral(i64),
+    Identifier { name: Symbol, span: Span },
+    // `raw` is the literal exactly as written, for messages and formatting
+    IntegerLiteral { value: i64, raw: String },
+    FloatLiteral(f64),
     StringLiteral(String),
+    CharLiteral(char),
     Boolean(bool),
-    Prefix { operator: String, right: Box<Expression> },
-    Infix { left: Box<Expression>, operator: String, right: Box<Expression> },
+    // Operations that can fail carry the source they were parsed from (see diagnostics)
+    Prefix { operator: String, right: Box<Expression>, span: Span },
+    Infix { left: Box<Expression>, operator: String, right: Box<Expression>, span: Span },
     If { condition: Box<Expression>, consequence: BlockStatement, alternative: Option<BlockStatement> },
-    FunctionLiteral { parameters: Vec<String>, body: BlockStatement },
+    // `variadic`: the last parameter collects the arguments past the others (`...args`)
+    FunctionLiteral { parameters: Vec<Symbol>, variadic: bool, body: BlockStatement },
     // NOTE: We use "Call" (not CallExpression)
-    Call { function: Box<Expression>, arguments: Vec<Expression> },
+    Call { function: Box<Expression>, arguments: Vec<Expression>, span: Span },
     // NOTE: We use Tuple Variant for Array (ArrayLiteral(Vec...))
     ArrayLiteral(Vec<Expression>),
-    IndexExpression { left: Box<Expression>, index: Box<Expression> },
+    IndexExpression { left: Box<Expression>, index: Box<Expression>, span: Span },
+    // object.property: a hash's (or module's) value for the string key "property"
+    Member { object: Box<Expression>, property: Symbol, span: Span },
+    // start..end, or start..=end with `inclusive`: an array of the integers between
+    Range { start: Box<Expression>, end: Box<Expression>, inclusive: bool, span: Span },
     While { condition: Box<Expression>, body: BlockStatement },
+    // for (init; condition; post) { body }: every part of the header may be left out
+    For { init: Option<Box<Statement>>, condition: Option<Box<Expression>>, post: Option<Box<Statement>>, body: BlockStatement },
+    // for (variable in iterable) { body }; `for (k, v in h)` also binds `value`.
+    // The span is the iterable's, for a value that can't be iterated
+    ForIn { variable: Symbol, value: Option<Symbol>, iterable: Box<Expression>, body: BlockStatement, span: Span },
     HashLiteral(HashLiteral), 
+    // The span covers `match (subject)`, for a value no arm accepts
+    Match { subject: Box<Expression>, arms: Vec<MatchArm>, span: Span },
+    // `{ ... }` where a value goes: its own scope, worth its last statement
+    Block(BlockStatement),
+}
+
+impl Expression {
+    // The variant's name, for --stats
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Expression::Identifier { .. } => "Identifier",
+            Expression::IntegerLiteral { .. } => "IntegerLiteral",
+            Expression::FloatLiteral(_) => "FloatLiteral",
+            Expression::StringLiteral(_) => "StringLiteral",
+            Expression::CharLiteral(_) => "CharLiteral",
+            Expression::Boolean(_) => "Boolean",
+            Expression::Prefix { .. } => "Prefix",
+            Expression::Infix { .. } => "Infix",
+            Expression::If { .. } => "If",
+            Expression::FunctionLiteral { .. } => "FunctionLiteral",
+            Expression::Call { .. } => "Call",
+            Expression::ArrayLiteral(_) => "ArrayLiteral",
+            Expression::IndexExpression { .. } => "IndexExpression",
+            Expression::Member { .. } => "Member",
+            Expression::Range { .. } => "Range",
+            Expression::While { .. } => "While",
+            Expression::For { .. } => "For",
+            Expression::ForIn { .. } => "ForIn",
+            Expression::HashLiteral(_) => "HashLiteral",
+            Expression::Match { .. } => "Match",
+            Expression::Block(_) => "Block",
+        }
+    }
 }
 
-// Display Implementation (for debugging/printing)
+// Display renders source that parses back to the same tree (see
+// tests/round_trip.rs), kept on one line: a block joins its statements with
+// "; ", and operations are fully parenthesised so precedence never matters.
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Expression::Identifier(s) => write!(f, "{}", s),
-            Expression::IntegerLiteral(i) => write!(f, "{}", i),
-            Expression::StringLiteral(s) => write!(f, "\"{}\"", s),
+            Expression::Identifier { name, .. } => write!(f, "{}", name),
+            Expression::IntegerLiteral { raw, .. } => write!(f, "{}", raw),
+            Expression::FloatLiteral(value) => write!(f, "{}", float_literal(*value)),
+            Expression::StringLiteral(s) => write!(f, "\"{}\"", escape(s, '"')),
+            Expression::CharLiteral(c) => write!(f, "'{}'", escape(&c.to_string(), '\'')),
             Expression::Boolean(b) => write!(f, "{}", b),
-            Expression::Prefix { operator, right } => write!(f, "({}{})", operator, right),
-            Expression::Infix { left, operator, right } => write!(f, "({} {} {})", left, operator, right),
-            Expression::If { .. } => write!(f, "if ..."),
-            Expression::FunctionLiteral { .. } => write!(f, "fn(...)"),
-            Expression::Call { function, .. } => write!(f, "{}(...)", function),
-            Expression::ArrayLiteral(elements) => write!(f, "[{:?}]", elements),
-            Expression::IndexExpression { left, index } => write!(f, "({}[{}])", left, index),
-            Expression::While { .. } => write!(f, "while ..."),
-            Expression::HashLiteral(_) => write!(f, "{{...}}"),
-        }
-    }
-}
\ No newline at end of file
+            Expression::Prefix { operator, right, .. } => write!(f, "({}{})", operator, right),
+            Expression::Infix { left, operator, right, .. } => write!(f, "({} {} {})", left, operator, right),
+            Expression::If { condition, consequence, alternative } => {
+                write!(f, "if ({}) {}", condition, consequence)?;
+                match alternative {
+                    Some(alternative) => write!(f, " else {}", alternative),
+                    None => Ok(()),
+                }
+            },
+            Expression::FunctionLiteral { parameters, variadic, body } => {
+                write!(f, "fn({}) {}", parameter_list(parameters, *variadic), body)
+            },
+            Expression::Call { function, arguments, .. } => write!(f, "{}({})", function, comma_separated(arguments)),
+            Expression::ArrayLiteral(elements) => write!(f, "[{}]", comma_separated(elements)),
+            Expression::IndexExpression { left, index, .. } => write!(f, "({}[{}])", left, index),
+            Expression::Member { object, property, .. } => write!(f, "({}.{})", object, property),
+            Expression::Range { start, end, inclusive, .. } => write!(f, "({}{}{})", start, if *inclusive { "..=" } else { ".." }, end),
+            Expression::While { condition, body } => write!(f, "while ({}) {}", condition, body),
+            Expression::For { init, condition, post, body } => {
+                let init = init.as_ref().map_or(String::new(), |init| init.to_string());
+                let condition = condition.as_ref().map_or(String::new(), |c| format!(" {}", c));
+                let post = post.as_ref().map_or(String::new(), |p| format!(" {}", p));
+                write!(f, "for ({};{};{}) {}", init, condition, post, body)
+            },
+            Expression::ForIn { variable, value, iterable, body, .. } => match value {
+                Some(value) => write!(f, "for ({}, {} in {}) {}", variable, value, iterable, body),
+                None => write!(f, "for ({} in {}) {}", variable, iterable, body),
+            },
+            Expression::HashLiteral(node) => {
+                // A bare name before ':' is a string key; `(name)` is the variable's value
+                let pairs: Vec<String> = node.pairs.iter().map(|(key, value)| match key {
+                    Expression::Identifier { .. } => format!("({}): {}", key, value),
+                    _ => format!("{}: {}", key, value),
+                }).collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            },
+            Expression::Match { subject, arms, .. } => {
+                let arms: Vec<String> = arms.iter().map(|arm| match &arm.guard {
+                    Some(guard) => format!("{} if {} => {}", arm.pattern, guard, arm.body),
+                    None => format!("{} => {}", arm.pattern, arm.body),
+                }).collect();
+                write!(f, "match ({}) {{ {} }}", subject, arms.join(", "))
+            },
+            // Every statement ends in ';', so `{ x; }` isn't read back as the hash `{x}`
+            Expression::Block(block) if block.statements.is_empty() => write!(f, "{{ ; }}"),
+            Expression::Block(block) => {
+                let statements: Vec<String> = block.statements.iter().map(|s| format!("{};", s)).collect();
+                write!(f, "{{ {} }}", statements.join(" "))
+            },
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Let { name, value } => write!(f, "mut {} = {}", name, value),
+            Statement::LetDestructure { names, value, .. } => {
+                let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+                write!(f, "mut [{}] = {}", names.join(", "), value)
+            },
+            Statement::Assign { name, value, .. } => write!(f, "{} = {}", name, value),
+            Statement::CompoundAssign { name, operator, value, .. } => write!(f, "{} {}= {}", name, operator, value),
+            Statement::IndexAssign { left, index, value, .. } => write!(f, "{}[{}] = {}", left, index, value),
+            Statement::Return(Some(value)) => write!(f, "return {}", value),
+            Statement::Return(None) => write!(f, "return"),
+            Statement::Break(Some(value)) => write!(f, "break {}", value),
+            Statement::Break(None) => write!(f, "break"),
+            Statement::Continue => write!(f, "continue"),
+            Statement::Defer(body) => write!(f, "defer {}", body),
+            Statement::Expression(expr) => write!(f, "{}", expr),
+            Statement::Function { name, parameters, variadic, body } => {
+                write!(f, "fn {}({}) {}", name, parameter_list(parameters, *variadic), body)
+            },
+            Statement::Record { kind, name, fields } => {
+                let fields: Vec<String> = fields.iter().map(|(field, value)| format!("{}: {}", field, value)).collect();
+                write!(f, "{} {} {{ {} }}", kind.keyword(), name, fields.join(", "))
+            },
+        }
+    }
+}
+
+// `{ a; b }`, or `{}` when empty
+impl fmt::Display for BlockStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.statements.is_empty() {
+            return write!(f, "{{}}");
+        }
+        let statements: Vec<String> = self.statements.iter().map(|s| s.to_string()).collect();
+        write!(f, "{{ {} }}", statements.join("; "))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding(name) => write!(f, "{}", name),
+            Pattern::Literal(value) => write!(f, "{}", value),
+            Pattern::Array { elements, rest } => {
+                let mut items: Vec<String> = elements.iter().map(|p| p.to_string()).collect();
+                if let Some(rest) = rest { items.push(format!("{}...", rest)); }
+                write!(f, "[{}]", items.join(", "))
+            },
+            Pattern::Hash(pairs) => {
+                let pairs: Vec<String> = pairs.iter().map(|(key, pattern)| format!("{}: {}", key, pattern)).collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            },
+        }
+    }
+}
+
+fn comma_separated(items: &[Expression]) -> String {
+    items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// The lexer reads no exponents, so 1e16 is written out in full; a float always
+// keeps a decimal point so it doesn't come back as an integer
+fn float_literal(value: f64) -> String {
+    if !value.is_finite() { return crate::object::format_float(value); }
+    let digits = value.to_string();
+    if digits.contains('.') { digits } else { format!("{}.0", digits) }
+}
+
+// The escapes the lexer understands, for a literal in `quote`s
+fn escape(s: &str, quote: char) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\\' => out.push_str("\\\\"),
+            c if c == quote => { out.push('\\'); out.push(c); },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// "a, b, ...rest": parameters as they were written
+pub fn parameter_list(parameters: &[Symbol], variadic: bool) -> String {
+    let mut names: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+    if let (true, Some(last)) = (variadic, names.last_mut()) {
+        last.insert_str(0, "...");
+    }
+    names.join(", ")
+}
+
+// --- MUTABLE VISITOR ---
+//
+// Walks a tree in place. Override a method to rewrite that kind of node; call
+// the matching walk_* function from it to carry on into the node's children.
+
+pub trait VisitorMut {
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        walk_expression(self, expr);
+    }
+
+    fn visit_block(&mut self, block: &mut BlockStatement) {
+        walk_block(self, block);
+    }
+}
+
+pub fn walk_block<V: VisitorMut + ?Sized>(v: &mut V, block: &mut BlockStatement) {
+    for stmt in &mut block.statements {
+        v.visit_statement(stmt);
+    }
+}
+
+pub fn walk_statement<V: VisitorMut + ?Sized>(v: &mut V, stmt: &mut Statement) {
+    match stmt {
+        Statement::Let { value, .. } | Statement::LetDestructure { value, .. } | Statement::Assign { value, .. }
+        | Statement::CompoundAssign { value, .. } => {
+            v.visit_expression(value);
+        },
+        Statement::IndexAssign { left, index, value, .. } => {
+            v.visit_expression(left);
+            v.visit_expression(index);
+            v.visit_expression(value);
+        },
+        Statement::Return(Some(value)) | Statement::Break(Some(value)) | Statement::Expression(value) => v.visit_expression(value),
+        Statement::Return(None) | Statement::Break(None) | Statement::Continue => {},
+        Statement::Defer(body) | Statement::Function { body, .. } => v.visit_block(body),
+        Statement::Record { fields, .. } => {
+            for (_, value) in fields { v.visit_expression(value); }
+        },
+    }
+}
+
+pub fn walk_expression<V: VisitorMut + ?Sized>(v: &mut V, expr: &mut Expression) {
+    match expr {
+        Expression::Identifier { .. } | Expression::IntegerLiteral { .. } | Expression::FloatLiteral(_)
+        | Expression::StringLiteral(_) | Expression::CharLiteral(_) | Expression::Boolean(_) => {},
+        Expression::Prefix { right, .. } => v.visit_expression(right),
+        Expression::Infix { left, right, .. } => {
+            v.visit_expression(left);
+            v.visit_expression(right);
+        },
+        Expression::If { condition, consequence, alternative } => {
+            v.visit_expression(condition);
+            v.visit_block(consequence);
+            if let Some(alternative) = alternative { v.visit_block(alternative); }
+        },
+        Expression::FunctionLiteral { body, .. } => v.visit_block(body),
+        Expression::Call { function, arguments, .. } => {
+            v.visit_expression(function);
+            for argument in arguments { v.visit_expression(argument); }
+        },
+        Expression::ArrayLiteral(elements) => {
+            for element in elements { v.visit_expression(element); }
+        },
+        Expression::IndexExpression { left, index, .. } => {
+            v.visit_expression(left);
+            v.visit_expression(index);
+        },
+        Expression::Member { object, .. } => v.visit_expression(object),
+        Expression::Range { start, end, .. } => {
+            v.visit_expression(start);
+            v.visit_expression(end);
+        },
+        Expression::While { condition, body } => {
+            v.visit_expression(condition);
+            v.visit_block(body);
+        },
+        Expression::For { init, condition, post, body } => {
+            if let Some(init) = init { v.visit_statement(init); }
+            if let Some(condition) = condition { v.visit_expression(condition); }
+            if let Some(post) = post { v.visit_statement(post); }
+            v.visit_block(body);
+        },
+        Expression::ForIn { iterable, body, .. } => {
+            v.visit_expression(iterable);
+            v.visit_block(body);
+        },
+        Expression::HashLiteral(hash) => {
+            for (key, value) in &mut hash.pairs {
+                v.visit_expression(key);
+                v.visit_expression(value);
+            }
+        },
+        Expression::Match { subject, arms, .. } => {
+            v.visit_expression(subject);
+            for arm in arms {
+                walk_pattern(v, &mut arm.pattern);
+                if let Some(guard) = &mut arm.guard { v.visit_expression(guard); }
+                v.visit_block(&mut arm.body);
+            }
+        },
+        Expression::Block(body) => v.visit_block(body),
+    }
+}
+
+// The expressions inside a pattern: literals and hash keys
+pub fn walk_pattern<V: VisitorMut + ?Sized>(v: &mut V, pattern: &mut Pattern) {
+    match pattern {
+        Pattern::Wildcard | Pattern::Binding(_) => {},
+        Pattern::Literal(value) => v.visit_expression(value),
+        Pattern::Array { elements, .. } => {
+            for element in elements { walk_pattern(v, element); }
+        },
+        Pattern::Hash(pairs) => {
+            for (key, pattern) in pairs {
+                v.visit_expression(key);
+                walk_pattern(v, pattern);
+            }
+        },
+    }
+}
+
+// --- JSON DUMP ---
+//
+// `flux --ast json file.flux` prints this for external tools. The schema is
+// versioned: anything that changes the shape below bumps AST_JSON_VERSION.
+//
+//   {"version": 1, "program": [Statement, ...]}   (one top-level statement per line)
+//
+// Every node is an object whose "type" comes first, then its fields in the
+// order listed. Blocks are arrays of statements; absent optional parts are null.
+//
+// Statements:
+//   let {name, value}            assign {name, value}      index_assign {left, index, value}
+//   let_destructure {names, value}
+//   return {value}               break {value}             continue {}
+//   defer {body}                 expression {expression}   function {name, parameters, variadic, body}
+//   material {name, fields: [{name, value}]}               context {name, fields: [{name, value}]}
+// Expressions:
+//   identifier {name}            integer {value, raw}      float {value}
+//   string {value}               char {value}              boolean {value}
+//   prefix {operator, right}
+//   infix {operator, left, right}                          if {condition, consequence, alternative}
+//   function {parameters, variadic, body}                  call {function, arguments}
+//   array {elements}             index {left, index}       member {object, property}
+//   range {start, end, inclusive}                          while {condition, body}
+//   for {init, condition, post, body}                     for_in {variable, value, iterable, body}
+//   hash {pairs: [{key, value}]} match {subject, arms: [{pattern, guard, body}]}
+//   block {body}
+// Patterns:
+//   wildcard {}                  binding {name}            literal {value}
+//   array {elements, rest}       hash {pairs: [{key, pattern}]}
+//
+// Nodes carry no source positions yet, although some keep spans for error excerpts.
+pub const AST_JSON_VERSION: u32 = 1;
+
+pub fn to_json(program: &[Statement]) -> String {
+    let statements: Vec<String> = program.iter().map(statement_json).collect();
+    if statements.is_empty() {
+        return format!("{{\"version\":{},\"program\":[]}}", AST_JSON_VERSION);
+    }
+    format!("{{\"version\":{},\"program\":[\n{}\n]}}", AST_JSON_VERSION, statements.join(",\n"))
+}
+
+// `s` as a quoted, escaped JSON string, for --ast json, --tokens and json_encode()
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    write_json_string(s, &mut out);
+    out
+}
+
+pub fn write_json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// {"type":"...", field: value, ...} with the fields already encoded
+fn node(node_type: &str, fields: &[(&str, String)]) -> String {
+    let mut out = format!("{{\"type\":\"{}\"", node_type);
+    for (name, value) in fields {
+        out.push_str(&format!(",\"{}\":{}", name, value));
+    }
+    out.push('}');
+    out
+}
+
+fn json_list<T>(items: &[T], encode: impl Fn(&T) -> String) -> String {
+    let items: Vec<String> = items.iter().map(encode).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn name_json(name: &Symbol) -> String {
+    json_string(name.as_str())
+}
+
+fn block_json(block: &BlockStatement) -> String {
+    json_list(&block.statements, statement_json)
+}
+
+fn statement_json(stmt: &Statement) -> String {
+    match stmt {
+        Statement::Let { name, value } => node("let", &[("name", name_json(name)), ("value", expression_json(value))]),
+        Statement::LetDestructure { names, value, .. } => node("let_destructure", &[
+            ("names", json_list(names, name_json)),
+            ("value", expression_json(value)),
+        ]),
+        Statement::Assign { name, value, .. } => node("assign", &[("name", name_json(name)), ("value", expression_json(value))]),
+        // Shown as the assignment it stands for, as it was before it had a node of its own
+        Statement::CompoundAssign { .. } => statement_json(&crate::ast_lower::lower_statement(stmt.clone())),
+        Statement::IndexAssign { left, index, value, .. } => node("index_assign", &[
+            ("left", expression_json(left)),
+            ("index", expression_json(index)),
+            ("value", expression_json(value)),
+        ]),
+        Statement::Return(value) => node("return", &[("value", value.as_ref().map_or("null".to_string(), expression_json))]),
+        Statement::Break(value) => node("break", &[("value", value.as_ref().map_or("null".to_string(), expression_json))]),
+        Statement::Continue => node("continue", &[]),
+        Statement::Defer(body) => node("defer", &[("body", block_json(body))]),
+        Statement::Expression(expr) => node("expression", &[("expression", expression_json(expr))]),
+        Statement::Function { name, parameters, variadic, body } => node("function", &[
+            ("name", name_json(name)),
+            ("parameters", json_list(parameters, name_json)),
+            ("variadic", variadic.to_string()),
+            ("body", block_json(body)),
+        ]),
+        Statement::Record { kind, name, fields } => node(kind.keyword(), &[
+            ("name", name_json(name)),
+            ("fields", json_list(fields, |(field, value)| {
+                format!("{{\"name\":{},\"value\":{}}}", name_json(field), expression_json(value))
+            })),
+        ]),
+    }
+}
+
+fn expression_json(expr: &Expression) -> String {
+    match expr {
+        Expression::Identifier { name, .. } => node("identifier", &[("name", name_json(name))]),
+        Expression::IntegerLiteral { value, raw } => node("integer", &[
+            ("value", value.to_string()),
+            ("raw", json_string(raw)),
+        ]),
+        Expression::FloatLiteral(value) => node("float", &[("value", crate::object::format_float(*value))]),
+        Expression::StringLiteral(s) => node("string", &[("value", json_string(s))]),
+        Expression::CharLiteral(c) => node("char", &[("value", json_string(&c.to_string()))]),
+        Expression::Boolean(b) => node("boolean", &[("value", b.to_string())]),
+        Expression::Prefix { operator, right, .. } => node("prefix", &[
+            ("operator", json_string(operator)),
+            ("right", expression_json(right)),
+        ]),
+        Expression::Infix { left, operator, right, .. } => node("infix", &[
+            ("operator", json_string(operator)),
+            ("left", expression_json(left)),
+            ("right", expression_json(right)),
+        ]),
+        Expression::If { condition, consequence, alternative } => node("if", &[
+            ("condition", expression_json(condition)),
+            ("consequence", block_json(consequence)),
+            ("alternative", alternative.as_ref().map_or("null".to_string(), block_json)),
+        ]),
+        Expression::FunctionLiteral { parameters, variadic, body } => node("function", &[
+            ("parameters", json_list(parameters, name_json)),
+            ("variadic", variadic.to_string()),
+            ("body", block_json(body)),
+        ]),
+        Expression::Call { function, arguments, .. } => node("call", &[
+            ("function", expression_json(function)),
+            ("arguments", json_list(arguments, expression_json)),
+        ]),
+        Expression::ArrayLiteral(elements) => node("array", &[("elements", json_list(elements, expression_json))]),
+        Expression::IndexExpression { left, index, .. } => node("index", &[
+            ("left", expression_json(left)),
+            ("index", expression_json(index)),
+        ]),
+        Expression::Member { object, property, .. } => node("member", &[
+            ("object", expression_json(object)),
+            ("property", name_json(property)),
+        ]),
+        Expression::Range { start, end, inclusive, .. } => node("range", &[
+            ("start", expression_json(start)),
+            ("end", expression_json(end)),
+            ("inclusive", inclusive.to_string()),
+        ]),
+        Expression::While { condition, body } => node("while", &[
+            ("condition", expression_json(condition)),
+            ("body", block_json(body)),
+        ]),
+        Expression::For { init, condition, post, body } => node("for", &[
+            ("init", init.as_deref().map_or("null".to_string(), statement_json)),
+            ("condition", condition.as_deref().map_or("null".to_string(), expression_json)),
+            ("post", post.as_deref().map_or("null".to_string(), statement_json)),
+            ("body", block_json(body)),
+        ]),
+        Expression::ForIn { variable, value, iterable, body, .. } => node("for_in", &[
+            ("variable", name_json(variable)),
+            ("value", value.as_ref().map_or("null".to_string(), name_json)),
+            ("iterable", expression_json(iterable)),
+            ("body", block_json(body)),
+        ]),
+        Expression::HashLiteral(hash) => node("hash", &[("pairs", json_list(&hash.pairs, |(key, value)| {
+            format!("{{\"key\":{},\"value\":{}}}", expression_json(key), expression_json(value))
+        }))]),
+        Expression::Match { subject, arms, .. } => node("match", &[
+            ("subject", expression_json(subject)),
+            ("arms", json_list(arms, |arm| format!(
+                "{{\"pattern\":{},\"guard\":{},\"body\":{}}}",
+                pattern_json(&arm.pattern),
+                arm.guard.as_ref().map_or("null".to_string(), expression_json),
+                block_json(&arm.body),
+            ))),
+        ]),
+        Expression::Block(body) => node("block", &[("body", block_json(body))]),
+    }
+}
+
+fn pattern_json(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => node("wildcard", &[]),
+        Pattern::Binding(name) => node("binding", &[("name", name_json(name))]),
+        Pattern::Literal(value) => node("literal", &[("value", expression_json(value))]),
+        Pattern::Array { elements, rest } => node("array", &[
+            ("elements", json_list(elements, pattern_json)),
+            ("rest", rest.as_ref().map_or("null".to_string(), name_json)),
+        ]),
+        Pattern::Hash(pairs) => node("hash", &[("pairs", json_list(pairs, |(key, pattern)| {
+            format!("{{\"key\":{},\"pattern\":{}}}", expression_json(key), pattern_json(pattern))
+        }))]),
+    }
+}
@@ -0,0 +1,67 @@
+// Desugaring: rewrites syntax that is only shorthand into the core nodes both
+// engines run, as a program reaches them (eval_program, Compiler::compile).
+// New sugar needs a rewrite here, not a case in the evaluator and another in
+// the compiler. The parser's tree keeps the sugar for tools such as --ast.
+//
+//   fn name(a) {...}   mut name = fn(a) {...}
+//   x += e             x = x + e                (likewise -=, *=, /=; the span stays)
+//   a && b             if (a) { if (b) { true } else { false } } else { false }
+//   a || b             if (a) { true } else { if (b) { true } else { false } }
+use crate::ast::{walk_expression, walk_statement, BlockStatement, Expression, Statement, VisitorMut};
+
+pub fn lower(mut program: Vec<Statement>) -> Vec<Statement> {
+    for stmt in &mut program {
+        Lowering.visit_statement(stmt);
+    }
+    program
+}
+
+// One statement, sugar inside it included
+pub fn lower_statement(mut stmt: Statement) -> Statement {
+    Lowering.visit_statement(&mut stmt);
+    stmt
+}
+
+struct Lowering;
+
+impl VisitorMut for Lowering {
+    // Children first, so sugar nested in sugar is lowered too
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        walk_statement(self, stmt);
+        *stmt = match std::mem::replace(stmt, Statement::Continue) {
+            Statement::Function { name, parameters, variadic, body } => {
+                Statement::Let { name, value: Expression::FunctionLiteral { parameters, variadic, body } }
+            },
+            Statement::CompoundAssign { name, operator, value, span } => Statement::Assign {
+                name,
+                value: Expression::Infix { left: Box::new(Expression::Identifier { name, span }), operator, right: Box::new(value), span },
+                span,
+            },
+            core => core,
+        };
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        walk_expression(self, expr);
+        *expr = match std::mem::replace(expr, Expression::Boolean(false)) {
+            Expression::Infix { left, operator, right, .. } if operator == "&&" => {
+                if_else(*left, truthiness(*right), Expression::Boolean(false))
+            },
+            Expression::Infix { left, operator, right, .. } if operator == "||" => {
+                if_else(*left, Expression::Boolean(true), truthiness(*right))
+            },
+            core => core,
+        };
+    }
+}
+
+// `if (condition) { then } else { otherwise }`
+fn if_else(condition: Expression, then: Expression, otherwise: Expression) -> Expression {
+    let block = |expr| BlockStatement { statements: vec![Statement::Expression(expr)] };
+    Expression::If { condition: Box::new(condition), consequence: block(then), alternative: Some(block(otherwise)) }
+}
+
+// true or false, as `value` is truthy or not
+fn truthiness(value: Expression) -> Expression {
+    if_else(value, Expression::Boolean(true), Expression::Boolean(false))
+}
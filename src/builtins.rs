@@ -1,113 +1,594 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
-use crate::object::Object;
-use std::io::{self, Write};
+use std::rc::Rc;
+use crate::ast::{Statement, Expression, json_string, write_json_string};
+use crate::object::{Object, BuiltinDef, Param, HashKey, HashObject, MemoCache, IterState, format_float, equality_key, get_hash_key, objects_equal, hash_key_to_object, new_iterator, to_iterator};
+use std::io::{self, BufRead, Write};
+use std::env;
 use std::fs;
+use std::path::PathBuf;
+use crate::config;
+use crate::paths;
+use crate::source::{self, SourceError};
 // Necessary imports for the "Import" system (Sub-Compiler)
 use crate::lexer::Lexer;
 use crate::parser::Parser;
 use crate::environment::Environment;
-use crate::evaluator::eval_program;
+use crate::intern::Symbol;
+use crate::evaluator::{eval_program, iterator_next, apply_function};
 
-// This function registers all the "Standard Library" functions
-pub fn new_environment() -> HashMap<String, Object> {
-    let mut store = HashMap::new();
-    
+// Type names a builtin parameter accepts (see Param); ANY accepts everything
+const ANY: &[&str] = &[];
+const INTEGER: &[&str] = &["INTEGER"];
+const STRING: &[&str] = &["STRING"];
+const ARRAY: &[&str] = &["ARRAY"];
+const HASH: &[&str] = &["HASH"];
+// Modules read like a hash of their exports, records of their fields
+const HASH_LIKE: &[&str] = &["HASH", "MODULE", "MATERIAL", "CONTEXT"];
+const CALLABLE: &[&str] = &["FUNCTION", "BUILTIN"];
+const ITERATOR: &[&str] = &["ITERATOR"];
+// Whatever to_iterator() accepts
+const ITERABLE: &[&str] = &["ARRAY", "HASH", "MODULE", "MATERIAL", "CONTEXT", "STRING", "ITERATOR"];
+
+const fn arg(name: &'static str, types: &'static [&'static str]) -> Param {
+    Param { name, types }
+}
+
+// The "Standard Library": every builtin with its signature and one-line documentation (shown by help())
+pub static BUILTINS: &[BuiltinDef] = &[
     // 1. System I/O
-    store.insert("print".to_string(), Object::Builtin(print_fn));
-    store.insert("input".to_string(), Object::Builtin(input_fn));
-    
+    BuiltinDef { name: "print", params: &[arg("values", ANY)], required: 0, variadic: true, doc: "write the values to stdout separated by spaces, then a newline", func: print_fn },
+    BuiltinDef { name: "write", params: &[arg("values", ANY)], required: 0, variadic: true, doc: "write the values to stdout with no separators or newline", func: write_fn },
+    BuiltinDef { name: "write_err", params: &[arg("values", ANY)], required: 0, variadic: true, doc: "like write, but to stderr", func: write_err_fn },
+    BuiltinDef { name: "input", params: &[arg("prompt", ANY)], required: 0, variadic: false, doc: "show the prompt and return one line of stdin as a string", func: input_fn },
+
     // 2. Data Helpers
-    store.insert("len".to_string(), Object::Builtin(len_fn));
-    store.insert("int".to_string(), Object::Builtin(int_fn));
-    
+    BuiltinDef { name: "len", params: &[arg("x", &["STRING", "ARRAY"])], required: 1, variadic: false, doc: "the number of characters in a string or elements in an array", func: len_fn },
+    BuiltinDef { name: "int", params: &[arg("x", &["STRING", "INTEGER", "FLOAT"])], required: 1, variadic: false, doc: "convert a string, integer or float (truncating) to an integer", func: int_fn },
+    BuiltinDef { name: "ord", params: &[arg("c", &["CHAR", "STRING"])], required: 1, variadic: false, doc: "the code point of a char (or one-character string) as an integer", func: ord_fn },
+    BuiltinDef { name: "chr", params: &[arg("n", INTEGER)], required: 1, variadic: false, doc: "the char with code point n", func: chr_fn },
+    BuiltinDef { name: "json_encode", params: &[arg("value", ANY), arg("indent", INTEGER)], required: 1, variadic: false, doc: "the value as JSON text, one element per line indented by indent spaces if given; integer and boolean hash keys become strings", func: json_encode_fn },
+    BuiltinDef { name: "scan", params: &[arg("s", STRING), arg("pattern", STRING)], required: 2, variadic: false, doc: "the {int}, {word} and {str} fields of s as an array, or null if s doesn't fit the pattern", func: scan_fn },
+
     // 3. File System
-    store.insert("read_file".to_string(), Object::Builtin(read_file_fn));
-    store.insert("write_file".to_string(), Object::Builtin(write_file_fn));
-    
+    BuiltinDef { name: "read_file", params: &[arg("path", STRING)], required: 1, variadic: false, doc: "the file's contents, or \"\" if it can't be read", func: read_file_fn },
+    BuiltinDef { name: "write_file", params: &[arg("path", STRING), arg("content", &["STRING", "INTEGER"])], required: 2, variadic: false, doc: "write the file, returning true on success", func: write_file_fn },
+    BuiltinDef { name: "path_join", params: &[arg("parts", STRING)], required: 1, variadic: true, doc: "the parts joined with the separator the first of them uses; an absolute part starts over", func: path_join_fn },
+    BuiltinDef { name: "path_dirname", params: &[arg("path", STRING)], required: 1, variadic: false, doc: "everything before the last step of path (\"\" if there is none)", func: path_dirname_fn },
+    BuiltinDef { name: "path_basename", params: &[arg("path", STRING)], required: 1, variadic: false, doc: "the last step of path", func: path_basename_fn },
+    BuiltinDef { name: "path_ext", params: &[arg("path", STRING)], required: 1, variadic: false, doc: "the extension of path without its dot (\"\" if there is none)", func: path_ext_fn },
+    BuiltinDef { name: "path_absolute", params: &[arg("path", STRING)], required: 1, variadic: false, doc: "path resolved against the working directory; the file needn't exist", func: path_absolute_fn },
+    BuiltinDef { name: "cwd", params: &[], required: 0, variadic: false, doc: "the working directory, which relative paths are resolved against", func: cwd_fn },
+    BuiltinDef { name: "set_cwd", params: &[arg("path", STRING)], required: 1, variadic: false, doc: "change the working directory", func: set_cwd_fn },
+
     // 4. Array Tools
-    store.insert("push".to_string(), Object::Builtin(push_fn));
-    store.insert("first".to_string(), Object::Builtin(first_fn));
-    store.insert("last".to_string(), Object::Builtin(last_fn));
-    store.insert("rest".to_string(), Object::Builtin(rest_fn));
+    BuiltinDef { name: "push", params: &[arg("array", ARRAY), arg("value", ANY)], required: 2, variadic: false, doc: "a new array with value appended", func: push_fn },
+    BuiltinDef { name: "first", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "the first element, or null if empty", func: first_fn },
+    BuiltinDef { name: "last", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "the last element, or null if empty", func: last_fn },
+    BuiltinDef { name: "rest", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "a new array without the first element", func: rest_fn },
+    BuiltinDef { name: "sum", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "the total of an array of integers (0 if empty)", func: sum_fn },
+    BuiltinDef { name: "min", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "the smallest integer or string, or null if empty", func: min_fn },
+    BuiltinDef { name: "max", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "the largest integer or string, or null if empty", func: max_fn },
+    BuiltinDef { name: "sort", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "a new array of the integers or strings in ascending order", func: sort_fn },
+    BuiltinDef { name: "sort_by", params: &[arg("array", ARRAY), arg("fn", CALLABLE)], required: 2, variadic: false, doc: "a new array ordered by the integer or string fn returns for each element", func: sort_by_fn },
+    BuiltinDef { name: "unique", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "a new array without repeats (by ==), keeping first occurrences", func: unique_fn },
+    BuiltinDef { name: "count", params: &[arg("array", ARRAY), arg("value", ANY)], required: 2, variadic: false, doc: "how many elements are == value", func: count_fn },
+    BuiltinDef { name: "deep_equal", params: &[arg("a", ANY), arg("b", ANY)], required: 2, variadic: false, doc: "whether a == b, element by element; values of different types are unequal rather than an error", func: deep_equal_fn },
+    BuiltinDef { name: "join", params: &[arg("array", ARRAY), arg("sep", STRING)], required: 1, variadic: false, doc: "the strings of the array joined by sep (default \"\")", func: join_fn },
+
+    // 5. Hash Tools
+    BuiltinDef { name: "get", params: &[arg("hash", HASH_LIKE), arg("key", ANY), arg("fallback", ANY)], required: 2, variadic: false, doc: "the value for key, or fallback (default null) if missing", func: get_fn },
+    BuiltinDef { name: "hash_with_default", params: &[arg("value", ANY)], required: 1, variadic: false, doc: "an empty hash that returns value for missing keys", func: hash_with_default_fn },
+    BuiltinDef { name: "counter", params: &[], required: 0, variadic: false, doc: "an empty hash that returns 0 for missing keys", func: counter_fn },
+    BuiltinDef { name: "merge", params: &[arg("hash", HASH), arg("other", HASH)], required: 2, variadic: false, doc: "hash with other's entries added over its own, keeping hash's default for missing keys (or other's, if hash has none)", func: merge_fn },
+    BuiltinDef { name: "deep_get", params: &[arg("obj", ANY), arg("path", ARRAY)], required: 2, variadic: false, doc: "follow an array of keys/indices, null on any missing link", func: deep_get_fn },
+    BuiltinDef { name: "deep_set", params: &[arg("obj", ANY), arg("path", ARRAY), arg("value", ANY)], required: 3, variadic: false, doc: "a copy of obj with value stored at path", func: deep_set_fn },
+    BuiltinDef { name: "keys", params: &[arg("hash", HASH_LIKE)], required: 1, variadic: false, doc: "the keys, integers then booleans then strings, each in order", func: keys_fn },
+    BuiltinDef { name: "to_pairs", params: &[arg("hash", HASH_LIKE)], required: 1, variadic: false, doc: "an array of [key, value] arrays, sorted by key", func: to_pairs_fn },
+    BuiltinDef { name: "from_pairs", params: &[arg("pairs", ARRAY)], required: 1, variadic: false, doc: "a hash from [key, value] arrays; later pairs win", func: from_pairs_fn },
+
+    // 6. Module System
+    BuiltinDef { name: "import", params: &[arg("path", STRING)], required: 1, variadic: false, doc: "run a .flux file and return its variables as a hash", func: import_fn },
+
+    // 7. Function Tools
+    BuiltinDef { name: "memoize", params: &[arg("fn", CALLABLE), arg("max_entries", INTEGER)], required: 1, variadic: false, doc: "fn with its results cached by argument list", func: memoize_fn },
+    BuiltinDef { name: "help", params: &[arg("x", ANY)], required: 1, variadic: false, doc: "print the documentation of a function, builtin or module", func: help_fn },
+    BuiltinDef { name: "doc", params: &[arg("x", ANY)], required: 1, variadic: false, doc: "the documentation of a function, builtin or module as a string (null if none)", func: doc_fn },
+
+    // 8. Randomness (deterministic after seed_random)
+    BuiltinDef { name: "seed_random", params: &[arg("n", INTEGER)], required: 1, variadic: false, doc: "reseed the random generator so later results repeat exactly", func: seed_random_fn },
+    BuiltinDef { name: "random", params: &[arg("n", INTEGER)], required: 1, variadic: false, doc: "a random integer from 0 to n - 1", func: random_fn },
+    BuiltinDef { name: "shuffle", params: &[arg("array", ARRAY)], required: 1, variadic: false, doc: "a new array with the elements in random order", func: shuffle_fn },
+    BuiltinDef { name: "sample", params: &[arg("array", ARRAY), arg("k", INTEGER)], required: 2, variadic: false, doc: "a new array of k distinct elements chosen at random", func: sample_fn },
+
+    // 9. Iterators (lazy sequences)
+    BuiltinDef { name: "range", params: &[arg("start", INTEGER), arg("end", INTEGER), arg("step", INTEGER)], required: 1, variadic: false, doc: "a lazy sequence of integers (start and step optional)", func: range_fn },
+    BuiltinDef { name: "iter", params: &[arg("x", ITERABLE)], required: 1, variadic: false, doc: "an iterator over an array, a hash's keys or a string's characters", func: iter_fn },
+    BuiltinDef { name: "next", params: &[arg("it", ITERATOR)], required: 1, variadic: false, doc: "the next value of an iterator, or null when exhausted", func: next_fn },
+    BuiltinDef { name: "map", params: &[arg("iterable", ITERABLE), arg("fn", CALLABLE)], required: 2, variadic: false, doc: "a lazy sequence of fn applied to each value", func: map_fn },
+    BuiltinDef { name: "filter", params: &[arg("iterable", ITERABLE), arg("fn", CALLABLE)], required: 2, variadic: false, doc: "a lazy sequence of the values for which fn is truthy", func: filter_fn },
+    BuiltinDef { name: "take", params: &[arg("iterable", ITERABLE), arg("n", INTEGER)], required: 2, variadic: false, doc: "a lazy sequence of at most n values", func: take_fn },
+    BuiltinDef { name: "collect", params: &[arg("iterable", ITERABLE)], required: 1, variadic: false, doc: "all remaining values as an array", func: collect_fn },
+
+    // 10. Logging (to stderr)
+    BuiltinDef { name: "log_debug", params: &[arg("msg", ANY), arg("data", HASH)], required: 1, variadic: false, doc: "log a debug line with an optional hash of data", func: log_debug_fn },
+    BuiltinDef { name: "log_info", params: &[arg("msg", ANY), arg("data", HASH)], required: 1, variadic: false, doc: "log an info line with an optional hash of data", func: log_info_fn },
+    BuiltinDef { name: "log_warn", params: &[arg("msg", ANY), arg("data", HASH)], required: 1, variadic: false, doc: "log a warning with an optional hash of data", func: log_warn_fn },
+    BuiltinDef { name: "log_error", params: &[arg("msg", ANY), arg("data", HASH)], required: 1, variadic: false, doc: "log an error with an optional hash of data", func: log_error_fn },
+    BuiltinDef { name: "set_log_level", params: &[arg("level", STRING)], required: 1, variadic: false, doc: "hide log lines below \"debug\", \"info\", \"warn\" or \"error\"", func: set_log_level_fn },
+    BuiltinDef { name: "set_log_format", params: &[arg("format", STRING)], required: 1, variadic: false, doc: "\"text\" (the default) or \"json\" for one JSON object per line", func: set_log_format_fn },
 
-    // 5. Module System
-    store.insert("import".to_string(), Object::Builtin(import_fn));
+    // 11. Debugging
+    BuiltinDef { name: "breakpoint", params: &[], required: 0, variadic: false, doc: "under --debug, pause in a shell on the calling scope (`continue` resumes, `locals` lists its variables); otherwise nothing", func: breakpoint_fn },
 
+    // 12. Command Line
+    BuiltinDef { name: "args", params: &[], required: 0, variadic: false, doc: "the strings that followed the script's name on the command line", func: args_fn },
+    BuiltinDef { name: "parse_args", params: &[arg("spec", HASH), arg("args", ARRAY)], required: 1, variadic: false, doc: "a hash of the flags in args (default args()) described by spec, plus \"rest\": the other arguments; spec maps each flag name to a hash of \"type\" (\"bool\", \"int\" or \"string\"), \"short\", \"default\", \"required\" and \"help\"", func: parse_args_fn },
+    BuiltinDef { name: "usage", params: &[arg("spec", HASH)], required: 1, variadic: false, doc: "the usage text for a parse_args spec", func: usage_fn },
+];
+
+// This function registers all the "Standard Library" functions
+pub fn new_environment() -> HashMap<Symbol, Object> {
+    let mut store = HashMap::new();
+    for def in BUILTINS {
+        store.insert(Symbol::intern(def.name), Object::Builtin(def));
+    }
     store
 }
 
+thread_local! {
+    static BUILTIN_SCOPE: Environment = {
+        let mut env = Environment::new();
+        for (name, tool) in new_environment() { env.set(name, tool); }
+        env.freeze()
+    };
+}
+
+// The builtins as one scope, built on first use and shared by every program
+// and module on this thread as their outermost scope. Nothing is ever set in
+// it: a binding that shadows a builtin lands in the program's or module's own
+// scope, and it is frozen, so `print = ...` can't reach it either.
+pub fn builtin_scope() -> Environment {
+    BUILTIN_SCOPE.with(|env| env.clone())
+}
+
+// Runs a builtin once its arguments fit its signature. Otherwise every builtin
+// fails the same way: "push: expected 2 arguments, got 1" or
+// "push: expected ARRAY as argument 1, got INTEGER".
+pub fn call(def: &BuiltinDef, args: Vec<Object>) -> Object {
+    crate::stats::builtin(def.name);
+    match check_args(def, &args) {
+        Ok(()) => (def.func)(args),
+        Err(msg) => Object::Error(msg),
+    }
+}
+
+pub fn check_args(def: &BuiltinDef, args: &[Object]) -> Result<(), String> {
+    let most = if def.variadic { usize::MAX } else { def.params.len() };
+    if args.len() < def.required || args.len() > most {
+        return Err(format!("{}: expected {}, got {}", def.name, arity(def), args.len()));
+    }
+    for (i, arg) in args.iter().enumerate() {
+        // Extra values of a variadic builtin take the last parameter's types
+        let param = &def.params[i.min(def.params.len() - 1)];
+        if !param.types.is_empty() && !param.types.contains(&arg.type_name()) {
+            return Err(format!("{}: expected {} as argument {}, got {}", def.name, one_of(param.types), i + 1, arg.type_name()));
+        }
+    }
+    Ok(())
+}
+
+// "no arguments", "1 argument", "2 or 3 arguments", "1 to 3 arguments", "at least 1 argument"
+fn arity(def: &BuiltinDef) -> String {
+    let count = |n: usize| match n {
+        0 => "no arguments".to_string(),
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    };
+    let (least, most) = (def.required, def.params.len());
+    if def.variadic {
+        format!("at least {}", count(least))
+    } else if least == most {
+        count(most)
+    } else if least + 1 == most {
+        format!("{} or {} arguments", least, most)
+    } else {
+        format!("{} to {} arguments", least, most)
+    }
+}
+
+// "ARRAY", "STRING or ARRAY", "ARRAY, HASH or STRING"
+fn one_of(types: &[&str]) -> String {
+    match types.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => "anything".to_string(),
+    }
+}
+
+// For match arms check_args has already ruled out
+fn unchecked(name: &str) -> Object {
+    Object::Error(format!("{}: arguments don't fit its signature", name))
+}
+
 // --- STANDARD I/O ---
 
-fn print_fn(args: Vec<Object>) -> Object {
-    for arg in args {
-        print!("{} ", arg);
+// Receives the values a script prints, unformatted, in place of the normal output.
+// The print hook gets print()'s arguments; the error hook gets write_err()'s,
+// and for log_*() the level name, the message and the data hash (if any).
+pub type OutputHook = Box<dyn FnMut(&[Object])>;
+
+// Where a Runtime's output goes. It belongs to the Runtime and is installed
+// here only while one of its runs is going (see install_output), so two
+// runtimes on a thread, or one run from inside another's hook, keep theirs apart.
+#[derive(Default)]
+pub struct Output {
+    pub print_hook: Option<OutputHook>,
+    pub error_hook: Option<OutputHook>,
+    pub io: Option<Io>,
+}
+
+// Streams for a Runtime's runs to use in place of stdin, stdout and stderr
+// (see Runtime::with_io). Log lines go to `errors` like write_err()'s output.
+pub struct Io {
+    pub input: Box<dyn BufRead>,
+    pub output: Box<dyn Write>,
+    pub errors: Box<dyn Write>,
+}
+
+// A Write that keeps what is written to it. Clones share the bytes, so a host
+// hands one clone to Io and reads the other once the run is over.
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    pub fn new() -> Capture {
+        Capture::default()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    // The bytes as text, with anything that isn't UTF-8 replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
     }
-    println!("");
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Output> = RefCell::new(Output::default());
+}
+
+// Make `output` the thread's for the run about to start, returning what was
+// installed before (which the caller puts back when the run ends)
+pub(crate) fn install_output(output: Output) -> Output {
+    OUTPUT.with(|slot| std::mem::replace(&mut *slot.borrow_mut(), output))
+}
+
+// False when no hook is installed. The hook is taken out while it runs,
+// so it may itself run Flux code that prints.
+fn call_hook(hook_of: fn(&mut Output) -> &mut Option<OutputHook>, args: &[Object]) -> bool {
+    let mut hook = match OUTPUT.with(|slot| hook_of(&mut slot.borrow_mut()).take()) {
+        Some(hook) => hook,
+        None => return false,
+    };
+    hook(args);
+    OUTPUT.with(|slot| {
+        let mut output = slot.borrow_mut();
+        let slot = hook_of(&mut output);
+        if slot.is_none() { *slot = Some(hook); }
+    });
+    true
+}
+
+// What print(), write() and help() write to: the runtime's Io if it has one, else stdout
+fn with_stdout<T>(write: impl FnOnce(&mut dyn Write) -> T) -> T {
+    OUTPUT.with(|slot| match slot.borrow_mut().io.as_mut() {
+        Some(io) => write(&mut *io.output),
+        None => write(&mut io::stdout().lock()),
+    })
+}
+
+// The same for write_err(), the log lines and the runtime's own warnings
+pub(crate) fn with_stderr<T>(write: impl FnOnce(&mut dyn Write) -> T) -> T {
+    OUTPUT.with(|slot| match slot.borrow_mut().io.as_mut() {
+        Some(io) => write(&mut *io.errors),
+        None => write(&mut io::stderr().lock()),
+    })
+}
+
+fn print_hook(output: &mut Output) -> &mut Option<OutputHook> {
+    &mut output.print_hook
+}
+
+fn error_hook(output: &mut Output) -> &mut Option<OutputHook> {
+    &mut output.error_hook
+}
+
+// Where breakpoint() reads its shell's commands and writes its answers.
+// None (the default) turns breakpoints off.
+pub struct DebugIo {
+    pub input: Box<dyn BufRead>,
+    pub output: Box<dyn Write>,
+}
+
+thread_local! {
+    static DEBUGGER: RefCell<Option<DebugIo>> = const { RefCell::new(None) };
+}
+
+pub fn set_debugger(io: Option<DebugIo>) {
+    DEBUGGER.with(|slot| *slot.borrow_mut() = io);
+}
+
+// Taken out while its shell runs, so a breakpoint() typed into the shell does nothing
+fn breakpoint_fn(_args: Vec<Object>) -> Object {
+    let mut io = match DEBUGGER.with(|slot| slot.borrow_mut().take()) {
+        Some(io) => io,
+        None => return Object::Null,
+    };
+    // The VM has no scopes to offer
+    if let Some(mut scope) = crate::evaluator::caller_scope() {
+        crate::repl::breakpoint_shell(&mut scope, &mut *io.input, &mut *io.output);
+    }
+    DEBUGGER.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        if slot.is_none() { *slot = Some(io); }
+    });
+    Object::Null
+}
+
+fn print_fn(args: Vec<Object>) -> Object {
+    if call_hook(print_hook, &args) { return Object::Null; }
+    with_stdout(|out| {
+        for arg in args {
+            let _ = write!(out, "{} ", arg);
+        }
+        let _ = writeln!(out);
+    });
+    Object::Null
+}
+
+// write/write_err flush immediately so partial lines (progress bars) show up right away
+fn write_fn(args: Vec<Object>) -> Object {
+    with_stdout(|out| {
+        for arg in args {
+            let _ = write!(out, "{}", arg);
+        }
+        let _ = out.flush();
+    });
+    Object::Null
+}
+
+fn write_err_fn(args: Vec<Object>) -> Object {
+    if call_hook(error_hook, &args) { return Object::Null; }
+    with_stderr(|err| {
+        for arg in args {
+            let _ = write!(err, "{}", arg);
+        }
+        let _ = err.flush();
+    });
     Object::Null
 }
 
 fn input_fn(args: Vec<Object>) -> Object {
-    if args.len() > 0 {
-        print!("{}", args[0]);
-        io::stdout().flush().unwrap();
+    if !config::current().policy.read_stdin {
+        return refused("input", "reading stdin");
+    }
+    if let Some(prompt) = args.first() {
+        with_stdout(|out| {
+            let _ = write!(out, "{}", prompt);
+            let _ = out.flush();
+        });
     }
     let mut buffer = String::new();
-    io::stdin().read_line(&mut buffer).unwrap();
-    Object::String(buffer.trim().to_string())
+    let read = OUTPUT.with(|slot| match slot.borrow_mut().io.as_mut() {
+        Some(io) => io.input.read_line(&mut buffer),
+        None => io::stdin().read_line(&mut buffer),
+    });
+    match read {
+        Ok(_) => Object::String(buffer.trim().to_string()),
+        Err(e) => Object::Error(format!("input: can't read stdin: {}", e)),
+    }
+}
+
+// The error for a builtin called to do something its runtime's Policy rules out
+fn refused(name: &str, what: &str) -> Object {
+    Object::Error(format!("{}: {} is not allowed by this runtime's policy", name, what))
 }
 
 // --- DATA TOOLS ---
 
 fn len_fn(args: Vec<Object>) -> Object {
-    if args.len() != 1 {
-        return Object::Error("len() takes exactly 1 argument".to_string());
-    }
     match &args[0] {
-        Object::String(s) => Object::Integer(s.len() as i64),
+        // Characters (code points), not UTF-8 bytes: len("héllo") is 5
+        Object::String(s) => Object::Integer(s.chars().count() as i64),
         Object::Array(arr) => Object::Integer(arr.len() as i64),
-        _ => Object::Error("argument to len() not supported".to_string()),
+        _ => unchecked("len"),
     }
 }
 
 fn int_fn(args: Vec<Object>) -> Object {
-    if args.len() != 1 { return Object::Error("int() takes 1 arg".to_string()); }
     match &args[0] {
         Object::String(s) => match s.parse::<i64>() {
             Ok(val) => Object::Integer(val),
             Err(_) => Object::Error(format!("Could not convert '{}' to int", s)),
         },
         Object::Integer(i) => Object::Integer(*i),
-        _ => Object::Error("Cannot convert to int".to_string()),
+        // Truncates toward zero
+        Object::Float(f) => Object::Integer(*f as i64),
+        _ => unchecked("int"),
+    }
+}
+
+fn ord_fn(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Char(c) => Object::Integer(*c as i64),
+        Object::String(s) if s.chars().count() == 1 => Object::Integer(s.chars().next().unwrap_or_default() as i64),
+        other => Object::Error(format!("ord() needs a char, got {}", other.type_name())),
+    }
+}
+
+fn chr_fn(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Integer(n) => match u32::try_from(*n).ok().and_then(char::from_u32) {
+            Some(c) => Object::Char(c),
+            None => Object::Error(format!("chr: {} is not a valid code point", n)),
+        },
+        _ => unchecked("chr"),
+    }
+}
+
+// scan("GET /a 200", "{word} {word} {int}") -> ["GET", "/a", 200].
+// The whole string must fit the pattern. Fields:
+//   {int}   an optionally signed integer
+//   {word}  a run of non-space characters
+//   {str}   any text up to whatever the pattern has next (the rest, if nothing)
+// Other text must appear as written, except that a run of spaces matches any
+// run of whitespace; `{{` and `}}` are literal braces.
+fn scan_fn(args: Vec<Object>) -> Object {
+    let (text, pattern) = match args.as_slice() {
+        [Object::String(text), Object::String(pattern)] => (text, pattern),
+        _ => return unchecked("scan"),
+    };
+    let pieces = match compile_scan_pattern(pattern) {
+        Ok(pieces) => pieces,
+        Err(e) => return Object::Error(format!("scan(): {}", e)),
+    };
+    let input: Vec<char> = text.chars().collect();
+    let mut fields = vec![];
+    if scan_match(&pieces, &input, &mut fields) { Object::Array(fields) } else { Object::Null }
+}
+
+#[derive(Debug, PartialEq)]
+enum ScanPiece {
+    Literal(Vec<char>),
+    Space,
+    Int,
+    Word,
+    Str,
+}
+
+fn compile_scan_pattern(pattern: &str) -> Result<Vec<ScanPiece>, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut pieces = vec![];
+    let mut literal = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '{' if chars.get(i + 1) == Some(&'{') => { literal.push('{'); i += 2; continue; },
+            '}' if chars.get(i + 1) == Some(&'}') => { literal.push('}'); i += 2; continue; },
+            '}' => return Err(format!("unmatched '}}' at column {} of the pattern (write '}}}}' for a brace)", i + 1)),
+            '{' => {
+                let close = match chars[i..].iter().position(|&c| c == '}') {
+                    Some(offset) => i + offset,
+                    None => return Err(format!("'{{' at column {} of the pattern is never closed", i + 1)),
+                };
+                let name: String = chars[i + 1..close].iter().collect();
+                let piece = match name.as_str() {
+                    "int" => ScanPiece::Int,
+                    "word" => ScanPiece::Word,
+                    "str" => ScanPiece::Str,
+                    _ => return Err(format!("unknown field '{{{}}}' at column {} of the pattern (use {{int}}, {{word}} or {{str}})", name, i + 1)),
+                };
+                if !literal.is_empty() { pieces.push(ScanPiece::Literal(std::mem::take(&mut literal))); }
+                pieces.push(piece);
+                i = close + 1;
+            },
+            c if c.is_whitespace() => {
+                if !literal.is_empty() { pieces.push(ScanPiece::Literal(std::mem::take(&mut literal))); }
+                if pieces.last() != Some(&ScanPiece::Space) { pieces.push(ScanPiece::Space); }
+                i += 1;
+            },
+            c => { literal.push(c); i += 1; },
+        }
+    }
+    if !literal.is_empty() { pieces.push(ScanPiece::Literal(literal)); }
+    Ok(pieces)
+}
+
+// Matches `pieces` against all of `input`, appending the fields to `out`.
+// {word} takes as much as it can and {str} as little, backing off until the
+// rest of the pattern fits.
+fn scan_match(pieces: &[ScanPiece], input: &[char], out: &mut Vec<Object>) -> bool {
+    let (piece, rest) = match pieces.split_first() {
+        Some(split) => split,
+        None => return input.is_empty(),
+    };
+    let run = |pred: fn(char) -> bool| input.iter().position(|&c| !pred(c)).unwrap_or(input.len());
+    match piece {
+        ScanPiece::Literal(text) => input.starts_with(text) && scan_match(rest, &input[text.len()..], out),
+        ScanPiece::Space => {
+            let n = run(char::is_whitespace);
+            n > 0 && scan_match(rest, &input[n..], out)
+        },
+        ScanPiece::Int => {
+            let sign = usize::from(matches!(input.first(), Some('-') | Some('+')));
+            let digits = input[sign..].iter().position(|c| !c.is_ascii_digit()).unwrap_or(input.len() - sign);
+            let end = sign + digits;
+            let value = match input[..end].iter().collect::<String>().parse::<i64>() {
+                Ok(value) if digits > 0 => value,
+                _ => return false,
+            };
+            out.push(Object::Integer(value));
+            scan_match(rest, &input[end..], out) || { out.pop(); false }
+        },
+        ScanPiece::Word => {
+            for end in (1..=run(|c| !c.is_whitespace())).rev() {
+                out.push(Object::String(input[..end].iter().collect()));
+                if scan_match(rest, &input[end..], out) { return true; }
+                out.pop();
+            }
+            false
+        },
+        ScanPiece::Str => {
+            for end in 1..=input.len() {
+                out.push(Object::String(input[..end].iter().collect()));
+                if scan_match(rest, &input[end..], out) { return true; }
+                out.pop();
+            }
+            false
+        },
     }
 }
 
 // --- FILE SYSTEM ---
 
 fn read_file_fn(args: Vec<Object>) -> Object {
-    if args.len() != 1 { return Object::Error("read_file takes 1 arg (path)".to_string()); }
+    if !config::current().policy.read_files {
+        return refused("read_file", "reading files");
+    }
     if let Object::String(path) = &args[0] {
         match fs::read_to_string(path) {
             Ok(content) => Object::String(content),
             Err(_) => Object::String("".to_string()), // Return empty string if missing (Safe Mode)
         }
     } else {
-        Object::Error("Argument must be a string path".to_string())
+        unchecked("read_file")
     }
 }
 
 fn write_file_fn(args: Vec<Object>) -> Object {
-    if args.len() != 2 { return Object::Error("write_file takes 2 args (path, content)".to_string()); }
-    
-    let path = match &args[0] {
-        Object::String(s) => s,
-        _ => return Object::Error("First arg must be path string".to_string()),
-    };
-    
+    if !config::current().policy.write_files {
+        return refused("write_file", "writing files");
+    }
     let content = match &args[1] {
         Object::String(s) => s.clone(),
         Object::Integer(i) => i.to_string(),
-        _ => return Object::Error("Second arg must be content string".to_string()),
+        _ => return unchecked("write_file"),
     };
+    let path = args[0].to_string();
 
     match fs::write(path, content) {
         Ok(_) => Object::Boolean(true),
@@ -115,69 +596,720 @@ fn write_file_fn(args: Vec<Object>) -> Object {
     }
 }
 
+// Paths: '/' and '\' both separate, whatever the host (see paths.rs)
+
+fn path_arg(args: &[Object]) -> &str {
+    match &args[0] {
+        Object::String(s) => s,
+        _ => "",
+    }
+}
+
+fn path_join_fn(args: Vec<Object>) -> Object {
+    let parts: Vec<&str> = args.iter().map(|arg| match arg {
+        Object::String(s) => s.as_str(),
+        _ => "",
+    }).collect();
+    Object::String(paths::join(&parts))
+}
+
+fn path_dirname_fn(args: Vec<Object>) -> Object {
+    Object::String(paths::dirname(path_arg(&args)))
+}
+
+fn path_basename_fn(args: Vec<Object>) -> Object {
+    Object::String(paths::basename(path_arg(&args)))
+}
+
+fn path_ext_fn(args: Vec<Object>) -> Object {
+    Object::String(paths::extension(path_arg(&args)))
+}
+
+fn path_absolute_fn(args: Vec<Object>) -> Object {
+    match paths::absolute(path_arg(&args)) {
+        Ok(abs) => Object::String(abs),
+        Err(e) => Object::Error(format!("path_absolute: {}", e)),
+    }
+}
+
+fn cwd_fn(_args: Vec<Object>) -> Object {
+    match env::current_dir() {
+        Ok(dir) => Object::String(dir.to_string_lossy().into_owned()),
+        Err(e) => Object::Error(format!("cwd: {}", e)),
+    }
+}
+
+fn set_cwd_fn(args: Vec<Object>) -> Object {
+    if !config::current().policy.write_files {
+        return refused("set_cwd", "changing the working directory");
+    }
+    let path = path_arg(&args);
+    match env::set_current_dir(path) {
+        Ok(()) => Object::Null,
+        Err(e) => Object::Error(format!("set_cwd: can't change to '{}': {}", path, e)),
+    }
+}
+
 // --- ARRAY TOOLS ---
 
 fn push_fn(args: Vec<Object>) -> Object {
-    if args.len() != 2 { return Object::Error("push takes 2 args (array, element)".to_string()); }
     match (&args[0], &args[1]) {
         (Object::Array(arr), val) => {
             let mut new_arr = arr.clone();
             new_arr.push(val.clone());
             Object::Array(new_arr)
         },
-        _ => Object::Error("First argument to push must be ARRAY".to_string()),
+        _ => unchecked("push"),
     }
 }
 
 fn first_fn(args: Vec<Object>) -> Object {
-    if args.len() != 1 { return Object::Error("first takes 1 arg".to_string()); }
     match &args[0] {
         Object::Array(arr) => {
-            if arr.len() > 0 { arr[0].clone() } else { Object::Null }
+            if !arr.is_empty() { arr[0].clone() } else { Object::Null }
         },
-        _ => Object::Error("Argument must be array".to_string()),
+        _ => unchecked("first"),
     }
 }
 
 fn last_fn(args: Vec<Object>) -> Object {
-    if args.len() != 1 { return Object::Error("last takes 1 arg".to_string()); }
     match &args[0] {
         Object::Array(arr) => {
-            if arr.len() > 0 { arr[arr.len() - 1].clone() } else { Object::Null }
+            if !arr.is_empty() { arr[arr.len() - 1].clone() } else { Object::Null }
         },
-        _ => Object::Error("Argument must be array".to_string()),
+        _ => unchecked("last"),
     }
 }
 
 fn rest_fn(args: Vec<Object>) -> Object {
-    if args.len() != 1 { return Object::Error("rest takes 1 arg".to_string()); }
     match &args[0] {
         Object::Array(arr) => {
-            if arr.len() > 0 { 
+            if !arr.is_empty() { 
                 // Return everything except the first element
                 Object::Array(arr[1..].to_vec()) 
             } else { 
                 Object::Null 
             }
         },
-        _ => Object::Error("Argument must be array".to_string()),
+        _ => unchecked("rest"),
+    }
+}
+
+// Builtins that look inside arrays name the first element that broke them,
+// e.g. "sum: expected INTEGER, got STRING at index 347"
+fn element_error(builtin: &str, expected: &str, got: &Object, index: usize) -> Object {
+    Object::Error(format!("{}: expected {}, got {} at index {}", builtin, expected, got.type_name(), index))
+}
+
+fn expect_elements(arr: &[Object], type_name: &str, builtin: &str) -> Result<(), Object> {
+    match arr.iter().position(|e| e.type_name() != type_name) {
+        Some(i) => Err(element_error(builtin, type_name, &arr[i], i)),
+        None => Ok(()),
+    }
+}
+
+// Ordering builtins accept all-integer or all-string arrays; the first element decides which
+fn expect_comparable(arr: &[Object], builtin: &str) -> Result<(), Object> {
+    match arr.first() {
+        None => Ok(()),
+        Some(first @ (Object::Integer(_) | Object::String(_))) => expect_elements(arr, first.type_name(), builtin),
+        Some(other) => Err(element_error(builtin, "INTEGER or STRING", other, 0)),
+    }
+}
+
+fn compare_elements(a: &Object, b: &Object) -> std::cmp::Ordering {
+    match (a, b) {
+        (Object::Integer(x), Object::Integer(y)) => x.cmp(y),
+        (Object::String(x), Object::String(y)) => x.cmp(y),
+        _ => std::cmp::Ordering::Equal,
+    }
+}
+
+fn sum_fn(args: Vec<Object>) -> Object {
+    let arr = match args.as_slice() {
+        [Object::Array(arr)] => arr,
+        _ => return unchecked("sum"),
+    };
+    if let Err(e) = expect_elements(arr, "INTEGER", "sum") { return e; }
+    let mut total: i64 = 0;
+    for (index, item) in arr.iter().enumerate() {
+        if let Object::Integer(i) = item {
+            total = match total.checked_add(*i) {
+                Some(total) => total,
+                None => return Object::Error(format!("sum: integer overflow at index {}", index)),
+            };
+        }
+    }
+    Object::Integer(total)
+}
+
+fn min_fn(args: Vec<Object>) -> Object { extreme(args, "min", std::cmp::Ordering::Less) }
+fn max_fn(args: Vec<Object>) -> Object { extreme(args, "max", std::cmp::Ordering::Greater) }
+
+fn extreme(args: Vec<Object>, builtin: &str, wanted: std::cmp::Ordering) -> Object {
+    let arr = match args.as_slice() {
+        [Object::Array(arr)] => arr,
+        _ => return unchecked(builtin),
+    };
+    if let Err(e) = expect_comparable(arr, builtin) { return e; }
+    let mut best: Option<&Object> = None;
+    for item in arr {
+        if best.is_none_or(|b| compare_elements(item, b) == wanted) { best = Some(item); }
+    }
+    best.cloned().unwrap_or(Object::Null)
+}
+
+fn sort_fn(args: Vec<Object>) -> Object {
+    let arr = match args.as_slice() {
+        [Object::Array(arr)] => arr,
+        _ => return unchecked("sort"),
+    };
+    if let Err(e) = expect_comparable(arr, "sort") { return e; }
+    let mut items = arr.clone();
+    items.sort_by(compare_elements);
+    Object::Array(items)
+}
+
+// Stable: elements with equal keys keep their order
+fn sort_by_fn(args: Vec<Object>) -> Object {
+    let (arr, function) = match args.as_slice() {
+        [Object::Array(arr), function] => (arr, function),
+        _ => return unchecked("sort_by"),
+    };
+    let mut keys = Vec::with_capacity(arr.len());
+    for item in arr {
+        let key = apply_function(function.clone(), vec![item.clone()]);
+        if let Object::Error(_) = key { return key; }
+        keys.push(key);
+    }
+    if let Err(e) = expect_comparable(&keys, "sort_by (key)") { return e; }
+    let mut order: Vec<usize> = (0..arr.len()).collect();
+    order.sort_by(|&a, &b| compare_elements(&keys[a], &keys[b]));
+    Object::Array(order.into_iter().map(|i| arr[i].clone()).collect())
+}
+
+fn unique_fn(args: Vec<Object>) -> Object {
+    let arr = match args.as_slice() {
+        [Object::Array(arr)] => arr,
+        _ => return unchecked("unique"),
+    };
+    // Values with an equality key are checked in a set; the rest against each other
+    let mut seen = std::collections::HashSet::new();
+    let mut unkeyed: Vec<&Object> = vec![];
+    let mut items = vec![];
+    for item in arr {
+        match equality_key(item) {
+            Some(key) => if !seen.insert(key) { continue; },
+            None if unkeyed.iter().any(|other| objects_equal(other, item)) => continue,
+            None => unkeyed.push(item),
+        }
+        items.push(item.clone());
+    }
+    Object::Array(items)
+}
+
+fn count_fn(args: Vec<Object>) -> Object {
+    match args.as_slice() {
+        [Object::Array(arr), value] => Object::Integer(arr.iter().filter(|item| objects_equal(item, value)).count() as i64),
+        _ => unchecked("count"),
+    }
+}
+
+fn deep_equal_fn(args: Vec<Object>) -> Object {
+    match args.as_slice() {
+        [a, b] => Object::Boolean(objects_equal(a, b)),
+        _ => unchecked("deep_equal"),
+    }
+}
+
+fn join_fn(args: Vec<Object>) -> Object {
+    let (arr, sep) = match args.as_slice() {
+        [Object::Array(arr)] => (arr, ""),
+        [Object::Array(arr), Object::String(sep)] => (arr, sep.as_str()),
+        _ => return unchecked("join"),
+    };
+    if let Err(e) = expect_elements(arr, "STRING", "join") { return e; }
+    let parts: Vec<String> = arr.iter().map(|item| item.to_string()).collect();
+    Object::String(parts.join(sep))
+}
+
+// --- HASH TOOLS ---
+
+// get(hash, key) or get(hash, key, fallback). The fallback wins over the hash's own default.
+fn get_fn(args: Vec<Object>) -> Object {
+    let hash = match args[0].as_hash() {
+        Some(hash) => hash,
+        None => return unchecked("get"),
+    };
+    let key = match get_hash_key(&args[1]) {
+        Some(k) => k,
+        None => return Object::Error(format!("get: unusable as hash key: {}", args[1])),
+    };
+    match (hash.pairs.get(&key), args.get(2)) {
+        (Some(val), _) => val.clone(),
+        (None, Some(fallback)) => fallback.clone(),
+        (None, None) => hash.lookup(&key),
+    }
+}
+
+// hash_with_default(value): an empty hash that answers missing keys with `value`
+fn hash_with_default_fn(args: Vec<Object>) -> Object {
+    Object::Hash(HashObject { pairs: HashMap::new(), default: Some(Box::new(args[0].clone())) })
+}
+
+// counter(): shorthand for hash_with_default(0)
+fn counter_fn(_args: Vec<Object>) -> Object {
+    hash_with_default_fn(vec![Object::Integer(0)])
+}
+
+// merge(hash, other): other's entries win; a counter merged with anything is still a counter
+fn merge_fn(args: Vec<Object>) -> Object {
+    match (&args[0], &args[1]) {
+        (Object::Hash(hash), Object::Hash(other)) => {
+            let mut merged = hash.clone();
+            merged.pairs.extend(other.pairs.iter().map(|(k, v)| (k.clone(), v.clone())));
+            if merged.default.is_none() { merged.default = other.default.clone(); }
+            Object::Hash(merged)
+        },
+        _ => unchecked("merge"),
+    }
+}
+
+// Pairs come out in key order, so the result doesn't depend on how the hash was built
+fn to_pairs_fn(args: Vec<Object>) -> Object {
+    let hash = match args[0].as_hash() {
+        Some(hash) => hash,
+        None => return unchecked("to_pairs"),
+    };
+    Object::Array(hash.sorted_pairs().into_iter()
+        .map(|(key, value)| Object::Array(vec![hash_key_to_object(key), value.clone()]))
+        .collect())
+}
+
+fn keys_fn(args: Vec<Object>) -> Object {
+    match args[0].as_hash() {
+        Some(hash) => Object::Array(hash.sorted_pairs().into_iter().map(|(key, _)| hash_key_to_object(key)).collect()),
+        None => unchecked("keys"),
     }
 }
 
+fn from_pairs_fn(args: Vec<Object>) -> Object {
+    let arr = match args.as_slice() {
+        [Object::Array(arr)] => arr,
+        _ => return unchecked("from_pairs"),
+    };
+    let mut pairs = HashMap::new();
+    for (i, item) in arr.iter().enumerate() {
+        let (key, value) = match item {
+            Object::Array(pair) if pair.len() == 2 => (&pair[0], &pair[1]),
+            Object::Array(pair) => return Object::Error(format!(
+                "from_pairs: expected a [key, value] pair, got an ARRAY of length {} at index {}", pair.len(), i)),
+            _ => return element_error("from_pairs", "a [key, value] ARRAY", item, i),
+        };
+        match get_hash_key(key) {
+            Some(k) => { pairs.insert(k, value.clone()); },
+            None => return element_error("from_pairs", "an INTEGER, BOOLEAN or STRING key", key, i),
+        }
+    }
+    Object::Hash(HashObject::new(pairs))
+}
+
+// deep_get(obj, path): follow a path of hash keys / array indices, null on any missing link
+fn deep_get_fn(args: Vec<Object>) -> Object {
+    let path = match &args[1] {
+        Object::Array(path) => path,
+        _ => return unchecked("deep_get"),
+    };
+    let mut current = args[0].clone();
+    for (i, step) in path.iter().enumerate() {
+        current = match (&current, step) {
+            (Object::Null, _) => return Object::Null,
+            (Object::Hash(hash) | Object::Module { exports: hash, .. } | Object::Record { fields: hash, .. }, key) => match get_hash_key(key) {
+                Some(k) => hash.lookup(&k),
+                None => return Object::Error(format!("deep_get: unusable hash key {} at path {}", key, path_to_string(&path[..=i]))),
+            },
+            (Object::Array(arr), Object::Integer(idx)) => {
+                if *idx < 0 { Object::Null } else { arr.get(*idx as usize).cloned().unwrap_or(Object::Null) }
+            },
+            (other, _) => return Object::Error(format!("deep_get: cannot index {} at path {}", other.type_name(), path_to_string(&path[..i]))),
+        };
+    }
+    current
+}
+
+// deep_set(obj, path, value): returns a copy of obj with value stored at path,
+// creating hashes for any missing intermediate links
+fn deep_set_fn(args: Vec<Object>) -> Object {
+    let path = match &args[1] {
+        Object::Array(path) => path,
+        _ => return unchecked("deep_set"),
+    };
+    match set_path(args[0].clone(), path, 0, args[2].clone()) {
+        Ok(obj) => obj,
+        Err(msg) => Object::Error(msg),
+    }
+}
+
+fn set_path(current: Object, path: &[Object], depth: usize, value: Object) -> Result<Object, String> {
+    if depth == path.len() { return Ok(value); }
+    let step = &path[depth];
+    match (current, step) {
+        (Object::Null, _) => set_path(Object::Hash(HashObject::default()), path, depth, value),
+        (Object::Hash(mut hash), key) => {
+            let k = get_hash_key(key)
+                .ok_or_else(|| format!("deep_set: unusable hash key {} at path {}", key, path_to_string(&path[..=depth])))?;
+            let child = hash.pairs.get(&k).cloned().unwrap_or(Object::Null);
+            let updated = set_path(child, path, depth + 1, value)?;
+            hash.pairs.insert(k, updated);
+            Ok(Object::Hash(hash))
+        },
+        (Object::Array(mut arr), Object::Integer(idx)) => {
+            // Writing one past the end appends
+            if *idx < 0 || *idx as usize > arr.len() {
+                return Err(format!("deep_set: index {} out of range at path {}", idx, path_to_string(&path[..=depth])));
+            }
+            let idx = *idx as usize;
+            let child = arr.get(idx).cloned().unwrap_or(Object::Null);
+            let updated = set_path(child, path, depth + 1, value)?;
+            if idx == arr.len() { arr.push(updated); } else { arr[idx] = updated; }
+            Ok(Object::Array(arr))
+        },
+        (other, _) => Err(format!("deep_set: cannot index {} at path {}", other.type_name(), path_to_string(&path[..depth]))),
+    }
+}
+
+// Paths are shown with string keys quoted, e.g. ["server", "ports", 0]
+fn path_to_string(path: &[Object]) -> String {
+    let parts: Vec<String> = path.iter().map(|p| match p {
+        Object::String(s) => format!("\"{}\"", s),
+        other => other.to_string(),
+    }).collect();
+    format!("[{}]", parts.join(", "))
+}
+
+// --- FUNCTION TOOLS ---
+
+// memoize(fn) or memoize(fn, max_entries)
+fn memoize_fn(args: Vec<Object>) -> Object {
+    let max_entries = match args.get(1) {
+        None => None,
+        Some(Object::Integer(n)) if *n > 0 => Some(*n as usize),
+        Some(other) => return Object::Error(format!("memoize: max_entries must be a positive integer, got {}", other)),
+    };
+    Object::Memoized {
+        function: Box::new(args[0].clone()),
+        cache: Rc::new(RefCell::new(MemoCache::new(max_entries))),
+    }
+}
+
+// --- DOCUMENTATION ---
+
+fn help_fn(args: Vec<Object>) -> Object {
+    with_stdout(|out| writeln!(out, "{}", describe(&args[0]))).ok();
+    Object::Null
+}
+
+fn doc_fn(args: Vec<Object>) -> Object {
+    match docstring(&args[0]) {
+        Some(text) => Object::String(text),
+        None => Object::Null,
+    }
+}
+
+// The docstring of a function (its body's leading string), a builtin or a module
+pub fn docstring(obj: &Object) -> Option<String> {
+    match obj {
+        Object::Function { body, .. } => body.docstring().map(|s| s.to_string()),
+        Object::Memoized { function, .. } => docstring(function),
+        Object::Builtin(def) => Some(format!("{}: {}", def.signature(), def.doc)),
+        Object::Module { exports, .. } => match exports.pairs.get(&HashKey::String(MODULE_DOC_KEY.to_string())) {
+            Some(Object::String(s)) => Some(s.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// What help() prints: a signature line, then the documentation
+pub fn describe(obj: &Object) -> String {
+    let signature = match obj {
+        Object::Function { parameters, variadic, .. } => format!("fn({})", crate::ast::parameter_list(parameters, *variadic)),
+        Object::Memoized { function, .. } => return describe(function),
+        Object::Builtin(def) => format!("builtin {}", def.name),
+        Object::Module { exports, .. } => {
+            // Builtins a module re-exports (`mut say = print`) aren't its own
+            let mut names: Vec<String> = exports.pairs.iter()
+                .filter_map(|(k, v)| match (k, v) {
+                    (_, Object::Builtin(_)) => None,
+                    (HashKey::String(s), _) if s != MODULE_DOC_KEY => Some(s.clone()),
+                    _ => None,
+                })
+                .collect();
+            names.sort();
+            format!("module exporting: {}", names.join(", "))
+        },
+        other => return format!("{} (no documentation)", other.type_name()),
+    };
+    match docstring(obj) {
+        Some(text) if !matches!(obj, Object::Builtin(_)) => format!("{}\n    {}", signature, text),
+        Some(text) => text,
+        None => format!("{}\n    (no documentation)", signature),
+    }
+}
+
+// --- RANDOMNESS ---
+
+// SplitMix64: tiny, fast, and identical on every platform, so seeded runs are reproducible
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..n (n > 0)
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+thread_local! {
+    static RNG: RefCell<Rng> = RefCell::new(Rng { state: time_seed() });
+}
+
+fn time_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+fn random_below(n: usize) -> usize {
+    RNG.with(|rng| rng.borrow_mut().below(n as u64) as usize)
+}
+
+fn seed_random_fn(args: Vec<Object>) -> Object {
+    match args.as_slice() {
+        [Object::Integer(seed)] => {
+            RNG.with(|rng| rng.borrow_mut().state = *seed as u64);
+            Object::Null
+        },
+        _ => unchecked("seed_random"),
+    }
+}
+
+fn random_fn(args: Vec<Object>) -> Object {
+    match args.as_slice() {
+        [Object::Integer(n)] if *n > 0 => Object::Integer(random_below(*n as usize) as i64),
+        [n] => Object::Error(format!("random: n must be a positive integer, got {}", n)),
+        _ => unchecked("random"),
+    }
+}
+
+fn shuffle_fn(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Array(arr) => {
+            let mut items = arr.clone();
+            // Fisher-Yates
+            for i in (1..items.len()).rev() {
+                items.swap(i, random_below(i + 1));
+            }
+            Object::Array(items)
+        },
+        _ => unchecked("shuffle"),
+    }
+}
+
+fn sample_fn(args: Vec<Object>) -> Object {
+    match (&args[0], &args[1]) {
+        (Object::Array(arr), Object::Integer(k)) => {
+            if *k < 0 || *k as usize > arr.len() {
+                return Object::Error(format!("sample count {} must be between 0 and the array length {}", k, arr.len()));
+            }
+            // A partial Fisher-Yates: only the first k positions need to be settled
+            let mut items = arr.clone();
+            for i in 0..*k as usize {
+                let j = i + random_below(items.len() - i);
+                items.swap(i, j);
+            }
+            items.truncate(*k as usize);
+            Object::Array(items)
+        },
+        _ => unchecked("sample"),
+    }
+}
+
+// --- ITERATORS ---
+
+// range(end), range(start, end) or range(start, end, step). Nothing is allocated up front.
+fn range_fn(args: Vec<Object>) -> Object {
+    let (start, end, step) = match args.as_slice() {
+        [Object::Integer(end)] => (0, *end, 1),
+        [Object::Integer(start), Object::Integer(end)] => (*start, *end, 1),
+        [Object::Integer(start), Object::Integer(end), Object::Integer(step)] => (*start, *end, *step),
+        _ => return unchecked("range"),
+    };
+    if step == 0 { return Object::Error("range: step cannot be 0".to_string()); }
+    new_iterator(IterState::Range { next: start, end, step })
+}
+
+fn iter_fn(args: Vec<Object>) -> Object {
+    to_iterator(&args[0]).unwrap_or_else(|| unchecked("iter"))
+}
+
+// next(it): the next value, or null once the iterator is exhausted
+fn next_fn(args: Vec<Object>) -> Object {
+    match &args[0] {
+        Object::Iterator(state) => iterator_next(state).unwrap_or(Object::Null),
+        _ => unchecked("next"),
+    }
+}
+
+fn map_fn(args: Vec<Object>) -> Object {
+    match to_iterator(&args[0]) {
+        Some(source) => new_iterator(IterState::Map { source: Box::new(source), function: Box::new(args[1].clone()) }),
+        None => unchecked("map"),
+    }
+}
+
+fn filter_fn(args: Vec<Object>) -> Object {
+    match to_iterator(&args[0]) {
+        Some(source) => new_iterator(IterState::Filter { source: Box::new(source), function: Box::new(args[1].clone()) }),
+        None => unchecked("filter"),
+    }
+}
+
+fn take_fn(args: Vec<Object>) -> Object {
+    let remaining = match &args[1] {
+        Object::Integer(n) if *n >= 0 => *n as usize,
+        other => return Object::Error(format!("take: n must be a non-negative integer, got {}", other)),
+    };
+    match to_iterator(&args[0]) {
+        Some(source) => new_iterator(IterState::Take { source: Box::new(source), remaining }),
+        None => unchecked("take"),
+    }
+}
+
+// collect(it): drain an iterator into an Array
+fn collect_fn(args: Vec<Object>) -> Object {
+    let state = match to_iterator(&args[0]) {
+        Some(Object::Iterator(state)) => state,
+        _ => return unchecked("collect"),
+    };
+    let mut items = vec![];
+    while let Some(val) = iterator_next(&state) {
+        if let Object::Error(_) = val { return val; }
+        items.push(val);
+    }
+    Object::Array(items)
+}
+
 // --- MODULE SYSTEM (IMPORTS) ---
 
+// Reserved key holding a module's docstring
+pub const MODULE_DOC_KEY: &str = "__doc__";
+
+// Supplies module source for import(name) from somewhere other than disk.
+// Called with the requested name, resolved against the importing file as import()
+// resolves it, and the module doing the importing (None at the top level);
+// Ok(None) means "not mine", and import() falls back to reading the file. An Err
+// is the host's own failure and fails the import with its message.
+pub type ModuleResolver = Box<dyn Fn(&str, Option<&str>) -> Result<Option<String>, String>>;
+
+thread_local! {
+    static MODULE_RESOLVER: RefCell<Option<ModuleResolver>> = RefCell::new(None);
+    // Names of the modules currently being imported, innermost last
+    static IMPORTING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    // The script being run, as given and as a canonical path
+    static ENTRY_SCRIPT: RefCell<Option<(String, PathBuf)>> = const { RefCell::new(None) };
+}
+
+pub fn set_module_resolver(resolver: ModuleResolver) {
+    MODULE_RESOLVER.with(|slot| *slot.borrow_mut() = Some(resolver));
+}
+
+// Remember which file is the main script, so a module importing it is caught
+pub fn set_entry_script(path: Option<&str>) {
+    let entry = path.map(|path| (path.to_string(), module_identity(path)));
+    ENTRY_SCRIPT.with(|slot| *slot.borrow_mut() = entry);
+}
+
+// Two import paths name the same module when they lead to the same file
+fn module_identity(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+// An error if importing `filename` now would run a module that is already
+// running: the entry script, or one further up the chain (an import cycle)
+fn reentry_error(filename: &str) -> Option<String> {
+    let id = module_identity(filename);
+    let entry = ENTRY_SCRIPT.with(|slot| slot.borrow().clone());
+    let importing = IMPORTING.with(|stack| stack.borrow().clone());
+    let mut chain: Vec<&str> = entry.iter().map(|(name, _)| name.as_str()).chain(importing.iter().map(|name| name.as_str())).collect();
+    chain.push(filename);
+    let chain = chain.join(" -> ");
+
+    if entry.as_ref().is_some_and(|(_, entry_id)| *entry_id == id) && !config::current().allow_entry_import {
+        return Some(format!("Module '{}' is the script being run; importing it would run it again ({})", filename, chain));
+    }
+    if importing.iter().any(|name| module_identity(name) == id) {
+        return Some(format!("Import cycle: {}", chain));
+    }
+    None
+}
+
+// The file's contents, refusing (before reading) anything over the size limit.
+// Err(None) means there is no such file.
+fn read_module_file(filename: &str) -> Result<String, Option<String>> {
+    if !config::current().policy.read_files {
+        return Err(Some(format!("import: reading module '{}' from disk is not allowed by this runtime's policy", filename)));
+    }
+    let limit = config::current().max_module_size;
+    let size = fs::metadata(filename).map_err(|_| None)?.len();
+    if size > limit {
+        return Err(Some(format!(
+            "Module '{}' is {} bytes, over the {}-byte limit for modules (raise it with --max-module-size)",
+            filename, size, limit
+        )));
+    }
+    source::read(filename).map_err(|e| match e {
+        SourceError::NotFound => None,
+        e => Some(format!("Module '{}' can't be read: {}", filename, e)),
+    })
+}
+
 fn import_fn(args: Vec<Object>) -> Object {
-    if args.len() != 1 { return Object::Error("import takes 1 arg (filename)".to_string()); }
-    
-    let filename = match &args[0] {
+    let name = match &args[0] {
         Object::String(s) => s,
-        _ => return Object::Error("import path must be a string".to_string()),
+        _ => return unchecked("import"),
     };
+    // A relative path is relative to the file doing the importing: the module
+    // being loaded, or else the script being run (with none, the working directory).
+    // Normalized, so "./a.flux" and "lib/../a.flux" are one module to a resolver
+    let importer = IMPORTING.with(|stack| stack.borrow().last().cloned());
+    let base = importer.clone().or_else(|| ENTRY_SCRIPT.with(|slot| slot.borrow().as_ref().map(|(name, _)| name.clone())));
+    let filename = &paths::relative_to(base.as_deref().unwrap_or(""), name);
+
+    if let Some(msg) = reentry_error(filename) { return Object::Error(msg); }
 
-    // 1. Read the module file
-    let contents = match fs::read_to_string(filename) {
-        Ok(c) => c,
-        Err(_) => return Object::Error(format!("Module '{}' not found", filename)),
+    // 1. Find the module source: the host's resolver first, then the filesystem
+    let resolved = MODULE_RESOLVER.with(|resolver| {
+        resolver.borrow().as_ref().map(|resolve| resolve(filename, importer.as_deref()))
+    });
+    let contents = match resolved {
+        Some(Ok(Some(source))) => source,
+        Some(Err(reason)) => return Object::Error(format!("Module '{}' can't be resolved: {}", filename, reason)),
+        Some(Ok(None)) | None => match read_module_file(filename) {
+            Ok(c) => c,
+            Err(Some(msg)) => return Object::Error(msg),
+            Err(None) => return Object::Error(format!("Module '{}' not found", filename)),
+        },
     };
 
     // 2. Parse it
@@ -185,21 +1317,449 @@ fn import_fn(args: Vec<Object>) -> Object {
     let mut p = Parser::new(l);
     let program = p.parse_program();
 
-    if !p.errors.is_empty() {
-        return Object::Error(format!("Parse errors in module {}: {:?}", filename, p.errors));
+    if program.has_errors() {
+        let errors: Vec<String> = program.errors.iter().map(|e| e.to_string()).collect();
+        return Object::Error(format!("Parse errors in module {}: {:?}", filename, errors));
+    }
+
+    // 3. Evaluate it in a FRESH environment, tagged with the module's path
+    // Builtins come from the shared scope around it, so the module can use
+    // print/math/etc (and import other modules!) without copying them in
+    let mut env = Environment::new_module(filename, builtin_scope());
+
+    IMPORTING.with(|stack| stack.borrow_mut().push(filename.clone()));
+    let result = eval_program(&program.statements, &mut env);
+    IMPORTING.with(|stack| stack.borrow_mut().pop());
+    // A module that fails while loading (a refused import included) fails the import
+    if let Object::Error(_) = result { return result; }
+
+    // 4. Export all variables
+    let mut exports = env.to_hash();
+
+    // A leading string literal documents the module
+    if let Some(Statement::Expression(Expression::StringLiteral(text))) = program.statements.first() {
+        exports.pairs.insert(HashKey::String(MODULE_DOC_KEY.to_string()), Object::String(text.clone()));
     }
+    Object::Module { name: module_name(filename), path: filename.clone(), exports }
+}
+
+// "lib/math.flux" -> "math", the same as path_basename without path_ext
+fn module_name(path: &str) -> String {
+    match paths::stem(path) {
+        stem if stem.is_empty() => path.to_string(),
+        stem => stem,
+    }
+}
 
-    // 3. Evaluate it in a FRESH environment
-    let mut env = Environment::new();
-    
-    // Inject standard tools so the module can use print/math/etc
-    // We call new_environment() recursively here. 
-    // This allows modules to import other modules!
-    let tools = new_environment();
-    for (name, tool) in tools { env.set(name, tool); }
+// --- COMMAND LINE ---
 
-    let _result = eval_program(&program, &mut env);
+thread_local! {
+    // What followed the script's name on the command line
+    static SCRIPT_ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn set_script_args(args: Vec<String>) {
+    SCRIPT_ARGS.with(|slot| *slot.borrow_mut() = args);
+}
 
-    // 4. Return the Environment as a HashMap (Export all variables)
-    env.to_hash()
-}
\ No newline at end of file
+fn args_fn(_args: Vec<Object>) -> Object {
+    SCRIPT_ARGS.with(|slot| Object::Array(slot.borrow().iter().cloned().map(Object::String).collect()))
+}
+
+// The key parse_args puts the arguments that aren't flags under
+const REST_KEY: &str = "rest";
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlagType {
+    Bool,
+    Int,
+    String,
+}
+
+// One entry of a parse_args spec
+struct Flag {
+    name: String,
+    flag_type: FlagType,
+    short: Option<String>,
+    default: Object,
+    required: bool,
+    help: String,
+}
+
+// The spec's flags by name. `builtin` names the caller in errors.
+fn flags_of(spec: &HashObject, builtin: &str) -> Result<Vec<Flag>, String> {
+    let mut flags = vec![];
+    for (key, entry) in spec.sorted_pairs() {
+        let name = match key {
+            HashKey::String(name) if name != REST_KEY => name.clone(),
+            HashKey::String(_) => return Err(format!("{}: \"{}\" can't be a flag; it holds the other arguments", builtin, REST_KEY)),
+            other => return Err(format!("{}: flag names are strings, got {}", builtin, hash_key_to_object(other))),
+        };
+        let entry = match entry {
+            Object::Hash(entry) => entry,
+            other => return Err(format!("{}: --{} needs a hash describing it, got {}", builtin, name, other.type_name())),
+        };
+        let field = |field: &str| entry.pairs.get(&HashKey::String(field.to_string()));
+        let flag_type = match field("type") {
+            None => FlagType::String,
+            Some(Object::String(t)) if t == "string" => FlagType::String,
+            Some(Object::String(t)) if t == "int" => FlagType::Int,
+            Some(Object::String(t)) if t == "bool" => FlagType::Bool,
+            Some(other) => return Err(format!("{}: --{} has type {}; use \"bool\", \"int\" or \"string\"", builtin, name, other)),
+        };
+        let short = match field("short") {
+            None => None,
+            Some(Object::String(s)) if s.chars().count() == 1 => Some(s.clone()),
+            Some(other) => return Err(format!("{}: the short form of --{} must be one character, got {}", builtin, name, other)),
+        };
+        let default = match (field("default"), flag_type) {
+            (None, FlagType::Bool) => Object::Boolean(false),
+            (None, _) => Object::Null,
+            (Some(value @ Object::Boolean(_)), FlagType::Bool)
+            | (Some(value @ Object::Integer(_)), FlagType::Int)
+            | (Some(value @ Object::String(_)), FlagType::String) => value.clone(),
+            (Some(other), _) => return Err(format!("{}: the default of --{} doesn't fit its type: {}", builtin, name, other)),
+        };
+        let required = matches!(field("required"), Some(Object::Boolean(true)));
+        let help = field("help").map(|help| help.to_string()).unwrap_or_default();
+        flags.push(Flag { name, flag_type, short, default, required, help });
+    }
+    Ok(flags)
+}
+
+// "usage: tool.flux [options] [args...]" and a line per flag, names lined up
+fn usage_text(flags: &[Flag]) -> String {
+    let script = ENTRY_SCRIPT.with(|slot| slot.borrow().as_ref().map(|(name, _)| paths::basename(name)));
+    let mut lines = vec![format!("usage: {} [options] [args...]", script.as_deref().unwrap_or("script"))];
+    let rows: Vec<(String, String)> = flags.iter().map(|flag| {
+        let short = flag.short.as_ref().map_or("    ".to_string(), |s| format!("-{}, ", s));
+        let value = match flag.flag_type {
+            FlagType::Bool => "",
+            FlagType::Int => " <int>",
+            FlagType::String => " <string>",
+        };
+        let mut notes = vec![];
+        if !flag.help.is_empty() { notes.push(flag.help.clone()); }
+        if flag.required {
+            notes.push("(required)".to_string());
+        } else if flag.flag_type != FlagType::Bool && flag.default != Object::Null {
+            notes.push(format!("(default: {})", flag.default));
+        }
+        (format!("  {}--{}{}", short, flag.name, value), notes.join(" "))
+    }).collect();
+    let width = rows.iter().map(|(left, _)| left.len()).max().unwrap_or(0);
+    for (left, notes) in rows {
+        lines.push(format!("{:width$}  {}", left, notes, width = width).trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+// `--name`, `--name=value`, `--name value`, `-n`, `-n=value` and `-n value`.
+// Arguments that aren't flags (a lone `-` and negative numbers among them)
+// go to "rest", and so does everything after `--`.
+fn parse_flags(flags: &[Flag], argv: &[String]) -> Result<HashMap<HashKey, Object>, String> {
+    let mut values: HashMap<HashKey, Object> = flags.iter().map(|flag| (HashKey::String(flag.name.clone()), flag.default.clone())).collect();
+    let mut given = vec![];
+    let mut rest = vec![];
+    let mut argv = argv.iter();
+    while let Some(arg) = argv.next() {
+        if arg == "--" {
+            rest.extend(argv.by_ref().map(|arg| Object::String(arg.clone())));
+            break;
+        }
+        let (written, inline) = match arg.split_once('=') {
+            Some((written, value)) => (written, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        let flag = if let Some(name) = written.strip_prefix("--") {
+            flags.iter().find(|flag| flag.name == name)
+        } else if let Some(short) = written.strip_prefix('-') && short.starts_with(|c: char| !c.is_ascii_digit()) {
+            flags.iter().find(|flag| flag.short.as_deref() == Some(short))
+        } else {
+            rest.push(Object::String(arg.clone()));
+            continue;
+        };
+        let flag = flag.ok_or_else(|| format!("unknown flag {}", written))?;
+        let value = match (flag.flag_type, inline) {
+            (FlagType::Bool, None | Some("true")) => Object::Boolean(true),
+            (FlagType::Bool, Some("false")) => Object::Boolean(false),
+            (FlagType::Bool, Some(other)) => return Err(format!("{} is true or false, got '{}'", written, other)),
+            (flag_type, inline) => {
+                let text = inline.map(str::to_string).or_else(|| argv.next().cloned()).ok_or_else(|| format!("{} needs a value", written))?;
+                match flag_type {
+                    FlagType::Int => Object::Integer(text.parse().map_err(|_| format!("{} needs an integer, got '{}'", written, text))?),
+                    _ => Object::String(text),
+                }
+            },
+        };
+        given.push(flag.name.as_str());
+        values.insert(HashKey::String(flag.name.clone()), value);
+    }
+    if let Some(missing) = flags.iter().find(|flag| flag.required && !given.contains(&flag.name.as_str())) {
+        return Err(format!("missing required flag --{}", missing.name));
+    }
+    values.insert(HashKey::String(REST_KEY.to_string()), Object::Array(rest));
+    Ok(values)
+}
+
+fn parse_args_fn(args: Vec<Object>) -> Object {
+    let spec = match &args[0] {
+        Object::Hash(spec) => spec,
+        _ => return unchecked("parse_args"),
+    };
+    let flags = match flags_of(spec, "parse_args") {
+        Ok(flags) => flags,
+        Err(msg) => return Object::Error(msg),
+    };
+    let argv: Vec<String> = match args.get(1) {
+        Some(Object::Array(items)) => items.iter().map(|item| item.to_string()).collect(),
+        _ => SCRIPT_ARGS.with(|slot| slot.borrow().clone()),
+    };
+    match parse_flags(&flags, &argv) {
+        Ok(pairs) => Object::Hash(HashObject { pairs, default: None }),
+        Err(msg) => Object::Error(format!("parse_args: {}\n{}", msg, usage_text(&flags))),
+    }
+}
+
+fn usage_fn(args: Vec<Object>) -> Object {
+    let spec = match &args[0] {
+        Object::Hash(spec) => spec,
+        _ => return unchecked("usage"),
+    };
+    match flags_of(spec, "usage") {
+        Ok(flags) => Object::String(usage_text(&flags)),
+        Err(msg) => Object::Error(msg),
+    }
+}
+
+// --- LOGGING ---
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn from_name(name: &str) -> Option<LogLevel> {
+        match name.to_ascii_lowercase().as_str() {
+            "debug" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+struct Logger {
+    min_level: LogLevel,
+    json: bool,
+}
+
+thread_local! {
+    // FLUX_LOG picks the starting level; --log-level and set_log_level() override it
+    static LOGGER: RefCell<Logger> = RefCell::new(Logger {
+        min_level: std::env::var("FLUX_LOG").ok()
+            .and_then(|name| LogLevel::from_name(&name))
+            .unwrap_or(LogLevel::Info),
+        json: false,
+    });
+}
+
+// For the --log-level flag; false if `name` is not a level
+pub fn set_log_level(name: &str) -> bool {
+    match LogLevel::from_name(name) {
+        Some(level) => {
+            LOGGER.with(|logger| logger.borrow_mut().min_level = level);
+            true
+        },
+        None => false,
+    }
+}
+
+fn set_log_level_fn(args: Vec<Object>) -> Object {
+    match args.as_slice() {
+        [Object::String(name)] if set_log_level(name) => Object::Null,
+        [level] => Object::Error(format!("set_log_level: level must be \"debug\", \"info\", \"warn\" or \"error\", not \"{}\"", level)),
+        _ => unchecked("set_log_level"),
+    }
+}
+
+fn set_log_format_fn(args: Vec<Object>) -> Object {
+    let json = match args.as_slice() {
+        [Object::String(format)] if format == "text" => false,
+        [Object::String(format)] if format == "json" => true,
+        [format] => return Object::Error(format!("set_log_format: format must be \"text\" or \"json\", not \"{}\"", format)),
+        _ => return unchecked("set_log_format"),
+    };
+    LOGGER.with(|logger| logger.borrow_mut().json = json);
+    Object::Null
+}
+
+fn log_debug_fn(args: Vec<Object>) -> Object { log_at(LogLevel::Debug, args) }
+fn log_info_fn(args: Vec<Object>) -> Object { log_at(LogLevel::Info, args) }
+fn log_warn_fn(args: Vec<Object>) -> Object { log_at(LogLevel::Warn, args) }
+fn log_error_fn(args: Vec<Object>) -> Object { log_at(LogLevel::Error, args) }
+
+// Text lines look like `2026-01-31T09:15:02.047Z WARN  disk almost full {"free":12}`;
+// JSON lines carry the same fields as {"ts", "level", "msg", "data"}.
+fn log_at(level: LogLevel, args: Vec<Object>) -> Object {
+    let (msg, data) = match args.as_slice() {
+        [msg] => (msg, None),
+        [msg, data] => (msg, Some(data)),
+        _ => return Object::Error(format!("log_{}: arguments don't fit its signature", level.name())),
+    };
+    let (min_level, json) = LOGGER.with(|logger| {
+        let logger = logger.borrow();
+        (logger.min_level, logger.json)
+    });
+    if level < min_level { return Object::Null; }
+
+    let mut hooked = vec![Object::String(level.name().to_string()), msg.clone()];
+    hooked.extend(data.cloned());
+    if call_hook(error_hook, &hooked) { return Object::Null; }
+
+    let timestamp = format_timestamp(std::time::SystemTime::now());
+    let line = if json {
+        let mut line = format!("{{\"ts\":{},\"level\":{},\"msg\":{}",
+            json_string(&timestamp), json_string(level.name()), json_string(&msg.to_string()));
+        if let Some(data) = data {
+            line.push_str(&format!(",\"data\":{}", json_or_error(data)));
+        }
+        line.push('}');
+        line
+    } else {
+        let mut line = format!("{} {:<5} {}", timestamp, level.name().to_uppercase(), msg);
+        if let Some(data) = data {
+            line.push(' ');
+            line.push_str(&json_or_error(data));
+        }
+        line
+    };
+    with_stderr(|err| {
+        let _ = writeln!(err, "{}", line);
+    });
+    Object::Null
+}
+
+// UTC, millisecond precision: 2026-01-31T09:15:02.047Z
+fn format_timestamp(time: std::time::SystemTime) -> String {
+    let since_epoch = time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60, since_epoch.subsec_millis())
+}
+
+fn json_encode_fn(args: Vec<Object>) -> Object {
+    let (value, indent) = match args.as_slice() {
+        [value] => (value, 0),
+        [value, Object::Integer(n)] if *n >= 0 => (value, *n as usize),
+        [_, other] => return Object::Error(format!("json_encode: indent must be a non-negative integer, got {}", other)),
+        _ => return unchecked("json_encode"),
+    };
+    let mut out = String::new();
+    match write_json(value, indent, 0, &mut out) {
+        Ok(()) => Object::String(out),
+        Err(msg) => Object::Error(format!("json_encode: {}", msg)),
+    }
+}
+
+// A log line still goes out when its data can't be encoded
+fn json_or_error(obj: &Object) -> String {
+    json_encode(obj).unwrap_or_else(|msg| json_string(&format!("ERROR: {}", msg)))
+}
+
+fn json_encode(obj: &Object) -> Result<String, String> {
+    let mut out = String::new();
+    write_json(obj, 0, 0, &mut out)?;
+    Ok(out)
+}
+
+// Appends `obj` to `out` as JSON, with `indent` spaces per level of nesting
+// (`depth` levels so far), or all on one line when `indent` is 0.
+//
+// Hash keys come out in key order (see HashObject::sorted_pairs), so the same
+// data always encodes the same way. JSON keys are strings: 1 and true are written
+// as "1" and "true", and a hash where that makes two keys the same is an error.
+// Values JSON has no form for (functions, iterators...) are written as their display string.
+fn write_json(obj: &Object, indent: usize, depth: usize, out: &mut String) -> Result<(), String> {
+    match obj {
+        Object::Integer(i) => out.push_str(&i.to_string()),
+        // JSON has no NaN or infinity
+        Object::Float(f) if f.is_finite() => out.push_str(&format_float(*f)),
+        Object::Float(_) => out.push_str("null"),
+        Object::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Object::Null => out.push_str("null"),
+        Object::String(s) => write_json_string(s, out),
+        Object::Char(c) => write_json_string(&c.to_string(), out),
+        Object::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 { out.push(','); }
+                json_line_break(indent, depth + 1, out);
+                write_json(item, indent, depth + 1, out)?;
+            }
+            if !items.is_empty() { json_line_break(indent, depth, out); }
+            out.push(']');
+        },
+        Object::Hash(hash) => {
+            let mut written: HashMap<String, &HashKey> = HashMap::new();
+            let pairs = hash.sorted_pairs();
+            out.push('{');
+            for (i, &(key, value)) in pairs.iter().enumerate() {
+                let text = match key {
+                    HashKey::Integer(n) => n.to_string(),
+                    HashKey::Boolean(b) => b.to_string(),
+                    HashKey::String(s) => s.clone(),
+                };
+                if let Some(other) = written.insert(text.clone(), key) {
+                    return Err(format!(
+                        "the keys {} and {} would both be written as {} (JSON keys are strings)",
+                        other, key, json_string(&text)
+                    ));
+                }
+                if i > 0 { out.push(','); }
+                json_line_break(indent, depth + 1, out);
+                write_json_string(&text, out);
+                out.push_str(if indent > 0 { ": " } else { ":" });
+                write_json(value, indent, depth + 1, out)?;
+            }
+            if !pairs.is_empty() { json_line_break(indent, depth, out); }
+            out.push('}');
+        },
+        other => write_json_string(&other.to_string(), out),
+    }
+    Ok(())
+}
+
+// A newline and the indentation of `depth` levels (nothing in compact mode)
+fn json_line_break(indent: usize, depth: usize, out: &mut String) {
+    if indent == 0 { return; }
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', indent * depth));
+}
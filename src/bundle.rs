@@ -0,0 +1,147 @@
+// Bundles (`flux_compiler bundle entry.flux -o out.fluxb`): a script and every
+// module it imports, packed into one file so a deployment doesn't depend on a
+// tree of relative paths.
+//
+// import() runs modules through the interpreter, which works from source, so a
+// bundle keeps each module's source (checked to parse when bundling) keyed by
+// its path as import() resolves it against the importing file, taken from the
+// entry script's directory. Running a bundle installs a module resolver that
+// serves those imports from the bundle's module table; with no script file to
+// resolve against, the entry's own imports come to it exactly as written.
+//
+// Only imports with a constant string path can be found ahead of time; any
+// other import() call is a bundling error that points at the call.
+//
+// The file format is text; lengths are in bytes and each source is followed
+// by a newline:
+//
+//     fluxb 1
+//     entry <len> <name>
+//     <source>
+//     module <len> <name>
+//     <source>
+//     ...
+use std::collections::{HashMap, HashSet};
+use crate::builtins;
+use crate::paths;
+use crate::source::{self, SourceError};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::TokenType;
+
+pub const BUNDLE_VERSION: u32 = 1;
+pub const BUNDLE_EXTENSION: &str = "fluxb";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    pub entry: String,
+    pub source: String,
+    // Import path -> module source, in the order they were first imported
+    pub modules: Vec<(String, String)>,
+}
+
+impl Bundle {
+    // Reads `entry` and, transitively, every module it imports. Each import is
+    // resolved against its importing file, as import() resolves it, and keyed
+    // relative to the entry's directory, which is where a bundle runs from:
+    // the same bundle comes out whatever directory it is built from.
+    pub fn build(entry: &str) -> Result<Bundle, String> {
+        let source = source::read(entry).map_err(|e| format!("Cannot read {}: {}", entry, e))?;
+        let root = paths::dirname(entry);
+        let mut modules = vec![];
+        let mut seen = HashSet::new();
+        let mut problems = vec![];
+        let mut pending = vec![(paths::basename(entry), source.clone())];
+        while let Some((key, text)) = pending.pop() {
+            let file = paths::join(&[&root, &key]);
+            check_parses(&file, &text, &mut problems);
+            for import in static_imports(&file, &text, &mut problems) {
+                let import = paths::relative_to(&key, &import);
+                if !seen.insert(import.clone()) { continue; }
+                match source::read(paths::join(&[&root, &import])) {
+                    Ok(module) => {
+                        modules.push((import.clone(), module.clone()));
+                        pending.push((import, module));
+                    },
+                    Err(SourceError::NotFound) => problems.push(format!("{}: module '{}' not found", file, import)),
+                    Err(e) => problems.push(format!("{}: module '{}' can't be read: {}", file, import, e)),
+                }
+            }
+        }
+        if !problems.is_empty() {
+            return Err(format!("Cannot bundle {}:\n\t{}", entry, problems.join("\n\t")));
+        }
+        Ok(Bundle { entry: entry.to_string(), source, modules })
+    }
+
+    pub fn encode(&self) -> String {
+        let mut out = format!("fluxb {}\n", BUNDLE_VERSION);
+        out.push_str(&format!("entry {} {}\n{}\n", self.source.len(), self.entry, self.source));
+        for (name, source) in &self.modules {
+            out.push_str(&format!("module {} {}\n{}\n", source.len(), name, source));
+        }
+        out
+    }
+
+    pub fn decode(text: &str) -> Result<Bundle, String> {
+        let (header, mut rest) = text.split_once('\n').ok_or("not a Flux bundle")?;
+        if header != format!("fluxb {}", BUNDLE_VERSION) {
+            return Err(format!("not a version {} Flux bundle (header '{}')", BUNDLE_VERSION, header));
+        }
+        let mut entry = None;
+        let mut modules = vec![];
+        while !rest.is_empty() {
+            let (line, after) = rest.split_once('\n').ok_or("truncated bundle")?;
+            let mut parts = line.splitn(3, ' ');
+            let (kind, len, name) = match (parts.next(), parts.next().and_then(|n| n.parse::<usize>().ok()), parts.next()) {
+                (Some(kind), Some(len), Some(name)) => (kind, len, name.to_string()),
+                _ => return Err(format!("malformed bundle section '{}'", line)),
+            };
+            let source = after.get(..len).ok_or("truncated bundle")?.to_string();
+            rest = after[len..].strip_prefix('\n').ok_or("truncated bundle")?;
+            match kind {
+                "entry" => entry = Some((name, source)),
+                "module" => modules.push((name, source)),
+                _ => return Err(format!("unknown bundle section '{}'", kind)),
+            }
+        }
+        let (entry, source) = entry.ok_or("bundle has no entry script")?;
+        Ok(Bundle { entry, source, modules })
+    }
+
+    // Serve import() from this bundle for the rest of the thread.
+    // Anything not in it falls back to the filesystem as usual.
+    pub fn install(&self) {
+        let table: HashMap<String, String> = self.modules.iter().cloned().collect();
+        builtins::set_module_resolver(Box::new(move |name, _importer| {
+            Ok(table.get(name).cloned())
+        }));
+    }
+}
+
+// Modules are parsed when imported; finding their errors now beats finding them in production
+fn check_parses(file: &str, source: &str, problems: &mut Vec<String>) {
+    let mut p = Parser::new(Lexer::new(source.to_string()));
+    for error in p.parse_program().errors {
+        problems.push(format!("{}: {}", file, error));
+    }
+}
+
+// The paths of `import("...")` calls, in source order; other uses of import are problems
+fn static_imports(file: &str, source: &str, problems: &mut Vec<String>) -> Vec<String> {
+    let tokens = Lexer::tokenize(source);
+    let mut imports = vec![];
+    for (i, tok) in tokens.iter().enumerate() {
+        if tok.token_type != TokenType::Identifier || tok.literal != "import" { continue; }
+        let kinds: Vec<TokenType> = tokens[i + 1..].iter().take(3).map(|t| t.token_type).collect();
+        if kinds == [TokenType::LParen, TokenType::String, TokenType::RParen] {
+            imports.push(tokens[i + 2].literal.clone());
+        } else {
+            problems.push(format!(
+                "{} line {}:{}: import() needs a constant string path to be bundled",
+                file, tok.line, tok.column
+            ));
+        }
+    }
+    imports
+}
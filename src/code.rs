@@ -1,5 +1,3 @@
-use std::fmt;
-
 // 1. Define the Opcodes (The "Assembly Language" of Flux)
 // We use simple bytes (u8) to represent instructions.
 pub type Instructions = Vec<u8>;
@@ -18,6 +16,23 @@ pub const OP_JUMP_NOT_TRUTHY: Opcode = 8;
 pub const OP_JUMP: Opcode = 9;
 pub const OP_GET_GLOBAL: Opcode = 10;
 pub const OP_SET_GLOBAL: Opcode = 11;
+pub const OP_SUB: Opcode = 12;
+pub const OP_MUL: Opcode = 13;
+pub const OP_DIV: Opcode = 14;
+pub const OP_CALL: Opcode = 15;
+pub const OP_RETURN_VALUE: Opcode = 16;
+pub const OP_RETURN: Opcode = 17;
+pub const OP_GET_LOCAL: Opcode = 18;
+pub const OP_SET_LOCAL: Opcode = 19;
+pub const OP_LESS_THAN: Opcode = 20;
+pub const OP_POW: Opcode = 21;
+pub const OP_LESS_EQUAL: Opcode = 22;
+pub const OP_GREATER_EQUAL: Opcode = 23;
+pub const OP_BANG: Opcode = 24;
+pub const OP_MINUS: Opcode = 25;
+pub const OP_GET_BUILTIN: Opcode = 26;
+pub const OP_INDEX: Opcode = 27;
+pub const OP_SET_INDEX: Opcode = 28;
 // 2. Definition Struct (Helper to understand operands)
 // e.g., OP_CONSTANT needs 2 extra bytes to store the index of the constant.
 pub struct Definition {
@@ -36,10 +51,31 @@ pub fn lookup(op: u8) -> Option<Definition> {
         OP_EQUAL    => Some(Definition { name: "OpEqual".to_string(), operand_widths: vec![] }),
         OP_NOT_EQUAL=> Some(Definition { name: "OpNotEqual".to_string(), operand_widths: vec![] }),
         OP_GREATER_THAN => Some(Definition { name: "OpGreaterThan".to_string(), operand_widths: vec![] }),
+        OP_LESS_THAN    => Some(Definition { name: "OpLessThan".to_string(), operand_widths: vec![] }),
+        OP_LESS_EQUAL   => Some(Definition { name: "OpLessEqual".to_string(), operand_widths: vec![] }),
+        OP_GREATER_EQUAL => Some(Definition { name: "OpGreaterEqual".to_string(), operand_widths: vec![] }),
         OP_JUMP_NOT_TRUTHY => Some(Definition { name: "OpJumpNotTruthy".to_string(), operand_widths: vec![2] }),
         OP_JUMP            => Some(Definition { name: "OpJump".to_string(), operand_widths: vec![2] }),
         OP_GET_GLOBAL => Some(Definition { name: "OpGetGlobal".to_string(), operand_widths: vec![2] }),
         OP_SET_GLOBAL => Some(Definition { name: "OpSetGlobal".to_string(), operand_widths: vec![2] }),
+        OP_SUB      => Some(Definition { name: "OpSub".to_string(), operand_widths: vec![] }),
+        OP_MUL      => Some(Definition { name: "OpMul".to_string(), operand_widths: vec![] }),
+        OP_DIV      => Some(Definition { name: "OpDiv".to_string(), operand_widths: vec![] }),
+        OP_POW      => Some(Definition { name: "OpPow".to_string(), operand_widths: vec![] }),
+        // Prefix operators: !x and -x
+        OP_BANG     => Some(Definition { name: "OpBang".to_string(), operand_widths: vec![] }),
+        OP_MINUS    => Some(Definition { name: "OpMinus".to_string(), operand_widths: vec![] }),
+        // Functions: OpCall's operand is the argument count, locals are addressed by slot
+        OP_CALL         => Some(Definition { name: "OpCall".to_string(), operand_widths: vec![1] }),
+        OP_RETURN_VALUE => Some(Definition { name: "OpReturnValue".to_string(), operand_widths: vec![] }),
+        OP_RETURN       => Some(Definition { name: "OpReturn".to_string(), operand_widths: vec![] }),
+        OP_GET_LOCAL    => Some(Definition { name: "OpGetLocal".to_string(), operand_widths: vec![1] }),
+        OP_SET_LOCAL    => Some(Definition { name: "OpSetLocal".to_string(), operand_widths: vec![1] }),
+        // Builtins are addressed by their place in the interpreter's table
+        OP_GET_BUILTIN  => Some(Definition { name: "OpGetBuiltin".to_string(), operand_widths: vec![1] }),
+        // Indexing: OpSetIndex's operand is how many indexes deep the assignment goes
+        OP_INDEX        => Some(Definition { name: "OpIndex".to_string(), operand_widths: vec![] }),
+        OP_SET_INDEX    => Some(Definition { name: "OpSetIndex".to_string(), operand_widths: vec![1] }),
         _ => None,
     }
 }
@@ -61,11 +97,12 @@ pub fn make(op: Opcode, operands: Vec<usize>) -> Instructions {
     for (i, &o) in operands.iter().enumerate() {
         let width = def.operand_widths[i];
         match width {
+            // Write u16 (Big Endian)
             2 => {
-                // Write u16 (Big Endian)
                 instruction[offset] = ((o >> 8) & 0xFF) as u8;
                 instruction[offset + 1] = (o & 0xFF) as u8;
             },
+            1 => instruction[offset] = o as u8,
             _ => {},
         }
         offset += width;
@@ -99,10 +136,8 @@ fn read_operands(def: &Definition, ins: &[u8]) -> (Vec<usize>, usize) {
     let mut offset = 0;
     for width in &def.operand_widths {
         match width {
-            2 => {
-                let val = read_u16(&ins[offset..]);
-                operands.push(val);
-            },
+            2 => operands.push(read_u16(&ins[offset..])),
+            1 => operands.push(ins[offset] as usize),
             _ => {},
         }
         offset += *width;
@@ -1,16 +1,96 @@
+use std::collections::HashMap;
+use std::rc::Rc;
 use crate::ast;
+use crate::ast_lower;
+use crate::intern;
 use crate::code;
-use crate::object::Object;
-use crate::symbol_table::SymbolTable;
+use crate::diagnostics;
+use crate::object::{Object, CompiledFunction};
+use crate::symbol_table::{SymbolScope, SymbolTable};
+use crate::token::Span;
 
 pub struct Compiler {
-    pub instructions: code::Instructions,
     pub constants: Vec<Object>,
     pub symbol_table: SymbolTable,
-    
-    // Tracking for "pop" removal (to make blocks return values like expressions)
+
+    // One scope per function being compiled; scopes[0] is the main program
+    scopes: Vec<CompilationScope>,
+
+    // --opt=inline: top-level functions whose direct calls are compiled in place
+    inline: bool,
+    inlinable: HashMap<intern::Symbol, InlineCandidate>,
+
+    // Where each literal already in `constants` lives, so it is only added once
+    constant_index: HashMap<ConstantKey, usize>,
+    // How many times each constant was asked for (see CompilerState::uses)
+    constant_uses: Vec<usize>,
+}
+
+// What one program's compile leaves for the next: a session (the REPL with
+// --vm) compiles each line into the same pool and globals as the lines before.
+#[derive(Clone, Default)]
+pub struct CompilerState {
+    pub constants: Vec<Object>,
+    pub symbol_table: SymbolTable,
+    constant_index: HashMap<ConstantKey, usize>,
+    constant_uses: Vec<usize>,
+}
+
+impl CompilerState {
+    // How many literals, across every program compiled so far, compiled to constant `index`
+    pub fn uses(&self, index: usize) -> usize {
+        self.constant_uses.get(index).copied().unwrap_or(0)
+    }
+}
+
+// Literals that are equal can share a constant; functions never do
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Null,
+    Integer(i64),
+    // By bits, so 0.0 and -0.0 stay apart
+    Float(u64),
+    Char(char),
+    String(String),
+}
+
+fn constant_key(obj: &Object) -> Option<ConstantKey> {
+    match obj {
+        Object::Null => Some(ConstantKey::Null),
+        Object::Integer(value) => Some(ConstantKey::Integer(*value)),
+        Object::Float(value) => Some(ConstantKey::Float(value.to_bits())),
+        Object::Char(c) => Some(ConstantKey::Char(*c)),
+        Object::String(s) => Some(ConstantKey::String(s.clone())),
+        _ => None,
+    }
+}
+
+// Functions bigger than this (in bytes of bytecode) keep their calls
+const INLINE_MAX_INSTRUCTIONS: usize = 32;
+
+// A one-expression function that only reads its parameters: `fn sq(x) { x * x }`
+#[derive(Clone)]
+struct InlineCandidate {
+    parameters: Vec<intern::Symbol>,
+    body: ast::Expression,
+}
+
+// Instructions of one function body, with tracking for "pop" removal
+// (to make blocks return values like expressions)
+struct CompilationScope {
+    instructions: code::Instructions,
     last_instruction: Option<EmittedInstruction>,
     previous_instruction: Option<EmittedInstruction>,
+    // Enclosing loops, innermost last (a function body starts with none)
+    loops: Vec<LoopContext>,
+    // The source of each instruction that can fail, keyed by the offset just past it
+    spans: Vec<(usize, Span)>,
+}
+
+// Where `continue` jumps to, and the `break` jumps to patch once the loop's end is known
+struct LoopContext {
+    start: usize,
+    break_jumps: Vec<usize>,
 }
 
 #[derive(Clone, Copy)]
@@ -19,39 +99,202 @@ struct EmittedInstruction {
     position: usize,
 }
 
+impl CompilationScope {
+    fn new() -> CompilationScope {
+        CompilationScope { instructions: vec![], last_instruction: None, previous_instruction: None, loops: vec![], spans: vec![] }
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
 impl Compiler {
     pub fn new() -> Compiler {
+        Compiler::new_with_state(CompilerState::default())
+    }
+
+    // Carry on from an earlier compile: its constants and globals stay where they are
+    pub fn new_with_state(state: CompilerState) -> Compiler {
         Compiler {
-            instructions: vec![],
-            constants: vec![],
-            symbol_table: SymbolTable::new(),
-            last_instruction: None,
-            previous_instruction: None,
+            constants: state.constants,
+            symbol_table: state.symbol_table,
+            scopes: vec![CompilationScope::new()],
+            inline: false,
+            inlinable: HashMap::new(),
+            constant_index: state.constant_index,
+            constant_uses: state.constant_uses,
+        }
+    }
+
+    // What the next program of the session starts from
+    pub fn state(&self) -> CompilerState {
+        CompilerState {
+            constants: self.constants.clone(),
+            symbol_table: self.symbol_table.clone(),
+            constant_index: self.constant_index.clone(),
+            constant_uses: self.constant_uses.clone(),
         }
     }
 
+    // Splice calls to tiny top-level functions into their callers instead of
+    // calling them. Results are the same; the calls just aren't made.
+    pub fn with_inlining(mut self, inline: bool) -> Compiler {
+        self.inline = inline;
+        self
+    }
+
+    // The main program's instructions
+    pub fn instructions(&self) -> &code::Instructions {
+        &self.scopes[0].instructions
+    }
+
+    // Where the main program's failing instructions came from (see CompiledFunction::spans)
+    pub fn spans(&self) -> &[(usize, Span)] {
+        &self.scopes[0].spans
+    }
+
     pub fn compile(&mut self, program: Vec<ast::Statement>) -> Result<(), String> {
-        for stmt in program {
-            self.compile_statement(stmt)?;
+        let program = ast_lower::lower(program);
+        // Pass 1: reserve a global for every top-level name, so calls may come
+        // before a function's definition, mutually recursive functions resolve,
+        // and hoisted bodies can read and assign globals declared further down.
+        // Only functions are bound early (pass 2): the VM fails a read of any
+        // other global whose definition hasn't run yet.
+        for stmt in &program {
+            if let ast::Statement::Let { name, .. } = stmt {
+                self.symbol_table.define(*name);
+            }
+        }
+
+        if self.inline {
+            self.find_inline_candidates(&program);
+        }
+
+        // Pass 2: compile those functions and bind them before anything runs
+        let mut hoisted = HashMap::new(); // statement position -> function constant
+        for (pos, stmt) in program.iter().enumerate() {
+            if let Some((name, parameters, variadic, body)) = function_definition(stmt) {
+                let const_index = self.compile_function(name.as_str(), parameters.clone(), variadic, body.clone())?;
+                self.emit_function_binding(*name, const_index);
+                hoisted.insert(pos, const_index);
+            }
+        }
+
+        // Pass 3: everything in order. Definitions rebind their (already compiled)
+        // function at their original position, exactly like the interpreter.
+        for (pos, stmt) in program.into_iter().enumerate() {
+            match hoisted.get(&pos) {
+                Some(&const_index) => {
+                    if let Some((name, ..)) = function_definition(&stmt) {
+                        self.emit_function_binding(*name, const_index);
+                    }
+                },
+                None => self.compile_statement(stmt)?,
+            }
         }
         Ok(())
     }
 
+    fn emit_function_binding(&mut self, name: intern::Symbol, const_index: usize) {
+        self.emit(code::OP_CONSTANT, vec![const_index]);
+        let symbol = self.symbol_table.define(name);
+        self.emit_set(symbol.scope, symbol.index);
+    }
+
     fn compile_statement(&mut self, stmt: ast::Statement) -> Result<(), String> {
         match stmt {
             ast::Statement::Let { name, value } => {
                 // 1. Compile value (pushes result to stack)
-                self.compile_expression(value)?;
+                match value {
+                    ast::Expression::FunctionLiteral { parameters, variadic, body } => {
+                        let const_index = self.compile_function(name.as_str(), parameters, variadic, body)?;
+                        self.emit(code::OP_CONSTANT, vec![const_index]);
+                    },
+                    value => self.compile_expression(value)?,
+                }
                 // 2. Define symbol and get index
                 let symbol = self.symbol_table.define(name);
-                // 3. Emit SetGlobal
-                self.emit(code::OP_SET_GLOBAL, vec![symbol.index]);
+                // 3. Emit SetGlobal / SetLocal
+                self.emit_set(symbol.scope, symbol.index);
+            },
+            ast::Statement::Assign { name, value, span } => {
+                let symbol = match self.symbol_table.resolve(name) {
+                    // Builtins can be shadowed with a new variable, never assigned
+                    Some(symbol) if symbol.scope != SymbolScope::Builtin => symbol,
+                    _ => return Err(error_at(span, format!("Cannot assign to undeclared variable '{}' (declare it with `mut {} = ...`)", name, name))),
+                };
+                if symbol.scope == SymbolScope::Local && !self.symbol_table.defines(name) {
+                    return Err(error_at(span, format!("Closures over local variables are not supported by the compiler yet: {}", name)));
+                }
+                self.compile_expression(value)?;
+                self.emit_set(symbol.scope, symbol.index);
+            },
+            // `a[i][j] = v` rebuilds `a` with the new element and stores it back, as the
+            // interpreter does: the indexes run first, then the value, then `a` is read
+            ast::Statement::IndexAssign { left, index, value, span } => {
+                let target = left.to_string();
+                let mut path = vec![];
+                let name = match index_path(left, &mut path) {
+                    Some(name) => name,
+                    None => return Err(error_at(span, format!("Cannot assign to an index of {}", target))),
+                };
+                path.push(index);
+                let symbol = match self.symbol_table.resolve(name) {
+                    Some(symbol) if symbol.scope != SymbolScope::Builtin => symbol,
+                    _ => return Err(error_at(span, format!("Variable '{}' not found", name))),
+                };
+                if symbol.scope == SymbolScope::Local && !self.symbol_table.defines(name) {
+                    return Err(error_at(span, format!("Closures over local variables are not supported by the compiler yet: {}", name)));
+                }
+                let depth = path.len();
+                for key in path {
+                    self.compile_expression(key)?;
+                }
+                self.compile_expression(value)?;
+                self.emit_get(symbol.scope, symbol.index);
+                self.emit(code::OP_SET_INDEX, vec![depth]);
+                self.mark(span);
+                self.emit_set(symbol.scope, symbol.index);
+            },
+            // Both leave the loop's value on the stack and jump to its end
+            ast::Statement::Break(value) => {
+                if self.current_scope().loops.is_empty() {
+                    return Err("break outside of a loop".to_string());
+                }
+                match value {
+                    Some(v) => self.compile_expression(v)?,
+                    None => self.emit_null(),
+                }
+                let jump_pos = self.emit(code::OP_JUMP, vec![9999]);
+                if let Some(ctx) = self.current_scope().loops.last_mut() {
+                    ctx.break_jumps.push(jump_pos);
+                }
+            },
+            ast::Statement::Continue => {
+                let start = match self.current_scope().loops.last() {
+                    Some(ctx) => ctx.start,
+                    None => return Err("continue outside of a loop".to_string()),
+                };
+                self.emit(code::OP_JUMP, vec![start]);
+            },
+            ast::Statement::Defer(_) => return Err("defer is only supported by the interpreter so far".to_string()),
+            ast::Statement::Record { kind, .. } => return Err(format!("{} is only supported by the interpreter so far", kind.keyword())),
+            ast::Statement::Return(value) => {
+                match value {
+                    Some(v) => self.compile_expression(v)?,
+                    None => self.emit_null(),
+                }
+                self.emit(code::OP_RETURN_VALUE, vec![]);
             },
             ast::Statement::Expression(exp) => {
                 self.compile_expression(exp)?;
                 // Statement expressions pop their result to keep stack clean
-                //self.emit(code::OP_POP, vec![]); 
+                self.emit(code::OP_POP, vec![]);
             },
+            ast::Statement::CompoundAssign { .. } | ast::Statement::Function { .. } => unreachable!("lowered by ast_lower"),
             _ => return Err("Statement type not implemented yet".to_string()),
         }
         Ok(())
@@ -59,41 +302,73 @@ impl Compiler {
 
     fn compile_expression(&mut self, exp: ast::Expression) -> Result<(), String> {
         match exp {
-            ast::Expression::Infix { left, operator, right } => {
-                // Special Case: Swap < to >
-                if operator == "<" {
-                    self.compile_expression(*right)?;
-                    self.compile_expression(*left)?;
-                    self.emit(code::OP_GREATER_THAN, vec![]);
-                    return Ok(());
-                }
-
+            ast::Expression::Infix { left, operator, right, span } => {
+                // Operands always run left to right, as in the interpreter;
+                // every comparison has its own opcode, so none swaps them
                 self.compile_expression(*left)?;
                 self.compile_expression(*right)?;
-                
+
                 match operator.as_str() {
                     "+" => { self.emit(code::OP_ADD, vec![]); },
+                    "-" => { self.emit(code::OP_SUB, vec![]); },
+                    "*" => { self.emit(code::OP_MUL, vec![]); },
+                    "/" => { self.emit(code::OP_DIV, vec![]); },
+                    "**" => { self.emit(code::OP_POW, vec![]); },
                     "==" => { self.emit(code::OP_EQUAL, vec![]); },
                     "!=" => { self.emit(code::OP_NOT_EQUAL, vec![]); },
                     ">"  => { self.emit(code::OP_GREATER_THAN, vec![]); },
+                    "<"  => { self.emit(code::OP_LESS_THAN, vec![]); },
+                    "<=" => { self.emit(code::OP_LESS_EQUAL, vec![]); },
+                    ">=" => { self.emit(code::OP_GREATER_EQUAL, vec![]); },
                     _ => return Err(format!("Unknown operator: {}", operator)),
                 };
+                self.mark(span);
             },
-            ast::Expression::IntegerLiteral(value) => {
+            ast::Expression::IntegerLiteral { value, .. } => {
                 let integer = Object::Integer(value);
-                let const_index = self.add_constant(integer); 
+                let const_index = self.add_constant(integer);
                 self.emit(code::OP_CONSTANT, vec![const_index]);
             },
+            ast::Expression::FloatLiteral(value) => {
+                let const_index = self.add_constant(Object::Float(value));
+                self.emit(code::OP_CONSTANT, vec![const_index]);
+            },
+            ast::Expression::CharLiteral(c) => {
+                let const_index = self.add_constant(Object::Char(c));
+                self.emit(code::OP_CONSTANT, vec![const_index]);
+            },
+            ast::Expression::StringLiteral(s) => {
+                let const_index = self.add_constant(Object::String(s));
+                self.emit(code::OP_CONSTANT, vec![const_index]);
+            },
+            ast::Expression::Prefix { operator, right, span } => {
+                self.compile_expression(*right)?;
+                match operator.as_str() {
+                    "!" => { self.emit(code::OP_BANG, vec![]); },
+                    "-" => { self.emit(code::OP_MINUS, vec![]); },
+                    _ => return Err(format!("Unknown operator: {}", operator)),
+                };
+                self.mark(span);
+            },
             ast::Expression::Boolean(true)  => { self.emit(code::OP_TRUE, vec![]); },
             ast::Expression::Boolean(false) => { self.emit(code::OP_FALSE, vec![]); },
-            
+
             // --- VARIABLES ---
-            ast::Expression::Identifier(name) => {
-                if let Some(symbol) = self.symbol_table.resolve(&name) {
-                    self.emit(code::OP_GET_GLOBAL, vec![symbol.index]);
-                } else {
-                    return Err(format!("Undefined variable: {}", name));
+            ast::Expression::Identifier { name, span } => {
+                let symbol = match self.symbol_table.resolve(name) {
+                    Some(symbol) => symbol,
+                    None => return Err(error_at(span, format!("Undefined variable: {}", name))),
+                };
+                if symbol.scope == SymbolScope::Local && !self.symbol_table.defines(name) {
+                    return Err(error_at(span, format!("Closures over local variables are not supported by the compiler yet: {}", name)));
                 }
+                self.emit_get(symbol.scope, symbol.index);
+            },
+            ast::Expression::IndexExpression { left, index, span } => {
+                self.compile_expression(*left)?;
+                self.compile_expression(*index)?;
+                self.emit(code::OP_INDEX, vec![]);
+                self.mark(span);
             },
 
             // --- IF / ELSE ---
@@ -104,33 +379,116 @@ impl Compiler {
                 let jump_not_truthy_pos = self.emit(code::OP_JUMP_NOT_TRUTHY, vec![9999]);
 
                 self.compile_block(consequence)?;
-                if self.last_instruction_is_pop() { self.remove_last_pop(); }
+                if self.last_instruction_is(code::OP_POP) { self.remove_last_pop(); }
 
                 // Emit Jump with dummy 9999
                 let jump_pos = self.emit(code::OP_JUMP, vec![9999]);
 
                 // Patch NotTruthy
-                let after_consequence_pos = self.instructions.len();
+                let after_consequence_pos = self.current_instructions().len();
                 self.change_operand(jump_not_truthy_pos, after_consequence_pos);
 
                 if let Some(alt) = alternative {
                     self.compile_block(alt)?;
-                    if self.last_instruction_is_pop() { self.remove_last_pop(); }
+                    if self.last_instruction_is(code::OP_POP) { self.remove_last_pop(); }
                 } else {
                     // Else-less ifs return Null
-                    let null_idx = self.add_constant(Object::Null);
-                    self.emit(code::OP_CONSTANT, vec![null_idx]);
+                    self.emit_null();
                 }
 
                 // Patch Jump
-                let after_alternative_pos = self.instructions.len();
+                let after_alternative_pos = self.current_instructions().len();
                 self.change_operand(jump_pos, after_alternative_pos);
             },
+
+            // --- WHILE ---
+            // start: <cond>; JumpNotTruthy exit; <body>; Jump start
+            // exit:  push Null   (the value of a loop that ends normally)
+            // end:   <- every break jumps here with its value already pushed
+            ast::Expression::While { condition, body } => {
+                let start = self.current_instructions().len();
+                self.compile_expression(*condition)?;
+                let exit_jump = self.emit(code::OP_JUMP_NOT_TRUTHY, vec![9999]);
+
+                self.current_scope().loops.push(LoopContext { start, break_jumps: vec![] });
+                let body_result = self.compile_block(body);
+                let ctx = self.current_scope().loops.pop().expect("loop context vanished");
+                body_result?;
+
+                self.emit(code::OP_JUMP, vec![start]);
+                let exit_pos = self.current_instructions().len();
+                self.change_operand(exit_jump, exit_pos);
+                self.emit_null();
+
+                let end = self.current_instructions().len();
+                for jump in ctx.break_jumps {
+                    self.change_operand(jump, end);
+                }
+            },
+
+            ast::Expression::For { .. } | ast::Expression::ForIn { .. } => return Err("for loops are only supported by the interpreter so far".to_string()),
+            ast::Expression::Block(_) => return Err("block expressions are only supported by the interpreter so far".to_string()),
+
+            // --- FUNCTIONS ---
+            ast::Expression::FunctionLiteral { parameters, variadic, body } => {
+                let const_index = self.compile_function("<anonymous>", parameters, variadic, body)?;
+                self.emit(code::OP_CONSTANT, vec![const_index]);
+            },
+            ast::Expression::Call { function, arguments, span } => {
+                if let ast::Expression::Identifier { name, .. } = *function
+                    && let Some(candidate) = self.inline_candidate(name, arguments.len())
+                {
+                    return self.compile_inlined(name, candidate, arguments);
+                }
+                self.compile_expression(*function)?;
+                let num_args = arguments.len();
+                if num_args > u8::MAX as usize {
+                    return Err(format!("Too many arguments in call: {}", num_args));
+                }
+                for arg in arguments {
+                    self.compile_expression(arg)?;
+                }
+                self.emit(code::OP_CALL, vec![num_args]);
+                self.mark(span);
+            },
             _ => return Err("Expression type not implemented yet".to_string()),
         }
         Ok(())
     }
 
+    // Compiles a function body into a constant and returns the constant's index
+    fn compile_function(&mut self, name: &str, parameters: Vec<intern::Symbol>, variadic: bool, body: ast::BlockStatement) -> Result<usize, String> {
+        if variadic {
+            return Err("rest parameters are only supported by the interpreter so far".to_string());
+        }
+        self.enter_scope();
+        let num_parameters = parameters.len();
+        for param in parameters {
+            self.symbol_table.define(param);
+        }
+
+        if let Err(e) = self.compile_block(body) {
+            self.leave_scope();
+            return Err(e);
+        }
+        // The last expression is the implicit return value
+        if self.last_instruction_is(code::OP_POP) {
+            self.replace_last_pop_with_return();
+        }
+        if !self.last_instruction_is(code::OP_RETURN_VALUE) {
+            self.emit(code::OP_RETURN, vec![]);
+        }
+
+        // Local slots are a one-byte operand
+        let num_locals = self.symbol_table.num_definitions;
+        let CompilationScope { instructions, spans, .. } = self.leave_scope();
+        if num_locals > u8::MAX as usize {
+            return Err(format!("Too many local variables in {}: {} (at most {})", name, num_locals, u8::MAX));
+        }
+        let func = CompiledFunction { name: name.to_string(), instructions, num_locals, num_parameters, spans };
+        Ok(self.add_constant(Object::CompiledFunction(Rc::new(func))))
+    }
+
     fn compile_block(&mut self, block: ast::BlockStatement) -> Result<(), String> {
         for stmt in block.statements {
             self.compile_statement(stmt)?;
@@ -138,43 +496,287 @@ impl Compiler {
         Ok(())
     }
 
+    // --- INLINING ---
+
+    // A function qualifies when it is defined once at the top level, never
+    // reassigned anywhere, small, and its body is a single expression of
+    // literals, operators and its own parameters. That rules out recursion,
+    // closures and globals that could change between calls.
+    fn find_inline_candidates(&mut self, program: &[ast::Statement]) {
+        for stmt in program {
+            let (name, parameters, variadic, body) = match function_definition(stmt) {
+                Some(definition) => definition,
+                None => continue,
+            };
+            let expression = match body.statements.as_slice() {
+                [ast::Statement::Expression(e)] | [ast::Statement::Return(Some(e))] => e,
+                _ => continue,
+            };
+            if !reads_only(expression, parameters) || bindings_of(*name, program) != 1 {
+                continue;
+            }
+            let mut scratch = Compiler::new();
+            if let Ok(index) = scratch.compile_function(name.as_str(), parameters.clone(), variadic, body.clone())
+                && let Object::CompiledFunction(func) = &scratch.constants[index]
+                && func.instructions.len() <= INLINE_MAX_INSTRUCTIONS
+            {
+                let candidate = InlineCandidate { parameters: parameters.clone(), body: expression.clone() };
+                self.inlinable.insert(*name, candidate);
+            }
+        }
+    }
+
+    // Only calls that really reach the global function (not a local of the same name)
+    fn inline_candidate(&self, name: intern::Symbol, num_args: usize) -> Option<InlineCandidate> {
+        let candidate = self.inlinable.get(&name)?;
+        let symbol = self.symbol_table.resolve(name)?;
+        (symbol.scope == SymbolScope::Global && candidate.parameters.len() == num_args).then(|| candidate.clone())
+    }
+
+    // The arguments run first, left to right, into slots named `function.param`
+    // (names no program can spell); the body then reads those slots. Inlined
+    // bodies make no calls, so nested inlining of the same function can share them.
+    fn compile_inlined(&mut self, name: intern::Symbol, candidate: InlineCandidate, arguments: Vec<ast::Expression>) -> Result<(), String> {
+        for arg in arguments {
+            self.compile_expression(arg)?;
+        }
+        let mut renames = HashMap::new();
+        let mut slots = vec![];
+        for param in &candidate.parameters {
+            let slot = intern::Symbol::intern(&format!("{}.{}", name, param));
+            renames.insert(*param, slot);
+            slots.push(self.symbol_table.define(slot));
+        }
+        for symbol in slots.iter().rev() {
+            self.emit_set(symbol.scope, symbol.index);
+        }
+        self.compile_expression(rename(&candidate.body, &renames))
+    }
+
+    // --- SCOPES ---
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::new());
+        let outer = std::mem::replace(&mut self.symbol_table, SymbolTable::new());
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> CompilationScope {
+        let scope = self.scopes.pop().expect("leave_scope without enter_scope");
+        if let Some(outer) = self.symbol_table.outer.take() {
+            self.symbol_table = *outer;
+        }
+        scope
+    }
+
+    fn current_scope(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().expect("compiler has no scope")
+    }
+
+    fn current_instructions(&mut self) -> &mut code::Instructions {
+        &mut self.current_scope().instructions
+    }
+
     // --- HELPERS ---
 
+    // A literal that is already in the pool is reused rather than added again
     pub fn add_constant(&mut self, obj: Object) -> usize {
+        let key = constant_key(&obj);
+        if let Some(index) = key.as_ref().and_then(|key| self.constant_index.get(key)).copied() {
+            self.constant_uses[index] += 1;
+            return index;
+        }
         self.constants.push(obj);
-        self.constants.len() - 1
+        self.constant_uses.push(1);
+        let index = self.constants.len() - 1;
+        if let Some(key) = key {
+            self.constant_index.insert(key, index);
+        }
+        index
     }
 
     pub fn emit(&mut self, op: code::Opcode, operands: Vec<usize>) -> usize {
         let ins = code::make(op, operands);
-        let pos = self.instructions.len();
-        self.instructions.extend(ins);
-        
-        self.previous_instruction = self.last_instruction;
-        self.last_instruction = Some(EmittedInstruction { opcode: op, position: pos });
-        
+        let scope = self.current_scope();
+        let pos = scope.instructions.len();
+        scope.instructions.extend(ins);
+
+        scope.previous_instruction = scope.last_instruction;
+        scope.last_instruction = Some(EmittedInstruction { opcode: op, position: pos });
+
         pos
     }
 
+    // The instruction just emitted is the operation at `span`
+    fn mark(&mut self, span: Span) {
+        let scope = self.current_scope();
+        let end = scope.instructions.len();
+        scope.spans.push((end, span));
+    }
+
+    fn emit_null(&mut self) {
+        let null_idx = self.add_constant(Object::Null);
+        self.emit(code::OP_CONSTANT, vec![null_idx]);
+    }
+
+    fn emit_get(&mut self, scope: SymbolScope, index: usize) {
+        match scope {
+            SymbolScope::Global => self.emit(code::OP_GET_GLOBAL, vec![index]),
+            SymbolScope::Local => self.emit(code::OP_GET_LOCAL, vec![index]),
+            SymbolScope::Builtin => self.emit(code::OP_GET_BUILTIN, vec![index]),
+        };
+    }
+
+    fn emit_set(&mut self, scope: SymbolScope, index: usize) {
+        match scope {
+            SymbolScope::Global => self.emit(code::OP_SET_GLOBAL, vec![index]),
+            SymbolScope::Local => self.emit(code::OP_SET_LOCAL, vec![index]),
+            SymbolScope::Builtin => unreachable!("builtins are never assigned"),
+        };
+    }
+
     fn change_operand(&mut self, op_pos: usize, operand: usize) {
-        let op = self.instructions[op_pos];
+        let instructions = self.current_instructions();
+        let op = instructions[op_pos];
         let new_instruction = code::make(op, vec![operand]);
         for (i, byte) in new_instruction.iter().enumerate() {
-            self.instructions[op_pos + i] = *byte;
+            instructions[op_pos + i] = *byte;
         }
     }
 
-    fn last_instruction_is_pop(&self) -> bool {
-        match self.last_instruction {
-            Some(ins) => ins.opcode == code::OP_POP,
+    fn last_instruction_is(&mut self, op: code::Opcode) -> bool {
+        match self.current_scope().last_instruction {
+            Some(ins) => ins.opcode == op,
             None => false,
         }
     }
 
     fn remove_last_pop(&mut self) {
-        if let Some(ins) = self.last_instruction {
-            self.instructions.truncate(ins.position);
-            self.last_instruction = self.previous_instruction;
+        let scope = self.current_scope();
+        if let Some(ins) = scope.last_instruction {
+            scope.instructions.truncate(ins.position);
+            scope.last_instruction = scope.previous_instruction;
+        }
+    }
+
+    fn replace_last_pop_with_return(&mut self) {
+        let scope = self.current_scope();
+        if let Some(ins) = scope.last_instruction {
+            scope.instructions[ins.position] = code::OP_RETURN_VALUE;
+            scope.last_instruction = Some(EmittedInstruction { opcode: code::OP_RETURN_VALUE, position: ins.position });
         }
     }
-}
\ No newline at end of file
+}
+
+// A compile error about the code at `span`, noted so a script run from a file
+// shows the line, as a runtime error does (see diagnostics)
+// The variable at the root of `a[i][j]`, collecting [i, j] on the way
+fn index_path(target: ast::Expression, path: &mut Vec<ast::Expression>) -> Option<intern::Symbol> {
+    match target {
+        ast::Expression::Identifier { name, .. } => Some(name),
+        ast::Expression::IndexExpression { left, index, .. } => {
+            let name = index_path(*left, path)?;
+            path.push(*index);
+            Some(name)
+        },
+        _ => None,
+    }
+}
+
+fn error_at(span: Span, msg: String) -> String {
+    diagnostics::note(span, &msg);
+    msg
+}
+
+// True if `exp` is built from literals and operators over `parameters` alone
+fn reads_only(exp: &ast::Expression, parameters: &[intern::Symbol]) -> bool {
+    match exp {
+        ast::Expression::IntegerLiteral { .. } | ast::Expression::FloatLiteral(_)
+        | ast::Expression::CharLiteral(_) | ast::Expression::Boolean(_) => true,
+        ast::Expression::Identifier { name, .. } => parameters.contains(name),
+        ast::Expression::Prefix { right, .. } => reads_only(right, parameters),
+        ast::Expression::Infix { left, right, .. } => reads_only(left, parameters) && reads_only(right, parameters),
+        _ => false,
+    }
+}
+
+// `exp` with its identifiers replaced as `renames` says (only called on reads_only expressions)
+fn rename(exp: &ast::Expression, renames: &HashMap<intern::Symbol, intern::Symbol>) -> ast::Expression {
+    match exp {
+        ast::Expression::Identifier { name, span } => ast::Expression::Identifier { name: *renames.get(name).unwrap_or(name), span: *span },
+        ast::Expression::Prefix { operator, right, span } => ast::Expression::Prefix {
+            operator: operator.clone(),
+            right: Box::new(rename(right, renames)),
+            span: *span,
+        },
+        ast::Expression::Infix { left, operator, right, span } => ast::Expression::Infix {
+            left: Box::new(rename(left, renames)),
+            operator: operator.clone(),
+            right: Box::new(rename(right, renames)),
+            span: *span,
+        },
+        other => other.clone(),
+    }
+}
+
+// How many statements anywhere in `statements`, function bodies included, bind `name`
+fn bindings_of(name: intern::Symbol, statements: &[ast::Statement]) -> usize {
+    statements.iter().map(|stmt| match stmt {
+        ast::Statement::Let { name: bound, value } | ast::Statement::Assign { name: bound, value, .. } => {
+            usize::from(*bound == name) + expression_bindings_of(name, value)
+        },
+        ast::Statement::LetDestructure { names, value, .. } => {
+            usize::from(names.contains(&name)) + expression_bindings_of(name, value)
+        },
+        ast::Statement::Record { name: bound, fields, .. } => {
+            usize::from(*bound == name) + fields.iter().map(|(_, value)| expression_bindings_of(name, value)).sum::<usize>()
+        },
+        ast::Statement::Defer(body) => bindings_of(name, &body.statements),
+        ast::Statement::IndexAssign { left, index, value, .. } => {
+            [left, index, value].into_iter().map(|e| expression_bindings_of(name, e)).sum()
+        },
+        ast::Statement::Return(Some(e)) | ast::Statement::Expression(e) | ast::Statement::Break(Some(e)) => expression_bindings_of(name, e),
+        ast::Statement::Return(None) | ast::Statement::Break(None) | ast::Statement::Continue => 0,
+        ast::Statement::CompoundAssign { .. } | ast::Statement::Function { .. } => unreachable!("lowered by ast_lower"),
+    }).sum()
+}
+
+fn expression_bindings_of(name: intern::Symbol, exp: &ast::Expression) -> usize {
+    let all = |exps: &[&ast::Expression]| -> usize { exps.iter().map(|e| expression_bindings_of(name, e)).sum() };
+    match exp {
+        ast::Expression::Prefix { right, .. } => all(&[right]),
+        ast::Expression::Infix { left, right, .. } => all(&[left, right]),
+        ast::Expression::If { condition, consequence, alternative } => {
+            all(&[condition]) + bindings_of(name, &consequence.statements)
+                + alternative.as_ref().map_or(0, |alt| bindings_of(name, &alt.statements))
+        },
+        ast::Expression::FunctionLiteral { body, .. } => bindings_of(name, &body.statements),
+        ast::Expression::Call { function, arguments, .. } => all(&[function]) + arguments.iter().map(|a| all(&[a])).sum::<usize>(),
+        ast::Expression::ArrayLiteral(items) => items.iter().map(|item| all(&[item])).sum(),
+        ast::Expression::IndexExpression { left, index, .. } => all(&[left, index]),
+        ast::Expression::Member { object, .. } => all(&[object]),
+        ast::Expression::Range { start, end, .. } => all(&[start, end]),
+        ast::Expression::While { condition, body } => all(&[condition]) + bindings_of(name, &body.statements),
+        ast::Expression::For { init, condition, post, body } => {
+            let header = [init, post].into_iter().flatten().map(|stmt| bindings_of(name, std::slice::from_ref(&**stmt))).sum::<usize>();
+            header + condition.as_ref().map_or(0, |c| all(&[c])) + bindings_of(name, &body.statements)
+        },
+        ast::Expression::ForIn { variable, value, iterable, body, .. } => {
+            usize::from(*variable == name) + usize::from(*value == Some(name)) + all(&[iterable]) + bindings_of(name, &body.statements)
+        },
+        ast::Expression::HashLiteral(hash) => hash.pairs.iter().map(|(k, v)| all(&[k, v])).sum(),
+        ast::Expression::Match { subject, arms, .. } => all(&[subject]) + arms.iter().map(|arm| {
+            arm.guard.as_ref().map_or(0, |g| all(&[g])) + bindings_of(name, &arm.body.statements)
+        }).sum::<usize>(),
+        ast::Expression::Block(body) => bindings_of(name, &body.statements),
+        _ => 0,
+    }
+}
+
+// `mut name = fn(...) {...}` at the top level (`fn name(...) {...}` is lowered to it)
+fn function_definition(stmt: &ast::Statement) -> Option<(&intern::Symbol, &Vec<intern::Symbol>, bool, &ast::BlockStatement)> {
+    match stmt {
+        ast::Statement::Let { name, value: ast::Expression::FunctionLiteral { parameters, variadic, body } } => Some((name, parameters, *variadic, body)),
+        _ => None,
+    }
+}
@@ -0,0 +1,171 @@
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Limits shared by the evaluator and the VM, so both engines stop a runaway
+// script at the same point and with the same kind of message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuntimeConfig {
+    // Nested function calls before "stack overflow"
+    pub max_call_depth: usize,
+    // Slots in the VM's value stack (locals and temporaries of every active call)
+    pub stack_size: usize,
+    // Function calls plus loop iterations allowed in one run; None is unlimited
+    pub fuel: Option<u64>,
+    // Largest file import() will read, in bytes
+    pub max_module_size: u64,
+    // Whether a module may import the script being run (which runs it again)
+    pub allow_entry_import: bool,
+    // Parser errors reported in full; any more are only counted
+    pub max_parse_errors: usize,
+    // What the builtins may do outside the script
+    pub policy: Policy,
+}
+
+// What a script may reach beyond its own values (see Runtime::with_policy).
+// A builtin the policy rules out fails with an error saying so.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy {
+    // read_file(), and import() of modules from disk (a module resolver still serves them)
+    pub read_files: bool,
+    // write_file() and set_cwd()
+    pub write_files: bool,
+    // input()
+    pub read_stdin: bool,
+}
+
+impl Policy {
+    // The default, and what the CLI runs with
+    pub const ALLOW_ALL: Policy = Policy { read_files: true, write_files: true, read_stdin: true };
+    // For code the host doesn't trust: no files and no stdin
+    pub const SANDBOX: Policy = Policy { read_files: false, write_files: false, read_stdin: false };
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy::ALLOW_ALL
+    }
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> RuntimeConfig {
+        RuntimeConfig {
+            max_call_depth: 1024,
+            stack_size: 65536,
+            fuel: None,
+            max_module_size: 4 * 1024 * 1024,
+            allow_entry_import: false,
+            max_parse_errors: crate::parser::DEFAULT_MAX_ERRORS,
+            policy: Policy::ALLOW_ALL,
+        }
+    }
+}
+
+// Native stack the tree-walking evaluator may need per Flux call, with room for
+// big function bodies in unoptimised builds
+const NATIVE_STACK_PER_CALL: usize = 64 * 1024;
+
+// The deepest --max-depth accepted: its native stack (about 3 GiB) is still
+// one a 64-bit host can reserve for a thread
+pub const MAX_CALL_DEPTH: usize = 50_000;
+
+impl RuntimeConfig {
+    // Stack size for a thread that runs the evaluator under this config
+    pub fn native_stack_size(&self) -> usize {
+        (self.max_call_depth.saturating_add(64)).saturating_mul(NATIVE_STACK_PER_CALL)
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<RuntimeConfig> = RefCell::new(RuntimeConfig::default());
+    static FUEL_USED: Cell<u64> = const { Cell::new(0) };
+    static CANCELLATION: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+    static TICKS: Cell<u32> = const { Cell::new(0) };
+}
+
+// Install the limits for everything run on this thread from now on (resets the fuel meter)
+pub fn set(config: RuntimeConfig) {
+    CURRENT.with(|current| *current.borrow_mut() = config);
+    FUEL_USED.with(|used| used.set(0));
+}
+
+pub fn current() -> RuntimeConfig {
+    CURRENT.with(|current| *current.borrow())
+}
+
+// Spend one unit of fuel; Err once the budget is gone (or the run is cancelled)
+pub fn burn_fuel() -> Result<(), String> {
+    check_cancelled()?;
+    let limit = match current().fuel {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    FUEL_USED.with(|used| {
+        let spent = used.get() + 1;
+        used.set(spent);
+        if spent > limit {
+            Err(format!("Out of fuel: the script used up its budget of {} calls and loop iterations", limit))
+        } else {
+            Ok(())
+        }
+    })
+}
+
+// A switch another thread can flip to stop a run (see Runtime::cancellation_token).
+// Once cancelled it stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub const CANCELLED: &str = "Cancelled: the host stopped the script";
+
+// Statements, calls and loop iterations between two looks at the token: a
+// cancelled loop stops within microseconds, and the check costs a counter
+// bump the rest of the time
+const CANCEL_CHECK_INTERVAL: u32 = 1024;
+
+// The token runs on this thread answer to, if any
+pub fn set_cancellation(token: Option<CancellationToken>) {
+    CANCELLATION.with(|current| *current.borrow_mut() = token);
+}
+
+// Err once the host has cancelled the run, noticed at the next check
+pub fn check_cancelled() -> Result<(), String> {
+    let ticks = TICKS.with(|ticks| {
+        let n = ticks.get().wrapping_add(1);
+        ticks.set(n);
+        n
+    });
+    if !ticks.is_multiple_of(CANCEL_CHECK_INTERVAL) { return Ok(()); }
+    let cancelled = CANCELLATION.with(|current| current.borrow().as_ref().is_some_and(CancellationToken::is_cancelled));
+    if cancelled { Err(CANCELLED.to_string()) } else { Ok(()) }
+}
+
+// How many of the innermost calls a stack overflow message names
+const SHOWN_FRAMES: usize = 5;
+
+// `calls` is the active call stack, outermost first, including the call that overflowed
+pub fn stack_overflow_message(calls: &[String]) -> String {
+    let innermost: Vec<&str> = calls.iter().rev().take(SHOWN_FRAMES).map(|name| name.as_str()).collect();
+    format!(
+        "Stack overflow: call depth {} reached (innermost calls: {}{}). \
+         Rewrite the recursion as a while loop or pass the result along in an accumulator, \
+         or raise the limit with --max-depth",
+        calls.len(),
+        innermost.join(" <- "),
+        if calls.len() > SHOWN_FRAMES { " <- ..." } else { "" },
+    )
+}
@@ -0,0 +1,285 @@
+// Golden-file runner (--corpus dir, and `cargo test` through tests/corpus.rs):
+// every dir/*.flux is run the way the flux_compiler binary would run it, in
+// process, with its output captured through Runtime::with_io, and the output
+// is compared with the matching .expected file. FLUX_UPDATE_EXPECT=1 rewrites
+// the .expected files instead. In a build with the VM every program is also
+// run by the VM, and any difference between the two engines is reported as a
+// failure; so is a difference with inlining on. Programs only the interpreter
+// can run are named in dir/interpreter_only.txt, with why, and skip both VM runs.
+// A first line of `// flux-args: --ast json` adds those arguments to every run
+// (--ast json, --tokens, --edition, --fuel, --max-module-size and --max-errors).
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use crate::ast;
+use crate::builtins::{Capture, Io};
+use crate::config::RuntimeConfig;
+use crate::lexer::Lexer;
+use crate::object::Object;
+use crate::parser::Edition;
+use crate::runtime::{Engine, Runtime};
+use crate::source;
+
+// The file naming the programs not to run with the VM, one per line; `#` starts a comment
+const INTERPRETER_ONLY: &str = "interpreter_only.txt";
+
+// Returns true when every program matched. The programs run from inside `dir`,
+// so relative imports find their fixtures; the working directory is put back after.
+pub fn run(dir: &Path) -> bool {
+    let home = match env::current_dir() {
+        Ok(home) => home,
+        Err(e) => {
+            println!("Cannot find the working directory: {}", e);
+            return false;
+        }
+    };
+    if let Err(e) = env::set_current_dir(dir) {
+        println!("Cannot read corpus directory {}: {}", dir.display(), e);
+        return false;
+    }
+    let passed = run_here(dir);
+    let _ = env::set_current_dir(home);
+    passed
+}
+
+// `dir` is the working directory now; it is only named in messages
+fn run_here(dir: &Path) -> bool {
+    // By file name, the way a user in the directory would name them
+    let mut programs: Vec<PathBuf> = match fs::read_dir(".") {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| PathBuf::from(e.file_name())))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "flux"))
+            .collect(),
+        Err(e) => {
+            println!("Cannot read corpus directory {}: {}", dir.display(), e);
+            return false;
+        }
+    };
+    programs.sort();
+    let interpreter_only = match read_interpreter_only(Path::new(".")) {
+        Ok(names) => names,
+        Err(e) => {
+            println!("Cannot read {}: {}", dir.join(INTERPRETER_ONLY).display(), e);
+            return false;
+        }
+    };
+
+    let mut failed = 0;
+    // A name that matches no program is a mistake, not a skip
+    for name in &interpreter_only {
+        if !programs.iter().any(|program| program.file_name().is_some_and(|file| file.to_string_lossy() == *name)) {
+            println!("FAIL {}: lists {}, which is not in {}", INTERPRETER_ONLY, name, dir.display());
+            failed += 1;
+        }
+    }
+
+    let update = env::var("FLUX_UPDATE_EXPECT").is_ok_and(|v| v == "1");
+    let mut skipped = 0;
+    for program in &programs {
+        let name = program.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let expected_path = program.with_extension("expected");
+        let actual = match run_program(program, Engine::Interpreter, false) {
+            Ok(out) => out,
+            Err(e) => {
+                println!("FAIL {}: {}", name, e);
+                failed += 1;
+                continue;
+            }
+        };
+
+        if update {
+            if let Err(e) = fs::write(&expected_path, &actual) {
+                println!("FAIL {}: cannot write {}: {}", name, dir.join(&expected_path).display(), e);
+                failed += 1;
+            } else {
+                println!("updated {}", name);
+            }
+            continue;
+        }
+
+        let mut ok = true;
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {},
+            Ok(expected) => {
+                println!("FAIL {}: output differs from {}", name, dir.join(&expected_path).display());
+                print_diff(&expected, &actual);
+                ok = false;
+            },
+            Err(_) => {
+                println!("FAIL {}: no {} (run with FLUX_UPDATE_EXPECT=1 to create it)", name, dir.join(&expected_path).display());
+                ok = false;
+            },
+        }
+
+        let skip_vm = interpreter_only.contains(&name);
+        if skip_vm {
+            skipped += 1;
+        } else if !cross_check_vm(program, &name, &actual) {
+            ok = false;
+        }
+
+        match (ok, skip_vm) {
+            (true, false) => println!("ok   {}", name),
+            (true, true) => println!("ok   {} (interpreter only)", name),
+            (false, _) => failed += 1,
+        }
+    }
+
+    println!("{} programs, {} failed, {} run by the interpreter only", programs.len(), failed, skipped);
+    failed == 0
+}
+
+// The VM must print what the interpreter printed, with and without inlining
+#[cfg(feature = "vm")]
+fn cross_check_vm(program: &Path, name: &str, interpreted: &str) -> bool {
+    let vm_out = match run_program(program, Engine::Vm, false) {
+        Ok(out) => out,
+        Err(e) => {
+            println!("FAIL {}: under the VM, {}", name, e);
+            return false;
+        }
+    };
+    if let Some(error) = vm_out.lines().next().filter(|line| line.starts_with("Compiler Error")) {
+        println!("FAIL {}: {} (list it in {} if only the interpreter can run it)", name, error, INTERPRETER_ONLY);
+        return false;
+    }
+    let mut ok = true;
+    if vm_out != interpreted {
+        println!("FAIL {}: the VM disagrees with the interpreter", name);
+        print_diff(interpreted, &vm_out);
+        ok = false;
+    }
+    match run_program(program, Engine::Vm, true) {
+        Ok(inlined_out) if inlined_out == vm_out => {},
+        Ok(inlined_out) => {
+            println!("FAIL {}: --opt=inline changes the VM's output", name);
+            print_diff(&vm_out, &inlined_out);
+            ok = false;
+        },
+        Err(e) => {
+            println!("FAIL {}: under the VM with --opt=inline, {}", name, e);
+            ok = false;
+        },
+    }
+    ok
+}
+
+#[cfg(not(feature = "vm"))]
+fn cross_check_vm(_program: &Path, _name: &str, _interpreted: &str) -> bool {
+    true
+}
+
+// What the CLI does instead of running the script, if anything
+enum Mode {
+    Run,
+    Tokens,
+    AstJson,
+}
+
+// What `flux_compiler --no-init [--vm [--opt=inline]] <flux-args> program` would
+// print: its stdout, then its stderr. Each run gets a thread of its own, sized
+// like the CLI's, so thread-local state (the log level, the random seed) starts
+// fresh and a panic is reported rather than ending the whole corpus.
+fn run_program(program: &Path, engine: Engine, inline: bool) -> Result<String, String> {
+    // Lossy, so programs that aren't valid UTF-8 still reach the runtime (which reports them)
+    let bytes = fs::read(program).map_err(|e| e.to_string())?;
+    let source = String::from_utf8_lossy(&bytes);
+    let extra = source.lines().next().and_then(|line| line.strip_prefix("// flux-args:")).unwrap_or("");
+    let mut args = extra.split_whitespace();
+    let (mut config, mut edition, mut mode) = (RuntimeConfig::default(), None, Mode::Run);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("flux-args: {} needs a value", arg));
+        let number = |value: &str| value.parse::<u64>().map_err(|_| format!("flux-args: {} needs a number", arg));
+        match arg {
+            "--ast" if value()? == "json" => mode = Mode::AstJson,
+            "--tokens" => mode = Mode::Tokens,
+            "--edition" => edition = Some(Edition::from_name(value()?).ok_or("flux-args: unknown edition")?),
+            "--fuel" => config.fuel = Some(number(value()?)?),
+            "--max-module-size" => config.max_module_size = number(value()?)?,
+            "--max-errors" => config.max_parse_errors = number(value()?)? as usize,
+            _ => return Err(format!("flux-args: the corpus runner doesn't support {}", arg)),
+        }
+    }
+
+    let path = program.to_path_buf();
+    let worker = thread::Builder::new()
+        .stack_size(config.native_stack_size())
+        .spawn(move || {
+            let (output, errors) = (Capture::new(), Capture::new());
+            let io = Io { input: Box::new(io::empty()), output: Box::new(output.clone()), errors: Box::new(errors.clone()) };
+            let mut runtime = Runtime::new().with_config(config).with_engine(engine).with_io(io);
+            #[cfg(feature = "vm")]
+            { runtime = runtime.with_inlining(inline); }
+            #[cfg(not(feature = "vm"))]
+            let _ = inline;
+            if let Some(edition) = edition {
+                runtime = runtime.with_edition(edition);
+            }
+            let mut text = String::new();
+            match mode {
+                Mode::Run => match runtime.run_file(&path) {
+                    Ok(Object::Null) => {},
+                    Ok(result) => { let _ = writeln!(text, "{}", result); },
+                    Err(e) => { let _ = writeln!(text, "{}", e); },
+                },
+                Mode::Tokens => match source::read(&path) {
+                    Ok(source) => text.push_str(&Lexer::dump(&source)),
+                    Err(e) => { let _ = writeln!(text, "Error reading file {}: {}", path.display(), e); },
+                },
+                Mode::AstJson => match source::read(&path).map_err(|e| format!("Error reading file {}: {}", path.display(), e)) {
+                    Ok(source) => match runtime.parse(&source) {
+                        Ok(program) => { let _ = writeln!(text, "{}", ast::to_json(&program)); },
+                        Err(e) => { let _ = writeln!(text, "{}", e); },
+                    },
+                    Err(msg) => { let _ = writeln!(text, "{}", msg); },
+                },
+            }
+            format!("{}{}{}", output.text(), text, errors.text())
+        })
+        .map_err(|e| format!("can't start a thread to run it: {}", e))?;
+    worker.join().map_err(|_| "it panicked".to_string())
+}
+
+// The names in dir/interpreter_only.txt; no file means none
+fn read_interpreter_only(dir: &Path) -> Result<Vec<String>, std::io::Error> {
+    let text = match fs::read_to_string(dir.join(INTERPRETER_ONLY)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    Ok(text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+// A line diff from the longest common subsequence: "-" only in `old`, "+" only in `new`
+fn print_diff(old: &str, new: &str) {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    // lcs[i][j] = length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            println!("      {}", a[i]);
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            println!("    + {}", b[j]);
+            j += 1;
+        } else {
+            println!("    - {}", a[i]);
+            i += 1;
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Where an error came from. Both engines note the span of the operation that
+// failed (an infix, prefix, call or index expression, a variable read, an
+// assignment, a for-in's iterable or a match) the moment it fails, and the
+// compiler notes the name it rejects; run_file keeps the script's source and,
+// when the run ends in that same error, shows the line with the operation
+// underlined:
+//
+//     ERROR: Type mismatch
+//      --> line 2:14
+//       |
+//     2 | mut total = (1 + "two") * 3
+//       |              ^^^^^^^^^
+//
+// Only code of the script itself is noted. An error inside an imported module
+// is pinned on the call in the script that led to it.
+use std::cell::RefCell;
+use crate::token::Span;
+
+thread_local! {
+    // The first failure noted during this run, with its message
+    static FAILURE: RefCell<Option<(Span, String)>> = const { RefCell::new(None) };
+}
+
+// Forget the previous run's failure
+pub fn clear() {
+    FAILURE.with(|f| *f.borrow_mut() = None);
+}
+
+// `span` failed with `message`. The innermost failure is noted first, and the
+// expressions it propagates through leave it alone.
+pub fn note(span: Span, message: &str) {
+    FAILURE.with(|f| {
+        let mut failure = f.borrow_mut();
+        if failure.is_none() {
+            *failure = Some((span, message.to_string()));
+        }
+    });
+}
+
+// Where the run's error `message` came from, if it was noted. A failure that was
+// caught and replaced by a different error doesn't count.
+pub fn take(message: &str) -> Option<Span> {
+    FAILURE.with(|f| match f.borrow_mut().take() {
+        Some((span, noted)) if noted == message => Some(span),
+        _ => None,
+    })
+}
+
+// The line `span` starts on, with the span underlined. A span that runs onto
+// later lines is underlined to the end of its first one.
+pub fn excerpt(source: &str, span: Span) -> Option<String> {
+    let start = span.start.min(source.len());
+    if !source.is_char_boundary(start) { return None; }
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+    let text = source[line_start..line_end].trim_end_matches('\r');
+    let end = span.end.clamp(start, line_start + text.len());
+    if !source.is_char_boundary(end) { return None; }
+
+    let line = source[..start].matches('\n').count() + 1;
+    let before = &source[line_start..start];
+    // Tabs stay tabs so the underline lines up however they are displayed
+    let indent: String = before.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let width = source[start..end].chars().count().max(1);
+    let gutter = " ".repeat(line.to_string().len());
+    Some(format!(
+        "{}--> line {}:{}\n{} |\n{} | {}\n{} | {}{}",
+        gutter, line, before.chars().count() + 1,
+        gutter,
+        line, text,
+        gutter, indent, "^".repeat(width),
+    ))
+}
@@ -1,50 +1,249 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use crate::object::{Object, HashKey};
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+use crate::object::{Object, HashKey, HashObject};
+use crate::intern::Symbol;
+use crate::stats;
 
-#[derive(Debug, PartialEq, Clone)]
+// An Environment is a cheap, shared handle: cloning it (e.g. when a function
+// literal captures its scope) points at the SAME variables, so a function can
+// see bindings made after it was created. This is what makes recursion work.
+//
+// It is also a reference cycle: a function bound in the scope it captured
+// keeps that scope alive, and the scope keeps the function. Rc never frees
+// such a pair. A weak link from the function can't replace the strong one,
+// since a closure returned from a call must keep the call's scope alive
+// once nothing else does. Instead a call's scope is released when the call
+// returns (see release): if only its own functions still hold it, it is
+// cleared, which breaks the cycle. A Runtime clears its top-level scope when
+// dropped (see clear). A scope whose function was handed out still outlives
+// that function.
+#[derive(Clone, Default)]
 pub struct Environment {
-    store: HashMap<String, Object>,
+    store: Rc<Store>,
     outer: Option<Box<Environment>>,
+    // Path of the module whose code runs in this scope (None for the main program).
+    // Enclosed scopes inherit it, so every function a module defines remembers its file.
+    module: Option<Rc<str>>,
+    // The shared builtin scope: assignments never land in it (see assign)
+    frozen: bool,
+}
+
+thread_local! {
+    static LIVE_SCOPES: Cell<usize> = const { Cell::new(0) };
+}
+
+// The variables of one scope. Counted while alive, so a test can tell a leak.
+struct Store(RefCell<HashMap<Symbol, Object>>);
+
+impl Store {
+    fn new() -> Rc<Store> {
+        LIVE_SCOPES.with(|live| live.set(live.get() + 1));
+        Rc::new(Store(RefCell::new(HashMap::new())))
+    }
+}
+
+impl Default for Store {
+    fn default() -> Store {
+        LIVE_SCOPES.with(|live| live.set(live.get() + 1));
+        Store(RefCell::new(HashMap::new()))
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        LIVE_SCOPES.with(|live| live.set(live.get() - 1));
+    }
+}
+
+impl Deref for Store {
+    type Target = RefCell<HashMap<Symbol, Object>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// How many scopes this thread has that haven't been freed
+pub fn live_scopes() -> usize {
+    LIVE_SCOPES.with(|live| live.get())
 }
 
 impl Environment {
     pub fn new() -> Environment {
         Environment {
-            store: HashMap::new(),
+            store: Store::new(),
             outer: None,
+            module: None,
+            frozen: false,
+        }
+    }
+
+    // This scope, closed to assignment from now on
+    pub fn freeze(mut self) -> Environment {
+        self.frozen = true;
+        self
+    }
+
+    // The top-level scope of a module being imported from `path`, inside `outer`
+    // (the shared builtin scope, so the module's own bindings are its exports)
+    pub fn new_module(path: &str, outer: Environment) -> Environment {
+        Environment {
+            store: Store::new(),
+            outer: Some(Box::new(outer)),
+            module: Some(Rc::from(path)),
+            frozen: false,
         }
     }
 
     pub fn new_enclosed(outer: Environment) -> Environment {
         Environment {
-            store: HashMap::new(),
+            store: Store::new(),
+            module: outer.module.clone(),
             outer: Some(Box::new(outer)),
+            frozen: false,
         }
     }
 
-    pub fn get(&self, name: &str) -> Option<Object> {
-        match self.store.get(name) {
-            Some(obj) => Some(obj.clone()),
-            None => match &self.outer {
-                Some(outer) => outer.get(name),
-                None => None,
-            },
+    pub fn module(&self) -> Option<Rc<str>> {
+        self.module.clone()
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Object> {
+        stats::env_get();
+        let mut scope = self;
+        loop {
+            if let Some(obj) = scope.store.borrow().get(&name) {
+                return Some(stats::cloned(obj));
+            }
+            scope = scope.outer.as_deref()?;
         }
     }
 
-    pub fn set(&mut self, name: String, val: Object) -> Object {
-        self.store.insert(name, val.clone());
+    pub fn set(&mut self, name: Symbol, val: Object) -> Object {
+        stats::env_set();
+        self.store.borrow_mut().insert(name, val.clone());
         val
     }
 
-    // NEW: Convert the Environment into a Hash Object
-    // This allows us to return a "Module" as a simple HashMap of variables
-    pub fn to_hash(&self) -> Object {
+    // `name = val`: rebinds `name` in the innermost scope that has it. False,
+    // changing nothing, when no scope does (a frozen one doesn't count).
+    pub fn assign(&mut self, name: Symbol, val: Object) -> bool {
+        stats::env_set();
+        let mut scope: &Environment = self;
+        loop {
+            if scope.frozen { return false; }
+            if let Some(slot) = scope.store.borrow_mut().get_mut(&name) {
+                *slot = val;
+                return true;
+            }
+            match scope.outer.as_deref() {
+                Some(outer) => scope = outer,
+                None => return false,
+            }
+        }
+    }
+
+    // The binding of `name` in this scope itself, ignoring outer ones
+    pub fn get_local(&self, name: Symbol) -> Option<Object> {
+        self.store.borrow().get(&name).cloned()
+    }
+
+    pub fn remove(&mut self, name: Symbol) {
+        self.store.borrow_mut().remove(&name);
+    }
+
+    // Drop every binding of this scope itself, breaking the cycles through
+    // the functions defined in it. Functions still holding it see it empty.
+    pub fn clear(&mut self) {
+        self.store.borrow_mut().clear();
+    }
+
+    // A call's scope, once the call has returned. The functions it bound hold
+    // it (a cycle); when they are the only ones that do, nothing else can
+    // reach it again, so it is cleared for Rc to free. The count errs low
+    // (iterators and memoize caches aren't looked into), so a scope that is
+    // still reachable is never cleared; at worst one that isn't is kept.
+    pub fn release(&mut self) {
+        let held = Rc::strong_count(&self.store);
+        if held == 1 { return; }
+        let mut seen = vec![Rc::as_ptr(&self.store)];
+        let internal: usize = self.store.borrow().values().map(|value| references_in(value, &self.store, &mut seen)).sum();
+        if internal == held - 1 {
+            self.clear();
+        }
+    }
+
+    // The handles to `target` in this handle's chain of scopes: one if the
+    // chain passes through it, plus those in the values of the scopes
+    // nested inside it on the way. `seen` keeps a scope from being counted twice.
+    fn references_to(&self, target: &Rc<Store>, seen: &mut Vec<*const Store>) -> usize {
+        let mut nested = vec![];
+        let mut scope = self;
+        while !Rc::ptr_eq(&scope.store, target) {
+            nested.push(&scope.store);
+            match scope.outer.as_deref() {
+                Some(outer) => scope = outer,
+                None => return 0,
+            }
+        }
+        let mut count = 1;
+        for store in nested {
+            if seen.contains(&Rc::as_ptr(store)) { continue; }
+            seen.push(Rc::as_ptr(store));
+            count += store.borrow().values().map(|value| references_in(value, target, seen)).sum::<usize>();
+        }
+        count
+    }
+
+    // The variables of this scope itself, by name
+    pub fn locals(&self) -> Vec<(Symbol, Object)> {
+        let mut locals: Vec<(Symbol, Object)> = self.store.borrow().iter().map(|(name, value)| (*name, value.clone())).collect();
+        locals.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        locals
+    }
+
+    // The variables of this scope as a hash, keyed by name (a module's exports)
+    pub fn to_hash(&self) -> HashObject {
         let mut pairs = HashMap::new();
-        for (key, value) in &self.store {
-            let hash_key = HashKey::String(key.clone());
+        for (key, value) in self.store.borrow().iter() {
+            let hash_key = HashKey::String(key.to_string());
             pairs.insert(hash_key, value.clone());
         }
-        Object::Hash(pairs)
+        HashObject::new(pairs)
     }
-}
\ No newline at end of file
+}
+
+// The handles to `target` held by `value` (see Environment::references_to)
+fn references_in(value: &Object, target: &Rc<Store>, seen: &mut Vec<*const Store>) -> usize {
+    match value {
+        Object::Function { env, .. } => env.references_to(target, seen),
+        Object::Memoized { function, .. } => references_in(function, target, seen),
+        Object::Array(items) => items.iter().map(|item| references_in(item, target, seen)).sum(),
+        Object::Hash(hash) | Object::Module { exports: hash, .. } | Object::Record { fields: hash, .. } => {
+            let default = hash.default.as_deref().map_or(0, |default| references_in(default, target, seen));
+            default + hash.pairs.values().map(|item| references_in(item, target, seen)).sum::<usize>()
+        },
+        _ => 0,
+    }
+}
+
+// Two handles are equal when they share the same scope.
+// (Comparing contents could recurse forever: a function lives in the env it captured.)
+impl PartialEq for Environment {
+    fn eq(&self, other: &Environment) -> bool {
+        Rc::ptr_eq(&self.store, &other.store)
+    }
+}
+
+// Only the variable names, for the same reason as PartialEq
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let store = self.store.borrow();
+        let mut names: Vec<&str> = store.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        write!(f, "Environment {:?}", names)
+    }
+}
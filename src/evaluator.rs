@@ -1,70 +1,232 @@
-use crate::ast::{Statement, Expression, BlockStatement};
-use crate::object::{Object, HashKey};
+use crate::ast::{Statement, Expression, BlockStatement, MatchArm, Pattern};
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::object::{self, Object, IterState, HashKey, HashObject, integer_add, integer_divide, integer_multiply, integer_negate, integer_power, integer_subtract, hash_key_to_object};
 use crate::environment::Environment;
+use crate::intern::Symbol;
+use crate::ast_lower;
+use crate::config;
+use crate::diagnostics;
+use crate::stats;
+use crate::token::Span;
+
+thread_local! {
+    // The functions currently being called (and their module, if any), outermost first
+    static CALL_STACK: RefCell<Vec<(Symbol, Option<Rc<str>>)>> = const { RefCell::new(Vec::new()) };
+    // Blocks deferred by each running function (and program), in the order they were deferred
+    static DEFERRED: RefCell<Vec<Vec<(BlockStatement, Environment)>>> = const { RefCell::new(Vec::new()) };
+    // The scope of each builtin call in progress, innermost last
+    static CALLER_SCOPES: RefCell<Vec<Environment>> = const { RefCell::new(Vec::new()) };
+}
+
+// The scope the running builtin was called from (None when the VM called it)
+pub fn caller_scope() -> Option<Environment> {
+    CALLER_SCOPES.with(|scopes| scopes.borrow().last().cloned())
+}
 
 pub fn eval_program(program: &[Statement], env: &mut Environment) -> Object {
-    let mut result = Object::Null;
-    for stmt in program {
-        result = eval_statement(stmt, env);
-        if let Object::Return(val) = result { return *val; }
-        if let Object::Error(_) = result { return result; }
+    let program = ast_lower::lower(program.to_vec());
+    hoist_functions(&program, env);
+    with_deferred(&mut || {
+        let mut result = Object::Null;
+        for stmt in &program {
+            if let Err(e) = config::check_cancelled() { return Object::Error(e); }
+            result = eval_statement(stmt, env);
+            if let Object::Return(val) = result { return *val; }
+            if let Object::Error(_) = result { return result; }
+        }
+        result
+    })
+}
+
+// Runs `body` (a function's or a program's) and then whatever it deferred, last
+// deferred first, however it finished. An error leaves only after the deferred
+// blocks have run. A deferred block's value is dropped, but its error replaces
+// a result that wasn't already an error.
+fn with_deferred(body: &mut dyn FnMut() -> Object) -> Object {
+    DEFERRED.with(|d| d.borrow_mut().push(vec![]));
+    let mut result = body();
+    let deferred = DEFERRED.with(|d| d.borrow_mut().pop()).unwrap_or_default();
+    for (block, mut env) in deferred.into_iter().rev() {
+        // Its own defers run when it finishes, like a function's
+        let outcome = with_deferred(&mut || eval_block(&block, &mut env));
+        if is_error(&outcome) && !is_error(&result) {
+            result = outcome;
+        }
     }
     result
 }
 
+// Bind top-level functions before anything runs, so a call may appear above
+// the definition and mutually recursive functions can find each other.
+// The definitions still execute in order later, rebinding the same function.
+fn hoist_functions(program: &[Statement], env: &mut Environment) {
+    for stmt in program {
+        if let Statement::Let { name, value: Expression::FunctionLiteral { parameters, variadic, body } } = stmt {
+            let func = Object::Function { parameters: parameters.clone(), variadic: *variadic, body: body.clone(), env: env.clone() };
+            env.set(*name, func);
+        }
+    }
+}
+
 fn eval_statement(stmt: &Statement, env: &mut Environment) -> Object {
     match stmt {
         Statement::Expression(exp) => eval(exp, env),
         Statement::Return(val) => {
-            let value = eval(val, env);
+            let value = match val {
+                Some(val) => eval(val, env),
+                None => Object::Null,
+            };
             if is_error(&value) { return value; }
             Object::Return(Box::new(value))
         },
         Statement::Let { name, value } => {
             let val = eval(value, env);
             if is_error(&val) { return val; }
-            env.set(name.clone(), val);
+            env.set(*name, val);
             Object::Null
         },
-        // NEW: Handle Assignment
-        Statement::Assign { name, value } => {
+        Statement::LetDestructure { names, value, span } => {
+            let val = eval(value, env);
+            if is_error(&val) { return val; }
+            match destructure(names, val) {
+                Ok(bindings) => {
+                    for (name, val) in bindings { env.set(name, val); }
+                    Object::Null
+                },
+                Err(err) => noted(err, *span, env),
+            }
+        },
+        // `x = value` changes the nearest `mut x`, which may be outside the function
+        Statement::Assign { name, value, span } => {
             let val = eval(value, env);
             if is_error(&val) { return val; }
-            // Note: We currently just overwrite. 
-            // A safer language would check if 'name' exists first!
-            env.set(name.clone(), val); 
+            if env.assign(*name, val) { return Object::Null; }
+            let msg = format!("Cannot assign to undeclared variable '{}' (declare it with `mut {} = ...`)", name, name);
+            noted(Object::Error(msg), *span, env)
+        },
+        Statement::IndexAssign { left, index, value, span } => noted(assign_index(left, index, value, env), *span, env),
+        Statement::Record { kind, name, fields } => {
+            let mut pairs = std::collections::HashMap::new();
+            for (field, value) in fields {
+                let val = eval(value, env);
+                if is_error(&val) { return val; }
+                pairs.insert(HashKey::String(field.to_string()), val);
+            }
+            env.set(*name, Object::Record { kind: *kind, name: name.to_string(), fields: HashObject::new(pairs) });
             Object::Null
         },
-        _ => Object::Null,
+        Statement::Defer(body) => DEFERRED.with(|d| match d.borrow_mut().last_mut() {
+            Some(frame) => {
+                frame.push((body.clone(), env.clone()));
+                Object::Null
+            },
+            None => Object::Error("defer outside of a function".to_string()),
+        }),
+        // The parser only allows these inside a loop, which stops them
+        Statement::Break(value) => {
+            let value = match value {
+                Some(value) => eval(value, env),
+                None => Object::Null,
+            };
+            if is_error(&value) { return value; }
+            Object::Break(Box::new(value))
+        },
+        Statement::Continue => Object::Continue,
+        Statement::CompoundAssign { .. } | Statement::Function { .. } => unreachable!("lowered by ast_lower"),
+    }
+}
+
+// Each name with its element; extra elements are left out
+fn destructure(names: &[Symbol], value: Object) -> Result<Vec<(Symbol, Object)>, Object> {
+    match value {
+        Object::Array(elements) if elements.len() >= names.len() => Ok(names.iter().copied().zip(elements).collect()),
+        Object::Array(elements) => Err(Object::Error(format!(
+            "Can't destructure: expected at least {} elements, got {}", names.len(), elements.len()
+        ))),
+        other => Err(Object::Error(format!(
+            "Can't destructure {}: expected an array of at least {} elements", other.type_name(), names.len()
+        ))),
+    }
+}
+
+// Containers are values, so `a[i][j] = v` rebuilds a[i] with the new element
+// and then stores that back into `a`, all the way up to the variable.
+// Like everything else this runs left to right: the indexes, then the value.
+fn assign_index(left: &Expression, index: &Expression, value: &Expression, env: &mut Environment) -> Object {
+    let mut path = vec![];
+    let name = match index_path(left, &mut path) {
+        Some(name) => name,
+        None => return Object::Error(format!("Cannot assign to an index of {}", left)),
+    };
+    path.push(index);
+
+    let mut keys = vec![];
+    for key_node in path {
+        let key = eval(key_node, env);
+        if is_error(&key) { return key; }
+        keys.push(key);
+    }
+    let val = eval(value, env);
+    if is_error(&val) { return val; }
+
+    let container = match env.get(name) {
+        Some(obj) => obj,
+        None => return Object::Error(format!("Variable '{}' not found", name)),
+    };
+    // The update goes to the scope the variable lives in, as `name = value` does
+    let updated = match object::set_index(container, &keys, val) {
+        Ok(updated) => updated,
+        Err(e) => return e,
+    };
+    if env.assign(name, updated) { return Object::Null; }
+    Object::Error(format!("Cannot assign to undeclared variable '{}' (declare it with `mut {} = ...`)", name, name))
+}
+
+// The variable at the root of `a[i][j]`, collecting [i, j] on the way
+fn index_path<'a>(target: &'a Expression, path: &mut Vec<&'a Expression>) -> Option<Symbol> {
+    match target {
+        Expression::Identifier { name, .. } => Some(*name),
+        Expression::IndexExpression { left, index, .. } => {
+            let name = index_path(left, path)?;
+            path.push(index);
+            Some(name)
+        },
+        _ => None,
     }
 }
 
 // MAKE THIS PUBLIC or accessible within the file
 fn eval(node: &Expression, env: &mut Environment) -> Object {
+    stats::node(node.kind());
     match node {
-        Expression::IntegerLiteral(i) => Object::Integer(*i),
+        Expression::IntegerLiteral { value, .. } => Object::Integer(*value),
+        Expression::FloatLiteral(value) => Object::Float(*value),
         Expression::Boolean(b) => Object::Boolean(*b),
         Expression::StringLiteral(s) => Object::String(s.clone()),
-        Expression::Prefix { operator, right } => {
+        Expression::CharLiteral(c) => Object::Char(*c),
+        Expression::Prefix { operator, right, span } => {
             let right_val = eval(right, env);
             if is_error(&right_val) { return right_val; }
-            eval_prefix(operator, right_val)
+            noted(eval_prefix(operator, right_val), *span, env)
         },
-        Expression::Infix { left, operator, right } => {
+        Expression::Infix { left, operator, right, span } => {
             let left_val = eval(left, env);
             if is_error(&left_val) { return left_val; }
             let right_val = eval(right, env);
             if is_error(&right_val) { return right_val; }
-            eval_infix(operator, left_val, right_val)
+            noted(eval_infix(operator, left_val, right_val), *span, env)
         },
-        Expression::Identifier(name) => match env.get(name) {
+        Expression::Identifier { name, span } => match env.get(*name) {
             Some(obj) => obj,
-            None => Object::Error(format!("Variable '{}' not found", name)),
+            None => noted(Object::Error(format!("Variable '{}' not found", name)), *span, env),
         },
         Expression::If { condition, consequence, alternative } => {
-            let cond = eval(condition, env);
-            if is_error(&cond) { return cond; }
-            if is_truthy(&cond) {
+            let truthy = match eval_condition(condition, env) {
+                Ok(truthy) => truthy,
+                Err(error) => return error,
+            };
+            if truthy {
                 eval_block(consequence, env)
             } else if let Some(alt) = alternative {
                 eval_block(alt, env)
@@ -75,24 +237,55 @@ fn eval(node: &Expression, env: &mut Environment) -> Object {
         Expression::While { condition, body } => {
             let mut result = Object::Null;
             loop {
-                let cond = eval(condition, env);
-                if is_error(&cond) { return cond; }
-                if !is_truthy(&cond) { break; }
-                result = eval_block(body, env);
-                if let Object::Return(_) = result { return result; }
+                match eval_condition(condition, env) {
+                    Ok(true) => {},
+                    Ok(false) => break,
+                    Err(error) => return error,
+                }
+                if let Err(e) = config::burn_fuel() { return Object::Error(e); }
+                result = match eval_loop_body(body, env) {
+                    Ok(value) => value,
+                    Err(exit) => return exit,
+                };
             }
             result
         },
-        Expression::FunctionLiteral { parameters, body } => {
-            Object::Function { parameters: parameters.clone(), body: body.clone(), env: env.clone() }
+        Expression::For { init, condition, post, body } => {
+            let declared: Vec<Symbol> = match init.as_deref() {
+                Some(Statement::Let { name, .. }) => vec![*name],
+                Some(Statement::LetDestructure { names, .. }) => names.clone(),
+                _ => vec![],
+            };
+            loop_scoped(&declared, env, |env| eval_for(init.as_deref(), condition.as_deref(), post.as_deref(), body, env))
+        },
+        Expression::ForIn { variable, value, iterable, body, span } => {
+            let names: Vec<Symbol> = std::iter::once(*variable).chain(*value).collect();
+            loop_scoped(&names, env, |env| eval_for_in(*variable, *value, iterable, *span, body, env))
+        },
+        Expression::FunctionLiteral { parameters, variadic, body } => {
+            Object::Function { parameters: parameters.clone(), variadic: *variadic, body: body.clone(), env: env.clone() }
         },
         // CORRECT: Matches Call (not CallExpression)
-        Expression::Call { function, arguments } => {
+        Expression::Call { function, arguments, span } => {
             let func = eval(function, env);
             if is_error(&func) { return func; }
             let args = eval_expressions(arguments, env);
             if args.len() == 1 && is_error(&args[0]) { return args[0].clone(); }
-            apply_function(func, args)
+            let name = match function.as_ref() {
+                Expression::Identifier { name, .. } => *name,
+                // module.name(...) or module["name"](...)
+                Expression::Member { property, .. } => *property,
+                Expression::IndexExpression { index, .. } => match index.as_ref() {
+                    Expression::StringLiteral(key) => Symbol::intern(key),
+                    _ => Symbol::intern("<anonymous>"),
+                },
+                _ => Symbol::intern("<anonymous>"),
+            };
+            let builtin = matches!(func, Object::Builtin(_));
+            if builtin { CALLER_SCOPES.with(|scopes| scopes.borrow_mut().push(env.clone())); }
+            let result = call_function(name, func, args);
+            if builtin { CALLER_SCOPES.with(|scopes| scopes.borrow_mut().pop()); }
+            noted(result, *span, env)
         },
         // CORRECT: Matches Tuple Variant
         Expression::ArrayLiteral(elements) => {
@@ -100,15 +293,102 @@ fn eval(node: &Expression, env: &mut Environment) -> Object {
             if elems.len() == 1 && is_error(&elems[0]) { return elems[0].clone(); }
             Object::Array(elems)
         },
-        Expression::IndexExpression { left, index } => {
+        Expression::IndexExpression { left, index, span } => {
             let l = eval(left, env);
             if is_error(&l) { return l; }
             let i = eval(index, env);
             if is_error(&i) { return i; }
-            eval_index(l, i)
+            noted(object::index(l, i), *span, env)
+        },
+        Expression::Member { object, property, span } => {
+            let value = eval(object, env);
+            if is_error(&value) { return value; }
+            noted(eval_member(value, *property), *span, env)
+        },
+        Expression::Range { start, end, inclusive, span } => {
+            let first = eval(start, env);
+            if is_error(&first) { return first; }
+            let last = eval(end, env);
+            if is_error(&last) { return last; }
+            noted(eval_range(first, last, *inclusive), *span, env)
         },
         // NEW: Hash Map
         Expression::HashLiteral(node) => eval_hash_literal(node, env),
+        // Bindings made inside stay inside; assignments still reach outer variables
+        Expression::Block(block) => eval_block(block, &mut Environment::new_enclosed(env.clone())),
+        Expression::Match { subject, arms, span } => {
+            let value = eval(subject, env);
+            if is_error(&value) { return value; }
+            eval_match(value, arms, *span, env)
+        },
+    }
+}
+
+// Try each arm in order; the first whose pattern (and guard) accepts the value wins.
+// Bindings live in an enclosed scope so they don't leak past the arm.
+fn eval_match(value: Object, arms: &[MatchArm], span: Span, env: &mut Environment) -> Object {
+    for arm in arms {
+        let mut bindings = vec![];
+        if !match_pattern(&arm.pattern, &value, &mut bindings, env) { continue; }
+
+        let mut scope = Environment::new_enclosed(env.clone());
+        for (name, val) in bindings { scope.set(name, val); }
+
+        if let Some(guard) = &arm.guard {
+            match eval_condition(guard, &mut scope) {
+                Ok(true) => {},
+                Ok(false) => continue,
+                Err(error) => return error,
+            }
+        }
+        return eval_block(&arm.body, &mut scope);
+    }
+    noted(Object::Error(format!("No match arm for value: {}", value)), span, env)
+}
+
+fn match_pattern(pattern: &Pattern, value: &Object, bindings: &mut Vec<(Symbol, Object)>, env: &mut Environment) -> bool {
+    match pattern {
+        Pattern::Wildcard => true,
+        Pattern::Binding(name) => {
+            bindings.push((*name, value.clone()));
+            true
+        },
+        Pattern::Literal(lit) => object::objects_equal(&eval(lit, env), value),
+        Pattern::Array { elements, rest } => {
+            let items = match value {
+                Object::Array(items) => items,
+                _ => return false,
+            };
+            let fits = match rest {
+                Some(_) => items.len() >= elements.len(),
+                None => items.len() == elements.len(),
+            };
+            if !fits { return false; }
+            for (pat, item) in elements.iter().zip(items.iter()) {
+                if !match_pattern(pat, item, bindings, env) { return false; }
+            }
+            if let Some(name) = rest {
+                bindings.push((*name, Object::Array(items[elements.len()..].to_vec())));
+            }
+            true
+        },
+        Pattern::Hash(pairs) => {
+            let map = match value.as_hash() {
+                Some(hash) => &hash.pairs,
+                None => return false,
+            };
+            for (key_node, pat) in pairs {
+                let key = match crate::object::get_hash_key(&eval(key_node, env)) {
+                    Some(k) => k,
+                    None => return false,
+                };
+                match map.get(&key) {
+                    Some(item) => if !match_pattern(pat, item, bindings, env) { return false; },
+                    None => return false,
+                }
+            }
+            true
+        },
     }
 }
 
@@ -125,7 +405,7 @@ fn eval_hash_literal(node: &crate::ast::HashLiteral, env: &mut Environment) -> O
         if is_error(&value) { return value; }
         pairs.insert(hash_key, value);
     }
-    Object::Hash(pairs)
+    Object::Hash(HashObject::new(pairs))
 }
 
 fn eval_expressions(exps: &[Expression], env: &mut Environment) -> Vec<Object> {
@@ -141,94 +421,387 @@ fn eval_expressions(exps: &[Expression], env: &mut Environment) -> Vec<Object> {
 fn eval_block(block: &BlockStatement, env: &mut Environment) -> Object {
     let mut result = Object::Null;
     for stmt in &block.statements {
+        if let Err(e) = config::check_cancelled() { return Object::Error(e); }
         result = eval_statement(stmt, env);
-        if let Object::Return(_) = result { return result; }
-        if let Object::Error(_) = result { return result; }
+        if matches!(result, Object::Return(_) | Object::Error(_) | Object::Break(_) | Object::Continue) { return result; }
     }
     result
 }
 
-fn eval_index(left: Object, index: Object) -> Object {
-    match (left, index) {
-        (Object::Array(arr), Object::Integer(idx)) => {
-            if idx < 0 || idx >= arr.len() as i64 { return Object::Null; }
-            arr[idx as usize].clone()
-        },
-        (Object::Hash(pairs), index_obj) => {
-            match crate::object::get_hash_key(&index_obj) {
-                Some(key) => match pairs.get(&key) {
-                    Some(obj) => obj.clone(),
-                    None => Object::Null,
-                },
-                None => Object::Error(format!("Unusable as hash key: {}", index_obj)),
-            }
+// One run of a loop's body: Ok with its value to carry on (`continue` included),
+// or Err with what the whole loop finishes with. `break` ends the loop with its
+// value; a return or an error leaves it and goes on up.
+fn eval_loop_body(body: &BlockStatement, env: &mut Environment) -> Result<Object, Object> {
+    match eval_block(body, env) {
+        Object::Break(value) => Err(*value),
+        Object::Continue => Ok(Object::Null),
+        result @ (Object::Return(_) | Object::Error(_)) => Err(result),
+        result => Ok(result),
+    }
+}
+
+// More elements than this is almost certainly a mistake, and would take
+// hundreds of megabytes to build
+const MAX_RANGE_LEN: i128 = 1_000_000;
+
+// The integers from `start` up to `end` (and including it if `inclusive`);
+// empty when the range runs backwards
+fn eval_range(start: Object, end: Object, inclusive: bool) -> Object {
+    let (first, last) = match (&start, &end) {
+        (Object::Integer(first), Object::Integer(last)) => (*first, *last),
+        _ => return Object::Error(format!("Range bounds must be integers, got {}..{}", start.type_name(), end.type_name())),
+    };
+    let dots = if inclusive { "..=" } else { ".." };
+    let len = (last as i128 - first as i128 + i128::from(inclusive)).max(0);
+    if len > MAX_RANGE_LEN {
+        return Object::Error(format!(
+            "Range too large: {}{}{} has {} elements, over the limit of {}", first, dots, last, len, MAX_RANGE_LEN
+        ));
+    }
+    let values = if inclusive { (first..=last).map(Object::Integer).collect() } else { (first..last).map(Object::Integer).collect() };
+    Object::Array(values)
+}
+
+// `value.property` reads the string key "property", missing keys giving null as with `[]`
+fn eval_member(value: Object, property: Symbol) -> Object {
+    match value {
+        Object::Hash(hash) | Object::Module { exports: hash, .. } | Object::Record { fields: hash, .. } => {
+            hash.lookup(&HashKey::String(property.as_str().to_string()))
         },
-        _ => Object::Error("Index operator not supported".to_string()),
+        other => Object::Error(format!(
+            "Can't read field '{}' of {}: only hashes, modules and records have fields", property, other.type_name()
+        )),
     }
 }
 
-fn apply_function(func: Object, args: Vec<Object>) -> Object {
+// A call from Flux code: tracked on CALL_STACK so a too-deep recursion
+// can say which functions were involved. An error leaving a function that
+// a module defined gets a trace line naming the module's file.
+fn call_function(name: Symbol, func: Object, args: Vec<Object>) -> Object {
+    if let Err(e) = config::burn_fuel() { return Object::Error(e); }
+    let limit = config::current().max_call_depth;
+    let module = defining_module(&func);
+    let overflow = CALL_STACK.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        stack.push((name, module.clone()));
+        (stack.len() > limit).then(|| {
+            let calls: Vec<String> = stack.iter().map(|(name, module)| frame_name(*name, module)).collect();
+            config::stack_overflow_message(&calls)
+        })
+    });
+    let result = match overflow {
+        Some(msg) => Object::Error(msg),
+        None => apply_function(func, args),
+    };
+    CALL_STACK.with(|stack| stack.borrow_mut().pop());
+    match result {
+        Object::Error(msg) if module.is_some() => Object::Error(format!("{}\n    at {}", msg, frame_name(name, &module))),
+        result => result,
+    }
+}
+
+// The file of the module a function was defined in (None for the main program)
+fn defining_module(func: &Object) -> Option<Rc<str>> {
     match func {
-        Object::Function { parameters, body, env } => {
+        Object::Function { env, .. } => env.module(),
+        Object::Memoized { function, .. } => defining_module(function),
+        _ => None,
+    }
+}
+
+// "area (modules/geometry.flux)" for module functions, just the name otherwise
+fn frame_name(name: Symbol, module: &Option<Rc<str>>) -> String {
+    match module {
+        Some(path) => format!("{} ({})", name, path),
+        None => name.to_string(),
+    }
+}
+
+// Also how builtins call back into Flux functions (sort_by, map, ...)
+pub fn apply_function(func: Object, args: Vec<Object>) -> Object {
+    match func {
+        Object::Function { mut parameters, variadic, body, env } => {
             let mut enclosed = crate::environment::Environment::new_enclosed(env);
+            let mut args = args;
+            // The rest parameter gets whatever the others leave, possibly nothing
+            if variadic && let Some(rest) = parameters.pop() {
+                let extra = args.split_off(parameters.len().min(args.len()));
+                enclosed.set(rest, Object::Array(extra));
+            }
             for (param, arg) in parameters.iter().zip(args.iter()) {
-                enclosed.set(param.clone(), arg.clone());
+                enclosed.set(*param, arg.clone());
             }
-            let result = eval_block(&body, &mut enclosed);
+            let result = with_deferred(&mut || eval_block(&body, &mut enclosed));
+            enclosed.release();
             if let Object::Return(val) = result { *val } else { result }
         },
-        Object::Builtin(builtin_fn) => builtin_fn(args),
+        Object::Builtin(builtin) => crate::builtins::call(builtin, args),
+        Object::Memoized { function, cache } => {
+            // Unhashable arguments can't be cached: just call through
+            let key: Option<Vec<_>> = args.iter().map(crate::object::get_hash_key).collect();
+            let key = match key {
+                Some(k) => k,
+                None => return apply_function(*function, args),
+            };
+            // Release the borrow before calling: recursive calls use the same cache
+            let cached = cache.borrow().get(&key);
+            if let Some(hit) = cached { return hit; }
+            let result = apply_function(*function, args);
+            if !is_error(&result) {
+                cache.borrow_mut().insert(key, result.clone());
+            }
+            result
+        },
         _ => Object::Error("Not a function".to_string()),
     }
 }
 
-fn is_truthy(obj: &Object) -> bool {
-    match obj {
-        Object::Null => false,
-        Object::Boolean(true) => true,
-        Object::Boolean(false) => false,
-        _ => true,
+// Advance an iterator. None means it is exhausted; an Error from a
+// map/filter callback comes back as Some(Error) for the caller to stop on.
+pub fn iterator_next(state: &Rc<RefCell<IterState>>) -> Option<Object> {
+    let mut st = state.borrow_mut();
+    match &mut *st {
+        IterState::Range { next, end, step } => {
+            let done = if *step > 0 { *next >= *end } else { *next <= *end };
+            if done { return None; }
+            let val = *next;
+            // Past i64's range there are no more values to give
+            *next = next.checked_add(*step).unwrap_or(*end);
+            Some(Object::Integer(val))
+        },
+        IterState::Array { items, pos } => {
+            let val = items.get(*pos).cloned();
+            *pos += 1;
+            val
+        },
+        IterState::Chars { chars, pos } => {
+            let val = chars.get(*pos).map(|c| Object::String(c.to_string()));
+            *pos += 1;
+            val
+        },
+        IterState::Take { source, remaining } => {
+            if *remaining == 0 { return None; }
+            *remaining -= 1;
+            let source = source.clone();
+            drop(st);
+            source_next(&source)
+        },
+        // Adapters call back into user code, so the borrow must be released first
+        IterState::Map { source, function } => {
+            let (source, function) = (source.clone(), function.clone());
+            drop(st);
+            let val = source_next(&source)?;
+            if is_error(&val) { return Some(val); }
+            Some(apply_function(*function, vec![val]))
+        },
+        IterState::Filter { source, function } => {
+            let (source, function) = (source.clone(), function.clone());
+            drop(st);
+            loop {
+                let val = source_next(&source)?;
+                if is_error(&val) { return Some(val); }
+                let keep = apply_function((*function).clone(), vec![val.clone()]);
+                match object::is_truthy(&keep) {
+                    Ok(true) => return Some(val),
+                    Ok(false) => {},
+                    Err(msg) => return Some(Object::Error(msg)),
+                }
+            }
+        },
+    }
+}
+
+fn source_next(source: &Object) -> Option<Object> {
+    match source {
+        Object::Iterator(state) => iterator_next(state),
+        _ => None,
+    }
+}
+
+// Whether an if, while, for or match guard condition holds; an error (from
+// evaluating it, or as its value) stops the program instead
+fn eval_condition(condition: &Expression, env: &mut Environment) -> Result<bool, Object> {
+    object::is_truthy(&eval(condition, env)).map_err(Object::Error)
+}
+
+// The variables a loop header declares belong to the loop: afterwards each
+// name goes back to whatever it was before
+fn loop_scoped(names: &[Symbol], env: &mut Environment, run: impl FnOnce(&mut Environment) -> Object) -> Object {
+    let previous: Vec<Option<Object>> = names.iter().map(|name| env.get_local(*name)).collect();
+    let result = run(env);
+    for (name, previous) in names.iter().zip(previous) {
+        match previous {
+            Some(value) => { env.set(*name, value); },
+            None => env.remove(*name),
+        }
+    }
+    result
+}
+
+fn eval_for(init: Option<&Statement>, condition: Option<&Expression>, post: Option<&Statement>, body: &BlockStatement, env: &mut Environment) -> Object {
+    if let Some(init) = init {
+        let value = eval_statement(init, env);
+        if is_error(&value) { return value; }
+    }
+    let mut result = Object::Null;
+    loop {
+        if let Some(condition) = condition {
+            match eval_condition(condition, env) {
+                Ok(true) => {},
+                Ok(false) => break,
+                Err(error) => return error,
+            }
+        }
+        if let Err(e) = config::burn_fuel() { return Object::Error(e); }
+        result = match eval_loop_body(body, env) {
+            Ok(value) => value,
+            Err(exit) => return exit,
+        };
+        if let Some(post) = post {
+            let value = eval_statement(post, env);
+            if is_error(&value) { return value; }
+        }
+    }
+    result
+}
+
+// Anything to_iterator takes, walked one item at a time, so a range is never
+// built as an array. Arrays give their elements and iterators their values
+// (with `for (i, x in xs)`, their positions too); hashes give their keys in
+// key order (with `for (k, v in h)`, their values too), and so do records
+fn eval_for_in(variable: Symbol, value: Option<Symbol>, iterable: &Expression, span: Span, body: &BlockStatement, env: &mut Environment) -> Object {
+    let subject = eval(iterable, env);
+    if is_error(&subject) { return subject; }
+    let state = match object::to_iterator(&subject) {
+        Some(Object::Iterator(state)) => state,
+        _ => {
+            let msg = format!("for-in can't iterate over {}: only arrays, hashes, records, strings and iterators", subject.type_name());
+            return noted(Object::Error(msg), span, env);
+        },
+    };
+    let hash = match &subject {
+        Object::Hash(hash) | Object::Module { exports: hash, .. } | Object::Record { fields: hash, .. } => Some(hash),
+        _ => None,
+    };
+    let mut result = Object::Null;
+    let mut position = 0;
+    while let Some(item) = iterator_next(&state) {
+        if is_error(&item) { return item; }
+        if let Err(e) = config::burn_fuel() { return Object::Error(e); }
+        match value {
+            Some(value) => {
+                let (key, element) = match hash {
+                    Some(hash) => {
+                        let element = object::get_hash_key(&item).and_then(|key| hash.pairs.get(&key).cloned()).unwrap_or(Object::Null);
+                        (item, element)
+                    },
+                    None => (Object::Integer(position), item),
+                };
+                env.set(variable, key);
+                env.set(value, element);
+            },
+            None => { env.set(variable, item); },
+        }
+        position += 1;
+        result = match eval_loop_body(body, env) {
+            Ok(value) => value,
+            Err(exit) => return exit,
+        };
     }
+    result
 }
 
 fn is_error(obj: &Object) -> bool {
-    match obj {
-        Object::Error(_) => true,
-        _ => false,
+    matches!(obj, Object::Error(_))
+}
+
+// `result` of the operation at `span`; a failure is noted for the error excerpt
+fn noted(result: Object, span: Span, env: &Environment) -> Object {
+    if let Object::Error(msg) = &result && env.module().is_none() {
+        diagnostics::note(span, msg);
     }
+    result
 }
 
 fn eval_prefix(op: &str, right: Object) -> Object {
     match op {
-        "!" => match right {
-            Object::Boolean(true) => Object::Boolean(false),
-            Object::Boolean(false) => Object::Boolean(true),
-            Object::Null => Object::Boolean(true),
-            _ => Object::Boolean(false),
+        "!" => match object::is_truthy(&right) {
+            Ok(truthy) => Object::Boolean(!truthy),
+            Err(msg) => Object::Error(msg),
         },
         "-" => match right {
-            Object::Integer(val) => Object::Integer(-val),
+            Object::Integer(val) => integer_result(integer_negate(val)),
+            Object::Float(val) => Object::Float(-val),
             _ => Object::Error("Unknown operator: -".to_string()),
         },
         _ => Object::Error(format!("Unknown operator: {}", op)),
     }
 }
 
+fn eval_float_infix(op: &str, l: f64, r: f64) -> Object {
+    match op {
+        "+" => Object::Float(l + r),
+        "-" => Object::Float(l - r),
+        "*" => Object::Float(l * r),
+        "/" if r == 0.0 => Object::Error("Division by zero".to_string()),
+        "/" => Object::Float(l / r),
+        "**" => Object::Float(l.powf(r)),
+        "<" => Object::Boolean(l < r),
+        ">" => Object::Boolean(l > r),
+        "<=" => Object::Boolean(l <= r),
+        ">=" => Object::Boolean(l >= r),
+        "==" => Object::Boolean(l == r),
+        "!=" => Object::Boolean(l != r),
+        _ => Object::Error(format!("Unknown op: {}", op)),
+    }
+}
+
+// `x in xs`: an element of an array (by ==), a key of a hash, or part of a string
+fn eval_membership(needle: &Object, haystack: &Object) -> Object {
+    match (needle, haystack) {
+        (_, Object::Array(items)) => Object::Boolean(items.iter().any(|item| object::objects_equal(item, needle))),
+        (_, Object::Hash(hash)) => Object::Boolean(match object::equality_key(needle) {
+            Some(key) => hash.pairs.contains_key(&key),
+            None => hash.pairs.keys().any(|key| object::objects_equal(&hash_key_to_object(key), needle)),
+        }),
+        (Object::String(part), Object::String(s)) => Object::Boolean(s.contains(part.as_str())),
+        (Object::Char(c), Object::String(s)) => Object::Boolean(s.contains(*c)),
+        (_, Object::String(_)) => Object::Error(format!("Can't look for {} in a STRING; use a STRING or CHAR", needle.type_name())),
+        _ => Object::Error(format!("'in' needs an ARRAY, HASH or STRING on its right, got {}", haystack.type_name())),
+    }
+}
+
+fn integer_result(result: Result<i64, String>) -> Object {
+    match result {
+        Ok(value) => Object::Integer(value),
+        Err(msg) => Object::Error(msg),
+    }
+}
+
 fn eval_infix(op: &str, left: Object, right: Object) -> Object {
+    if op == "in" { return eval_membership(&left, &right); }
     match (left, right) {
         // 1. Integer Math
         (Object::Integer(l), Object::Integer(r)) => match op {
-            "+" => Object::Integer(l + r),
-            "-" => Object::Integer(l - r),
-            "*" => Object::Integer(l * r),
-            "/" => Object::Integer(l / r),
+            "+" => integer_result(integer_add(l, r)),
+            "-" => integer_result(integer_subtract(l, r)),
+            "*" => integer_result(integer_multiply(l, r)),
+            "/" => integer_result(integer_divide(l, r)),
+            "**" => integer_result(integer_power(l, r)),
             "<" => Object::Boolean(l < r),
             ">" => Object::Boolean(l > r),
+            "<=" => Object::Boolean(l <= r),
+            ">=" => Object::Boolean(l >= r),
             "==" => Object::Boolean(l == r),
             "!=" => Object::Boolean(l != r),
             _ => Object::Error(format!("Unknown op: {}", op)),
         },
         
+        // Floats, with an integer on either side promoted to float
+        (Object::Float(l), Object::Float(r)) => eval_float_infix(op, l, r),
+        (Object::Integer(l), Object::Float(r)) => eval_float_infix(op, l as f64, r),
+        (Object::Float(l), Object::Integer(r)) => eval_float_infix(op, l, r as f64),
+
         // 2. Boolean Logic
         (Object::Boolean(l), Object::Boolean(r)) => match op {
             "==" => Object::Boolean(l == r),
@@ -241,9 +814,33 @@ fn eval_infix(op: &str, left: Object, right: Object) -> Object {
             "+" => Object::String(format!("{}{}", l, r)),
             "==" => Object::Boolean(l == r),
             "!=" => Object::Boolean(l != r),
+            // Strings compare by code point, like sort()
+            "<" => Object::Boolean(l < r),
+            ">" => Object::Boolean(l > r),
+            "<=" => Object::Boolean(l <= r),
+            ">=" => Object::Boolean(l >= r),
             _ => Object::Error("Unknown string op".to_string()),
         },
 
+        // Chars compare by code point and append to strings
+        (Object::Char(l), Object::Char(r)) => match op {
+            "==" => Object::Boolean(l == r),
+            "!=" => Object::Boolean(l != r),
+            "<" => Object::Boolean(l < r),
+            ">" => Object::Boolean(l > r),
+            "<=" => Object::Boolean(l <= r),
+            ">=" => Object::Boolean(l >= r),
+            _ => Object::Error(format!("Unknown char op: {}", op)),
+        },
+        (Object::String(l), Object::Char(r)) => match op {
+            "+" => Object::String(format!("{}{}", l, r)),
+            _ => Object::Error("Type mismatch".to_string()),
+        },
+        (Object::Char(l), Object::String(r)) => match op {
+            "+" => Object::String(format!("{}{}", l, r)),
+            _ => Object::Error("Type mismatch".to_string()),
+        },
+
         // 4. String Mixed (String + Int)
         (Object::String(l), Object::Integer(r)) => match op {
             "+" => Object::String(format!("{}{}", l, r)),
@@ -253,6 +850,10 @@ fn eval_infix(op: &str, left: Object, right: Object) -> Object {
             "+" => Object::String(format!("{}{}", l, r)),
             _ => Object::Error("Type mismatch".to_string()),
         },
+        (l @ Object::String(_), r @ Object::Float(_)) | (l @ Object::Float(_), r @ Object::String(_)) => match op {
+            "+" => Object::String(format!("{}{}", l, r)),
+            _ => Object::Error("Type mismatch".to_string()),
+        },
 
         // 5. NEW: String + Array (Fixes your error)
         (Object::String(l), Object::Array(r)) => match op {
@@ -271,8 +872,22 @@ fn eval_infix(op: &str, left: Object, right: Object) -> Object {
                 new_vec.extend(r);
                 Object::Array(new_vec)
             },
+            "==" => Object::Boolean(object::objects_equal(&Object::Array(l), &Object::Array(r))),
+            "!=" => Object::Boolean(!object::objects_equal(&Object::Array(l), &Object::Array(r))),
             _ => Object::Error("Unknown array operator".to_string()),
         },
+        // Key order doesn't matter: {"a": 1, "b": 2} == {"b": 2, "a": 1}
+        (l @ Object::Hash(_), r @ Object::Hash(_)) => match op {
+            "==" => Object::Boolean(object::objects_equal(&l, &r)),
+            "!=" => Object::Boolean(!object::objects_equal(&l, &r)),
+            _ => Object::Error(format!("Unknown hash operator: {}", op)),
+        },
+        // Records are equal when kind, name and fields all are
+        (l @ Object::Record { .. }, r @ Object::Record { .. }) => match op {
+            "==" => Object::Boolean(object::objects_equal(&l, &r)),
+            "!=" => Object::Boolean(!object::objects_equal(&l, &r)),
+            _ => Object::Error(format!("Unknown {} operator: {}", l.type_name().to_lowercase(), op)),
+        },
 
         (Object::String(l), Object::Null) => {
              Object::String(format!("{}null", l))
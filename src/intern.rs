@@ -0,0 +1,60 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+// An interned identifier: a small Copy id standing for a name, so the AST,
+// the compiler's symbol table and Environment keys compare and hash ids
+// instead of cloning strings. Ids are per thread, like the rest of the runtime.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// Names are leaked on purpose: a program only ever has so many distinct
+// identifiers, and 'static text keeps as_str() free.
+#[derive(Default)]
+struct Interner {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, Symbol>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&id) = interner.ids.get(name) {
+                return id;
+            }
+            let id = Symbol(interner.names.len() as u32);
+            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+            interner.names.push(name);
+            interner.ids.insert(name, id);
+            id
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Shows the name, so ASTs still read naturally in {:?} output
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
@@ -1,10 +1,23 @@
-use crate::token::{Token, TokenType};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::token::{Span, Token, TokenType};
 
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
     read_position: usize,
     ch: char,
+    // Position of `ch`, counted from 1
+    line: usize,
+    column: usize,
+    // Byte offset of `ch` in the source
+    offset: usize,
+    // Whether a line break came before the token last returned
+    newline_before: bool,
+    // Set once the iterator has handed out the EOF token
+    finished: bool,
+    // No token yet: `#!` lines (a shebang, pragmas) are still skipped like comments
+    in_header: bool,
 }
 
 impl Lexer {
@@ -14,12 +27,32 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: '\0',
+            line: 1,
+            column: 0,
+            offset: 0,
+            newline_before: false,
+            finished: false,
+            in_header: true,
         };
         l.read_char();
+        // A byte order mark isn't part of the program (its bytes still count in offsets)
+        if l.ch == '\u{FEFF}' {
+            l.read_char();
+            l.column = 1;
+        }
         l
     }
 
     fn read_char(&mut self) {
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        if self.read_position > 0 && self.position < self.input.len() {
+            self.offset += self.input[self.position].len_utf8();
+        }
         if self.read_position >= self.input.len() {
             self.ch = '\0';
         } else {
@@ -31,28 +64,52 @@ impl Lexer {
 
     fn peek_char(&self) -> char {
         if self.read_position >= self.input.len() {
-            return '\0';
+            '\0'
         } else {
-            return self.input[self.read_position];
+            self.input[self.read_position]
         }
     }
 
+    // Every token of `input`, ending with EOF
+    pub fn tokenize(input: &str) -> Vec<Token> {
+        Lexer::new(input.to_string()).collect()
+    }
+
+    // What --tokens prints: one token per line, with its position, type and literal
+    pub fn dump(input: &str) -> String {
+        Lexer::tokenize(input).iter().map(|tok| {
+            format!("{}:{} {:?} {}\n", tok.line, tok.column, tok.token_type, crate::ast::json_string(&tok.literal))
+        }).collect()
+    }
+
     pub fn next_token(&mut self) -> Token {
+        self.newline_before = false;
         self.skip_whitespace();
 
-        // Comment Skipping
-        if self.ch == '/' && self.peek_char() == '/' {
+        // Comment Skipping. Before the first token `#!` lines count as comments too:
+        // a shebang lets scripts run directly, and pragmas are read by FilePragmas
+        while self.at_comment() {
             self.skip_comment();
-            return self.next_token();
+            self.skip_whitespace();
         }
+        self.in_header = false;
 
+        let (line, column, start) = (self.line, self.column, self.offset);
+        let mut tok = self.read_token();
+        tok.line = line;
+        tok.column = column;
+        tok.span = Span { start, end: self.offset };
+        tok
+    }
+
+    // The token starting at `ch`; next_token fills in where it starts
+    fn read_token(&mut self) -> Token {
         if is_letter(self.ch) {
             let literal = self.read_identifier();
             let token_type = lookup_ident(&literal);
-            return Token { token_type, literal };
+            return Token { token_type, literal, line: 0, column: 0, span: Span::default() };
         } else if is_digit(self.ch) {
-            let literal = self.read_number();
-            return Token { token_type: TokenType::Int, literal };
+            return self.read_number();
         }
 
         let tok = match self.ch {
@@ -60,6 +117,9 @@ impl Lexer {
                 if self.peek_char() == '=' {
                     self.read_char();
                     self.new_token(TokenType::Eq, "==")
+                } else if self.peek_char() == '>' {
+                    self.read_char();
+                    self.new_token(TokenType::FatArrow, "=>")
                 } else {
                     self.new_token(TokenType::Assign, "=")
                 }
@@ -69,39 +129,105 @@ impl Lexer {
                     self.read_char();
                     self.new_token(TokenType::NotEq, "!=")
                 } else {
-                    // FIX: Return Bang instead of Illegal
-                    self.new_token(TokenType::Bang, "!") 
+                    // Only `!=` with no space is not-equal; `! =` is a Bang then an Assign
+                    self.new_token(TokenType::Bang, "!")
                 }
             },
-            // FIX: Add Semicolon handler
-            ';' => self.new_token(TokenType::Semicolon, ";"), 
-            
-            '"' => {
-                let str_lit = self.read_string();
-                self.new_token(TokenType::String, &str_lit)
+            // Optional statement terminator; the parser skips any run of them
+            ';' => self.new_token(TokenType::Semicolon, ";"),
+
+            '"' => match self.read_string() {
+                Some(str_lit) => self.new_token(TokenType::String, &str_lit),
+                None => self.new_token(TokenType::UnterminatedString, ""),
+            },
+            '\'' => match self.read_char_literal() {
+                Some(chars) => self.new_token(TokenType::Char, &chars),
+                None => self.new_token(TokenType::Illegal, "'"),
+            },
+            '&' if self.peek_char() == '&' => {
+                self.read_char();
+                self.new_token(TokenType::And, "&&")
+            },
+            '|' if self.peek_char() == '|' => {
+                self.read_char();
+                self.new_token(TokenType::Or, "||")
+            },
+            '*' if self.peek_char() == '*' => {
+                self.read_char();
+                self.new_token(TokenType::Power, "**")
+            },
+            '+' | '-' | '*' | '/' if self.peek_char() == '=' => {
+                let (token_type, literal) = match self.ch {
+                    '+' => (TokenType::PlusAssign, "+="),
+                    '-' => (TokenType::MinusAssign, "-="),
+                    '*' => (TokenType::AsteriskAssign, "*="),
+                    _ => (TokenType::SlashAssign, "/="),
+                };
+                self.read_char();
+                self.new_token(token_type, literal)
             },
             '+' => self.new_token(TokenType::Plus, "+"),
             '-' => self.new_token(TokenType::Minus, "-"),
             '*' => self.new_token(TokenType::Asterisk, "*"),
             '/' => self.new_token(TokenType::Slash, "/"),
-            '<' => self.new_token(TokenType::Lt, "<"),
-            '>' => self.new_token(TokenType::Gt, ">"),
+            '<' => {
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    self.new_token(TokenType::LtEq, "<=")
+                } else {
+                    self.new_token(TokenType::Lt, "<")
+                }
+            },
+            '>' => {
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    self.new_token(TokenType::GtEq, ">=")
+                } else {
+                    self.new_token(TokenType::Gt, ">")
+                }
+            },
             ',' => self.new_token(TokenType::Comma, ","),
             ':' => self.new_token(TokenType::Colon, ":"),
+            '?' => self.new_token(TokenType::Question, "?"),
             '(' => self.new_token(TokenType::LParen, "("),
             ')' => self.new_token(TokenType::RParen, ")"),
             '{' => self.new_token(TokenType::LBrace, "{"),
             '}' => self.new_token(TokenType::RBrace, "}"),
             '[' => self.new_token(TokenType::LBracket, "["),
             ']' => self.new_token(TokenType::RBracket, "]"),
-            '\0' => self.new_token(TokenType::EOF, ""),
-            _ => self.new_token(TokenType::Illegal, ""),
+            '.' if self.peek_char() == '.' && self.input.get(self.read_position + 1) == Some(&'.') => {
+                self.read_char();
+                self.read_char();
+                self.new_token(TokenType::DotDotDot, "...")
+            },
+            '.' if self.peek_char() == '.' && self.input.get(self.read_position + 1) == Some(&'=') => {
+                self.read_char();
+                self.read_char();
+                self.new_token(TokenType::DotDotEq, "..=")
+            },
+            '.' if self.peek_char() == '.' => {
+                self.read_char();
+                self.new_token(TokenType::DotDot, "..")
+            },
+            '.' => self.new_token(TokenType::Dot, "."),
+            // A NUL byte in the file is just another stray character, not the end
+            '\0' if self.position >= self.input.len() => self.new_token(TokenType::EOF, ""),
+            // Anything else is reported by the parser, character and all
+            other => self.new_token(TokenType::Illegal, &other.to_string()),
         };
 
         self.read_char();
         tok
     }
 
+    fn at_comment(&self) -> bool {
+        match (self.ch, self.peek_char()) {
+            ('/', '/') => true,
+            ('#', '!') => self.in_header,
+            _ => false,
+        }
+    }
+
     fn skip_comment(&mut self) {
         while self.ch != '\n' && self.ch != '\0' {
             self.read_char();
@@ -109,55 +235,203 @@ impl Lexer {
         self.skip_whitespace();
     }
 
-    fn read_string(&mut self) -> String {
-        let pos = self.position + 1; 
+    // The string's contents, or None if the input ends before the closing quote.
+    // Strings may span lines.
+    fn read_string(&mut self) -> Option<String> {
+        let mut out = String::new();
         loop {
             self.read_char();
-            if self.ch == '"' || self.ch == '\0' { break; }
+            match self.ch {
+                '"' => return Some(out),
+                '\0' => return None,
+                '\\' => {
+                    self.read_char();
+                    if self.ch == '\0' { return None; }
+                    self.push_escape(&mut out);
+                },
+                ch => out.push(ch),
+            }
+        }
+    }
+
+    // What a character literal's quotes hold, or None if the line (or input)
+    // ends first. Any length comes back; the parser rejects all but one.
+    fn read_char_literal(&mut self) -> Option<String> {
+        let mut out = String::new();
+        loop {
+            if matches!(self.peek_char(), '\n' | '\0') { return None; }
+            self.read_char();
+            match self.ch {
+                '\'' => return Some(out),
+                '\\' => {
+                    if matches!(self.peek_char(), '\n' | '\0') { return None; }
+                    self.read_char();
+                    self.push_escape(&mut out);
+                },
+                ch => out.push(ch),
+            }
+        }
+    }
+
+    // The character after a backslash, shared by string and character literals
+    fn push_escape(&self, out: &mut String) {
+        match self.ch {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            '"' => out.push('"'),
+            '\'' => out.push('\''),
+            '\\' => out.push('\\'),
+            // Unknown escapes are kept as written
+            other => { out.push('\\'); out.push(other); },
         }
-        self.input[pos..self.position].iter().collect()
     }
 
     fn new_token(&self, token_type: TokenType, literal: &str) -> Token {
-        Token { token_type, literal: literal.to_string() }
+        Token { token_type, literal: literal.to_string(), line: 0, column: 0, span: Span::default() }
     }
 
-    // CRITICAL FIX: Allow digits inside identifiers
+    // Starts at a letter (see next_token); digits are allowed after that
+    // (`n1`, `foo_2_bar`), so `2x` still lexes as `2` then `x`.
     fn read_identifier(&mut self) -> String {
         let pos = self.position;
-        // We accept letters OR digits (e.g., 'n1', 'var2')
-        while is_letter(self.ch) || is_digit(self.ch) { 
-            self.read_char(); 
+        while is_identifier_continue(self.ch) {
+            self.read_char();
         }
         self.input[pos..self.position].iter().collect()
     }
 
-    fn read_number(&mut self) -> String {
+    // `12` is an Int and `1.5` a Float; `_` may separate digits (`1_000_000`).
+    // `5.`, `1.2.3` and misplaced underscores (`100_`, `1__0`, `1_.5`) come back as a
+    // single Illegal token for the parser to report; `1..` is left alone (the `..`
+    // isn't part of the number).
+    fn read_number(&mut self) -> Token {
         let pos = self.position;
-        while is_digit(self.ch) { self.read_char(); }
-        self.input[pos..self.position].iter().collect()
+        self.read_digits();
+        let mut token_type = TokenType::Int;
+        // Only a digit after the dot makes a float; `xs.len` and `1.foo` are member access
+        if self.ch == '.' && is_digit(self.peek_char()) {
+            token_type = TokenType::Float;
+            self.read_char();
+            self.read_digits();
+            // A second decimal point: swallow the rest so it is reported as one literal
+            if self.ch == '.' && is_digit(self.peek_char()) {
+                token_type = TokenType::Illegal;
+                while is_digit(self.ch) || self.ch == '.' || self.ch == '_' { self.read_char(); }
+            }
+        }
+        let literal: String = self.input[pos..self.position].iter().collect();
+        // Every underscore must sit between two digits
+        let chars: Vec<char> = literal.chars().collect();
+        let misplaced = chars.iter().enumerate().any(|(i, &c)| {
+            c == '_' && !(i > 0 && is_digit(chars[i - 1]) && chars.get(i + 1).is_some_and(|&next| is_digit(next)))
+        });
+        if misplaced { token_type = TokenType::Illegal; }
+        Token { token_type, literal, line: 0, column: 0, span: Span::default() }
+    }
+
+    fn read_digits(&mut self) {
+        while is_digit(self.ch) || self.ch == '_' { self.read_char(); }
     }
 
     fn skip_whitespace(&mut self) {
-        while self.ch.is_whitespace() { self.read_char(); }
+        while self.ch.is_whitespace() {
+            if self.ch == '\n' { self.newline_before = true; }
+            self.read_char();
+        }
+    }
+
+    pub fn newline_before(&self) -> bool {
+        self.newline_before
+    }
+
+    // Where the lexer is, so the parser can read ahead and come back
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            position: self.position,
+            read_position: self.read_position,
+            ch: self.ch,
+            line: self.line,
+            column: self.column,
+            offset: self.offset,
+            newline_before: self.newline_before,
+            finished: self.finished,
+            in_header: self.in_header,
+        }
+    }
+
+    pub fn rewind(&mut self, at: Checkpoint) {
+        self.position = at.position;
+        self.read_position = at.read_position;
+        self.ch = at.ch;
+        self.line = at.line;
+        self.column = at.column;
+        self.offset = at.offset;
+        self.newline_before = at.newline_before;
+        self.finished = at.finished;
+        self.in_header = at.in_header;
+    }
+}
+
+// Everything about a lexer but its input
+#[derive(Clone, Copy)]
+pub struct Checkpoint {
+    position: usize,
+    read_position: usize,
+    ch: char,
+    line: usize,
+    column: usize,
+    offset: usize,
+    newline_before: bool,
+    finished: bool,
+    in_header: bool,
+}
+
+// Tokens up to and including EOF (calling next_token directly keeps returning EOF)
+impl Iterator for Lexer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.finished { return None; }
+        let tok = self.next_token();
+        self.finished = tok.token_type == TokenType::EOF;
+        Some(tok)
     }
 }
 
 fn is_letter(ch: char) -> bool { ch.is_alphabetic() || ch == '_' }
-fn is_digit(ch: char) -> bool { ch.is_numeric() }
+// Names may use any script's letters and digits (`café`, `größe2`, `x²`); number literals are ASCII
+fn is_identifier_continue(ch: char) -> bool { ch.is_alphanumeric() || ch == '_' }
+fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+
+// Only the keywords every edition reserves. Newer ones (`match`, `break`, `let`, ...)
+// come out as identifiers; the parser's keyword table decides what they mean.
+pub const KEYWORDS: &[(&str, TokenType)] = &[
+    ("fn", TokenType::Fn),
+    ("mut", TokenType::Mut),
+    ("if", TokenType::If),
+    ("else", TokenType::Else),
+    ("true", TokenType::True),
+    ("false", TokenType::False),
+    ("return", TokenType::Return),
+    ("while", TokenType::While),
+];
+
+thread_local! {
+    // Spellings an embedding added with add_keyword, for the rest of the thread
+    static EXTRA_KEYWORDS: RefCell<HashMap<String, TokenType>> = RefCell::new(HashMap::new());
+}
+
+// Make `word` lex as `token_type` everywhere, e.g. a translated keyword set
+// (`add_keyword("si", TokenType::If)`). Like the module resolver, this belongs
+// to the thread, so it applies to every Runtime running on it.
+pub fn add_keyword(word: &str, token_type: TokenType) {
+    EXTRA_KEYWORDS.with(|extra| extra.borrow_mut().insert(word.to_string(), token_type));
+}
 
 fn lookup_ident(ident: &str) -> TokenType {
-    match ident {
-        "fn" => TokenType::Fn,
-        "mut" => TokenType::Mut,
-        "if" => TokenType::If,
-        "else" => TokenType::Else,
-        "true" => TokenType::True,
-        "false" => TokenType::False,
-        "return" => TokenType::Return,
-        "material" => TokenType::Material,
-        "context" => TokenType::Context,
-        "while" => TokenType::While,
-        _ => TokenType::Identifier,
+    if let Some((_, token_type)) = KEYWORDS.iter().find(|(word, _)| *word == ident) {
+        return *token_type;
     }
+    EXTRA_KEYWORDS.with(|extra| extra.borrow().get(ident).copied()).unwrap_or(TokenType::Identifier)
 }
\ No newline at end of file
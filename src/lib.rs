@@ -0,0 +1,46 @@
+// The Flux language as a library: lexer, parser, tree-walking evaluator,
+// bytecode compiler and VM. The `flux_compiler` binary is a thin CLI over it.
+// The evaluator (with the builtins) and the compiler with its VM are the
+// `eval` and `vm` features; a build needs at least one of them.
+#[cfg(not(any(feature = "eval", feature = "vm")))]
+compile_error!("flux_compiler needs the \"eval\" feature, the \"vm\" feature or both");
+
+pub mod intern;
+pub mod token;
+pub mod lexer;
+pub mod ast;
+pub mod ast_lower;
+pub mod parser;
+pub mod object;
+pub mod environment;
+#[cfg(feature = "eval")]
+pub mod evaluator;
+#[cfg(feature = "eval")]
+pub mod builtins;
+#[cfg(feature = "vm")]
+pub mod code;
+#[cfg(feature = "vm")]
+pub mod compiler;
+#[cfg(feature = "vm")]
+pub mod vm;
+#[cfg(feature = "vm")]
+pub mod symbol_table;
+pub mod config;
+pub mod pragma;
+pub mod source;
+pub mod paths;
+pub mod diagnostics;
+pub mod stats;
+pub mod runtime;
+#[cfg(feature = "eval")]
+pub mod bundle;
+#[cfg(feature = "eval")]
+pub mod corpus;
+pub mod repl;
+pub mod ui;
+
+#[cfg(feature = "eval")]
+pub use builtins::{set_module_resolver, Capture, Io, ModuleResolver, OutputHook};
+pub use lexer::add_keyword;
+pub use config::{CancellationToken, Policy};
+pub use runtime::{Runtime, Engine, RunError};
@@ -1,85 +1,389 @@
-mod token;
-mod lexer;
-mod ast;
-mod parser;
-mod object;
-mod environment;
-mod evaluator;
-mod builtins;
-mod code;
-mod compiler; 
-mod vm;
-mod symbol_table;
-
 use std::env;
-use std::fs;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
-use crate::environment::Environment;
-use crate::evaluator::eval_program;
+use std::path::{Path, PathBuf};
+use flux_compiler::{ast, config, repl, Runtime};
+#[cfg(all(feature = "eval", feature = "vm"))]
+use flux_compiler::Engine;
+#[cfg(feature = "eval")]
+use flux_compiler::builtins;
+#[cfg(feature = "eval")]
+use flux_compiler::bundle::{self, Bundle};
+#[cfg(feature = "eval")]
+use flux_compiler::corpus;
+use flux_compiler::lexer::Lexer;
+use flux_compiler::source;
+use flux_compiler::parser::Edition;
+use flux_compiler::object::Object;
+use flux_compiler::config::RuntimeConfig;
+use flux_compiler::ui::ColorChoice;
+use std::thread;
+
+// Everything the command line asked for
+struct Options {
+    filename: Option<String>,
+    init: Option<String>,
+    no_init: bool,
+    #[cfg(all(feature = "eval", feature = "vm"))]
+    use_vm: bool,
+    // None unless --edition was given, so a script's pragma can choose
+    edition: Option<Edition>,
+    #[cfg(feature = "eval")]
+    log_level: Option<String>,
+    config: RuntimeConfig,
+    #[cfg(feature = "eval")]
+    corpus: Option<String>,
+    // --ast json: print the parse tree instead of running
+    ast_json: bool,
+    // --tokens: print what the lexer makes of the file instead of running
+    tokens: bool,
+    // --check: only parse the file and report its errors
+    check: bool,
+    // --plain: no colors, whatever the terminal supports
+    plain: bool,
+    // --opt=inline: inline calls to tiny functions in the VM
+    #[cfg(feature = "vm")]
+    inline: bool,
+    // --stats: report what the run did when it ends
+    stats: bool,
+    // --debug: breakpoint() opens a shell (when stdin is a terminal)
+    #[cfg(feature = "eval")]
+    debug: bool,
+    // Everything after the script's name, for args()
+    script_args: Vec<String>,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: flux_compiler [filename.flux]");
+    let mut opts = Options {
+        filename: None,
+        init: None,
+        no_init: false,
+        #[cfg(all(feature = "eval", feature = "vm"))]
+        use_vm: false,
+        edition: None,
+        #[cfg(feature = "eval")]
+        log_level: None,
+        config: RuntimeConfig::default(),
+        #[cfg(feature = "eval")]
+        corpus: None,
+        ast_json: false,
+        tokens: false,
+        check: false,
+        plain: false,
+        #[cfg(feature = "vm")]
+        inline: false,
+        stats: false,
+        #[cfg(feature = "eval")]
+        debug: false,
+        script_args: vec![],
+    };
+
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "bundle") {
+        args.next();
+        #[cfg(feature = "eval")]
+        bundle_command(args);
+        #[cfg(not(feature = "eval"))]
+        without_feature("bundle", "eval");
         return;
     }
-
-    // --- VM DEBUG START ---
-    println!("--- VM DEBUG ---");
-    
-    let input = "mut x = 50; mut y = 10; x + y";
-    let l = Lexer::new(input.to_string());
-    let mut p = Parser::new(l);
-    let program = p.parse_program();
-
-    // 2. Compile it
-    let mut comp = crate::compiler::Compiler::new();
-    match comp.compile(program) {
-        Ok(_) => {
-            // 3. Run it in the VM!
-            let mut machine = crate::vm::VM::new(comp);
-            match machine.run() {
-                Ok(_) => {
-                    println!("VM Executed Successfully.");
-                    // Check the stack. If you commented out OP_POP in compiler.rs, 
-                    // this should show Some(Integer(3)).
-                    println!("Stack Top: {:?}", machine.stack_top());
-                },
-                Err(e) => println!("VM Runtime Error: {}", e),
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-init" => opts.no_init = true,
+            #[cfg(all(feature = "eval", feature = "vm"))]
+            "--vm" => opts.use_vm = true,
+            // The VM is the only engine there is
+            #[cfg(not(feature = "eval"))]
+            "--vm" => {},
+            #[cfg(not(feature = "vm"))]
+            "--vm" | "--opt" | "--opt=inline" => {
+                without_feature(&arg, "vm");
+                return;
+            },
+            #[cfg(not(feature = "eval"))]
+            "--log-level" | "--corpus" | "--debug" => {
+                without_feature(&arg, "eval");
+                return;
+            },
+            "--plain" => opts.plain = true,
+            "--edition" => match args.next().as_deref().and_then(Edition::from_name) {
+                Some(e) => opts.edition = Some(e),
+                None => {
+                    println!("--edition needs 'compatible' or 'strict'");
+                    return;
+                }
+            },
+            #[cfg(feature = "eval")]
+            "--log-level" => match args.next() {
+                Some(level) if builtins::set_log_level(&level) => opts.log_level = Some(level),
+                _ => {
+                    println!("--log-level needs 'debug', 'info', 'warn' or 'error'");
+                    return;
+                }
+            },
+            "--max-depth" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(depth @ 1..=config::MAX_CALL_DEPTH) => opts.config.max_call_depth = depth,
+                _ => {
+                    println!("--max-depth needs a number from 1 to {}", config::MAX_CALL_DEPTH);
+                    return;
+                }
+            },
+            "--stack-size" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(slots) => opts.config.stack_size = slots,
+                None => {
+                    println!("--stack-size needs a number");
+                    return;
+                }
+            },
+            "--max-module-size" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(bytes) => opts.config.max_module_size = bytes,
+                None => {
+                    println!("--max-module-size needs a number of bytes");
+                    return;
+                }
+            },
+            "--fuel" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(fuel) => opts.config.fuel = Some(fuel),
+                None => {
+                    println!("--fuel needs a number");
+                    return;
+                }
+            },
+            #[cfg(feature = "eval")]
+            "--corpus" => match args.next() {
+                Some(dir) => opts.corpus = Some(dir),
+                None => {
+                    println!("--corpus needs a directory");
+                    return;
+                }
+            },
+            "--ast" => match args.next().as_deref() {
+                Some("json") => opts.ast_json = true,
+                _ => {
+                    println!("--ast needs 'json'");
+                    return;
+                }
+            },
+            "--ast=json" => opts.ast_json = true,
+            "--tokens" => opts.tokens = true,
+            "--check" => opts.check = true,
+            "--max-errors" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => opts.config.max_parse_errors = n,
+                None => {
+                    println!("--max-errors needs a number");
+                    return;
+                }
+            },
+            #[cfg(feature = "vm")]
+            "--opt" => match args.next().as_deref() {
+                Some("inline") => opts.inline = true,
+                _ => {
+                    println!("--opt needs 'inline'");
+                    return;
+                }
+            },
+            #[cfg(feature = "vm")]
+            "--opt=inline" => opts.inline = true,
+            "--stats" => opts.stats = true,
+            #[cfg(feature = "eval")]
+            "--debug" => opts.debug = true,
+            "--init" => match args.next() {
+                Some(path) => opts.init = Some(path),
+                None => {
+                    println!("--init needs a path");
+                    return;
+                }
+            },
+            // The rest of the command line is the script's, flags and all
+            _ if opts.filename.is_none() => {
+                opts.filename = Some(arg);
+                opts.script_args = args.by_ref().collect();
+            },
+            _ => {
+                println!("Usage: flux_compiler [--vm] [--plain] [--edition compatible|strict] [--log-level level] [--max-depth n] [--stack-size n] [--fuel n] [--max-module-size bytes] [--init path] [--no-init] [--ast json] [--tokens] [--check] [--max-errors n] [--opt inline] [--stats] [--debug] [filename.flux [args...] | bundle.fluxb [args...] | --corpus dir]");
+                println!("       flux_compiler bundle entry.flux [-o out.fluxb]");
+                return;
             }
-        },
-        Err(e) => println!("Compiler Error: {}", e),
+        }
+    }
+
+    #[cfg(feature = "eval")]
+    if let Some(dir) = opts.corpus.take() {
+        let passed = corpus::run(Path::new(&dir));
+        std::process::exit(if passed { 0 } else { 1 });
     }
-    println!("----------------");
-    // --- VM DEBUG END ---
 
-    // Now run the actual file using the old interpreter (for now)
-    run_file(&args[1]);
+    // The evaluator recurses on the native stack, so run on a thread whose
+    // stack is big enough for the configured call depth
+    let worker = thread::Builder::new()
+        .stack_size(opts.config.native_stack_size())
+        .spawn(move || {
+            // Builtin state is per thread: set it on the one doing the work
+            #[cfg(feature = "eval")]
+            if let Some(level) = &opts.log_level { builtins::set_log_level(level); }
+            run(opts);
+        })
+        .unwrap_or_else(|e| {
+            println!("ERROR: can't start the interpreter thread: {}", e);
+            std::process::exit(1);
+        });
+    // A panic has already printed its message; the exit status still has to say so
+    if worker.join().is_err() {
+        std::process::exit(101);
+    }
 }
 
-fn run_file(filename: &str) {
-    let contents = match fs::read_to_string(filename) {
-        Ok(c) => c,
-        Err(_) => { println!("Error reading file"); return; }
+fn run(opts: Options) {
+    let color = ColorChoice::detect(opts.plain);
+    let mut runtime = Runtime::new()
+        .with_config(opts.config)
+        .with_stats(opts.stats);
+    // A build with one engine has no choice to make
+    #[cfg(all(feature = "eval", feature = "vm"))]
+    if opts.use_vm { runtime = runtime.with_engine(Engine::Vm); }
+    #[cfg(feature = "vm")]
+    { runtime = runtime.with_inlining(opts.inline); }
+    #[cfg(feature = "eval")]
+    {
+        runtime = runtime.with_debugger(opts.debug);
+        runtime.set_script_args(opts.script_args);
+    }
+    if let Some(edition) = opts.edition {
+        runtime = runtime.with_edition(edition);
+    }
+
+    // No script: start the interactive shell
+    let filename = match opts.filename {
+        Some(f) => f,
+        None => {
+            let init_path = if opts.no_init {
+                None
+            } else {
+                opts.init.map(PathBuf::from).or_else(repl::default_init_path)
+            };
+            repl::start(init_path, runtime, color);
+            return;
+        }
     };
-    
-    let l = Lexer::new(contents);
-    let mut p = Parser::new(l);
-    let program = p.parse_program();
-
-    if !p.errors.is_empty() {
-        println!("Parser Errors:");
-        for msg in p.errors { println!("\t{}", msg); }
+
+    if opts.tokens {
+        print_tokens(&filename);
+        return;
+    }
+
+    if opts.ast_json {
+        print_ast(&runtime, &filename);
         return;
     }
 
-    let mut env = Environment::new();
-    let tools = builtins::new_environment();
-    for (name, tool) in tools { env.set(name, tool); }
-    
-    let result = eval_program(&program, &mut env);
-    if result != crate::object::Object::Null {
-        println!("{}", result);
+    if opts.check {
+        check(&runtime, &filename);
+        return;
+    }
+
+    // Scripts only get an init file when one is asked for explicitly
+    if !opts.no_init && let Some(path) = opts.init {
+        repl::load_init_file(Path::new(&path), &mut runtime);
+    }
+
+    #[cfg(feature = "eval")]
+    let result = if Path::new(&filename).extension().is_some_and(|ext| ext == bundle::BUNDLE_EXTENSION) {
+        runtime.run_bundle(&filename)
+    } else {
+        runtime.run_file(&filename)
+    };
+    #[cfg(not(feature = "eval"))]
+    let result = runtime.run_file(&filename);
+    match result {
+        Ok(Object::Null) => {},
+        Ok(result) => println!("{}", result),
+        Err(e) => println!("{}", color.error(&e.to_string())),
     }
-}
\ No newline at end of file
+    // On stderr, so the script's own output stays as it was
+    if opts.stats {
+        eprint!("{}", runtime.stats().report());
+    }
+}
+
+// A flag or command for a part of Flux this build of the binary leaves out
+#[cfg(not(all(feature = "eval", feature = "vm")))]
+fn without_feature(flag: &str, feature: &str) {
+    println!("{} needs the \"{}\" feature, which this build of flux_compiler was compiled without", flag, feature);
+}
+
+// One token per line: position, type and literal
+fn print_tokens(filename: &str) {
+    let source = match source::read(filename) {
+        Ok(source) => source,
+        Err(e) => {
+            println!("Error reading file {}: {}", filename, e);
+            return;
+        }
+    };
+    print!("{}", Lexer::dump(&source));
+}
+
+// Parse errors, if any; silence means the file parses
+fn check(runtime: &Runtime, filename: &str) {
+    let source = match source::read(filename) {
+        Ok(source) => source,
+        Err(e) => {
+            println!("Error reading file {}: {}", filename, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = runtime.parse(&source) {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn print_ast(runtime: &Runtime, filename: &str) {
+    let source = match source::read(filename) {
+        Ok(source) => source,
+        Err(e) => {
+            println!("Error reading file {}: {}", filename, e);
+            return;
+        }
+    };
+    match runtime.parse(&source) {
+        Ok(program) => println!("{}", ast::to_json(&program)),
+        Err(e) => println!("{}", e),
+    }
+}
+
+// flux_compiler bundle entry.flux [-o out.fluxb]
+#[cfg(feature = "eval")]
+fn bundle_command(mut args: impl Iterator<Item = String>) {
+    let mut entry = None;
+    let mut output = None;
+    let mut extra = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next(),
+            _ if entry.is_none() => entry = Some(arg),
+            _ => extra = true,
+        }
+    }
+    let (entry, output) = match (entry, output) {
+        (Some(entry), Some(output)) if !extra => (entry, output),
+        (Some(entry), None) if !extra => {
+            let output = Path::new(&entry).with_extension(bundle::BUNDLE_EXTENSION).to_string_lossy().into_owned();
+            (entry, output)
+        },
+        _ => {
+            println!("Usage: flux_compiler bundle entry.flux [-o out.fluxb]");
+            std::process::exit(2);
+        }
+    };
+    let built = match Bundle::build(&entry) {
+        Ok(built) => built,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = std::fs::write(&output, built.encode()) {
+        println!("Cannot write {}: {}", output, e);
+        std::process::exit(1);
+    }
+    println!("Bundled {} module(s) into {}", built.modules.len(), output);
+}
@@ -1,9 +1,12 @@
 use std::fmt;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::rc::Rc;
 
 // 1. Define what can be a Key (Strings, Ints, Bools)
-#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+// Ordered (integers, then booleans, then strings) so hashes can be listed deterministically
+#[derive(PartialEq, Eq, Hash, Clone, Debug, PartialOrd, Ord)]
 pub enum HashKey {
     Integer(i64),
     Boolean(bool),
@@ -14,29 +17,245 @@ pub enum HashKey {
 #[derive(Debug, PartialEq, Clone)]
 pub enum Object {
     Integer(i64),
+    Float(f64),
     Boolean(bool),
     String(String),
+    Char(char),
     Return(Box<Object>),
+    // `break` (with the loop's value) and `continue`, on their way out to the innermost loop
+    Break(Box<Object>),
+    Continue,
     Error(String),
     Null,
     Function {
-        parameters: Vec<String>,
+        parameters: Vec<crate::intern::Symbol>,
+        // The last parameter takes the extra arguments as an array
+        variadic: bool,
         body: crate::ast::BlockStatement,
         env: crate::environment::Environment,
     },
-    Builtin(fn(Vec<Object>) -> Object),
+    Builtin(&'static BuiltinDef),
     Array(Vec<Object>),
     // NEW: The Hash Map
-    Hash(HashMap<HashKey, Object>), 
+    Hash(HashObject), 
+    // What import() returns: the module's top-level bindings, plus where they came from
+    Module {
+        name: String,
+        path: String,
+        exports: HashObject,
+    },
+    // A function wrapped by memoize(); the cache is shared by every copy of the wrapper
+    Memoized {
+        function: Box<Object>,
+        cache: Rc<RefCell<MemoCache>>,
+    },
+    // What `material Steel { ... }` or `context Lab { ... }` binds: its fields
+    // read like a hash's string keys
+    Record {
+        kind: crate::ast::RecordKind,
+        name: String,
+        fields: HashObject,
+    },
+    // A lazy sequence. Copies share the cursor, so they all advance together.
+    Iterator(Rc<RefCell<IterState>>),
+    // A function body compiled to bytecode (VM only)
+    #[cfg(feature = "vm")]
+    CompiledFunction(Rc<CompiledFunction>),
+}
+
+#[cfg(feature = "vm")]
+#[derive(Debug, PartialEq)]
+pub struct CompiledFunction {
+    // The name it was bound to, for error messages ("<anonymous>" otherwise)
+    pub name: String,
+    pub instructions: crate::code::Instructions,
+    pub num_locals: usize,
+    pub num_parameters: usize,
+    // (offset just past an instruction that can fail, source it was compiled from)
+    pub spans: Vec<(usize, crate::token::Span)>,
+}
+
+// The built-in iterator kinds. Adapters (Map/Filter/Take) wrap another Iterator object.
+#[derive(Debug, PartialEq)]
+pub enum IterState {
+    Range { next: i64, end: i64, step: i64 },
+    Array { items: Vec<Object>, pos: usize },
+    Chars { chars: Vec<char>, pos: usize },
+    Map { source: Box<Object>, function: Box<Object> },
+    Filter { source: Box<Object>, function: Box<Object> },
+    Take { source: Box<Object>, remaining: usize },
+}
+
+pub fn new_iterator(state: IterState) -> Object {
+    Object::Iterator(Rc::new(RefCell::new(state)))
+}
+
+// Helper: Arrays, Hashes (their keys) and Strings (their chars) iterate implicitly
+pub fn to_iterator(obj: &Object) -> Option<Object> {
+    match obj {
+        Object::Iterator(_) => Some(obj.clone()),
+        Object::Array(items) => Some(new_iterator(IterState::Array { items: items.clone(), pos: 0 })),
+        Object::Hash(hash) | Object::Module { exports: hash, .. } | Object::Record { fields: hash, .. } => {
+            // In key order, the same as keys() and printing
+            let keys = hash.sorted_pairs().into_iter().map(|(key, _)| hash_key_to_object(key)).collect();
+            Some(new_iterator(IterState::Array { items: keys, pos: 0 }))
+        },
+        Object::String(s) => Some(new_iterator(IterState::Chars { chars: s.chars().collect(), pos: 0 })),
+        _ => None,
+    }
+}
+
+// A native function from the builtins table. Its parameters are checked
+// against the arguments before `func` runs, so `func` may rely on them.
+#[derive(Debug)]
+pub struct BuiltinDef {
+    pub name: &'static str,
+    pub params: &'static [Param],
+    // How many of `params` a call must pass; the rest are optional
+    pub required: usize,
+    // The last parameter takes any number of values (print(values...))
+    pub variadic: bool,
+    // What it does; help() and doc() put the signature in front
+    pub doc: &'static str,
+    pub func: fn(Vec<Object>) -> Object,
+}
+
+// One builtin parameter: its name (for docs) and the type names it accepts (empty: any type)
+#[derive(Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub types: &'static [&'static str],
+}
+
+impl BuiltinDef {
+    // e.g. "join(array, sep)" or "print(values...)"
+    pub fn signature(&self) -> String {
+        let names: Vec<&str> = self.params.iter().map(|p| p.name).collect();
+        let dots = if self.variadic { "..." } else { "" };
+        format!("{}({}{})", self.name, names.join(", "), dots)
+    }
+}
+
+// Builtin names are unique, and comparing function pointers isn't reliable
+impl PartialEq for BuiltinDef {
+    fn eq(&self, other: &BuiltinDef) -> bool {
+        self.name == other.name
+    }
+}
+
+// A hash's entries, plus an optional value handed out for missing keys
+// (see hash_with_default / counter)
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct HashObject {
+    pub pairs: HashMap<HashKey, Object>,
+    pub default: Option<Box<Object>>,
+}
+
+impl HashObject {
+    pub fn new(pairs: HashMap<HashKey, Object>) -> HashObject {
+        HashObject { pairs, default: None }
+    }
+
+    // Entries in key order (integers, then booleans, then strings): the one order
+    // used wherever a hash is shown or taken apart, so output is stable
+    pub fn sorted_pairs(&self) -> Vec<(&HashKey, &Object)> {
+        let mut pairs: Vec<(&HashKey, &Object)> = self.pairs.iter().collect();
+        pairs.sort_by(|a, b| a.0.cmp(b.0));
+        pairs
+    }
+
+    // Lookup as seen by indexing: a missing key yields the default, if any
+    pub fn lookup(&self, key: &HashKey) -> Object {
+        match self.pairs.get(key) {
+            Some(obj) => obj.clone(),
+            None => match &self.default {
+                Some(default) => (**default).clone(),
+                None => Object::Null,
+            },
+        }
+    }
+}
+
+// Results of a memoized function, keyed by the full argument list.
+// With a limit set, the oldest entry is evicted first.
+#[derive(Debug, PartialEq)]
+pub struct MemoCache {
+    entries: HashMap<Vec<HashKey>, Object>,
+    order: VecDeque<Vec<HashKey>>,
+    max_entries: Option<usize>,
+}
+
+impl MemoCache {
+    pub fn new(max_entries: Option<usize>) -> MemoCache {
+        MemoCache { entries: HashMap::new(), order: VecDeque::new(), max_entries }
+    }
+
+    pub fn get(&self, key: &[HashKey]) -> Option<Object> {
+        self.entries.get(key).cloned()
+    }
+
+    pub fn insert(&mut self, key: Vec<HashKey>, value: Object) {
+        if let Some(max) = self.max_entries
+            && self.entries.len() >= max
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.entries.remove(&oldest);
+        }
+        if self.entries.insert(key.clone(), value).is_none() {
+            self.order.push_back(key);
+        }
+    }
+}
+
+impl Object {
+    // The name used for this kind of value in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Char(_) => "CHAR",
+            Object::Return(_) => "RETURN_VALUE",
+            Object::Break(_) => "BREAK",
+            Object::Continue => "CONTINUE",
+            Object::Error(_) => "ERROR",
+            Object::Null => "NULL",
+            Object::Function { .. } | Object::Memoized { .. } => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Module { .. } => "MODULE",
+            Object::Record { kind: crate::ast::RecordKind::Material, .. } => "MATERIAL",
+            Object::Record { kind: crate::ast::RecordKind::Context, .. } => "CONTEXT",
+            Object::Iterator(_) => "ITERATOR",
+            #[cfg(feature = "vm")]
+            Object::CompiledFunction(_) => "COMPILED_FUNCTION",
+        }
+    }
+
+    // Hashes, modules (through their exports) and records (through their
+    // fields) all read like a hash
+    pub fn as_hash(&self) -> Option<&HashObject> {
+        match self {
+            Object::Hash(hash) => Some(hash),
+            Object::Module { exports, .. } => Some(exports),
+            Object::Record { fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Object::Integer(val) => write!(f, "{}", val),
+            Object::Float(val) => write!(f, "{}", format_float(*val)),
             Object::Boolean(val) => write!(f, "{}", val),
             Object::String(val) => write!(f, "{}", val),
-            Object::Return(val) => write!(f, "{}", val),
+            Object::Char(val) => write!(f, "{}", val),
+            Object::Return(val) | Object::Break(val) => write!(f, "{}", val),
+            Object::Continue => write!(f, "continue"),
             Object::Error(val) => write!(f, "ERROR: {}", val),
             Object::Null => write!(f, "null"),
             Object::Function { .. } => write!(f, "fn(...)"),
@@ -46,22 +265,116 @@ impl fmt::Display for Object {
                 write!(f, "[{}]", params.join(", "))
             },
             // NEW: Print format for Hashes
-            Object::Hash(pairs) => {
+            Object::Hash(hash) => {
                 let mut str_pairs = Vec::new();
-                for (key, value) in pairs {
-                    let key_str = match key {
-                        HashKey::Integer(i) => i.to_string(),
-                        HashKey::Boolean(b) => b.to_string(),
-                        HashKey::String(s) => format!("\"{}\"", s), // Quote string keys
-                    };
-                    str_pairs.push(format!("{}: {}", key_str, value));
+                for (key, value) in hash.sorted_pairs() {
+                    str_pairs.push(format!("{}: {}", key, value));
                 }
                 write!(f, "{{{}}}", str_pairs.join(", "))
             },
+            Object::Module { name, path, .. } => write!(f, "<module \"{}\" from \"{}\">", name, path),
+            // As declared: `material Steel {density: 7850, grade: S355}`, fields in key order
+            Object::Record { kind, name, fields } => {
+                let fields: Vec<String> = fields.sorted_pairs().into_iter().map(|(key, value)| match key {
+                    HashKey::String(field) => format!("{}: {}", field, value),
+                    other => format!("{}: {}", other, value),
+                }).collect();
+                write!(f, "{} {} {{{}}}", kind.keyword(), name, fields.join(", "))
+            },
+            Object::Memoized { function, .. } => write!(f, "memoized {}", function),
+            Object::Iterator(_) => write!(f, "[iterator]"),
+            #[cfg(feature = "vm")]
+            Object::CompiledFunction(_) => write!(f, "[compiled function]"),
         }
     }
 }
 
+// A key as written in a hash literal: string keys are quoted, so 1 and "1" differ
+impl fmt::Display for HashKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HashKey::Integer(i) => write!(f, "{}", i),
+            HashKey::Boolean(b) => write!(f, "{}", b),
+            HashKey::String(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+// The one float format, shared by both engines, printing and JSON: the
+// shortest text that reads back as the same value. Whole floats keep a ".0"
+// so they don't read as integers, very large or small ones use an exponent
+// (2.0, 0.1, -0.0, 1e300, 1e-7), and the non-finite values are NaN,
+// Infinity and -Infinity.
+pub fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+// Integer `base ** exponent`, shared by both engines so they fail alike
+pub fn integer_power(base: i64, exponent: i64) -> Result<i64, String> {
+    if exponent < 0 {
+        return Err(format!("Negative exponent: {} ** {} (integer powers need an exponent of 0 or more)", base, exponent));
+    }
+    u32::try_from(exponent).ok()
+        .and_then(|exponent| base.checked_pow(exponent))
+        .ok_or_else(|| format!("Integer overflow: {} ** {}", base, exponent))
+}
+
+// Integer `+`, `-` and `*`, shared by both engines so they fail alike: an
+// error naming the operation when the result doesn't fit in an i64
+pub fn integer_add(l: i64, r: i64) -> Result<i64, String> {
+    l.checked_add(r).ok_or_else(|| format!("Integer overflow: {} + {}", l, r))
+}
+
+pub fn integer_subtract(l: i64, r: i64) -> Result<i64, String> {
+    l.checked_sub(r).ok_or_else(|| format!("Integer overflow: {} - {}", l, r))
+}
+
+pub fn integer_multiply(l: i64, r: i64) -> Result<i64, String> {
+    l.checked_mul(r).ok_or_else(|| format!("Integer overflow: {} * {}", l, r))
+}
+
+// Integer `-x`: i64::MIN has no positive counterpart
+pub fn integer_negate(value: i64) -> Result<i64, String> {
+    value.checked_neg().ok_or_else(|| format!("Integer overflow: -({})", value))
+}
+
+// Integer `/`, shared by both engines so they fail alike: rounding toward
+// zero, and an error rather than a panic for a zero divisor or i64::MIN / -1
+pub fn integer_divide(dividend: i64, divisor: i64) -> Result<i64, String> {
+    if divisor == 0 {
+        return Err("Division by zero".to_string());
+    }
+    dividend.checked_div(divisor).ok_or_else(|| format!("Integer overflow: {} / {}", dividend, divisor))
+}
+
+// Whether a value used as a condition counts as true, shared by both engines
+// (and by `!`): false and null don't, every other value does. An error is
+// neither; it stops the program, with its own message, rather than quietly
+// picking a branch.
+pub fn is_truthy(obj: &Object) -> Result<bool, String> {
+    match obj {
+        Object::Boolean(b) => Ok(*b),
+        Object::Null => Ok(false),
+        Object::Error(msg) => Err(msg.clone()),
+        _ => Ok(true),
+    }
+}
+
+// Helper: Turn a HashKey back into the Object it was made from
+pub fn hash_key_to_object(key: &HashKey) -> Object {
+    match key {
+        HashKey::Integer(i) => Object::Integer(*i),
+        HashKey::Boolean(b) => Object::Boolean(*b),
+        HashKey::String(s) => Object::String(s.clone()),
+    }
+}
+
 // Helper: Try to convert an Object into a HashKey
 pub fn get_hash_key(obj: &Object) -> Option<HashKey> {
     match obj {
@@ -70,4 +383,108 @@ pub fn get_hash_key(obj: &Object) -> Option<HashKey> {
         Object::String(s) => Some(HashKey::String(s.clone())),
         _ => None,
     }
-}
\ No newline at end of file
+}
+
+// `left[index]` in both engines: out of range is null, and a hash's missing
+// key is its default (see hash_with_default)
+pub fn index(left: Object, index: Object) -> Object {
+    match (left, index) {
+        (Object::Array(arr), Object::Integer(idx)) => {
+            if idx < 0 || idx >= arr.len() as i64 { return Object::Null; }
+            crate::stats::cloned(&arr[idx as usize])
+        },
+        // Strings index by character, like len() counts them, giving a one-character string
+        (Object::String(s), Object::Integer(idx)) => {
+            if idx < 0 { return Object::Null; }
+            match s.chars().nth(idx as usize) {
+                Some(c) => Object::String(c.to_string()),
+                None => Object::Null,
+            }
+        },
+        (Object::Hash(hash) | Object::Module { exports: hash, .. } | Object::Record { fields: hash, .. }, index_obj) => {
+            match get_hash_key(&index_obj) {
+                Some(key) => hash.lookup(&key),
+                None => Object::Error(format!("Unusable as hash key: {}", index_obj)),
+            }
+        },
+        _ => Object::Error("Index operator not supported".to_string()),
+    }
+}
+
+// `container` with the element at keys[0]..keys[n] replaced by `value`
+pub fn set_index(container: Object, keys: &[Object], value: Object) -> Result<Object, Object> {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => return Ok(value),
+    };
+    let value = if rest.is_empty() {
+        value
+    } else {
+        let inner = index(container.clone(), key.clone());
+        if let Object::Error(_) = inner { return Err(inner); }
+        set_index(inner, rest, value)?
+    };
+    match (container, key) {
+        (Object::Array(mut arr), Object::Integer(i)) => {
+            let i = *i;
+            if i < 0 || i >= arr.len() as i64 {
+                return Err(Object::Error(format!("Index {} out of range for array of length {}", i, arr.len())));
+            }
+            arr[i as usize] = value;
+            Ok(Object::Array(arr))
+        },
+        (Object::Hash(mut hash), key) => match get_hash_key(key) {
+            Some(k) => {
+                hash.pairs.insert(k, value);
+                Ok(Object::Hash(hash))
+            },
+            None => Err(Object::Error(format!("Unusable as hash key: {}", key))),
+        },
+        _ => Err(Object::Error("Index assignment not supported".to_string())),
+    }
+}
+
+// The one notion of equality, shared by `==` and `!=` in both engines, match
+// patterns, `in`, unique(), count() and deep_equal():
+// - numbers compare by value, so 1 equals 1.0, and NaN equals nothing, itself included
+// - arrays are equal when they have the same length and equal elements in the same order
+// - hashes are equal when they have the same keys with equal values, in any
+//   order, and the same default for missing keys (see hash_with_default)
+// - values of different types are never equal (the interpreter's `==` reports
+//   two scalars of different types as a mismatch before it gets here)
+// - records are equal when they have the same kind, name and equal fields
+// - anything else (functions, modules, iterators) compares structurally
+pub fn objects_equal(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Integer(l), Object::Float(r)) | (Object::Float(r), Object::Integer(l)) => *l as f64 == *r,
+        (Object::Array(l), Object::Array(r)) => l.len() == r.len() && l.iter().zip(r).all(|(l, r)| objects_equal(l, r)),
+        (Object::Hash(l), Object::Hash(r)) => hashes_equal(l, r),
+        (Object::Record { kind: lk, name: ln, fields: l }, Object::Record { kind: rk, name: rn, fields: r }) => {
+            lk == rk && ln == rn && hashes_equal(l, r)
+        },
+        _ => a == b,
+    }
+}
+
+fn hashes_equal(l: &HashObject, r: &HashObject) -> bool {
+    let defaults_equal = match (&l.default, &r.default) {
+        (Some(l), Some(r)) => objects_equal(l, r),
+        (None, None) => true,
+        _ => false,
+    };
+    defaults_equal
+        && l.pairs.len() == r.pairs.len()
+        && l.pairs.iter().all(|(key, value)| r.pairs.get(key).is_some_and(|other| objects_equal(value, other)))
+}
+
+// A key two values share exactly when objects_equal says they are equal, for
+// the values that can have one: a whole float shares its integer's key. Numbers
+// beyond 2^53, where integer and float comparison rounds, get none.
+pub fn equality_key(obj: &Object) -> Option<HashKey> {
+    const EXACT: u64 = 1 << 53;
+    match obj {
+        Object::Integer(i) if i.unsigned_abs() > EXACT => None,
+        Object::Float(f) if f.fract() == 0.0 && f.abs() <= EXACT as f64 => Some(HashKey::Integer(*f as i64)),
+        _ => get_hash_key(obj),
+    }
+}
@@ -1,62 +1,305 @@
-use crate::token::{Token, TokenType};
-use crate::ast::{Statement, Expression, BlockStatement, HashLiteral};
+use crate::token::{Span, Token, TokenType};
+use crate::intern::Symbol;
+use std::fmt;
+use crate::ast::{Statement, Expression, BlockStatement, HashLiteral, MatchArm, Pattern, Program, RecordKind};
 
+// Loosest first. Prefix `-` and `!` sit above every binary operator and below
+// calls and indexing, so `-a * b` is `(-a) * b` and `-a[0]` is `-(a[0])`.
 #[derive(PartialEq, PartialOrd)]
 enum Precedence {
-    Lowest, Equals, LessGreater, Sum, Product, Prefix, Call, Index,
+    Lowest, Ternary, Range, Or, And, Equals, LessGreater, Sum, Product, Power, Prefix, Call, Index,
 }
 
 fn token_precedence(t: &TokenType) -> Precedence {
     match t {
+        TokenType::Question => Precedence::Ternary,
+        TokenType::DotDot | TokenType::DotDotEq => Precedence::Range,
+        TokenType::Or => Precedence::Or,
+        TokenType::And => Precedence::And,
         TokenType::Eq | TokenType::NotEq => Precedence::Equals,
-        TokenType::Lt | TokenType::Gt => Precedence::LessGreater,
+        TokenType::Lt | TokenType::Gt | TokenType::LtEq | TokenType::GtEq => Precedence::LessGreater,
         TokenType::Plus | TokenType::Minus => Precedence::Sum,
         TokenType::Slash | TokenType::Asterisk => Precedence::Product,
+        TokenType::Power => Precedence::Power,
         TokenType::LParen => Precedence::Call,
-        TokenType::LBracket => Precedence::Index,
+        TokenType::LBracket | TokenType::Dot => Precedence::Index,
         _ => Precedence::Lowest,
     }
 }
 
+// `in` is an identifier to the lexer, but a comparison between two expressions
+fn infix_precedence(token: &Token) -> Precedence {
+    if token.token_type == TokenType::Identifier && token.literal == "in" { Precedence::LessGreater } else { token_precedence(&token.token_type) }
+}
+
+// Which keyword set a script is parsed with.
+// Compatible (the default) keeps words added after 1.0 usable as names and only
+// treats them as keywords where the grammar needs them; Strict reserves them everywhere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edition {
+    Compatible,
+    Strict,
+}
+
+impl Edition {
+    pub fn from_name(name: &str) -> Option<Edition> {
+        match name {
+            "compatible" => Some(Edition::Compatible),
+            "strict" => Some(Edition::Strict),
+            _ => None,
+        }
+    }
+}
+
+// Keywords introduced after 1.0, which older scripts may use as variable names
+const CONTEXTUAL_KEYWORDS: &[(&str, TokenType)] = &[
+    ("match", TokenType::Match),
+    ("break", TokenType::Break),
+    ("continue", TokenType::Continue),
+    ("defer", TokenType::Defer),
+    ("for", TokenType::For),
+    ("material", TokenType::Material),
+    ("context", TokenType::Context),
+    // An alias of `mut`, for everyone who types `let` out of habit
+    ("let", TokenType::Mut),
+];
+
+// A problem in the source and the token it was found at. Shown as
+// "line 12:5: expected ')' but got '{'".
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
 pub struct Parser {
     l: crate::lexer::Lexer,
+    edition: Edition,
     cur_token: Token,
     peek_token: Token,
-    pub errors: Vec<String>,
+    // The peek token starts a new line (so it can't continue the current expression)
+    peek_on_new_line: bool,
+    // How many (), [] or hash {} we are inside. At 0 a line break ends the
+    // current statement; inside them expressions may run over several lines.
+    nesting: usize,
+    // How many loop bodies we are inside within the current function (or
+    // deferred block); `break` and `continue` need at least one
+    loops: usize,
+    // Brackets of any kind opened and not yet closed, up to the current token,
+    // innermost last
+    brackets: Vec<TokenType>,
+    // Reading what may turn out to be a for loop's names (`for (k, v in h)`
+    // with `for` as an identifier), where `in` ends an expression instead of
+    // testing membership
+    in_ends_expression: bool,
+    // The expression about to be parsed starts an expression statement, so an
+    // `=` after it may assign to it (`grid[0] = 1`); anywhere else `=` is an error
+    assignable: bool,
+    // The first `max_errors` errors, in the order they were found
+    errors: Vec<ParseError>,
+    // Every error found, kept or not
+    error_count: usize,
+    max_errors: usize,
+    // Tokens read, and how many of them were characters Flux has no use for
+    tokens_read: usize,
+    illegal_tokens: usize,
 }
 
+// Errors a parser keeps unless told otherwise. Past that they are only counted,
+// so a binary file can't fill memory (or a terminal) with them.
+pub const DEFAULT_MAX_ERRORS: usize = 100;
+
 impl Parser {
-    pub fn new(mut l: crate::lexer::Lexer) -> Parser {
-        let cur_token = l.next_token();
-        let peek_token = l.next_token();
-        Parser { l, cur_token, peek_token, errors: vec![] }
+    pub fn new(l: crate::lexer::Lexer) -> Parser {
+        Parser::with_edition(l, Edition::Compatible)
+    }
+
+    pub fn with_edition(l: crate::lexer::Lexer, edition: Edition) -> Parser {
+        let eof = Token { token_type: TokenType::EOF, literal: String::new(), line: 1, column: 1, span: Span::default() };
+        let mut p = Parser {
+            l, edition, cur_token: eof.clone(), peek_token: eof, peek_on_new_line: false, nesting: 0, loops: 0, brackets: vec![], in_ends_expression: false, assignable: false,
+            errors: vec![], error_count: 0, max_errors: DEFAULT_MAX_ERRORS, tokens_read: 0, illegal_tokens: 0,
+        };
+        p.next_token();
+        p.next_token();
+        p
+    }
+
+    pub fn with_max_errors(mut self, max_errors: usize) -> Parser {
+        self.max_errors = max_errors;
+        self
+    }
+
+    fn error(&mut self, error: ParseError) {
+        self.error_count += 1;
+        if self.errors.len() < self.max_errors {
+            self.errors.push(error);
+        }
+    }
+
+    // What to say after the kept errors when there were more: how many, and,
+    // if a quarter of the input is characters Flux doesn't use, that it may not be Flux
+    fn error_summary(&self) -> Option<String> {
+        let dropped = self.error_count.saturating_sub(self.errors.len());
+        if dropped == 0 { return None; }
+        let noun = if dropped == 1 { "error" } else { "errors" };
+        let mut summary = format!("... and {} more {}", thousands(dropped), noun);
+        if self.illegal_tokens * 4 >= self.tokens_read {
+            summary.push_str(&format!(
+                "\n{} of the {} tokens are characters Flux doesn't use; is this a Flux source file?",
+                thousands(self.illegal_tokens), thousands(self.tokens_read)
+            ));
+        }
+        Some(summary)
     }
 
     fn next_token(&mut self) {
-        self.cur_token = self.peek_token.clone();
-        self.peek_token = self.l.next_token();
+        let mut tok = self.l.next_token();
+        if tok.token_type != TokenType::EOF {
+            self.tokens_read += 1;
+            if tok.token_type == TokenType::Illegal { self.illegal_tokens += 1; }
+        }
+        self.peek_on_new_line = self.l.newline_before();
+        if self.edition == Edition::Strict
+            && tok.token_type == TokenType::Identifier
+            && let Some(keyword) = contextual_keyword(&tok.literal)
+        {
+            tok.token_type = keyword;
+        }
+        self.cur_token = std::mem::replace(&mut self.peek_token, tok);
+        match self.cur_token.token_type {
+            TokenType::LParen | TokenType::LBracket | TokenType::LBrace => self.brackets.push(self.cur_token.token_type),
+            TokenType::RParen => self.close_bracket(TokenType::LParen),
+            TokenType::RBracket => self.close_bracket(TokenType::LBracket),
+            TokenType::RBrace => self.close_bracket(TokenType::LBrace),
+            _ => {},
+        }
     }
 
-    pub fn parse_program(&mut self) -> Vec<Statement> {
-        let mut program = vec![];
+    // A closer closes its opener and anything left open inside it; one
+    // that matches nothing open (the `]` in `{ ] }`) closes nothing, so a
+    // typo can't make a block's `}` look like it belongs to something else
+    fn close_bracket(&mut self, opener: TokenType) {
+        if let Some(pos) = self.brackets.iter().rposition(|open| *open == opener) {
+            self.brackets.truncate(pos);
+        }
+    }
+
+    // Under the compatible edition `word` is a keyword here only if the current
+    // token spells it; callers check the grammatical position themselves.
+    fn cur_is_contextual(&self, word: &str) -> bool {
+        self.cur_token.token_type == TokenType::Identifier && self.cur_token.literal == word
+    }
+
+    // A strict-edition keyword used where a name belongs (reported once per word)
+    fn reserved_word_error(&mut self, word: &str) {
+        let msg = format!(
+            "'{}' is a keyword in the strict edition and can't be used as a name; rename it (e.g. '{}_') or run with --edition compatible",
+            word, word
+        );
+        if !self.errors.iter().any(|e| e.message == msg) {
+            self.error(located(&self.cur_token, &msg));
+        }
+    }
+
+    // Check `has_errors` before running the result: with errors it holds only
+    // the statements that parsed
+    pub fn parse_program(&mut self) -> Program {
+        let mut statements = vec![];
         while self.cur_token.token_type != TokenType::EOF {
-            if let Some(stmt) = self.parse_statement() {
-                program.push(stmt);
+            if let Some(stmt) = self.parse_statement_or_recover() {
+                statements.push(stmt);
+            }
+            self.next_token();
+        }
+        let summary = self.error_summary();
+        Program { statements, errors: std::mem::take(&mut self.errors), summary }
+    }
+
+    // A statement, or None after skipping to the end of one that didn't parse
+    // (its errors are recorded), so the next statement starts cleanly. An
+    // empty statement is None too, and has nothing to skip.
+    fn parse_statement_or_recover(&mut self) -> Option<Statement> {
+        let opens = matches!(self.cur_token.token_type, TokenType::LParen | TokenType::LBracket | TokenType::LBrace);
+        let base = self.brackets.len() - usize::from(opens);
+        let stmt = self.parse_statement();
+        if stmt.is_none() { self.synchronize(base); }
+        stmt
+    }
+
+    // Skip what is left of a broken statement that started with `base`
+    // brackets open: once the brackets it opened are closed, up to a `;` or a
+    // line break, leaving a `}` that closes the enclosing block for the block
+    // to see. A statement keyword ends it even inside brackets, so one missing
+    // ')' doesn't swallow the rest of the file.
+    fn synchronize(&mut self, base: usize) {
+        loop {
+            let level = self.brackets.len() <= base;
+            match self.cur_token.token_type {
+                TokenType::EOF => return,
+                TokenType::Semicolon if level => return,
+                _ => {},
+            }
+            let keyword = matches!(
+                self.peek_token.token_type,
+                TokenType::EOF | TokenType::Mut | TokenType::Return | TokenType::Break | TokenType::Continue | TokenType::Defer
+            );
+            if keyword || (level && (self.peek_on_new_line || self.peek_token.token_type == TokenType::RBrace)) {
+                return;
             }
             self.next_token();
         }
-        program
     }
 
     fn parse_statement(&mut self) -> Option<Statement> {
         match self.cur_token.token_type {
             TokenType::Mut => self.parse_let_statement(),
             TokenType::Return => self.parse_return_statement(),
+            TokenType::Break | TokenType::Continue if self.peek_token.token_type == TokenType::Assign => {
+                let word = self.cur_token.literal.clone();
+                self.reserved_word_error(&word);
+                None
+            },
+            TokenType::Break => self.parse_break_statement(),
+            TokenType::Continue => self.parse_continue_statement(),
+            TokenType::Defer => self.parse_defer_statement(),
+            // `fn name(...)` declares; a bare `fn(...)` is still a function literal
+            TokenType::Fn if self.peek_token.token_type == TokenType::Identifier => self.parse_function_statement(),
+            TokenType::Material | TokenType::Context => self.parse_record_statement(),
+            // An empty statement, as in `x = 1;;`
+            TokenType::Semicolon => None,
             // NEW: Check for Assignment (Identifier followed by =)
             TokenType::Identifier => {
                 if self.peek_token.token_type == TokenType::Assign {
                     return self.parse_assignment_statement();
                 }
+                if self.peek_token.token_type.compound_operator().is_some() {
+                    return self.parse_compound_assignment();
+                }
+                // Compatible edition: `break`/`continue` at the start of a statement, unless
+                // what follows makes it a name (`break + 1`)
+                if self.cur_is_contextual("break") && !self.loop_word_is_name() { return self.parse_break_statement(); }
+                if self.cur_is_contextual("continue") && !self.loop_word_is_name() { return self.parse_continue_statement(); }
+                // Compatible edition: `let name ...` declares, like `mut`; `let` alone is still a name
+                if self.cur_is_contextual("let") && self.peek_token.token_type == TokenType::Identifier {
+                    return self.parse_let_statement();
+                }
+                if self.cur_is_contextual("defer") && self.peek_token.token_type == TokenType::LBrace {
+                    return self.parse_defer_statement();
+                }
+                // Compatible edition: `material Name {` declares; `material` alone is still a name
+                if (self.cur_is_contextual("material") || self.cur_is_contextual("context"))
+                    && self.peek_token.token_type == TokenType::Identifier
+                {
+                    return self.parse_record_statement();
+                }
                 self.parse_expression_statement()
             },
             _ => self.parse_expression_statement(),
@@ -66,53 +309,276 @@ impl Parser {
     // NEW FUNCTION
     fn parse_assignment_statement(&mut self) -> Option<Statement> {
         // We are currently on the Identifier
-        let name = self.cur_token.literal.clone();
+        let start = self.cur_token.span;
+        let name = Symbol::intern(&self.cur_token.literal);
         
         self.next_token(); // Move to '='
         self.next_token(); // Move to Value
 
         let value = self.parse_expression(Precedence::Lowest)?;
 
+        let span = start.to(self.cur_token.span);
         if self.peek_token.token_type == TokenType::Semicolon {
             self.next_token();
         }
 
-        Some(Statement::Assign { name, value })
+        Some(Statement::Assign { name, value, span })
+    }
+
+    // `x += e` is sugar for `x = x + e` (likewise -=, *=, /=; see ast_lower)
+    fn parse_compound_assignment(&mut self) -> Option<Statement> {
+        let start = self.cur_token.span;
+        let name = Symbol::intern(&self.cur_token.literal);
+        self.next_token();
+        let operator = self.cur_token.token_type.compound_operator()?.to_string();
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
+        let span = start.to(self.cur_token.span);
+        Some(Statement::CompoundAssign { name, operator, value, span })
     }
 
     fn parse_let_statement(&mut self) -> Option<Statement> {
-        self.next_token(); 
+        let keyword = self.cur_token.literal.clone();
+        let start = self.cur_token.span;
+        self.next_token();
+        if self.cur_token.token_type == TokenType::LBracket {
+            return self.parse_let_destructure(start);
+        }
         let name = match self.cur_token.token_type {
-            TokenType::Identifier => self.cur_token.literal.clone(),
-            _ => return None,
+            TokenType::Identifier => Symbol::intern(&self.cur_token.literal),
+            _ => {
+                let word = self.cur_token.literal.clone();
+                if contextual_keyword(&word).is_some() {
+                    self.reserved_word_error(&word);
+                } else {
+                    let msg = format!("expected a name after '{}' but got {}", keyword, describe(&self.cur_token));
+                    self.error(located(&self.cur_token, &msg));
+                }
+                return None;
+            },
         };
-        self.next_token();
-        if self.cur_token.token_type != TokenType::Assign { return None; }
+        if !self.expect_peek(TokenType::Assign) { return None; }
         self.next_token();
         let value = self.parse_expression(Precedence::Lowest)?;
         if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
         Some(Statement::Let { name, value })
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
+    // `mut [a, b] = value`, with the current token on the '['
+    fn parse_let_destructure(&mut self, start: Span) -> Option<Statement> {
+        let mut names = vec![];
+        loop {
+            self.next_token();
+            match self.cur_token.token_type {
+                TokenType::Identifier => names.push(Symbol::intern(&self.cur_token.literal)),
+                TokenType::RBracket if names.is_empty() => {
+                    self.error(located(&self.cur_token, "expected at least one name between '[' and ']'"));
+                    return None;
+                },
+                _ => {
+                    let msg = format!("expected a name in '[...]' but got {}", describe(&self.cur_token));
+                    self.error(located(&self.cur_token, &msg));
+                    return None;
+                },
+            }
+            if self.peek_token.token_type != TokenType::Comma { break; }
+            self.next_token();
+            if self.peek_token.token_type == TokenType::RBracket { break; }
+        }
+        if !self.expect_peek(TokenType::RBracket) { return None; }
+        if !self.expect_peek(TokenType::Assign) { return None; }
         self.next_token();
         let value = self.parse_expression(Precedence::Lowest)?;
+        let span = start.to(self.cur_token.span);
+        if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
+        Some(Statement::LetDestructure { names, value, span })
+    }
+
+    // `fn name(a, b) { ... }`, the same as `mut name = fn(a, b) { ... }`
+    fn parse_function_statement(&mut self) -> Option<Statement> {
+        self.next_token();
+        let name = Symbol::intern(&self.cur_token.literal);
+        if !self.expect_peek(TokenType::LParen) { return None; }
+        let (parameters, variadic) = self.parse_function_parameters()?;
+        if !self.expect_peek(TokenType::LBrace) { return None; }
+        let body = self.parse_function_body();
+        Some(Statement::Function { name, parameters, variadic, body })
+    }
+
+    // `material Steel { density: 7850, grade }`: fields are names, each with a
+    // value or, alone, the variable of that name (as in a hash literal)
+    fn parse_record_statement(&mut self) -> Option<Statement> {
+        let material = self.cur_token.token_type == TokenType::Material || self.cur_is_contextual("material");
+        let kind = if material { RecordKind::Material } else { RecordKind::Context };
+        if !self.expect_peek(TokenType::Identifier) { return None; }
+        let name = Symbol::intern(&self.cur_token.literal);
+        if !self.expect_peek(TokenType::LBrace) { return None; }
+        let opener = self.cur_token.clone();
+        let mut fields: Vec<(Symbol, Expression)> = vec![];
+        while self.peek_token.token_type != TokenType::RBrace {
+            self.next_token();
+            let word = self.cur_token.literal.clone();
+            let identifier = self.cur_token.token_type == TokenType::Identifier;
+            if !identifier && contextual_keyword(&word).is_none() {
+                let msg = format!("expected a field name in {} {} but got {}", kind.keyword(), name, describe(&self.cur_token));
+                self.error(located(&self.cur_token, &msg));
+                return None;
+            }
+            let field = Symbol::intern(&word);
+            let field_span = self.cur_token.span;
+            if fields.iter().any(|(seen, _)| *seen == field) {
+                let msg = format!("{} {} has two fields named '{}'", kind.keyword(), name, field);
+                self.error(located(&self.cur_token, &msg));
+                return None;
+            }
+            let value = match self.peek_token.token_type {
+                TokenType::Colon => {
+                    self.next_token();
+                    self.next_token();
+                    self.nested(|p| p.parse_expression(Precedence::Lowest))?
+                },
+                _ if identifier => Expression::Identifier { name: field, span: field_span },
+                // A keyword has no variable to stand for
+                _ => {
+                    self.expect_peek(TokenType::Colon);
+                    return None;
+                },
+            };
+            fields.push((field, value));
+            if self.peek_token.token_type == TokenType::RBrace { break; }
+            if self.peek_token.token_type != TokenType::Comma {
+                self.unclosed(&opener, "',' or '}'");
+                return None;
+            }
+            self.next_token();
+        }
+        self.next_token();
+        if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
+        Some(Statement::Record { kind, name, fields })
+    }
+
+    fn parse_return_statement(&mut self) -> Option<Statement> {
+        let value = if self.statement_ends() {
+            None
+        } else {
+            self.next_token();
+            Some(self.parse_expression(Precedence::Lowest)?)
+        };
         if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
         Some(Statement::Return(value))
     }
 
+    fn parse_break_statement(&mut self) -> Option<Statement> {
+        let in_loop = self.check_in_loop();
+        let value = if self.statement_ends() {
+            None
+        } else {
+            self.next_token();
+            Some(self.parse_expression(Precedence::Lowest)?)
+        };
+        if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
+        in_loop.then_some(Statement::Break(value))
+    }
+
+    fn parse_continue_statement(&mut self) -> Option<Statement> {
+        let in_loop = self.check_in_loop();
+        if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
+        in_loop.then_some(Statement::Continue)
+    }
+
+    // Whether the statement ends after the current token, so a bare `return`
+    // or `break` doesn't take the next line as its value
+    fn statement_ends(&self) -> bool {
+        matches!(self.peek_token.token_type, TokenType::Semicolon | TokenType::RBrace | TokenType::EOF)
+            || (self.peek_on_new_line && self.nesting == 0)
+    }
+
+    // Compatible edition, with `break` or `continue` starting a statement: whether
+    // it is a variable in an expression, as in scripts from before they were
+    // keywords. Only when the next token carries on an expression: `continue`
+    // takes no value, so any of them; `break` takes one, so in a loop not those
+    // that could also start it (`break -1`, `break (x)` and `break [x]` are values).
+    fn loop_word_is_name(&self) -> bool {
+        if self.statement_ends() || infix_precedence(&self.peek_token) == Precedence::Lowest {
+            return false;
+        }
+        let starts_value = matches!(self.peek_token.token_type, TokenType::Minus | TokenType::LParen | TokenType::LBracket);
+        self.cur_token.literal == "continue" || self.loops == 0 || !starts_value
+    }
+
+    // With `break` or `continue` as the current token: false (and an error) if
+    // there is no loop for it to leave. A function's body doesn't see the loops
+    // around the function.
+    fn check_in_loop(&mut self) -> bool {
+        if self.loops > 0 { return true; }
+        let msg = format!("'{}' outside of a loop: it only works inside a while or for body", self.cur_token.literal);
+        self.error(located(&self.cur_token, &msg));
+        false
+    }
+
+    fn parse_defer_statement(&mut self) -> Option<Statement> {
+        if !self.expect_peek(TokenType::LBrace) { return None; }
+        let body = self.parse_function_body();
+        if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
+        Some(Statement::Defer(body))
+    }
+
     fn parse_expression_statement(&mut self) -> Option<Statement> {
+        self.assignable = true;
         let expr = self.parse_expression(Precedence::Lowest)?;
+
+        // `target[index] = value` only becomes recognisable once the index expression is parsed
+        if self.peek_token.token_type == TokenType::Assign
+            && let Expression::IndexExpression { left, index, span } = expr
+        {
+            self.next_token(); // Move to '='
+            self.next_token(); // Move to Value
+            let value = self.parse_expression(Precedence::Lowest)?;
+            if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
+            return Some(Statement::IndexAssign { left: *left, index: *index, value, span });
+        }
+
         if self.peek_token.token_type == TokenType::Semicolon { self.next_token(); }
         Some(Statement::Expression(expr))
     }
 
     fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+        // Where the expression starts, and so every operation built on its left side
+        let start = self.cur_token.span;
+        // Only for this expression, not the ones inside it
+        let assignable = std::mem::take(&mut self.assignable);
         // 1. Prefix
         let left = match self.cur_token.token_type {
-            TokenType::Identifier => Some(Expression::Identifier(self.cur_token.literal.clone())),
-            TokenType::Int => Some(Expression::IntegerLiteral(self.cur_token.literal.parse().unwrap_or(0))),
+            // Compatible edition: `match(x) { ... }` is a match, `match(x)` alone a call
+            TokenType::Identifier if self.cur_is_contextual("match") && self.peek_token.token_type == TokenType::LParen => {
+                self.parse_match_or_call()
+            },
+            // Compatible edition: `for(...)` is a loop when it has a loop header, a call otherwise
+            TokenType::Identifier if self.cur_is_contextual("for") && self.peek_token.token_type == TokenType::LParen => {
+                self.parse_for_or_call()
+            },
+            TokenType::Identifier => Some(Expression::Identifier { name: Symbol::intern(&self.cur_token.literal), span: start }),
+            TokenType::Int => self.parse_integer_literal(),
+            TokenType::Float => self.parse_float_literal(),
+            TokenType::Illegal if self.cur_token.literal.starts_with(|c: char| c.is_ascii_digit()) => {
+                self.error(located(&self.cur_token, &format!("malformed number literal: '{}'", self.cur_token.literal)));
+                None
+            },
             TokenType::String => Some(Expression::StringLiteral(self.cur_token.literal.clone())),
+            TokenType::UnterminatedString => {
+                self.error(located(&self.cur_token, "unterminated string literal: the file ends before its closing '\"'"));
+                None
+            },
+            TokenType::Char => self.parse_char_literal(),
+            TokenType::Illegal if self.cur_token.literal == "'" => {
+                self.error(located(&self.cur_token, "unterminated character literal: the line ends before its closing \"'\""));
+                None
+            },
+            TokenType::Illegal => {
+                self.error(located(&self.cur_token, &format!("unexpected character '{}'", self.cur_token.literal)));
+                None
+            },
             TokenType::True => Some(Expression::Boolean(true)),
             TokenType::False => Some(Expression::Boolean(false)),
             TokenType::Bang | TokenType::Minus => self.parse_prefix_expression(),
@@ -120,63 +586,190 @@ impl Parser {
             TokenType::If => self.parse_if_expression(),
             TokenType::Fn => self.parse_function_literal(),
             TokenType::LBracket => self.parse_array_literal(),
-            TokenType::LBrace => self.parse_hash_literal(),
+            TokenType::LBrace => self.parse_hash_or_block(),
             TokenType::While => self.parse_while_expression(),
-            _ => None,
+            TokenType::For if self.peek_token.token_type == TokenType::LParen => self.parse_for_expression(),
+            TokenType::Match if self.peek_token.token_type == TokenType::LParen => self.parse_match_expression(),
+            // Member access needs something on its left
+            TokenType::Dot => {
+                self.error(located(&self.cur_token, "unexpected '.'"));
+                None
+            },
+            TokenType::Match | TokenType::Break | TokenType::Continue | TokenType::Defer | TokenType::For => {
+                let word = self.cur_token.literal.clone();
+                self.reserved_word_error(&word);
+                None
+            },
+            _ => {
+                let msg = format!("no prefix parse function for {} found", describe(&self.cur_token));
+                self.error(located(&self.cur_token, &msg));
+                None
+            },
         };
 
-        if left.is_none() { return None; }
-        let mut left_expr = left.unwrap();
+        let mut left_expr = left?;
 
-        // 2. Infix
-        while self.peek_token.token_type != TokenType::Semicolon && precedence < token_precedence(&self.peek_token.token_type) {
+        // 2. Infix. An operator at the end of a line continues the expression on the
+        // next one, but a line that starts with one is a new statement.
+        while self.peek_token.token_type != TokenType::Semicolon
+            && !(self.peek_on_new_line && self.nesting == 0)
+            && !(self.in_ends_expression && self.peek_is_in())
+            && precedence < infix_precedence(&self.peek_token)
+        {
             match self.peek_token.token_type {
-                TokenType::Plus | TokenType::Minus | TokenType::Slash | TokenType::Asterisk |
-                TokenType::Eq | TokenType::NotEq | TokenType::Lt | TokenType::Gt => {
+                TokenType::Plus | TokenType::Minus | TokenType::Slash | TokenType::Asterisk | TokenType::Power |
+                TokenType::Eq | TokenType::NotEq | TokenType::Lt | TokenType::Gt |
+                TokenType::LtEq | TokenType::GtEq | TokenType::And | TokenType::Or => {
+                    self.next_token();
+                    left_expr = self.parse_infix_expression(left_expr, start)?;
+                },
+                TokenType::Identifier if self.peek_is_in() => {
+                    self.next_token();
+                    left_expr = self.parse_infix_expression(left_expr, start)?;
+                },
+                TokenType::Question => {
+                    self.next_token();
+                    left_expr = self.parse_ternary_expression(left_expr)?;
+                },
+                TokenType::DotDot | TokenType::DotDotEq => {
                     self.next_token();
-                    left_expr = self.parse_infix_expression(left_expr)?;
+                    left_expr = self.parse_range_expression(left_expr, start)?;
                 },
                 TokenType::LParen => {
                     self.next_token();
-                    left_expr = self.parse_call_expression(left_expr)?;
+                    left_expr = self.parse_call_expression(left_expr, start)?;
                 },
                 TokenType::LBracket => {
                     self.next_token();
-                    left_expr = self.parse_index_expression(left_expr)?;
+                    left_expr = self.parse_index_expression(left_expr, start)?;
                 },
-                _ => return Some(left_expr),
+                // Numbers have no fields: `1.foo` or `1._5` is a malformed number, reported at the '.'
+                TokenType::Dot if !matches!(left_expr, Expression::IntegerLiteral { .. } | Expression::FloatLiteral(_)) => {
+                    self.next_token();
+                    left_expr = self.parse_member_expression(left_expr, start)?;
+                },
+                _ => break,
             }
         }
+        // `a[0] += 1` or `f() -= 2`: only a plain variable can take a compound assignment
+        if let Some(op) = self.peek_token.token_type.compound_operator() {
+            let msg = format!("'{}=' needs a variable name on its left, got '{}'", op, left_expr);
+            self.error(located(&self.peek_token, &msg));
+            return None;
+        }
+        // `if (x = 5)` is almost always a comparison gone wrong, so assignment
+        // isn't an expression; a statement may still assign to an index
+        if self.peek_token.token_type == TokenType::Assign && precedence == Precedence::Lowest {
+            let msg = if !assignable {
+                "'=' can't be used inside an expression: assignment is a statement (to compare, use '==')".to_string()
+            } else if matches!(left_expr, Expression::IndexExpression { .. }) {
+                return Some(left_expr);
+            } else {
+                format!("'=' needs a variable name or an index on its left, got '{}'", left_expr)
+            };
+            self.error(located(&self.peek_token, &msg));
+            return None;
+        }
+        // `a ! = b` or `a!b`: a `!` on the same line can't start the next statement
+        if self.peek_token.token_type == TokenType::Bang && !self.peek_on_new_line {
+            self.error(located(&self.peek_token, "unexpected '!' after an expression: '!' only works as a prefix (not-equal is '!=', with no space)"));
+            return None;
+        }
         Some(left_expr)
     }
 
+    fn parse_integer_literal(&mut self) -> Option<Expression> {
+        match self.cur_token.literal.replace('_', "").parse::<i64>() {
+            Ok(value) => Some(Expression::IntegerLiteral { value, raw: self.cur_token.literal.clone() }),
+            Err(_) => {
+                // Never fall back to 0: a silently wrong number is worse than an error
+                self.error(located(&self.cur_token, &format!("integer literal out of range for 64-bit integer: '{}'", self.cur_token.literal)));
+                None
+            }
+        }
+    }
+
+    // 'a', '\n', '\'': exactly one character between the quotes
+    fn parse_char_literal(&mut self) -> Option<Expression> {
+        let mut chars = self.cur_token.literal.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(Expression::CharLiteral(c)),
+            (None, _) => {
+                self.error(located(&self.cur_token, "empty character literal: use a string (\"\") for no characters"));
+                None
+            },
+            _ => {
+                let msg = format!("character literal holds more than one character: '{}' (use a string for text)", self.cur_token.literal);
+                self.error(located(&self.cur_token, &msg));
+                None
+            },
+        }
+    }
+
+    fn parse_float_literal(&mut self) -> Option<Expression> {
+        match self.cur_token.literal.replace('_', "").parse::<f64>() {
+            Ok(value) => Some(Expression::FloatLiteral(value)),
+            Err(_) => {
+                self.error(located(&self.cur_token, &format!("malformed number literal: '{}'", self.cur_token.literal)));
+                None
+            },
+        }
+    }
+
     fn parse_prefix_expression(&mut self) -> Option<Expression> {
+        let start = self.cur_token.span;
         let operator = self.cur_token.literal.clone();
         self.next_token();
         let right = self.parse_expression(Precedence::Prefix)?;
-        Some(Expression::Prefix { operator, right: Box::new(right) })
+        Some(Expression::Prefix { operator, right: Box::new(right), span: start.to(self.cur_token.span) })
     }
 
-    fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
+    fn parse_infix_expression(&mut self, left: Expression, start: Span) -> Option<Expression> {
         let operator = self.cur_token.literal.clone();
-        let precedence = token_precedence(&self.cur_token.token_type);
+        let mut precedence = infix_precedence(&self.cur_token);
+        // Right-associative: 2 ** 3 ** 2 is 2 ** (3 ** 2)
+        if precedence == Precedence::Power { precedence = Precedence::Product; }
         self.next_token();
         let right = self.parse_expression(precedence)?;
-        Some(Expression::Infix { left: Box::new(left), operator, right: Box::new(right) })
+        Some(Expression::Infix { left: Box::new(left), operator, right: Box::new(right), span: start.to(self.cur_token.span) })
+    }
+
+    // `start..end` or `start..=end`: the bounds bind tighter than the range,
+    // so `0..n + 1` ends at n + 1
+    fn parse_range_expression(&mut self, start_expr: Expression, start: Span) -> Option<Expression> {
+        let inclusive = self.cur_token.token_type == TokenType::DotDotEq;
+        self.next_token();
+        let end = self.parse_expression(Precedence::Range)?;
+        Some(Expression::Range { start: Box::new(start_expr), end: Box::new(end), inclusive, span: start.to(self.cur_token.span) })
+    }
+
+    // `c ? a : b` is `if (c) { a } else { b }`. It binds looser than every other
+    // operator and groups to the right, so `a ? b : c ? d : e` chains. The
+    // branches parse on their own, so a hash literal's ':' in one is no trouble.
+    fn parse_ternary_expression(&mut self, condition: Expression) -> Option<Expression> {
+        self.next_token();
+        let consequence = self.parse_expression(Precedence::Lowest)?;
+        if !self.expect_peek(TokenType::Colon) { return None; }
+        self.next_token();
+        let alternative = self.parse_expression(Precedence::Lowest)?;
+        let block = |expr| BlockStatement { statements: vec![Statement::Expression(expr)] };
+        Some(Expression::If { condition: Box::new(condition), consequence: block(consequence), alternative: Some(block(alternative)) })
     }
 
     fn parse_grouped_expression(&mut self) -> Option<Expression> {
+        let opener = self.cur_token.clone();
         self.next_token();
-        let exp = self.parse_expression(Precedence::Lowest);
-        if self.peek_token.token_type == TokenType::RParen { self.next_token(); }
-        exp
+        let exp = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        if !self.expect_closer(&opener) { return None; }
+        Some(exp)
     }
 
     fn parse_if_expression(&mut self) -> Option<Expression> {
         if !self.expect_peek(TokenType::LParen) { return None; }
+        let opener = self.cur_token.clone();
         self.next_token();
-        let condition = self.parse_expression(Precedence::Lowest)?;
-        if !self.expect_peek(TokenType::RParen) { return None; }
+        let condition = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        if !self.closes_condition(&opener) { return None; }
         if !self.expect_peek(TokenType::LBrace) { return None; }
         let consequence = self.parse_block_statement();
         let mut alternative = None;
@@ -190,19 +783,289 @@ impl Parser {
 
     fn parse_while_expression(&mut self) -> Option<Expression> {
         if !self.expect_peek(TokenType::LParen) { return None; }
+        let opener = self.cur_token.clone();
         self.next_token();
-        let condition = self.parse_expression(Precedence::Lowest)?;
-        if !self.expect_peek(TokenType::RParen) { return None; }
+        let condition = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        if !self.closes_condition(&opener) { return None; }
         if !self.expect_peek(TokenType::LBrace) { return None; }
-        let body = self.parse_block_statement();
+        let body = self.parse_loop_body();
         Some(Expression::While { condition: Box::new(condition), body })
     }
 
+    fn parse_for_expression(&mut self) -> Option<Expression> {
+        self.next_token();
+        self.next_token();
+        self.parse_for_header()
+    }
+
+    // `for` spelled as an identifier: a declaration, an assignment, a `;` or an
+    // `in` inside the parentheses makes it a for loop, anything else is a call.
+    fn parse_for_or_call(&mut self) -> Option<Expression> {
+        let start = self.cur_token.span;
+        let function = Expression::Identifier { name: Symbol::intern(&self.cur_token.literal), span: start };
+        self.next_token();
+        self.next_token();
+        let declares = self.cur_token.token_type == TokenType::Mut
+            || (self.cur_is_contextual("let") && self.peek_token.token_type == TokenType::Identifier);
+        let assigns = self.cur_token.token_type == TokenType::Identifier
+            && (self.peek_token.token_type == TokenType::Assign || self.peek_token.token_type.compound_operator().is_some());
+        let iterates = self.cur_token.token_type == TokenType::Identifier && self.peek_is_in();
+        if declares || assigns || iterates || self.cur_token.token_type == TokenType::Semicolon {
+            return self.parse_for_header();
+        }
+        let mut arguments = vec![];
+        if self.cur_token.token_type != TokenType::RParen {
+            let first = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+            // `for (next(); ...)`: an expression can start a header too
+            if self.peek_token.token_type == TokenType::Semicolon {
+                self.next_token();
+                return self.parse_for_clauses(Some(Box::new(Statement::Expression(first))));
+            }
+            arguments.push(first);
+            while self.peek_token.token_type == TokenType::Comma {
+                self.next_token();
+                self.next_token();
+                // After `for (k,` a name followed by `in` is the loop's second name
+                let may_be_names = matches!(arguments.as_slice(), [Expression::Identifier { .. }]);
+                self.in_ends_expression = may_be_names;
+                let argument = self.nested(|p| p.parse_expression(Precedence::Lowest));
+                self.in_ends_expression = false;
+                arguments.push(argument?);
+            }
+            // `for (k, v in h)`: what looked like arguments were the loop's names
+            if let [Expression::Identifier { name: key, .. }, Expression::Identifier { name: value, .. }] = arguments.as_slice()
+                && self.peek_is_in()
+            {
+                return self.parse_for_in(*key, Some(*value));
+            }
+            if !self.expect_peek(TokenType::RParen) { return None; }
+        }
+        Some(Expression::Call { function: Box::new(function), arguments, span: start.to(self.cur_token.span) })
+    }
+
+    // Called with the first token inside `for (` as the current token
+    fn parse_for_header(&mut self) -> Option<Expression> {
+        if self.cur_token.token_type == TokenType::Identifier {
+            let variable = Symbol::intern(&self.cur_token.literal);
+            if self.peek_is_in() { return self.parse_for_in(variable, None); }
+            if self.peek_token.token_type == TokenType::Comma {
+                self.next_token();
+                if !self.expect_peek(TokenType::Identifier) { return None; }
+                let value = Symbol::intern(&self.cur_token.literal);
+                if !self.peek_is_in() {
+                    self.error(located(&self.peek_token, &format!("expected 'in' but got '{}'", self.peek_token.literal)));
+                    return None;
+                }
+                return self.parse_for_in(variable, Some(value));
+            }
+        }
+        let mut init = None;
+        if self.cur_token.token_type != TokenType::Semicolon {
+            init = Some(Box::new(self.nested(|p| p.parse_statement())?));
+            // A statement takes its own `;` when it has one
+            if self.cur_token.token_type != TokenType::Semicolon && !self.expect_peek(TokenType::Semicolon) { return None; }
+        }
+        self.parse_for_clauses(init)
+    }
+
+    // `in` separates a for loop's names from what they iterate over, and is
+    // otherwise the membership operator
+    fn peek_is_in(&self) -> bool {
+        self.peek_token.token_type == TokenType::Identifier && self.peek_token.literal == "in"
+    }
+
+    // Called with the last loop variable as the current token and `in` next
+    fn parse_for_in(&mut self, variable: Symbol, value: Option<Symbol>) -> Option<Expression> {
+        self.next_token();
+        self.next_token();
+        let start = self.cur_token.span;
+        let iterable = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        let span = start.to(self.cur_token.span);
+        if !self.expect_peek(TokenType::RParen) { return None; }
+        if !self.expect_peek(TokenType::LBrace) { return None; }
+        let body = self.parse_loop_body();
+        Some(Expression::ForIn { variable, value, iterable: Box::new(iterable), body, span })
+    }
+
+    // The condition, post statement and body, after the `;` that ends the initializer
+    fn parse_for_clauses(&mut self, init: Option<Box<Statement>>) -> Option<Expression> {
+        let mut condition = None;
+        if self.peek_token.token_type != TokenType::Semicolon {
+            self.next_token();
+            condition = Some(Box::new(self.nested(|p| p.parse_expression(Precedence::Lowest))?));
+        }
+        if !self.expect_peek(TokenType::Semicolon) { return None; }
+        let mut post = None;
+        if self.peek_token.token_type != TokenType::RParen {
+            self.next_token();
+            post = Some(Box::new(self.nested(|p| p.parse_statement())?));
+        }
+        if !self.expect_peek(TokenType::RParen) { return None; }
+        if !self.expect_peek(TokenType::LBrace) { return None; }
+        let body = self.parse_loop_body();
+        Some(Expression::For { init, condition, post, body })
+    }
+
+    // match (subject) { pattern [if guard] => expr_or_block, ... }
+    fn parse_match_expression(&mut self) -> Option<Expression> {
+        let start = self.cur_token.span;
+        if !self.expect_peek(TokenType::LParen) { return None; }
+        self.next_token();
+        let subject = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        if !self.expect_peek(TokenType::RParen) { return None; }
+        let span = start.to(self.cur_token.span);
+        if !self.expect_peek(TokenType::LBrace) { return None; }
+        self.at_statement_level(|p| p.parse_match_arms(subject, span))
+    }
+
+    // `match` spelled as an identifier: only a single parenthesised subject
+    // followed by `{` makes it a match expression, anything else is a call.
+    fn parse_match_or_call(&mut self) -> Option<Expression> {
+        let start = self.cur_token.span;
+        let function = Expression::Identifier { name: Symbol::intern(&self.cur_token.literal), span: start };
+        self.next_token();
+        let mut arguments = self.parse_expression_list(TokenType::RParen)?;
+        if arguments.len() == 1 && self.peek_token.token_type == TokenType::LBrace {
+            let span = start.to(self.cur_token.span);
+            self.next_token();
+            let subject = arguments.remove(0);
+            return self.at_statement_level(|p| p.parse_match_arms(subject, span));
+        }
+        Some(Expression::Call { function: Box::new(function), arguments, span: start.to(self.cur_token.span) })
+    }
+
+    // Called with the opening `{` of the arm list as the current token
+    fn parse_match_arms(&mut self, subject: Expression, span: Span) -> Option<Expression> {
+        let mut arms = vec![];
+        self.next_token();
+        while self.cur_token.token_type != TokenType::RBrace {
+            if self.cur_token.token_type == TokenType::EOF {
+                self.error(located(&self.cur_token, "unterminated match expression"));
+                return None;
+            }
+            let pattern = self.parse_pattern()?;
+            let mut guard = None;
+            if self.peek_token.token_type == TokenType::If {
+                self.next_token();
+                self.next_token();
+                guard = Some(self.parse_expression(Precedence::Lowest)?);
+            }
+            if !self.expect_peek(TokenType::FatArrow) { return None; }
+            self.next_token();
+            // A brace after '=>' is a block body; wrap a hash result in parentheses
+            let body = if self.cur_token.token_type == TokenType::LBrace {
+                self.parse_block_statement()
+            } else {
+                let expr = self.parse_expression(Precedence::Lowest)?;
+                BlockStatement { statements: vec![Statement::Expression(expr)] }
+            };
+            arms.push(MatchArm { pattern, guard, body });
+            if self.peek_token.token_type == TokenType::Comma { self.next_token(); }
+            self.next_token();
+        }
+        Some(Expression::Match { subject: Box::new(subject), arms, span })
+    }
+
+    fn parse_pattern(&mut self) -> Option<Pattern> {
+        match self.cur_token.token_type {
+            TokenType::Identifier if self.cur_token.literal == "_" => Some(Pattern::Wildcard),
+            TokenType::Identifier => Some(Pattern::Binding(Symbol::intern(&self.cur_token.literal))),
+            TokenType::Int | TokenType::Float | TokenType::String | TokenType::UnterminatedString | TokenType::Char | TokenType::True | TokenType::False => {
+                Some(Pattern::Literal(self.parse_expression(Precedence::Prefix)?))
+            },
+            TokenType::Minus if self.peek_token.token_type == TokenType::Int => {
+                self.next_token();
+                match self.parse_integer_literal()? {
+                    Expression::IntegerLiteral { value, raw } => Some(Pattern::Literal(Expression::IntegerLiteral { value: -value, raw: format!("-{}", raw) })),
+                    _ => None,
+                }
+            },
+            TokenType::LBracket => self.parse_array_pattern(),
+            TokenType::LBrace => self.parse_hash_pattern(),
+            _ => {
+                self.error(located(&self.cur_token, &format!("unexpected '{}' in match pattern", self.cur_token.literal)));
+                None
+            },
+        }
+    }
+
+    fn parse_array_pattern(&mut self) -> Option<Pattern> {
+        let mut elements = vec![];
+        let mut rest = None;
+        if self.peek_token.token_type == TokenType::RBracket {
+            self.next_token();
+            return Some(Pattern::Array { elements, rest });
+        }
+        loop {
+            self.next_token();
+            if self.cur_token.token_type == TokenType::Identifier && self.peek_token.token_type == TokenType::DotDotDot {
+                rest = Some(Symbol::intern(&self.cur_token.literal));
+                self.next_token();
+                if self.peek_token.token_type != TokenType::RBracket {
+                    self.error(located(&self.cur_token, "a rest pattern must come last in an array pattern"));
+                    return None;
+                }
+            } else {
+                elements.push(self.parse_pattern()?);
+            }
+            if self.peek_token.token_type != TokenType::Comma { break; }
+            self.next_token();
+        }
+        if !self.expect_peek(TokenType::RBracket) { return None; }
+        Some(Pattern::Array { elements, rest })
+    }
+
+    fn parse_hash_pattern(&mut self) -> Option<Pattern> {
+        let mut pairs = vec![];
+        if self.peek_token.token_type == TokenType::RBrace {
+            self.next_token();
+            return Some(Pattern::Hash(pairs));
+        }
+        loop {
+            self.next_token();
+            let key = match self.cur_token.token_type {
+                TokenType::Int | TokenType::String | TokenType::True | TokenType::False => {
+                    self.parse_expression(Precedence::Prefix)?
+                },
+                _ => {
+                    self.error(located(&self.cur_token, &format!("hash pattern keys must be literals, got '{}'", self.cur_token.literal)));
+                    return None;
+                },
+            };
+            if !self.expect_peek(TokenType::Colon) { return None; }
+            self.next_token();
+            pairs.push((key, self.parse_pattern()?));
+            if self.peek_token.token_type != TokenType::Comma { break; }
+            self.next_token();
+        }
+        if !self.expect_peek(TokenType::RBrace) { return None; }
+        Some(Pattern::Hash(pairs))
+    }
+
     fn parse_block_statement(&mut self) -> BlockStatement {
+        self.at_statement_level(|p| p.parse_block_statements())
+    }
+
+    fn parse_loop_body(&mut self) -> BlockStatement {
+        self.loops += 1;
+        let body = self.parse_block_statement();
+        self.loops -= 1;
+        body
+    }
+
+    // A function's or a deferred block's body, which no `break` can leave
+    fn parse_function_body(&mut self) -> BlockStatement {
+        let loops = std::mem::take(&mut self.loops);
+        let body = self.parse_block_statement();
+        self.loops = loops;
+        body
+    }
+
+    fn parse_block_statements(&mut self) -> BlockStatement {
         self.next_token();
         let mut statements = vec![];
         while self.cur_token.token_type != TokenType::RBrace && self.cur_token.token_type != TokenType::EOF {
-            if let Some(stmt) = self.parse_statement() { statements.push(stmt); }
+            if let Some(stmt) = self.parse_statement_or_recover() { statements.push(stmt); }
             self.next_token();
         }
         BlockStatement { statements }
@@ -210,32 +1073,57 @@ impl Parser {
 
     fn parse_function_literal(&mut self) -> Option<Expression> {
         if !self.expect_peek(TokenType::LParen) { return None; }
-        let parameters = self.parse_function_parameters()?;
+        let (parameters, variadic) = self.parse_function_parameters()?;
         if !self.expect_peek(TokenType::LBrace) { return None; }
-        let body = self.parse_block_statement();
-        Some(Expression::FunctionLiteral { parameters, body })
+        let body = self.parse_function_body();
+        Some(Expression::FunctionLiteral { parameters, variadic, body })
     }
 
-    fn parse_function_parameters(&mut self) -> Option<Vec<String>> {
+    // The names, and whether the last one is a rest parameter (`fn(level, ...args)`)
+    fn parse_function_parameters(&mut self) -> Option<(Vec<Symbol>, bool)> {
         let mut identifiers = vec![];
+        let mut rest: Option<Token> = None;
         if self.peek_token.token_type == TokenType::RParen {
             self.next_token();
-            return Some(identifiers);
+            return Some((identifiers, false));
         }
-        self.next_token();
-        identifiers.push(self.cur_token.literal.clone());
-        while self.peek_token.token_type == TokenType::Comma {
+        loop {
             self.next_token();
+            if let Some(dots) = &rest {
+                self.error(located(dots, "a rest parameter must come last in the parameter list"));
+                return None;
+            }
+            if self.cur_token.token_type == TokenType::DotDotDot {
+                rest = Some(self.cur_token.clone());
+                self.next_token();
+            }
+            identifiers.push(self.parameter_name()?);
+            if self.peek_token.token_type != TokenType::Comma { break; }
             self.next_token();
-            identifiers.push(self.cur_token.literal.clone());
+            // `fn(a, b,)`: a trailing comma before the ')'
+            if self.peek_token.token_type == TokenType::RParen { break; }
         }
         if !self.expect_peek(TokenType::RParen) { return None; }
-        Some(identifiers)
+        Some((identifiers, rest.is_some()))
+    }
+
+    fn parameter_name(&mut self) -> Option<Symbol> {
+        let word = self.cur_token.literal.clone();
+        if self.cur_token.token_type != TokenType::Identifier && contextual_keyword(&word).is_some() {
+            self.reserved_word_error(&word);
+            return None;
+        }
+        if self.cur_token.token_type != TokenType::Identifier {
+            let msg = format!("expected a parameter name but got {}", describe(&self.cur_token));
+            self.error(located(&self.cur_token, &msg));
+            return None;
+        }
+        Some(Symbol::intern(&word))
     }
 
-    fn parse_call_expression(&mut self, function: Expression) -> Option<Expression> {
+    fn parse_call_expression(&mut self, function: Expression, start: Span) -> Option<Expression> {
         let arguments = self.parse_expression_list(TokenType::RParen)?;
-        Some(Expression::Call { function: Box::new(function), arguments })
+        Some(Expression::Call { function: Box::new(function), arguments, span: start.to(self.cur_token.span) })
     }
 
     fn parse_array_literal(&mut self) -> Option<Expression> {
@@ -243,48 +1131,149 @@ impl Parser {
         Some(Expression::ArrayLiteral(elements))
     }
 
+    fn parse_hash_or_block(&mut self) -> Option<Expression> {
+        if self.brace_opens_hash() { return self.parse_hash_literal(); }
+        let opener = self.cur_token.clone();
+        let body = self.parse_block_statement();
+        if self.cur_token.token_type == TokenType::EOF {
+            self.unclosed(&opener, "'}'");
+            return None;
+        }
+        Some(Expression::Block(body))
+    }
+
+    // With `{` as the current token: whether it opens a hash rather than a
+    // block. `{}` is a hash, and so is `{name}` or anything with a ':' or ','
+    // before the end of what would be the block's first statement (a
+    // ternary's ':' aside); the rest are blocks.
+    fn brace_opens_hash(&mut self) -> bool {
+        let start = self.l.checkpoint();
+        let first = self.peek_token.token_type;
+        let mut token = first;
+        let (mut read, mut depth, mut ternaries) = (1, 0, 0);
+        let hash = loop {
+            match token {
+                TokenType::EOF => break false,
+                TokenType::LParen | TokenType::LBracket | TokenType::LBrace => depth += 1,
+                TokenType::RParen | TokenType::RBracket if depth > 0 => depth -= 1,
+                TokenType::RBrace if depth > 0 => depth -= 1,
+                TokenType::RBrace => break read == 1 || (read == 2 && first == TokenType::Identifier),
+                TokenType::Question if depth == 0 => ternaries += 1,
+                TokenType::Colon if depth == 0 && ternaries > 0 => ternaries -= 1,
+                TokenType::Colon | TokenType::Comma if depth == 0 => break true,
+                TokenType::Semicolon | TokenType::Assign | TokenType::Mut | TokenType::Return if depth == 0 => break false,
+                _ => {},
+            }
+            token = self.l.next_token().token_type;
+            read += 1;
+        };
+        self.l.rewind(start);
+        hash
+    }
+
     fn parse_hash_literal(&mut self) -> Option<Expression> {
+        let opener = self.cur_token.clone();
         let mut pairs = Vec::new();
         if self.peek_token.token_type == TokenType::RBrace {
-            self.next_token(); self.next_token();
+            self.next_token();
             return Some(Expression::HashLiteral(HashLiteral { pairs }));
         }
         self.next_token();
         loop {
-            let key = self.parse_expression(Precedence::Lowest)?;
-            if !self.expect_peek(TokenType::Colon) { return None; }
+            pairs.push(self.parse_hash_pair()?);
+            if self.peek_token.token_type == TokenType::RBrace { self.next_token(); break; }
+            if self.peek_token.token_type != TokenType::Comma {
+                self.unclosed(&opener, "',' or '}'");
+                return None;
+            }
             self.next_token();
-            let value = self.parse_expression(Precedence::Lowest)?;
-            pairs.push((key, value));
+            // A trailing comma before the '}'
             if self.peek_token.token_type == TokenType::RBrace { self.next_token(); break; }
-            if !self.expect_peek(TokenType::Comma) { return None; }
             self.next_token();
         }
         Some(Expression::HashLiteral(HashLiteral { pairs }))
     }
 
+    // `key: value`, where a bare name is a string key rather than a variable:
+    // `{host: h}` means `{"host": h}`, and `{host}` alone means `{"host": host}`.
+    // Any other key is an expression (`{1: x}`, `{(name): x}`).
+    fn parse_hash_pair(&mut self) -> Option<(Expression, Expression)> {
+        let word = self.cur_token.literal.clone();
+        let identifier = self.cur_token.token_type == TokenType::Identifier;
+        if (identifier || contextual_keyword(&word).is_some()) && self.peek_token.token_type == TokenType::Colon {
+            self.next_token();
+            self.next_token();
+            let value = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+            return Some((Expression::StringLiteral(word), value));
+        }
+        if identifier && matches!(self.peek_token.token_type, TokenType::Comma | TokenType::RBrace) {
+            let value = Expression::Identifier { name: Symbol::intern(&word), span: self.cur_token.span };
+            return Some((Expression::StringLiteral(word), value));
+        }
+        let key = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        if !self.expect_peek(TokenType::Colon) { return None; }
+        self.next_token();
+        let value = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        Some((key, value))
+    }
+
+    // With the opening bracket as the current token
     fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<Expression>> {
+        let opener = self.cur_token.clone();
         let mut list = vec![];
         if self.peek_token.token_type == end {
             self.next_token();
             return Some(list);
         }
         self.next_token();
-        list.push(self.parse_expression(Precedence::Lowest)?);
+        list.push(self.nested(|p| p.parse_expression(Precedence::Lowest))?);
         while self.peek_token.token_type == TokenType::Comma {
             self.next_token();
+            // `[1, 2,]` or `f(a, b,)`: a trailing comma before the closer
+            if self.peek_token.token_type == end { break; }
             self.next_token();
-            list.push(self.parse_expression(Precedence::Lowest)?);
+            list.push(self.nested(|p| p.parse_expression(Precedence::Lowest))?);
         }
-        if !self.expect_peek(end) { return None; }
+        if !self.expect_closer(&opener) { return None; }
         Some(list)
     }
 
-    fn parse_index_expression(&mut self, left: Expression) -> Option<Expression> {
+    fn parse_index_expression(&mut self, left: Expression, start: Span) -> Option<Expression> {
+        let opener = self.cur_token.clone();
         self.next_token();
-        let index = self.parse_expression(Precedence::Lowest)?;
-        if !self.expect_peek(TokenType::RBracket) { return None; }
-        Some(Expression::IndexExpression { left: Box::new(left), index: Box::new(index) })
+        let index = self.nested(|p| p.parse_expression(Precedence::Lowest))?;
+        if !self.expect_closer(&opener) { return None; }
+        Some(Expression::IndexExpression { left: Box::new(left), index: Box::new(index), span: start.to(self.cur_token.span) })
+    }
+
+    // `object.property`, with the '.' as the current token. A keyword that is
+    // only contextual (`m.match`) still names a field.
+    fn parse_member_expression(&mut self, object: Expression, start: Span) -> Option<Expression> {
+        let named = self.peek_token.token_type == TokenType::Identifier || contextual_keyword(&self.peek_token.literal).is_some();
+        if !named {
+            let msg = format!("expected a field name after '.' but got {}", describe(&self.peek_token));
+            self.error(located(&self.peek_token, &msg));
+            return None;
+        }
+        self.next_token();
+        let property = Symbol::intern(&self.cur_token.literal);
+        Some(Expression::Member { object: Box::new(object), property, span: start.to(self.cur_token.span) })
+    }
+
+    // Parse inside brackets, where line breaks don't end statements
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser) -> T) -> T {
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    // Parse a block or match arms, where line breaks end statements again
+    fn at_statement_level<T>(&mut self, parse: impl FnOnce(&mut Parser) -> T) -> T {
+        let nesting = std::mem::take(&mut self.nesting);
+        let result = parse(self);
+        self.nesting = nesting;
+        result
     }
 
     fn expect_peek(&mut self, t: TokenType) -> bool {
@@ -292,7 +1281,70 @@ impl Parser {
             self.next_token();
             true
         } else {
+            let msg = format!("expected '{}' but got {}", t.spelling(), describe(&self.peek_token));
+            self.error(located(&self.peek_token, &msg));
             false
         }
     }
-}
\ No newline at end of file
+
+    // The ')' after an if or while condition. When the body's '{' comes
+    // instead, the missing ')' is reported and parsing goes on into the body,
+    // so the body isn't mistaken for statements of its own.
+    fn closes_condition(&mut self, opener: &Token) -> bool {
+        self.expect_closer(opener) || self.peek_token.token_type == TokenType::LBrace
+    }
+
+    // expect_peek for the bracket that closes `opener`
+    fn expect_closer(&mut self, opener: &Token) -> bool {
+        let closer = match opener.token_type {
+            TokenType::LParen => TokenType::RParen,
+            TokenType::LBracket => TokenType::RBracket,
+            _ => TokenType::RBrace,
+        };
+        if self.peek_token.token_type == closer {
+            self.next_token();
+            return true;
+        }
+        self.unclosed(opener, &format!("'{}'", closer.spelling()));
+        false
+    }
+
+    // A missing closer is reported where it should have been, naming the
+    // opener it would close. The opener then counts as closed, so recovery
+    // stops at the end of the statement rather than at the next keyword.
+    fn unclosed(&mut self, opener: &Token, expected: &str) {
+        let msg = format!(
+            "expected {} but got {} (unclosed '{}' opened at line {}:{})",
+            expected, describe(&self.peek_token), opener.literal, opener.line, opener.column,
+        );
+        self.error(located(&self.peek_token, &msg));
+        self.close_bracket(opener.token_type);
+    }
+}
+
+fn located(tok: &Token, msg: &str) -> ParseError {
+    ParseError { message: msg.to_string(), line: tok.line, column: tok.column }
+}
+
+// A token as error messages quote it: "'}'", or "end of input"
+fn describe(tok: &Token) -> String {
+    match tok.token_type {
+        TokenType::EOF => "end of input".to_string(),
+        _ => format!("'{}'", tok.literal),
+    }
+}
+
+// 3214 -> "3,214"
+fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) { out.push(','); }
+        out.push(c);
+    }
+    out
+}
+
+fn contextual_keyword(word: &str) -> Option<TokenType> {
+    CONTEXTUAL_KEYWORDS.iter().find(|(name, _)| *name == word).map(|(_, t)| *t)
+}
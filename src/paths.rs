@@ -0,0 +1,121 @@
+// Taking paths apart and putting them together, for the path_* builtins and
+// import(). Both '/' and '\' separate on every host, so a script can handle a
+// Windows path on Unix and the other way round; a path that is joined onto
+// keeps the separator it already uses.
+use std::path::{self, Path, MAIN_SEPARATOR};
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+// "C:" in "C:\temp" or "c:/temp"
+fn drive_len(p: &str) -> usize {
+    let bytes = p.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' { 2 } else { 0 }
+}
+
+// Rooted: "/usr", "\\server\share", "C:\temp"
+pub fn is_absolute(p: &str) -> bool {
+    p[drive_len(p)..].starts_with(is_separator)
+}
+
+// The separator `p` already uses (its first one), if any
+fn separator_of(p: &str) -> Option<char> {
+    p.chars().find(|&c| is_separator(c))
+}
+
+// The root that no trimming may remove: "/", "C:\", "C:" or ""
+fn root_len(p: &str) -> usize {
+    let drive = drive_len(p);
+    if is_absolute(p) { drive + 1 } else { drive }
+}
+
+// Without trailing separators, unless that would eat the root
+fn trim_trailing(p: &str) -> &str {
+    let root = root_len(p);
+    let trimmed = p[root..].trim_end_matches(is_separator);
+    &p[..root + trimmed.len()]
+}
+
+// Each part is added like a directory step; an absolute part starts over, as in
+// PathBuf::push. Empty parts are skipped.
+pub fn join(parts: &[&str]) -> String {
+    let mut joined = String::new();
+    for part in parts.iter().filter(|part| !part.is_empty()) {
+        if is_absolute(part) || joined.is_empty() {
+            joined = part.to_string();
+            continue;
+        }
+        let separator = separator_of(&joined).or_else(|| separator_of(part)).unwrap_or(MAIN_SEPARATOR);
+        if !joined.ends_with(is_separator) && joined.len() > drive_len(&joined) {
+            joined.push(separator);
+        }
+        joined.push_str(part);
+    }
+    joined
+}
+
+// Everything before the last step: "a/b" for "a/b/c.flux", "/" for "/x", "" for "x"
+pub fn dirname(p: &str) -> String {
+    let p = trim_trailing(p);
+    let root = root_len(p);
+    match p[root..].rfind(is_separator) {
+        Some(i) => trim_trailing(&p[..root + i]).to_string(),
+        None => p[..root].to_string(),
+    }
+}
+
+// The last step: "c.flux" for "a/b/c.flux", "b" for "a/b/"
+pub fn basename(p: &str) -> String {
+    let p = trim_trailing(p);
+    let root = root_len(p);
+    let rest = &p[root..];
+    match rest.rfind(is_separator) {
+        Some(i) => rest[i + 1..].to_string(),
+        None => rest.to_string(),
+    }
+}
+
+// The extension without its dot: "flux" for "a/b.flux", "" for "a/.bashrc" or "a/b"
+pub fn extension(p: &str) -> String {
+    let name = basename(p);
+    Path::new(&name).extension().map(|ext| ext.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+// The last step without its extension: "math" for "lib/math.flux"
+pub fn stem(p: &str) -> String {
+    let name = basename(p);
+    Path::new(&name).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or(name)
+}
+
+// Without "." steps, and with "dir/.." pairs folded away: "a/c" for "a/./b/../c".
+// Purely textual, so a ".." after a symlink may not land where the OS would;
+// leading ".." steps of a relative path are kept, and ".." at the root is dropped.
+pub fn normalize(p: &str) -> String {
+    let root = root_len(p);
+    let mut steps: Vec<&str> = vec![];
+    for step in p[root..].split(is_separator) {
+        match step {
+            "" | "." => {},
+            ".." if steps.last().is_some_and(|&last| last != "..") => { steps.pop(); },
+            ".." if root > drive_len(p) => {},
+            _ => steps.push(step),
+        }
+    }
+    let separator = separator_of(p).unwrap_or(MAIN_SEPARATOR).to_string();
+    let normalized = format!("{}{}", &p[..root], steps.join(&separator));
+    if normalized.is_empty() { ".".to_string() } else { normalized }
+}
+
+// `p` as written in `file`: relative to the directory `file` is in, unless
+// it is absolute. How import() finds a module named by another file.
+pub fn relative_to(file: &str, p: &str) -> String {
+    normalize(&join(&[&dirname(file), p]))
+}
+
+// `p` resolved against the working directory, as import() and read_file()
+// resolve it, in the host's own path syntax. Nothing is looked up on disk,
+// so the file needn't exist.
+pub fn absolute(p: &str) -> Result<String, String> {
+    path::absolute(p).map(|abs| abs.to_string_lossy().into_owned()).map_err(|e| e.to_string())
+}
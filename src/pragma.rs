@@ -0,0 +1,62 @@
+// Options a script sets for itself in `#!` lines at the top of the file,
+// before any code (after a shebang and comments is fine):
+//
+//     #! pragma strict              the strict edition (same as `#! pragma edition strict`)
+//     #! pragma edition compatible  or `strict`; `1` and `2` name them too
+//     #! pragma fuel 1000000        a fuel budget for the run
+//
+// Options given on the command line win over the file's. A pragma this
+// version doesn't understand is a warning, not an error, so scripts can
+// carry pragmas for newer versions.
+use crate::parser::Edition;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilePragmas {
+    pub edition: Option<Edition>,
+    pub fuel: Option<u64>,
+    // One line per pragma that was ignored, with its line number
+    pub warnings: Vec<String>,
+}
+
+impl FilePragmas {
+    pub fn scan(source: &str) -> FilePragmas {
+        let mut pragmas = FilePragmas::default();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") { continue; }
+            let Some(rest) = line.strip_prefix("#!") else { break };
+            let mut words = rest.split_whitespace();
+            // Any other `#!` line is a shebang
+            if words.next() != Some("pragma") { continue; }
+            let words: Vec<&str> = words.collect();
+            if let Err(e) = pragmas.apply(&words) {
+                pragmas.warnings.push(format!("line {}: {} (ignored)", index + 1, e));
+            }
+        }
+        pragmas
+    }
+
+    fn apply(&mut self, words: &[&str]) -> Result<(), String> {
+        match words {
+            ["strict"] => self.edition = Some(Edition::Strict),
+            ["edition", name] => match edition_named(name) {
+                Some(edition) => self.edition = Some(edition),
+                None => return Err(format!("pragma edition needs 'compatible' or 'strict', not '{}'", name)),
+            },
+            ["fuel", n] => match n.parse() {
+                Ok(fuel) => self.fuel = Some(fuel),
+                Err(_) => return Err(format!("pragma fuel needs a number, not '{}'", n)),
+            },
+            _ => return Err(format!("unknown pragma '{}'", words.join(" "))),
+        }
+        Ok(())
+    }
+}
+
+fn edition_named(name: &str) -> Option<Edition> {
+    match name {
+        "1" => Some(Edition::Compatible),
+        "2" => Some(Edition::Strict),
+        _ => Edition::from_name(name),
+    }
+}
@@ -1,56 +1,231 @@
-use std::io::{self, Write};
+use std::io::{self, BufRead, Write};
+use std::env;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "eval")]
+use crate::builtins;
+#[cfg(feature = "eval")]
+use crate::environment::Environment;
+#[cfg(feature = "eval")]
+use crate::evaluator::eval_program;
+#[cfg(feature = "eval")]
 use crate::lexer::Lexer;
+#[cfg(feature = "eval")]
 use crate::parser::Parser;
-use crate::evaluator::eval_program;
-use crate::environment::Environment;
-use crate::builtins;
+use crate::parser::ParseError;
+#[cfg(feature = "vm")]
+use crate::runtime::Engine;
+use crate::runtime::{Runtime, RunError};
+use crate::ui::ColorChoice;
 
 const PROMPT: &str = ">> ";
+#[cfg(feature = "eval")]
+const DEBUG_PROMPT: &str = "debug> ";
+const INIT_FILE: &str = ".fluxrc.flux";
+
+// The startup script when --init names none: $FLUX_INIT if set, otherwise
+// ~/.fluxrc.flux. Only the last may be missing, which is the same as not
+// having one; a path someone chose is loaded, or reported if it can't be.
+pub fn default_init_path() -> Option<PathBuf> {
+    if let Ok(path) = env::var("FLUX_INIT") {
+        return Some(PathBuf::from(path));
+    }
+    let home = env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok()?;
+    Some(Path::new(&home).join(INIT_FILE)).filter(|path| path.exists())
+}
+
+// Run a startup script into the session's globals.
+// Problems are reported but never fatal: a broken init file must still leave a usable session.
+pub fn load_init_file(path: &Path, runtime: &mut Runtime) {
+    match runtime.load_init_file(path) {
+        Ok(()) => {},
+        Err(RunError::Io(e)) => println!("Init file {}: {}", path.display(), e),
+        Err(RunError::Parse(errors, summary)) => {
+            println!("Init file {} has errors, skipping it:", path.display());
+            for error in errors { println!("\t{}", error); }
+            for line in summary.iter().flat_map(|s| s.lines()) { println!("\t{}", line); }
+        },
+        Err(e) => println!("Init file {} failed: {}", path.display(), e),
+    }
+}
 
-pub fn start() {
+// `init` is the startup script to pre-load (None when --no-init was given or there is none).
+// Every line typed at the prompt runs in `runtime`, so definitions persist between lines.
+// Errors are colored unless `color` says the terminal (or the user) wants plain text.
+pub fn start(init: Option<PathBuf>, mut runtime: Runtime, color: ColorChoice) {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
-    let mut env = Environment::new();
 
-    // Load Tools ONCE so they persist between commands
-    let tools = builtins::new_environment();
-    for (name, tool) in tools {
-        env.set(name, tool);
+    if let Some(path) = init {
+        load_init_file(&path, &mut runtime);
     }
 
     println!("Flux OS v0.6 (Interactive Shell)");
     println!("Type 'exit' to shut down.");
     println!("-------------------------------");
 
-    loop {
-        print!("{}", PROMPT);
-        stdout.flush().unwrap();
-
-        let mut input = String::new();
-        stdin.read_line(&mut input).expect("Failed to read line");
+    // :watch expressions, shown again after every input that succeeds
+    let mut watches: Vec<String> = vec![];
 
+    read_loop(&mut stdin.lock(), &mut stdout, PROMPT, |input, _| {
         if input.trim() == "exit" {
             println!("Shutting down...");
-            break;
+            return false;
+        }
+
+        // :help name shows the documentation of whatever `name` is bound to
+        #[cfg(feature = "eval")]
+        if let Some(name) = input.trim().strip_prefix(":help") {
+            match runtime.get(name.trim()) {
+                Some(obj) => println!("{}", builtins::describe(&obj)),
+                None => println!("No such name: {}", name.trim()),
+            }
+            return true;
+        }
+
+        #[cfg(feature = "vm")]
+        if input.trim() == ":constants" {
+            show_constants(&runtime);
+            return true;
+        }
+
+        #[cfg(not(all(feature = "eval", feature = "vm")))]
+        if let Some(message) = left_out_command(input.trim()) {
+            println!("{}", message);
+            return true;
+        }
+
+        if input.trim().starts_with(":watch") || input.trim().starts_with(":unwatch") {
+            watch_command(input.trim(), &mut watches, &runtime, color);
+            return true;
+        }
+
+        match runtime.run_source(input) {
+            Ok(value) => {
+                println!("{}", value);
+                show_watches(&watches, &mut runtime, color);
+            },
+            Err(RunError::Parse(errors, summary)) => print_parser_errors(errors, summary, color),
+            Err(e) => println!("{}", color.error(&e.to_string())),
         }
+        true
+    });
+}
 
-        let l = Lexer::new(input);
-        let mut p = Parser::new(l);
-        let program = p.parse_program();
+// Prompt, read a line, hand it to `handle`; until `handle` says false or the input ends.
+// The shell and breakpoint()'s shell both run on this.
+fn read_loop(input: &mut dyn BufRead, output: &mut dyn Write, prompt: &str, mut handle: impl FnMut(&str, &mut dyn Write) -> bool) {
+    loop {
+        let _ = write!(output, "{}", prompt);
+        let _ = output.flush();
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {},
+        }
+        if !handle(&line, output) { return; }
+    }
+}
 
-        if !p.errors.is_empty() {
-            print_parser_errors(p.errors);
-            continue;
+// The shell breakpoint() opens: each line runs in `scope`, the scope breakpoint()
+// was called from, so it can read and change the paused program's variables.
+// `continue` (or the end of the input) lets the program go on.
+#[cfg(feature = "eval")]
+pub fn breakpoint_shell(scope: &mut Environment, input: &mut dyn BufRead, output: &mut dyn Write) {
+    let _ = writeln!(output, "Breakpoint: `continue` resumes, `locals` lists the variables here");
+    read_loop(input, output, DEBUG_PROMPT, |line, out| {
+        match line.trim() {
+            "continue" => return false,
+            "" => {},
+            "locals" => {
+                for (name, value) in scope.locals() {
+                    let _ = writeln!(out, "  {} = {}", name, value);
+                }
+            },
+            source => {
+                let mut parser = Parser::new(Lexer::new(source.to_string()));
+                let program = parser.parse_program();
+                if program.has_errors() {
+                    for error in &program.errors { let _ = writeln!(out, "\t{}", error); }
+                    return true;
+                }
+                let _ = writeln!(out, "{}", eval_program(&program.statements, scope));
+            },
         }
+        true
+    });
+}
 
-        let evaluated = eval_program(&program, &mut env);
-        println!("{}", evaluated);
+// :watch <expr> adds a watch, :watches lists them, :unwatch <n> removes one
+fn watch_command(command: &str, watches: &mut Vec<String>, runtime: &Runtime, color: ColorChoice) {
+    if command == ":watches" {
+        if watches.is_empty() { println!("No watches"); }
+        for (i, source) in watches.iter().enumerate() {
+            println!("  [{}] {}", i + 1, source);
+        }
+    } else if let Some(n) = command.strip_prefix(":unwatch") {
+        match n.trim().parse::<usize>() {
+            Ok(n) if n >= 1 && n <= watches.len() => println!("Removed watch [{}] {}", n, watches.remove(n - 1)),
+            _ => println!("No watch {}; :watches lists them", n.trim()),
+        }
+    } else if let Some(source) = command.strip_prefix(":watch ") {
+        let source = source.trim();
+        match runtime.parse(source) {
+            Ok(_) => {
+                watches.push(source.to_string());
+                println!("Watching [{}] {}", watches.len(), source);
+            },
+            Err(RunError::Parse(errors, summary)) => print_parser_errors(errors, summary, color),
+            Err(e) => println!("{}", color.error(&e.to_string())),
+        }
+    } else {
+        println!("Usage: :watch <expr>, :watches, :unwatch <n>");
     }
 }
 
-fn print_parser_errors(errors: Vec<String>) {
+// :constants lists the VM's constant pool, to see what makes it grow.
+// Each literal is added once, however many lines use it.
+#[cfg(feature = "vm")]
+fn show_constants(runtime: &Runtime) {
+    if runtime.engine() != Engine::Vm {
+        println!("Only the VM has a constant pool; start the shell with --vm");
+        return;
+    }
+    let state = runtime.compiler_state();
+    if state.constants.is_empty() { println!("No constants"); }
+    for (i, obj) in state.constants.iter().enumerate() {
+        let uses = state.uses(i);
+        println!("  [{}] {} {} ({} use{})", i, obj.type_name(), obj, uses, if uses == 1 { "" } else { "s" });
+    }
+}
+
+// Commands for the engine this build of the shell leaves out
+#[cfg(not(all(feature = "eval", feature = "vm")))]
+fn left_out_command(command: &str) -> Option<&'static str> {
+    if !cfg!(feature = "eval") && command.starts_with(":help") {
+        return Some(":help shows the builtins' documentation, and this build has no interpreter or builtins");
+    }
+    if !cfg!(feature = "vm") && command == ":constants" {
+        return Some(":constants lists the VM's constant pool, and this build has no VM");
+    }
+    None
+}
+
+// Each watch runs like a line typed at the prompt; a failing one only reports its error
+fn show_watches(watches: &[String], runtime: &mut Runtime, color: ColorChoice) {
+    for (i, source) in watches.iter().enumerate() {
+        match runtime.run_source(source) {
+            Ok(value) => println!("  [{}] {} = {}", i + 1, source, value),
+            Err(e) => println!("  [{}] {} = {}", i + 1, source, color.error(&e.to_string())),
+        }
+    }
+}
+
+fn print_parser_errors(errors: Vec<ParseError>, summary: Option<String>, color: ColorChoice) {
     println!("  Whoops! We hit a snag:");
-    for msg in errors {
-        println!("\t{}", msg);
+    for error in errors {
+        println!("\t{}", color.error(&error.to_string()));
+    }
+    for line in summary.iter().flat_map(|s| s.lines()) {
+        println!("\t{}", line);
     }
 }
\ No newline at end of file
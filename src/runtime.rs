@@ -0,0 +1,439 @@
+use std::fmt;
+#[cfg(feature = "eval")]
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use crate::ast::Statement;
+#[cfg(feature = "eval")]
+use crate::builtins::{self, DebugIo, Io, Output, OutputHook};
+#[cfg(feature = "eval")]
+use crate::bundle::Bundle;
+#[cfg(feature = "vm")]
+use crate::compiler::{Compiler, CompilerState};
+use crate::config::{self, CancellationToken, Policy, RuntimeConfig};
+use crate::diagnostics;
+use crate::stats::{self, Stats};
+#[cfg(feature = "eval")]
+use crate::environment::Environment;
+#[cfg(feature = "eval")]
+use crate::intern::Symbol;
+#[cfg(feature = "eval")]
+use crate::evaluator::eval_program;
+use crate::lexer::Lexer;
+use crate::object::Object;
+use crate::parser::{Parser, Edition, ParseError};
+use crate::pragma::FilePragmas;
+use crate::source;
+#[cfg(feature = "vm")]
+use crate::vm::VM;
+
+// Each engine is there only in a build with its feature (`eval` or `vm`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    // The tree-walking evaluator (the default; supports the whole language)
+    #[cfg(feature = "eval")]
+    Interpreter,
+    // The bytecode compiler and VM, which has the builtins only in a build with
+    // the interpreter too (they are the interpreter's)
+    #[cfg(feature = "vm")]
+    Vm,
+}
+
+// Every engine this build has, interpreter first. A new Runtime uses the first.
+impl Engine {
+    #[cfg(all(feature = "eval", feature = "vm"))]
+    pub const ALL: &'static [Engine] = &[Engine::Interpreter, Engine::Vm];
+    #[cfg(not(feature = "vm"))]
+    pub const ALL: &'static [Engine] = &[Engine::Interpreter];
+    #[cfg(not(feature = "eval"))]
+    pub const ALL: &'static [Engine] = &[Engine::Vm];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunError {
+    // The script file couldn't be read
+    Io(String),
+    // The errors kept, then what to add about the rest ("... and 3,214 more errors")
+    Parse(Vec<ParseError>, Option<String>),
+    // The message, and for a script run from a file, the source line it is about
+    Compile(String, Option<String>),
+    // The message, and for a script run from a file, the source line it came from
+    Runtime(String, Option<String>),
+    // The host cancelled the run through its CancellationToken. Whatever the
+    // script did before that stays done; no Flux code can catch it.
+    Cancelled,
+}
+
+// The CLI prints these as they are
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Io(msg) => write!(f, "Error reading file {}", msg),
+            RunError::Parse(errors, summary) => {
+                write!(f, "Parser Errors:")?;
+                for msg in errors { write!(f, "\n\t{}", msg)?; }
+                for line in summary.iter().flat_map(|s| s.lines()) { write!(f, "\n\t{}", line)?; }
+                Ok(())
+            },
+            RunError::Compile(msg, excerpt) => {
+                write!(f, "Compiler Error: {}", msg)?;
+                if let Some(excerpt) = excerpt { write!(f, "\n{}", excerpt)?; }
+                Ok(())
+            },
+            RunError::Cancelled => write!(f, "{}", Object::Error(config::CANCELLED.to_string())),
+            RunError::Runtime(msg, excerpt) => {
+                write!(f, "{}", Object::Error(msg.clone()))?;
+                if let Some(excerpt) = excerpt { write!(f, "\n{}", excerpt)?; }
+                Ok(())
+            },
+        }
+    }
+}
+
+// Everything needed to run Flux code, configured in one place:
+//
+//     let mut rt = Runtime::new().with_engine(Engine::Vm).with_fuel(10_000);
+//     let value = rt.run_source("1 + 2")?;
+//
+// Globals survive from one run to the next, so a Runtime doubles as a
+// session (that is how the REPL uses it). The VM keeps its own: the compiler
+// state and globals below, which the interpreter's runs don't see.
+pub struct Runtime {
+    engine: Engine,
+    edition: Edition,
+    // with_edition was called, so a script's edition pragma doesn't apply
+    edition_chosen: bool,
+    config: RuntimeConfig,
+    #[cfg(feature = "eval")]
+    env: Environment,
+    // --opt=inline for the VM's compiler
+    #[cfg(feature = "vm")]
+    inline: bool,
+    // Count what runs do (see stats)
+    stats: bool,
+    // The VM's session: every run compiles into this pool and symbol table
+    #[cfg(feature = "vm")]
+    compiled: CompilerState,
+    #[cfg(feature = "vm")]
+    vm_globals: Option<Vec<Option<Object>>>,
+    cancellation: CancellationToken,
+    // The print and error hooks and the Io, the thread's only while a run is going
+    #[cfg(feature = "eval")]
+    output: Output,
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
+}
+
+// The globals' functions hold the scope they are bound in (see Environment),
+// so they go with the Runtime rather than outliving it
+#[cfg(feature = "eval")]
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.env.clear();
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Runtime {
+        Runtime {
+            engine: Engine::ALL[0],
+            edition: Edition::Compatible,
+            edition_chosen: false,
+            config: RuntimeConfig::default(),
+            #[cfg(feature = "eval")]
+            env: Environment::new_enclosed(builtins::builtin_scope()),
+            #[cfg(feature = "vm")]
+            inline: false,
+            stats: false,
+            #[cfg(feature = "vm")]
+            compiled: CompilerState::default(),
+            #[cfg(feature = "vm")]
+            vm_globals: None,
+            cancellation: CancellationToken::new(),
+            #[cfg(feature = "eval")]
+            output: Output::default(),
+        }
+    }
+
+    pub fn with_engine(mut self, engine: Engine) -> Runtime {
+        self.engine = engine;
+        self
+    }
+
+    pub fn with_edition(mut self, edition: Edition) -> Runtime {
+        self.edition = edition;
+        self.edition_chosen = true;
+        self
+    }
+
+    pub fn with_config(mut self, config: RuntimeConfig) -> Runtime {
+        self.config = config;
+        self
+    }
+
+    // Let the compiler inline calls to tiny functions (VM only)
+    #[cfg(feature = "vm")]
+    pub fn with_inlining(mut self, inline: bool) -> Runtime {
+        self.inline = inline;
+        self
+    }
+
+    // Count node visits, variable accesses, clones, builtin calls and opcodes
+    // from here on, for stats(). The counters belong to the thread, not to one Runtime.
+    pub fn with_stats(mut self, on: bool) -> Runtime {
+        self.stats = on;
+        stats::reset();
+        self
+    }
+
+    pub fn with_fuel(mut self, fuel: u64) -> Runtime {
+        self.config.fuel = Some(fuel);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, depth: usize) -> Runtime {
+        self.config.max_call_depth = depth;
+        self
+    }
+
+    pub fn with_max_module_size(mut self, bytes: u64) -> Runtime {
+        self.config.max_module_size = bytes;
+        self
+    }
+
+    // What the builtins may touch: files, stdin (Policy::ALLOW_ALL unless set)
+    pub fn with_policy(mut self, policy: Policy) -> Runtime {
+        self.config.policy = policy;
+        self
+    }
+
+    // Read input() from, and write print(), write_err() and log lines to, `io`
+    // rather than the process's streams
+    #[cfg(feature = "eval")]
+    pub fn with_io(mut self, io: Io) -> Runtime {
+        self.output.io = Some(io);
+        self
+    }
+
+    // Hand print() output to `hook` as values instead of writing it to stdout.
+    // The hook is this runtime's: other runtimes on the thread don't call it.
+    #[cfg(feature = "eval")]
+    pub fn set_print_hook(&mut self, hook: OutputHook) {
+        self.output.print_hook = Some(hook);
+    }
+
+    // Let breakpoint() pause the script in a shell on stdin and stdout. Without a
+    // terminal on stdin there is nobody to type at it, so breakpoints stay off.
+    #[cfg(feature = "eval")]
+    pub fn with_debugger(self, on: bool) -> Runtime {
+        if on && io::stdin().is_terminal() {
+            // Not a lock on stdin, which the REPL reads too; a terminal hands over a line at a time anyway
+            let input = Box::new(io::BufReader::new(io::stdin()));
+            builtins::set_debugger(Some(DebugIo { input, output: Box::new(io::stdout()) }));
+        }
+        self
+    }
+
+    // Breakpoints on, with their shell reading `input` and writing `output` instead
+    #[cfg(feature = "eval")]
+    pub fn set_debugger_io(&mut self, input: Box<dyn BufRead>, output: Box<dyn Write>) {
+        builtins::set_debugger(Some(DebugIo { input, output }));
+    }
+
+    // What args() returns: the command line after the script's name.
+    // Like the stats counters, the arguments belong to the thread.
+    #[cfg(feature = "eval")]
+    pub fn set_script_args(&mut self, args: Vec<String>) {
+        builtins::set_script_args(args);
+    }
+
+    // The same for write_err() and the log_*() builtins, which otherwise go to stderr
+    #[cfg(feature = "eval")]
+    pub fn set_error_hook(&mut self, hook: OutputHook) {
+        self.output.error_hook = Some(hook);
+    }
+
+    // A handle that stops this runtime's runs from any thread: the current run
+    // ends with RunError::Cancelled soon after cancel(), and so does every later one
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    // The VM session's constant pool and symbol table, as the last run left them
+    #[cfg(feature = "vm")]
+    pub fn compiler_state(&self) -> &CompilerState {
+        &self.compiled
+    }
+
+    pub fn config(&self) -> &RuntimeConfig {
+        &self.config
+    }
+
+    // What the runs so far did (all zero unless with_stats(true) was used)
+    pub fn stats(&self) -> Stats {
+        stats::snapshot()
+    }
+
+    // A global of the interpreter session (builtins included)
+    #[cfg(feature = "eval")]
+    pub fn get(&self, name: &str) -> Option<Object> {
+        self.env.get(Symbol::intern(name))
+    }
+
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<Object, RunError> {
+        let path = path.as_ref();
+        let source = source::read(path).map_err(|e| RunError::Io(format!("{}: {}", path.display(), e)))?;
+        #[cfg(feature = "eval")]
+        builtins::set_entry_script(Some(&path.to_string_lossy()));
+        let result = self.with_output(|rt| rt.run_script(&source));
+        #[cfg(feature = "eval")]
+        builtins::set_entry_script(None);
+        result
+    }
+
+    // A .fluxb file: its imports are served from the bundle, not the filesystem
+    #[cfg(feature = "eval")]
+    pub fn run_bundle(&mut self, path: impl AsRef<Path>) -> Result<Object, RunError> {
+        let path = path.as_ref();
+        let text = source::read(path).map_err(|e| RunError::Io(format!("{}: {}", path.display(), e)))?;
+        let bundle = Bundle::decode(&text).map_err(|e| RunError::Io(format!("{}: {}", path.display(), e)))?;
+        bundle.install();
+        self.with_output(|rt| rt.run_script(&bundle.source))
+    }
+
+    // A whole script, under the options its pragmas ask for where this runtime
+    // was given none of its own. The runtime's options are back as they were afterwards.
+    fn run_script(&mut self, source: &str) -> Result<Object, RunError> {
+        let pragmas = FilePragmas::scan(source);
+        for warning in &pragmas.warnings {
+            #[cfg(feature = "eval")]
+            builtins::with_stderr(|err| writeln!(err, "Warning: {}", warning)).ok();
+            #[cfg(not(feature = "eval"))]
+            eprintln!("Warning: {}", warning);
+        }
+        let (edition, config) = (self.edition, self.config);
+        if let Some(edition) = pragmas.edition && !self.edition_chosen {
+            self.edition = edition;
+        }
+        if self.config.fuel.is_none() {
+            self.config.fuel = pragmas.fuel;
+        }
+        let result = match self.run_program(source) {
+            // The script's source is at hand here, so point at the failing code
+            Err(RunError::Runtime(msg, None)) => {
+                let excerpt = diagnostics::take(&msg).and_then(|span| diagnostics::excerpt(source, span));
+                Err(RunError::Runtime(msg, excerpt))
+            },
+            Err(RunError::Compile(msg, None)) => {
+                let excerpt = diagnostics::take(&msg).and_then(|span| diagnostics::excerpt(source, span));
+                Err(RunError::Compile(msg, excerpt))
+            },
+            result => result,
+        };
+        (self.edition, self.config) = (edition, config);
+        result
+    }
+
+    // The value of the program's last expression (Null if there is none)
+    pub fn run_source(&mut self, source: &str) -> Result<Object, RunError> {
+        self.with_output(|rt| rt.run_program(source))
+    }
+
+    // The runtime's output is installed for the length of `run` and taken back
+    // afterwards, leaving whatever was installed before (an outer runtime's) as it was
+    #[cfg(feature = "eval")]
+    fn with_output<T>(&mut self, run: impl FnOnce(&mut Runtime) -> T) -> T {
+        let outer = builtins::install_output(std::mem::take(&mut self.output));
+        let result = run(self);
+        self.output = builtins::install_output(outer);
+        result
+    }
+
+    // Without the builtins there is nothing to print with
+    #[cfg(not(feature = "eval"))]
+    fn with_output<T>(&mut self, run: impl FnOnce(&mut Runtime) -> T) -> T {
+        run(self)
+    }
+
+    fn run_program(&mut self, source: &str) -> Result<Object, RunError> {
+        let program = self.parse(source)?;
+        if self.cancellation.is_cancelled() { return Err(RunError::Cancelled); }
+        // Limits (and the fuel meter) are per run
+        config::set(self.config);
+        config::set_cancellation(Some(self.cancellation.clone()));
+        diagnostics::clear();
+        stats::set_enabled(self.stats);
+        let result = match self.engine {
+            #[cfg(feature = "eval")]
+            Engine::Interpreter => self.eval(&program),
+            #[cfg(feature = "vm")]
+            Engine::Vm => self.run_vm(program),
+        };
+        config::set_cancellation(None);
+        match result {
+            // However the error surfaced (inside a module, say), a cancelled run was cancelled
+            Err(RunError::Runtime(..)) if self.cancellation.is_cancelled() => Err(RunError::Cancelled),
+            result => result,
+        }
+    }
+
+    #[cfg(feature = "vm")]
+    fn run_vm(&mut self, program: Vec<Statement>) -> Result<Object, RunError> {
+        // Inlined calls burn no fuel, so a fuel budget keeps every call
+        let mut comp = Compiler::new_with_state(self.compiled.clone())
+            .with_inlining(self.inline && self.config.fuel.is_none());
+        // A program that doesn't compile leaves the session as it was
+        comp.compile(program).map_err(|msg| RunError::Compile(msg, None))?;
+        self.compiled = comp.state();
+        let mut machine = match self.vm_globals.take() {
+            Some(globals) => VM::new_with_globals(comp, globals),
+            None => VM::new(comp),
+        };
+        let result = machine.run().map_err(|msg| RunError::Runtime(msg, None));
+        let value = machine.last_popped_stack_elem().clone();
+        self.vm_globals = Some(std::mem::take(&mut machine.globals));
+        result.map(|_| value)
+    }
+
+    // A startup script, always run by the interpreter into the session's globals
+    #[cfg(feature = "eval")]
+    pub fn load_init_file(&mut self, path: &Path) -> Result<(), RunError> {
+        let source = source::read(path).map_err(|e| RunError::Io(e.to_string()))?;
+        let program = self.parse(&source)?;
+        config::set(self.config);
+        self.with_output(|rt| rt.eval(&program)).map(|_| ())
+    }
+
+    // Without the interpreter, the VM runs it into its own globals instead
+    #[cfg(not(feature = "eval"))]
+    pub fn load_init_file(&mut self, path: &Path) -> Result<(), RunError> {
+        let source = source::read(path).map_err(|e| RunError::Io(e.to_string()))?;
+        self.run_source(&source).map(|_| ())
+    }
+
+    // Parse only, with this runtime's edition (for tools such as --ast)
+    pub fn parse(&self, source: &str) -> Result<Vec<Statement>, RunError> {
+        let mut p = Parser::with_edition(Lexer::new(source.to_string()), self.edition)
+            .with_max_errors(self.config.max_parse_errors);
+        let program = p.parse_program();
+        if program.has_errors() {
+            return Err(RunError::Parse(program.errors, program.summary));
+        }
+        Ok(program.statements)
+    }
+
+    #[cfg(feature = "eval")]
+    fn eval(&mut self, program: &[Statement]) -> Result<Object, RunError> {
+        match eval_program(program, &mut self.env) {
+            Object::Error(msg) => Err(RunError::Runtime(msg, None)),
+            result => Ok(result),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// Reading Flux source from disk, for scripts, modules, bundles and init files
+// alike. A failed read says why (missing, not allowed, not UTF-8), and a
+// UTF-8 byte order mark that some editors put at the start is dropped.
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+const BOM: char = '\u{FEFF}';
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceError {
+    NotFound,
+    PermissionDenied,
+    // Byte offset of the first byte that isn't part of a valid UTF-8 sequence
+    InvalidUtf8(usize),
+    Other(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceError::NotFound => write!(f, "no such file"),
+            SourceError::PermissionDenied => write!(f, "permission denied"),
+            SourceError::InvalidUtf8(offset) => write!(
+                f, "not valid UTF-8 (the first invalid byte is at offset {}); Flux source must be saved as UTF-8", offset
+            ),
+            SourceError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+pub fn read(path: impl AsRef<Path>) -> Result<String, SourceError> {
+    let bytes = fs::read(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => SourceError::NotFound,
+        ErrorKind::PermissionDenied => SourceError::PermissionDenied,
+        _ => SourceError::Other(e.to_string()),
+    })?;
+    let text = String::from_utf8(bytes).map_err(|e| SourceError::InvalidUtf8(e.utf8_error().valid_up_to()))?;
+    Ok(strip_bom(&text).to_string())
+}
+
+pub fn strip_bom(source: &str) -> &str {
+    source.strip_prefix(BOM).unwrap_or(source)
+}
@@ -0,0 +1,152 @@
+// What a run spent its time on, for deciding which optimizations matter
+// (--stats, or Runtime::with_stats). Counting is off unless asked for: every
+// counting site first checks one thread-local flag, and does nothing else
+// while it is false.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+#[cfg(feature = "vm")]
+use crate::code;
+use crate::object::{HashKey, HashObject, Object};
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static STATS: RefCell<Stats> = RefCell::new(Stats::default());
+    // By opcode byte; named when a snapshot is taken, to keep the VM's loop cheap
+    static OPCODES: RefCell<[u64; 256]> = const { RefCell::new([0; 256]) };
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    // Evaluator visits of each kind of expression ("Infix", "Call", ...)
+    pub nodes: BTreeMap<&'static str, u64>,
+    // Variable lookups and bindings in the interpreter's environments
+    pub env_gets: u64,
+    pub env_sets: u64,
+    // Values copied out of variables, constants and containers
+    pub clones: u64,
+    // Calls of each builtin, by name
+    pub builtins: BTreeMap<&'static str, u64>,
+    // Instructions the VM executed, by opcode name ("OpAdd", ...)
+    pub opcodes: BTreeMap<String, u64>,
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.with(|enabled| enabled.set(on));
+}
+
+pub fn enabled() -> bool {
+    ENABLED.with(|enabled| enabled.get())
+}
+
+// Start counting from zero
+pub fn reset() {
+    STATS.with(|stats| *stats.borrow_mut() = Stats::default());
+    OPCODES.with(|opcodes| *opcodes.borrow_mut() = [0; 256]);
+}
+
+// The counts so far
+pub fn snapshot() -> Stats {
+    let stats = STATS.with(|stats| stats.borrow().clone());
+    Stats { opcodes: opcode_counts(), ..stats }
+}
+
+#[cfg(feature = "vm")]
+fn opcode_counts() -> BTreeMap<String, u64> {
+    OPCODES.with(|opcodes| {
+        let mut counts = BTreeMap::new();
+        for (op, n) in opcodes.borrow().iter().enumerate() {
+            if *n == 0 { continue; }
+            let name = code::lookup(op as code::Opcode).map_or_else(|| format!("Op{}", op), |def| def.name);
+            counts.insert(name, *n);
+        }
+        counts
+    })
+}
+
+// Nothing counts opcodes in a build without the VM
+#[cfg(not(feature = "vm"))]
+fn opcode_counts() -> BTreeMap<String, u64> {
+    BTreeMap::new()
+}
+
+fn count(update: impl FnOnce(&mut Stats)) {
+    if enabled() {
+        STATS.with(|stats| update(&mut stats.borrow_mut()));
+    }
+}
+
+pub fn node(kind: &'static str) {
+    count(|stats| *stats.nodes.entry(kind).or_default() += 1);
+}
+
+pub fn env_get() {
+    count(|stats| stats.env_gets += 1);
+}
+
+pub fn env_set() {
+    count(|stats| stats.env_sets += 1);
+}
+
+// `obj.clone()`, counted
+pub fn cloned(obj: &Object) -> Object {
+    count(|stats| stats.clones += 1);
+    obj.clone()
+}
+
+pub fn builtin(name: &'static str) {
+    count(|stats| *stats.builtins.entry(name).or_default() += 1);
+}
+
+#[cfg(feature = "vm")]
+pub fn opcode(op: code::Opcode) {
+    if enabled() {
+        OPCODES.with(|opcodes| opcodes.borrow_mut()[op as usize] += 1);
+    }
+}
+
+// Most frequent first, ties by name
+fn by_count<K: AsRef<str>>(counts: &BTreeMap<K, u64>) -> Vec<(&str, u64)> {
+    let mut rows: Vec<(&str, u64)> = counts.iter().map(|(name, n)| (name.as_ref(), *n)).collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    rows
+}
+
+impl Stats {
+    // The report --stats prints when the run ends
+    pub fn report(&self) -> String {
+        let mut out = String::from("--- stats ---\n");
+        let sections: [(&str, Vec<(&str, u64)>); 3] = [
+            ("evaluator nodes", by_count(&self.nodes)),
+            ("builtin calls", by_count(&self.builtins)),
+            ("VM opcodes", by_count(&self.opcodes)),
+        ];
+        for (title, rows) in &sections {
+            if rows.is_empty() { continue; }
+            let total: u64 = rows.iter().map(|(_, n)| n).sum();
+            let _ = writeln!(out, "{} ({}):", title, total);
+            for (name, n) in rows {
+                let _ = writeln!(out, "  {:>10}  {}", n, name);
+            }
+        }
+        let _ = writeln!(out, "environment: {} gets, {} sets", self.env_gets, self.env_sets);
+        let _ = writeln!(out, "object clones: {}", self.clones);
+        out
+    }
+
+    // {"nodes": {...}, "env_gets": n, "env_sets": n, "clones": n, "builtins": {...}, "opcodes": {...}}
+    pub fn to_object(&self) -> Object {
+        let counts = |rows: Vec<(&str, u64)>| -> Object {
+            let pairs = rows.into_iter().map(|(name, n)| (HashKey::String(name.to_string()), Object::Integer(n as i64)));
+            Object::Hash(HashObject::new(pairs.collect()))
+        };
+        let mut pairs = HashMap::new();
+        pairs.insert(HashKey::String("nodes".to_string()), counts(by_count(&self.nodes)));
+        pairs.insert(HashKey::String("env_gets".to_string()), Object::Integer(self.env_gets as i64));
+        pairs.insert(HashKey::String("env_sets".to_string()), Object::Integer(self.env_sets as i64));
+        pairs.insert(HashKey::String("clones".to_string()), Object::Integer(self.clones as i64));
+        pairs.insert(HashKey::String("builtins".to_string()), counts(by_count(&self.builtins)));
+        pairs.insert(HashKey::String("opcodes".to_string()), counts(by_count(&self.opcodes)));
+        Object::Hash(HashObject::new(pairs))
+    }
+}
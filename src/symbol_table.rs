@@ -1,21 +1,28 @@
 use std::collections::HashMap;
+use crate::intern;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SymbolScope {
     Global,
+    Local,
+    // One of builtins::BUILTINS, by its index: what a name no scope defines can
+    // still be, in a build with the interpreter (which the builtins come with)
+    Builtin,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Symbol {
-    pub name: String,
+    pub name: intern::Symbol,
     pub scope: SymbolScope,
     pub index: usize,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct SymbolTable {
-    store: HashMap<String, Symbol>,
+    store: HashMap<intern::Symbol, Symbol>,
     pub num_definitions: usize,
+    // Set for a function body's table; names not found here are looked up outside
+    pub outer: Option<Box<SymbolTable>>,
 }
 
 impl SymbolTable {
@@ -23,13 +30,28 @@ impl SymbolTable {
         SymbolTable {
             store: HashMap::new(),
             num_definitions: 0,
+            outer: None,
         }
     }
 
-    pub fn define(&mut self, name: String) -> Symbol {
+    pub fn new_enclosed(outer: SymbolTable) -> SymbolTable {
+        SymbolTable {
+            store: HashMap::new(),
+            num_definitions: 0,
+            outer: Some(Box::new(outer)),
+        }
+    }
+
+    // Defining a name again in the same scope reuses its slot, which is what
+    // lets the compiler pre-define top-level functions before compiling them.
+    pub fn define(&mut self, name: intern::Symbol) -> Symbol {
+        if let Some(existing) = self.store.get(&name) {
+            return existing.clone();
+        }
+        let scope = if self.outer.is_some() { SymbolScope::Local } else { SymbolScope::Global };
         let symbol = Symbol {
-            name: name.clone(),
-            scope: SymbolScope::Global,
+            name,
+            scope,
             index: self.num_definitions,
         };
         self.store.insert(name, symbol.clone());
@@ -37,7 +59,34 @@ impl SymbolTable {
         symbol
     }
 
-    pub fn resolve(&self, name: &str) -> Option<Symbol> {
-        self.store.get(name).cloned()
+    // True if `name` belongs to this scope rather than an enclosing one
+    pub fn defines(&self, name: intern::Symbol) -> bool {
+        self.store.contains_key(&name)
+    }
+
+    // The name defined at `index` in this (outermost) table
+    pub fn global_name(&self, index: usize) -> Option<intern::Symbol> {
+        self.store.values().find(|symbol| symbol.scope == SymbolScope::Global && symbol.index == index).map(|symbol| symbol.name)
     }
-}
\ No newline at end of file
+
+    pub fn resolve(&self, name: intern::Symbol) -> Option<Symbol> {
+        match self.store.get(&name) {
+            Some(symbol) => Some(symbol.clone()),
+            None => match &self.outer {
+                Some(outer) => outer.resolve(name),
+                None => builtin(name),
+            },
+        }
+    }
+}
+
+#[cfg(feature = "eval")]
+fn builtin(name: intern::Symbol) -> Option<Symbol> {
+    let index = crate::builtins::BUILTINS.iter().position(|def| def.name == name.as_str())?;
+    Some(Symbol { name, scope: SymbolScope::Builtin, index })
+}
+
+#[cfg(not(feature = "eval"))]
+fn builtin(_name: intern::Symbol) -> Option<Symbol> {
+    None
+}
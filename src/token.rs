@@ -2,8 +2,29 @@
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
+    // Where the token starts, both counted from 1
+    pub line: usize,
+    pub column: usize,
+    // The bytes of the source it was read from
+    pub span: Span,
 }
 
+// A range of bytes in the source, for pointing at the code an error came from
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // From the start of this span to the end of `last`
+    pub fn to(self, last: Span) -> Span {
+        Span { start: self.start, end: last.end }
+    }
+}
+
+// Clippy would have EOF spelled Eof, but the --tokens dump prints these names
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenType {
     Illegal,
@@ -12,25 +33,43 @@ pub enum TokenType {
     // Identifiers + Literals
     Identifier,
     Int,
+    Float,
     String,
+    // A string literal the input ended inside of
+    UnterminatedString,
+    // 'a': the literal is what the quotes hold, escapes decoded (the parser
+    // checks that it is exactly one character)
+    Char,
 
     // Operators
     Assign,
+    // Compound assignment: x += e is x = x + e
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
     Plus,
     Minus,
     Bang,
     Asterisk,
     Slash,
+    // ** (right-associative)
+    Power,
 
     // Comparators
     Lt,
     Gt,
+    LtEq,
+    GtEq,
     Eq,
     NotEq,
+    And,
+    Or,
 
     // Delimiters
     Comma,
     Colon,
+    Question,
     Semicolon,
     LParen,
     RParen,
@@ -38,6 +77,11 @@ pub enum TokenType {
     RBrace,
     LBracket,
     RBracket,
+    Dot,
+    DotDot,
+    DotDotEq,
+    DotDotDot,
+    FatArrow,
 
     // Keywords
     Fn,
@@ -48,8 +92,88 @@ pub enum TokenType {
     Else,
     Return,
     While,
+    Break,
+    Continue,
+    Match,
+    Defer,
+    For,
     
     // RESTORED TOKENS:
     Material,
     Context,
-}
\ No newline at end of file
+}
+impl TokenType {
+    // How the token reads in error messages
+    pub fn spelling(self) -> &'static str {
+        match self {
+            TokenType::Illegal => "illegal token",
+            TokenType::EOF => "end of input",
+            TokenType::Identifier => "identifier",
+            TokenType::Int => "integer",
+            TokenType::Float => "float",
+            TokenType::String => "string",
+            TokenType::UnterminatedString => "unterminated string",
+            TokenType::Char => "character",
+            TokenType::Assign => "=",
+            TokenType::PlusAssign => "+=",
+            TokenType::MinusAssign => "-=",
+            TokenType::AsteriskAssign => "*=",
+            TokenType::SlashAssign => "/=",
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Bang => "!",
+            TokenType::Asterisk => "*",
+            TokenType::Slash => "/",
+            TokenType::Power => "**",
+            TokenType::Lt => "<",
+            TokenType::Gt => ">",
+            TokenType::LtEq => "<=",
+            TokenType::GtEq => ">=",
+            TokenType::Eq => "==",
+            TokenType::NotEq => "!=",
+            TokenType::And => "&&",
+            TokenType::Or => "||",
+            TokenType::Comma => ",",
+            TokenType::Colon => ":",
+            TokenType::Question => "?",
+            TokenType::Semicolon => ";",
+            TokenType::LParen => "(",
+            TokenType::RParen => ")",
+            TokenType::LBrace => "{",
+            TokenType::RBrace => "}",
+            TokenType::LBracket => "[",
+            TokenType::RBracket => "]",
+            TokenType::Dot => ".",
+            TokenType::DotDot => "..",
+            TokenType::DotDotEq => "..=",
+            TokenType::DotDotDot => "...",
+            TokenType::FatArrow => "=>",
+            TokenType::Fn => "fn",
+            TokenType::Mut => "mut",
+            TokenType::True => "true",
+            TokenType::False => "false",
+            TokenType::If => "if",
+            TokenType::Else => "else",
+            TokenType::Return => "return",
+            TokenType::While => "while",
+            TokenType::Break => "break",
+            TokenType::Continue => "continue",
+            TokenType::Match => "match",
+            TokenType::Defer => "defer",
+            TokenType::For => "for",
+            TokenType::Material => "material",
+            TokenType::Context => "context",
+        }
+    }
+
+    // The arithmetic operator a compound assignment applies (`+=` -> "+")
+    pub fn compound_operator(self) -> Option<&'static str> {
+        match self {
+            TokenType::PlusAssign => Some("+"),
+            TokenType::MinusAssign => Some("-"),
+            TokenType::AsteriskAssign => Some("*"),
+            TokenType::SlashAssign => Some("/"),
+            _ => None,
+        }
+    }
+}
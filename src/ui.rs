@@ -0,0 +1,37 @@
+// Whether terminal output may use ANSI escapes. One decision shared by the
+// CLI, the REPL and error reporting, so `--plain`, NO_COLOR, TERM=dumb and a
+// redirected stdout (CI logs, editor shell buffers) all switch off the same things.
+use std::env;
+use std::io::{self, IsTerminal};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Color,
+    Plain,
+}
+
+impl ColorChoice {
+    // For this process, with `plain` set by --plain
+    pub fn detect(plain: bool) -> ColorChoice {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let term = env::var("TERM").ok();
+        ColorChoice::decide(plain, io::stdout().is_terminal(), no_color, term.as_deref())
+    }
+
+    // The rule itself, apart from the environment it is read from
+    pub fn decide(plain: bool, stdout_is_tty: bool, no_color: bool, term: Option<&str>) -> ColorChoice {
+        if plain || !stdout_is_tty || no_color || term == Some("dumb") {
+            ColorChoice::Plain
+        } else {
+            ColorChoice::Color
+        }
+    }
+
+    // Error text: red, or as it is when plain
+    pub fn error(self, text: &str) -> String {
+        match self {
+            ColorChoice::Color => format!("\x1b[31m{}\x1b[0m", text),
+            ColorChoice::Plain => text.to_string(),
+        }
+    }
+}
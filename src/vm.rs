@@ -1,112 +1,260 @@
+use std::rc::Rc;
 use crate::code;
 use crate::compiler::Compiler;
-use crate::object::Object;
+use crate::symbol_table::SymbolTable;
+use crate::object::{self, Object, CompiledFunction, integer_add, integer_divide, integer_multiply, integer_negate, integer_power, integer_subtract};
+use crate::config::{self, RuntimeConfig};
+use crate::diagnostics;
+use crate::stats;
+use crate::token::Span;
 
-const STACK_SIZE: usize = 2048;
 const GLOBALS_SIZE: usize = 65536; // Max 65k globals
 
+// One active function call: which code is running, where we are in it,
+// and where its locals start on the stack.
+struct Frame {
+    func: Rc<CompiledFunction>,
+    ip: usize,
+    base_pointer: usize,
+}
+
 pub struct VM {
     constants: Vec<Object>,
-    instructions: code::Instructions,
-    
+
+    // Values move on and off the top; a call's locals are the slots above its base pointer
     stack: Vec<Object>,
-    sp: usize, // Stack Pointer
-    
-    pub globals: Vec<Object>, // Global Storage
+    // The value of the last expression statement (see last_popped_stack_elem)
+    last_popped: Object,
+
+    // Global storage. A slot the compiler reserved is None until its
+    // definition runs, so reading a name before that is an error, as in the interpreter.
+    pub globals: Vec<Option<Object>>,
+    // The names of the global slots, for that error
+    symbols: SymbolTable,
+
+    frames: Vec<Frame>,
+
+    // Call depth, stack size and fuel, from the thread's RuntimeConfig
+    config: RuntimeConfig,
 }
 
 impl VM {
     pub fn new(bytecode: Compiler) -> VM {
+        VM::new_with_globals(bytecode, vec![None; GLOBALS_SIZE])
+    }
+
+    // Run with the globals an earlier program of the same session left behind
+    pub fn new_with_globals(bytecode: Compiler, globals: Vec<Option<Object>>) -> VM {
+        let config = config::current();
+        // The main program runs as a function without parameters or locals
+        let main_fn = CompiledFunction {
+            name: "<main>".to_string(),
+            instructions: bytecode.instructions().clone(),
+            num_locals: 0,
+            num_parameters: 0,
+            spans: bytecode.spans().to_vec(),
+        };
         VM {
             constants: bytecode.constants,
-            instructions: bytecode.instructions,
-            stack: vec![Object::Null; STACK_SIZE],
-            sp: 0,
-            globals: vec![Object::Null; GLOBALS_SIZE],
+            stack: Vec::with_capacity(config.stack_size),
+            last_popped: Object::Null,
+            globals,
+            symbols: bytecode.symbol_table,
+            frames: vec![Frame { func: Rc::new(main_fn), ip: 0, base_pointer: 0 }],
+            config,
+        }
+    }
+
+    // A global read before its definition ran (the compiler reserves every top-level name)
+    fn undefined_global(&self, index: usize) -> String {
+        match self.symbols.global_name(index) {
+            Some(name) => format!("Undefined variable: {}", name),
+            None => "Undefined variable".to_string(),
         }
     }
 
-    pub fn stack_top(&self) -> Option<&Object> {
-        if self.sp == 0 { return None; }
-        Some(&self.stack[self.sp - 1])
+    // The value of the last expression statement
+    pub fn last_popped_stack_elem(&self) -> &Object {
+        &self.last_popped
     }
 
     pub fn run(&mut self) -> Result<(), String> {
-        let mut ip = 0; // Instruction Pointer
+        let result = self.execute();
+        if let Err(msg) = &result && let Some(span) = self.failing_span() {
+            diagnostics::note(span, msg);
+        }
+        result
+    }
+
+    fn execute(&mut self) -> Result<(), String> {
+        loop {
+            let frame = self.frames.last_mut().expect("VM has no frame");
+            let ins = &frame.func.instructions;
+            if frame.ip >= ins.len() { break; }
 
-        while ip < self.instructions.len() {
-            let op = self.instructions[ip];
-            ip += 1;
+            let op = ins[frame.ip];
+            let ip = frame.ip + 1;
+            frame.ip = ip;
+            stats::opcode(op);
 
             match op {
                 code::OP_CONSTANT => {
-                    let const_index = u16::from_be_bytes([self.instructions[ip], self.instructions[ip+1]]) as usize;
-                    ip += 2;
-                    let obj = self.constants[const_index].clone();
+                    let const_index = self.read_u16_operand();
+                    let obj = stats::cloned(&self.constants[const_index]);
                     self.push(obj)?;
                 },
                 code::OP_POP => {
-                    self.pop();
+                    self.last_popped = self.pop();
                 },
-                
+
                 // --- ARITHMETIC ---
-                code::OP_ADD => {
-                    let right = self.pop();
-                    let left = self.pop();
-                    let result = self.execute_binary_operation(left, right)?;
-                    self.push(result)?;
+                code::OP_ADD | code::OP_SUB | code::OP_MUL | code::OP_DIV | code::OP_POW => {
+                    // Two integers: the result goes into the left one's slot, nothing moves
+                    if let [.., Object::Integer(l), Object::Integer(r)] = self.stack[..] {
+                        let value = integer_arithmetic(op, l, r)?;
+                        self.stack.pop();
+                        if let Some(Object::Integer(slot)) = self.stack.last_mut() { *slot = value; }
+                    } else {
+                        let right = self.pop();
+                        let left = self.pop();
+                        let result = self.execute_binary_operation(op, left, right)?;
+                        self.push(result)?;
+                    }
                 },
-                
+
                 // --- LOGIC ---
                 code::OP_TRUE => self.push(Object::Boolean(true))?,
                 code::OP_FALSE => self.push(Object::Boolean(false))?,
                 code::OP_EQUAL => {
                     let right = self.pop();
                     let left = self.pop();
-                    self.push(Object::Boolean(left == right))?;
+                    let equal = object::objects_equal(&left, &right);
+                    self.push(Object::Boolean(equal))?;
                 },
                 code::OP_NOT_EQUAL => {
                     let right = self.pop();
                     let left = self.pop();
-                    self.push(Object::Boolean(left != right))?;
+                    let equal = object::objects_equal(&left, &right);
+                    self.push(Object::Boolean(!equal))?;
                 },
-                code::OP_GREATER_THAN => {
-                    let right = self.pop();
-                    let left = self.pop();
-                    match (left, right) {
-                        (Object::Integer(l), Object::Integer(r)) => self.push(Object::Boolean(l > r))?,
-                        _ => return Err("Type mismatch for >".to_string()),
+                code::OP_GREATER_THAN | code::OP_LESS_THAN | code::OP_LESS_EQUAL | code::OP_GREATER_EQUAL => {
+                    if let [.., Object::Integer(l), Object::Integer(r)] = self.stack[..] {
+                        self.stack.pop();
+                        if let Some(slot) = self.stack.last_mut() { *slot = Object::Boolean(compare(op, &l, &r)); }
+                    } else {
+                        let right = self.pop();
+                        let left = self.pop();
+                        let result = self.execute_comparison(op, left, right)?;
+                        self.push(result)?;
                     }
                 },
 
+                // --- PREFIX OPERATORS ---
+                code::OP_BANG => {
+                    let operand = self.pop();
+                    let truthy = object::is_truthy(&operand)?;
+                    self.push(Object::Boolean(!truthy))?;
+                },
+                code::OP_MINUS => {
+                    let result = match self.pop() {
+                        Object::Integer(val) => Object::Integer(integer_negate(val)?),
+                        Object::Float(val) => Object::Float(-val),
+                        _ => return Err("Unknown operator: -".to_string()),
+                    };
+                    self.push(result)?;
+                },
+
                 // --- JUMPS ---
                 code::OP_JUMP => {
-                    let pos = u16::from_be_bytes([self.instructions[ip], self.instructions[ip+1]]) as usize;
-                    ip = pos;
+                    let pos = self.read_u16_operand();
+                    // Jumping backwards is a loop iteration
+                    if pos < ip { config::burn_fuel()?; }
+                    self.current_frame().ip = pos;
                 },
                 code::OP_JUMP_NOT_TRUTHY => {
-                    let pos = u16::from_be_bytes([self.instructions[ip], self.instructions[ip+1]]) as usize;
-                    ip += 2;
+                    let pos = self.read_u16_operand();
                     let condition = self.pop();
-                    if !self.is_truthy(condition) {
-                        ip = pos;
+                    if !object::is_truthy(&condition)? {
+                        self.current_frame().ip = pos;
                     }
                 },
 
                 // --- GLOBALS ---
                 code::OP_SET_GLOBAL => {
-                    let global_index = u16::from_be_bytes([self.instructions[ip], self.instructions[ip+1]]) as usize;
-                    ip += 2;
+                    let global_index = self.read_u16_operand();
                     let val = self.pop();
-                    self.globals[global_index] = val;
+                    self.globals[global_index] = Some(val);
                 },
                 code::OP_GET_GLOBAL => {
-                    let global_index = u16::from_be_bytes([self.instructions[ip], self.instructions[ip+1]]) as usize;
-                    ip += 2;
-                    let val = self.globals[global_index].clone();
+                    let global_index = self.read_u16_operand();
+                    let val = match &self.globals[global_index] {
+                        Some(val) => stats::cloned(val),
+                        None => return Err(self.undefined_global(global_index)),
+                    };
                     self.push(val)?;
                 },
 
+                // --- LOCALS ---
+                code::OP_SET_LOCAL => {
+                    let local_index = self.read_u8_operand();
+                    let base = self.current_frame().base_pointer;
+                    let val = self.pop();
+                    self.stack[base + local_index] = val;
+                },
+                code::OP_GET_LOCAL => {
+                    let local_index = self.read_u8_operand();
+                    let base = self.current_frame().base_pointer;
+                    let val = stats::cloned(&self.stack[base + local_index]);
+                    self.push(val)?;
+                },
+
+                // --- BUILTINS ---
+                #[cfg(feature = "eval")]
+                code::OP_GET_BUILTIN => {
+                    let builtin_index = self.read_u8_operand();
+                    self.push(Object::Builtin(&crate::builtins::BUILTINS[builtin_index]))?;
+                },
+
+                // --- INDEXING ---
+                code::OP_INDEX => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    match object::index(left, index) {
+                        Object::Error(msg) => return Err(msg),
+                        value => self.push(value)?,
+                    }
+                },
+                // [.., key1, .., keyN, value, container] -> [.., updated container]
+                code::OP_SET_INDEX => {
+                    let depth = self.read_u8_operand();
+                    let container = self.pop();
+                    let value = self.pop();
+                    let keys = self.stack.split_off(self.stack.len() - depth);
+                    match object::set_index(container, &keys, value) {
+                        Ok(updated) => self.push(updated)?,
+                        Err(Object::Error(msg)) => return Err(msg),
+                        Err(other) => return Err(format!("Index assignment failed: {}", other)),
+                    }
+                },
+
+                // --- FUNCTIONS ---
+                code::OP_CALL => {
+                    let num_args = self.read_u8_operand();
+                    self.call_function(num_args)?;
+                },
+                code::OP_RETURN_VALUE | code::OP_RETURN => {
+                    let return_value = if op == code::OP_RETURN_VALUE { self.pop() } else { Object::Null };
+                    // A return at the top level ends the program
+                    if self.frames.len() == 1 {
+                        self.last_popped = return_value;
+                        break;
+                    }
+                    let frame = self.frames.pop().expect("VM has no frame");
+                    // Drop the locals and the callee itself
+                    self.stack.truncate(frame.base_pointer - 1);
+                    self.push(return_value)?;
+                },
+
                 _ => return Err(format!("Unknown Opcode: {}", op)),
             }
         }
@@ -115,33 +263,182 @@ impl VM {
 
     // --- HELPERS ---
 
-    fn execute_binary_operation(&self, left: Object, right: Object) -> Result<Object, String> {
-        match (left, right) {
-            (Object::Integer(l), Object::Integer(r)) => Ok(Object::Integer(l + r)),
-            _ => Err("Type mismatch or unsupported operation".to_string()),
+    // The source of the instruction that just failed. Every error is returned
+    // after its instruction's operands are read, so the frame's ip is just past it.
+    fn failing_span(&self) -> Option<Span> {
+        let frame = self.frames.last()?;
+        frame.func.spans.iter().find(|(end, _)| *end == frame.ip).map(|(_, span)| *span)
+    }
+
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("VM has no frame")
+    }
+
+    fn read_u16_operand(&mut self) -> usize {
+        let frame = self.current_frame();
+        let ins = &frame.func.instructions;
+        let val = u16::from_be_bytes([ins[frame.ip], ins[frame.ip + 1]]) as usize;
+        frame.ip += 2;
+        val
+    }
+
+    fn read_u8_operand(&mut self) -> usize {
+        let frame = self.current_frame();
+        let val = frame.func.instructions[frame.ip] as usize;
+        frame.ip += 1;
+        val
+    }
+
+    // Stack layout on entry: [.., callee, arg1, .., argN]; the args become the first locals
+    fn call_function(&mut self, num_args: usize) -> Result<(), String> {
+        let func = match &self.stack[self.stack.len() - 1 - num_args] {
+            Object::CompiledFunction(func) => Rc::clone(func),
+            #[cfg(feature = "eval")]
+            Object::Builtin(def) => return self.call_builtin(def, num_args),
+            other => return Err(format!("Calling non-function: {}", other)),
+        };
+        if num_args != func.num_parameters {
+            return Err(format!("Wrong number of arguments: want={}, got={}", func.num_parameters, num_args));
+        }
+        config::burn_fuel()?;
+        // The main program's frame doesn't count as a call
+        if self.frames.len() > self.config.max_call_depth {
+            return Err(self.stack_overflow(&func));
+        }
+        let base_pointer = self.stack.len() - num_args;
+        if base_pointer + func.num_locals >= self.config.stack_size {
+            return Err(self.slots_full());
+        }
+        // The arguments are the first locals; the rest start out null
+        self.stack.resize(base_pointer + func.num_locals, Object::Null);
+        self.frames.push(Frame { func, ip: 0, base_pointer });
+        Ok(())
+    }
+
+    // Builtins run at once, on the values taken off the stack (callee included).
+    // One that takes a function takes the interpreter's kind, not a compiled one.
+    #[cfg(feature = "eval")]
+    fn call_builtin(&mut self, def: &object::BuiltinDef, num_args: usize) -> Result<(), String> {
+        let args = self.stack.split_off(self.stack.len() - num_args);
+        self.stack.pop();
+        match crate::builtins::call(def, args) {
+            Object::Error(msg) => Err(msg),
+            result => self.push(result),
+        }
+    }
+
+    fn stack_overflow(&self, callee: &CompiledFunction) -> String {
+        let mut calls: Vec<String> = self.frames[1..].iter().map(|f| f.func.name.clone()).collect();
+        calls.push(callee.name.clone());
+        config::stack_overflow_message(&calls)
+    }
+
+    fn slots_full(&self) -> String {
+        format!("Stack overflow: the VM's {} value slots are full; raise the limit with --stack-size", self.config.stack_size)
+    }
+
+    fn execute_binary_operation(&self, op: code::Opcode, left: Object, right: Object) -> Result<Object, String> {
+        if let (Object::Integer(l), Object::Integer(r)) = (&left, &right) {
+            return integer_arithmetic(op, *l, *r).map(Object::Integer);
+        }
+        if op == code::OP_ADD && let Some(text) = concatenation(&left, &right) {
+            return Ok(Object::String(text));
+        }
+        let (l, r) = match float_operands(&left, &right) {
+            Some(pair) => pair,
+            None => return Err("Type mismatch".to_string()),
+        };
+        match op {
+            code::OP_ADD => Ok(Object::Float(l + r)),
+            code::OP_SUB => Ok(Object::Float(l - r)),
+            code::OP_MUL => Ok(Object::Float(l * r)),
+            code::OP_POW => Ok(Object::Float(l.powf(r))),
+            _ => {
+                if r == 0.0 { return Err("Division by zero".to_string()); }
+                Ok(Object::Float(l / r))
+            },
+        }
+    }
+
+    fn execute_comparison(&self, op: code::Opcode, left: Object, right: Object) -> Result<Object, String> {
+        if let (Object::Integer(l), Object::Integer(r)) = (&left, &right) {
+            return Ok(Object::Boolean(compare(op, l, r)));
+        }
+        if let (Object::Char(l), Object::Char(r)) = (&left, &right) {
+            return Ok(Object::Boolean(compare(op, l, r)));
+        }
+        // By code point, like sort()
+        if let (Object::String(l), Object::String(r)) = (&left, &right) {
+            return Ok(Object::Boolean(compare(op, l, r)));
+        }
+        match float_operands(&left, &right) {
+            // PartialOrd: every comparison with NaN is false, as in the interpreter
+            Some((l, r)) => Ok(Object::Boolean(compare(op, &l, &r))),
+            None => Err(format!("Type mismatch for {}", comparison_symbol(op))),
         }
     }
 
     fn push(&mut self, obj: Object) -> Result<(), String> {
-        if self.sp >= STACK_SIZE {
-            return Err("Stack Overflow".to_string());
+        if self.stack.len() >= self.config.stack_size {
+            return Err(self.slots_full());
         }
-        self.stack[self.sp] = obj;
-        self.sp += 1;
+        self.stack.push(obj);
         Ok(())
     }
 
+    // Moves the top value out; nothing is left behind to clone or drop later
     fn pop(&mut self) -> Object {
-        if self.sp == 0 { return Object::Null; }
-        self.sp -= 1;
-        self.stack[self.sp].clone()
+        self.stack.pop().unwrap_or(Object::Null)
     }
+}
 
-    fn is_truthy(&self, obj: Object) -> bool {
-        match obj {
-            Object::Boolean(b) => b,
-            Object::Null => false,
-            _ => true,
-        }
+// What an arithmetic opcode makes of two integers
+fn integer_arithmetic(op: code::Opcode, l: i64, r: i64) -> Result<i64, String> {
+    match op {
+        code::OP_ADD => integer_add(l, r),
+        code::OP_SUB => integer_subtract(l, r),
+        code::OP_MUL => integer_multiply(l, r),
+        code::OP_POW => integer_power(l, r),
+        _ => integer_divide(l, r),
+    }
+}
+
+// Two numbers at least one of which is a float, with the integer (if any)
+// promoted, as the interpreter does
+fn float_operands(left: &Object, right: &Object) -> Option<(f64, f64)> {
+    match (left, right) {
+        (Object::Float(l), Object::Float(r)) => Some((*l, *r)),
+        (Object::Integer(l), Object::Float(r)) => Some((*l as f64, *r)),
+        (Object::Float(l), Object::Integer(r)) => Some((*l, *r as f64)),
+        _ => None,
     }
-}
\ No newline at end of file
+}
+
+// `+` with a string on either side joins the two as text, as the interpreter
+// does (an array only on the right)
+fn concatenation(left: &Object, right: &Object) -> Option<String> {
+    match (left, right) {
+        (Object::String(_), Object::String(_) | Object::Char(_) | Object::Integer(_) | Object::Float(_) | Object::Array(_))
+        | (Object::Char(_) | Object::Integer(_) | Object::Float(_), Object::String(_)) => Some(format!("{}{}", left, right)),
+        _ => None,
+    }
+}
+
+// The comparison a comparison opcode makes
+fn compare<T: PartialOrd>(op: code::Opcode, l: &T, r: &T) -> bool {
+    match op {
+        code::OP_GREATER_THAN => l > r,
+        code::OP_LESS_THAN => l < r,
+        code::OP_LESS_EQUAL => l <= r,
+        _ => l >= r,
+    }
+}
+
+fn comparison_symbol(op: code::Opcode) -> &'static str {
+    match op {
+        code::OP_GREATER_THAN => ">",
+        code::OP_LESS_THAN => "<",
+        code::OP_LESS_EQUAL => "<=",
+        _ => ">=",
+    }
+}
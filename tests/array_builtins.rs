@@ -0,0 +1,41 @@
+// sum, min, max, sort, unique and join: results, and errors that name the
+// element at fault
+#![cfg(feature = "eval")]
+use flux_compiler::{RunError, Runtime};
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+fn error_of(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("{} should fail at run time, got {:?}", source, other),
+    }
+}
+
+#[test]
+fn sum_adds_integers() {
+    assert_eq!(run("sum([1, 2, 3])"), "6");
+    assert_eq!(run("sum([])"), "0");
+    assert_eq!(run("sum([9223372036854775807, -1, 1])"), "9223372036854775807");
+}
+
+#[test]
+fn sum_reports_overflow_and_other_types_by_index() {
+    assert_eq!(error_of("sum([9223372036854775807, 1])"), "sum: integer overflow at index 1");
+    assert_eq!(error_of("sum([0, -9223372036854775807, -1, -1])"), "sum: integer overflow at index 3");
+    assert_eq!(error_of("sum([1, \"a\"])"), "sum: expected INTEGER, got STRING at index 1");
+    assert_eq!(error_of("sum([1, 2, 2.5])"), "sum: expected INTEGER, got FLOAT at index 2");
+}
+
+#[test]
+fn the_others_on_empty_and_mixed_arrays() {
+    assert_eq!(run("[min([]), max([]), sort([]), unique([]), join([], \",\")]"), "[null, null, [], [], ]");
+    assert_eq!(run("[min([3, 1, 2]), max([\"b\", \"c\", \"a\"]), sort([3, 1, 2])]"), "[1, c, [1, 2, 3]]");
+    assert_eq!(error_of("min([1, \"a\"])"), "min: expected INTEGER, got STRING at index 1");
+    assert_eq!(error_of("sort([true])"), "sort: expected INTEGER or STRING, got BOOLEAN at index 0");
+}
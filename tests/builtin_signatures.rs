@@ -0,0 +1,54 @@
+// Wrong calls to builtins fail with messages built from the signature table
+#![cfg(feature = "eval")]
+use flux_compiler::builtins::{self, BUILTINS};
+use flux_compiler::{RunError, Runtime};
+
+fn error_of(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("{} should fail at run time, got {:?}", source, other),
+    }
+}
+
+#[test]
+fn wrong_types_name_the_argument() {
+    for (source, expected) in [
+        ("push(1, 2)", "push: expected ARRAY as argument 1, got INTEGER"),
+        ("first(\"abc\")", "first: expected ARRAY as argument 1, got STRING"),
+        ("last({})", "last: expected ARRAY as argument 1, got HASH"),
+        ("rest(true)", "rest: expected ARRAY as argument 1, got BOOLEAN"),
+        ("len(5)", "len: expected STRING or ARRAY as argument 1, got INTEGER"),
+        ("join([\"a\"], 1)", "join: expected STRING as argument 2, got INTEGER"),
+        ("keys([1])", "keys: expected HASH, MODULE, MATERIAL or CONTEXT as argument 1, got ARRAY"),
+        ("sort_by([1], 2)", "sort_by: expected FUNCTION or BUILTIN as argument 2, got INTEGER"),
+        ("collect(3)", "collect: expected ARRAY, HASH, MODULE, MATERIAL, CONTEXT, STRING or ITERATOR as argument 1, got INTEGER"),
+        ("range(1, \"9\")", "range: expected INTEGER as argument 2, got STRING"),
+    ] {
+        assert_eq!(error_of(source), expected, "{}", source);
+    }
+}
+
+#[test]
+fn wrong_counts_say_what_fits() {
+    for (source, expected) in [
+        ("push([1])", "push: expected 2 arguments, got 1"),
+        ("len()", "len: expected 1 argument, got 0"),
+        ("counter(1)", "counter: expected no arguments, got 1"),
+        ("get({})", "get: expected 2 or 3 arguments, got 1"),
+        ("range()", "range: expected 1 to 3 arguments, got 0"),
+        ("deep_set({}, [], 1, 2)", "deep_set: expected 3 arguments, got 4"),
+    ] {
+        assert_eq!(error_of(source), expected, "{}", source);
+    }
+}
+
+#[test]
+fn every_builtin_has_a_consistent_signature() {
+    for def in BUILTINS {
+        assert!(def.required <= def.params.len(), "{}", def.name);
+        assert!(!def.variadic || !def.params.is_empty(), "{}", def.name);
+        assert!(builtins::check_args(def, &[]).is_ok() == (def.required == 0), "{}", def.name);
+    }
+    let print = BUILTINS.iter().find(|def| def.name == "print").unwrap();
+    assert_eq!(print.signature(), "print(values...)");
+}
@@ -0,0 +1,80 @@
+// Bundles a script with three modules, deletes the modules, and runs the bundle
+#![cfg(feature = "eval")]
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn flux(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .current_dir(dir)
+        .arg("--no-init")
+        .args(args)
+        .output()
+        .expect("failed to run flux_compiler");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn bundle(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .current_dir(dir)
+        .arg("bundle")
+        .args(args)
+        .output()
+        .expect("failed to run flux_compiler");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn fixture(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("flux_bundle_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    dir
+}
+
+#[test]
+fn bundle_runs_without_its_module_files() {
+    let dir = fixture("three");
+    fs::write(dir.join("lib/a.flux"), "mut b = import(\"b.flux\")\nmut double = fn(x) { b[\"inc\"](x) * 2 }\n").unwrap();
+    fs::write(dir.join("lib/b.flux"), "mut c = import(\"c.flux\")\nmut inc = fn(x) { x + c[\"one\"] }\n").unwrap();
+    fs::write(dir.join("lib/c.flux"), "mut one = 1\n").unwrap();
+    fs::write(dir.join("main.flux"), "mut a = import(\"lib/a.flux\")\nprint(a[\"double\"](20))\n").unwrap();
+    let expected = flux(&dir, &["main.flux"]);
+
+    let report = bundle(&dir, &["main.flux", "-o", "app.fluxb"]);
+    assert_eq!(report, "Bundled 3 module(s) into app.fluxb\n");
+    fs::remove_dir_all(dir.join("lib")).unwrap();
+    fs::remove_file(dir.join("main.flux")).unwrap();
+
+    assert_eq!(flux(&dir, &["app.fluxb"]), expected);
+    assert_eq!(expected.trim(), "42");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Built from outside the entry's directory, and run from somewhere else again.
+// Two spellings of one module are one entry in the bundle.
+#[test]
+fn bundle_resolves_imports_against_the_entry() {
+    let dir = fixture("nested");
+    fs::create_dir_all(dir.join("app/lib")).unwrap();
+    fs::write(dir.join("app/lib/a.flux"), "mut shared = import(\"../shared.flux\")\nmut name = \"a\" + shared[\"tag\"]\n").unwrap();
+    fs::write(dir.join("app/shared.flux"), "mut tag = \"!\"\n").unwrap();
+    fs::write(dir.join("app/main.flux"), "mut a = import(\"lib/a.flux\")\nmut again = import(\"./shared.flux\")\nprint(a[\"name\"] + again[\"tag\"])\n").unwrap();
+
+    let report = bundle(&dir, &["app/main.flux", "-o", "lib/app.fluxb"]);
+    assert_eq!(report, "Bundled 2 module(s) into lib/app.fluxb\n");
+    fs::remove_dir_all(dir.join("app")).unwrap();
+
+    assert_eq!(flux(&dir.join("lib"), &["app.fluxb"]).trim(), "a!!");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bundle_rejects_dynamic_imports() {
+    let dir = fixture("dynamic");
+    fs::write(dir.join("main.flux"), "mut name = \"lib/a\"\nmut a = import(name + \".flux\")\n").unwrap();
+
+    let report = bundle(&dir, &["main.flux"]);
+    assert!(report.contains("main.flux line 2:9: import() needs a constant string path"), "{}", report);
+    assert!(!dir.join("main.fluxb").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
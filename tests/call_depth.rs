@@ -0,0 +1,32 @@
+// --max-depth sets how deep calls may nest; the evaluator's thread gets a
+// native stack to match, so the flag only accepts depths that stack can have
+use std::process::Command;
+use flux_compiler::config::MAX_CALL_DEPTH;
+
+fn run_with_depth(depth: &str, source: &str) -> (String, Option<i32>) {
+    let path = std::env::temp_dir().join(format!("flux_depth_{}_{}.flux", depth, std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .args(["--plain", "--no-init", "--max-depth", depth])
+        .arg(&path)
+        .output()
+        .expect("failed to run flux_compiler");
+    let _ = std::fs::remove_file(&path);
+    (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code())
+}
+
+#[test]
+fn depths_out_of_range_are_a_usage_error() {
+    let usage = format!("--max-depth needs a number from 1 to {}\n", MAX_CALL_DEPTH);
+    for depth in ["100000000000", "0", "-3", "deep"] {
+        let (out, _) = run_with_depth(depth, "1");
+        assert_eq!(out, usage, "{}", depth);
+    }
+}
+
+#[test]
+fn the_deepest_depth_allowed_runs() {
+    let source = "mut down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } }\ndown(5000)";
+    let (out, code) = run_with_depth(&MAX_CALL_DEPTH.to_string(), source);
+    assert_eq!((out.as_str(), code), ("0\n", Some(0)));
+}
@@ -0,0 +1,74 @@
+// A host stops a runaway script from another thread with the runtime's
+// CancellationToken; the run ends with RunError::Cancelled, in either engine
+#![cfg(feature = "eval")]
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use flux_compiler::object::Object;
+use flux_compiler::{CancellationToken, Engine, RunError, Runtime};
+
+// Runs `source` on a new thread and cancels it once it has had time to get
+// going; the result (as text), what it printed, and how long it took to stop
+fn cancel_while_running(engine: Engine, source: &'static str) -> (Result<String, RunError>, Vec<String>, Duration) {
+    let (token_tx, token_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let printed = Rc::new(RefCell::new(vec![]));
+        let mut runtime = Runtime::new().with_engine(engine);
+        let sink = printed.clone();
+        runtime.set_print_hook(Box::new(move |values: &[Object]| {
+            sink.borrow_mut().push(values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" "));
+        }));
+        token_tx.send(runtime.cancellation_token()).unwrap();
+        // Values stay on the thread that made them
+        let result = runtime.run_source(source).map(|value| value.to_string());
+        let printed = printed.borrow().clone();
+        done_tx.send((result, printed, Instant::now())).unwrap();
+    });
+    let token: CancellationToken = token_rx.recv().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let cancelled_at = Instant::now();
+    token.cancel();
+    let (result, printed, stopped_at) = done_rx.recv_timeout(Duration::from_secs(5)).expect("the script didn't stop");
+    (result, printed, stopped_at - cancelled_at)
+}
+
+#[test]
+fn a_long_loop_stops_promptly_in_both_engines() {
+    for &engine in Engine::ALL {
+        let (result, _, took) = cancel_while_running(engine, "mut i = 0\nwhile (true) { i = i + 1 }");
+        assert_eq!(result, Err(RunError::Cancelled), "{:?}", engine);
+        assert!(took < Duration::from_millis(500), "{:?} took {:?} to stop", engine, took);
+    }
+}
+
+#[test]
+fn loops_without_statements_and_loops_of_calls_are_checked_too() {
+    let (result, _, _) = cancel_while_running(Engine::Interpreter, "while (true) { }");
+    assert_eq!(result, Err(RunError::Cancelled));
+    for &engine in Engine::ALL {
+        let (result, _, _) = cancel_while_running(engine, "mut one = fn() { 1 }\nwhile (true) { one() }");
+        assert_eq!(result, Err(RunError::Cancelled), "{:?}", engine);
+    }
+}
+
+#[test]
+fn what_ran_before_the_cancel_stays_done() {
+    let (result, printed, _) = cancel_while_running(Engine::Interpreter, "print(\"started\")\nmut i = 0\nwhile (true) { i = i + 1 }\nprint(\"finished\")");
+    assert_eq!(result, Err(RunError::Cancelled));
+    assert_eq!(printed, ["started"]);
+}
+
+#[test]
+fn a_cancelled_runtime_runs_nothing_more() {
+    let mut runtime = Runtime::new();
+    runtime.run_source("mut x = 1").unwrap();
+    runtime.cancellation_token().cancel();
+    assert_eq!(runtime.run_source("x = 2\nx"), Err(RunError::Cancelled));
+    assert_eq!(runtime.get("x"), Some(Object::Integer(1)));
+    assert_eq!(RunError::Cancelled.to_string(), "ERROR: Cancelled: the host stopped the script");
+    // Other runtimes on the thread have tokens of their own
+    assert_eq!(Runtime::new().run_source("1 + 1"), Ok(Object::Integer(2)));
+}
@@ -0,0 +1,60 @@
+// The VM's constant pool holds each literal once, across a whole session
+#![cfg(feature = "vm")]
+use std::io::Write;
+use std::process::{Command, Stdio};
+use flux_compiler::compiler::Compiler;
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::Parser;
+use flux_compiler::{Engine, Runtime};
+
+fn session(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .args(["--plain", "--no-init", "--vm"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run flux_compiler");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn a_program_adds_each_literal_once() {
+    let mut parser = Parser::new(Lexer::new("mut a = 2\nmut b = 2.5 * 2\nif (a > 2) { 2.5 } else { 2 }".to_string()));
+    let mut compiler = Compiler::new();
+    compiler.compile(parser.parse_program().statements).unwrap();
+    let pool: Vec<String> = compiler.constants.iter().map(|obj| obj.to_string()).collect();
+    assert_eq!(pool, ["2", "2.5"]);
+}
+
+#[test]
+fn the_same_string_on_ten_lines_is_one_constant() {
+    let mut runtime = Runtime::new().with_engine(Engine::Vm);
+    for _ in 0..10 {
+        assert_eq!(runtime.run_source("\"hello\"").unwrap().to_string(), "hello");
+    }
+    let state = runtime.compiler_state();
+    let hellos: Vec<usize> = (0..state.constants.len()).filter(|&i| state.constants[i].to_string() == "hello").collect();
+    assert_eq!(hellos.len(), 1, "{:?}", state.constants);
+    assert_eq!(state.uses(hellos[0]), 10);
+}
+
+#[test]
+fn later_lines_see_earlier_globals() {
+    let mut runtime = Runtime::new().with_engine(Engine::Vm);
+    runtime.run_source("mut x = 20").unwrap();
+    runtime.run_source("mut double = fn(n) { n * 2 }").unwrap();
+    assert_eq!(runtime.run_source("double(x) + 2").unwrap().to_string(), "42");
+    // A line that doesn't compile changes nothing
+    assert!(runtime.run_source("y = 1").is_err());
+    assert_eq!(runtime.run_source("x").unwrap().to_string(), "20");
+}
+
+#[test]
+fn the_shell_lists_the_pool() {
+    let out = session(&format!("{}:constants\nexit\n", "\"hello\"\n".repeat(10)));
+    let listed: Vec<&str> = out.lines().map(|line| line.trim_start_matches(">> ")).filter(|line| line.starts_with("  [")).collect();
+    assert_eq!(listed, ["  [0] STRING hello (10 uses)"]);
+    assert!(session(":constants\nexit\n").contains("No constants"));
+}
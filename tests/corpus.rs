@@ -0,0 +1,12 @@
+// `cargo test` runs the golden-file corpus: every tests/corpus/*.flux must
+// print its .expected output, under each engine (see flux_compiler::corpus).
+// FLUX_UPDATE_EXPECT=1 cargo test --test corpus rewrites the .expected files.
+#![cfg(feature = "eval")]
+use std::path::Path;
+use flux_compiler::corpus;
+
+#[test]
+fn every_program_prints_what_it_expects() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus");
+    assert!(corpus::run(&dir), "see the FAIL lines above");
+}
@@ -0,0 +1,65 @@
+// breakpoint() pauses in a shell on the calling scope
+#![cfg(feature = "eval")]
+use std::cell::RefCell;
+use std::io::{Cursor, Write};
+use std::rc::Rc;
+use flux_compiler::Runtime;
+
+// Everything the shell writes, readable after the run
+#[derive(Clone, Default)]
+struct Transcript(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Transcript {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transcript {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+const SCALE: &str = "mut scale = fn(x) {\n    mut factor = 2\n    breakpoint()\n    x * factor\n}\nscale(5)";
+
+fn debug_run(source: &str, commands: &str) -> (String, String) {
+    let transcript = Transcript::default();
+    let mut runtime = Runtime::new();
+    runtime.set_debugger_io(Box::new(Cursor::new(commands.to_string())), Box::new(transcript.clone()));
+    let value = runtime.run_source(source).unwrap();
+    (value.to_string(), transcript.text())
+}
+
+#[test]
+fn a_change_made_at_the_breakpoint_is_seen_by_the_program() {
+    let (value, shell) = debug_run(SCALE, "locals\nfactor = 10\nfactor + x\ncontinue\n");
+    assert_eq!(value, "50");
+    assert!(shell.contains("  factor = 2\n  x = 5\n"), "{}", shell);
+    assert!(shell.contains("debug> 15\n"), "{}", shell);
+}
+
+#[test]
+fn the_shell_reports_errors_and_keeps_going() {
+    let (value, shell) = debug_run(SCALE, "x +\nmissing\ncontinue\n");
+    assert_eq!(value, "10");
+    assert!(shell.contains("ERROR: Variable 'missing' not found"), "{}", shell);
+}
+
+#[test]
+fn the_end_of_the_input_resumes_the_program() {
+    let (value, _) = debug_run("mut total = 1\nbreakpoint()\ntotal + 1", "total = 41\n");
+    assert_eq!(value, "42");
+}
+
+#[test]
+fn without_a_debugger_breakpoint_does_nothing() {
+    let value = Runtime::new().run_source(SCALE).unwrap();
+    assert_eq!(value.to_string(), "10");
+    assert_eq!(Runtime::new().run_source("breakpoint()").unwrap().to_string(), "null");
+}
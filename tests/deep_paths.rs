@@ -0,0 +1,72 @@
+// deep_get and deep_set: paths of hash keys and array indices through nested
+// data, null for a missing link, and errors that name the path so far
+#![cfg(feature = "eval")]
+use flux_compiler::{RunError, Runtime};
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+fn error_of(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("{} should fail at run time, got {:?}", source, other),
+    }
+}
+
+const CONFIG: &str = "mut cfg = {\"server\": {\"ports\": [8080, 8443], \"name\": \"api\"}, \"debug\": false}\n";
+
+#[test]
+fn deep_get_follows_keys_and_indices() {
+    let source = format!("{}[deep_get(cfg, [\"server\", \"ports\", 1]), deep_get(cfg, [\"server\", \"name\"]), deep_get(cfg, [\"debug\"]), deep_get(cfg, []) == cfg]", CONFIG);
+    assert_eq!(run(&source), "[8443, api, false, true]");
+}
+
+#[test]
+fn deep_get_is_null_on_any_missing_link() {
+    for path in ["[\"client\"]", "[\"client\", \"ports\", 0]", "[\"server\", \"ports\", 2]", "[\"server\", \"ports\", -1]"] {
+        assert_eq!(run(&format!("{}deep_get(cfg, {})", CONFIG, path)), "null", "{}", path);
+    }
+}
+
+#[test]
+fn deep_get_names_the_path_to_what_cant_be_indexed() {
+    assert_eq!(error_of(&format!("{}deep_get(cfg, [\"server\", \"name\", 0])", CONFIG)), "deep_get: cannot index STRING at path [\"server\", \"name\"]");
+    assert_eq!(error_of(&format!("{}deep_get(cfg, [\"server\", \"ports\", \"first\"])", CONFIG)), "deep_get: cannot index ARRAY at path [\"server\", \"ports\"]");
+    assert_eq!(error_of(&format!("{}deep_get(cfg, [[1]])", CONFIG)), "deep_get: unusable hash key [1] at path [[1]]");
+}
+
+#[test]
+fn deep_set_creates_the_missing_hashes() {
+    let source = "mut out = deep_set({}, [\"a\", \"b\", \"c\"], 1)\n[deep_get(out, [\"a\", \"b\", \"c\"]), len(keys(out)), len(keys(deep_get(out, [\"a\"])))]";
+    assert_eq!(run(source), "[1, 1, 1]");
+    let source = format!("{}mut out = deep_set(cfg, [\"client\", \"name\"], \"web\")\n[deep_get(out, [\"client\", \"name\"]), deep_get(out, [\"server\", \"name\"])]", CONFIG);
+    assert_eq!(run(&source), "[web, api]");
+}
+
+#[test]
+fn deep_set_replaces_and_appends_array_elements() {
+    let source = format!(r#"{}
+        mut replaced = deep_set(cfg, ["server", "ports", 0], 9000)
+        mut appended = deep_set(cfg, ["server", "ports", 2], 9443)
+        [deep_get(replaced, ["server", "ports"]), deep_get(appended, ["server", "ports"]), deep_get(replaced, ["server", "name"])]
+    "#, CONFIG);
+    assert_eq!(run(&source), "[[9000, 8443], [8080, 8443, 9443], api]");
+}
+
+#[test]
+fn deep_set_returns_a_copy() {
+    let source = format!("{}mut updated = deep_set(cfg, [\"server\", \"ports\", 0], 1)\n[deep_get(cfg, [\"server\", \"ports\", 0]), deep_get(updated, [\"server\", \"ports\", 0])]", CONFIG);
+    assert_eq!(run(&source), "[8080, 1]");
+}
+
+#[test]
+fn deep_set_names_the_path_at_a_conflict() {
+    assert_eq!(error_of(&format!("{}deep_set(cfg, [\"server\", \"name\", \"first\"], 1)", CONFIG)), "deep_set: cannot index STRING at path [\"server\", \"name\"]");
+    assert_eq!(error_of(&format!("{}deep_set(cfg, [\"debug\", 0], 1)", CONFIG)), "deep_set: cannot index BOOLEAN at path [\"debug\"]");
+    assert_eq!(error_of(&format!("{}deep_set(cfg, [\"server\", \"ports\", 5], 1)", CONFIG)), "deep_set: index 5 out of range at path [\"server\", \"ports\", 5]");
+    assert_eq!(error_of(&format!("{}deep_set(cfg, [\"server\", \"ports\", \"x\"], 1)", CONFIG)), "deep_set: cannot index ARRAY at path [\"server\", \"ports\"]");
+}
@@ -0,0 +1,35 @@
+// Each comparison compiles to the opcode named after it, with its operands in source order
+#![cfg(feature = "vm")]
+use flux_compiler::code;
+use flux_compiler::compiler::Compiler;
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::Parser;
+
+fn disassemble(source: &str) -> String {
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert!(!program.has_errors(), "{:?}", program.errors);
+    let mut compiler = Compiler::new();
+    compiler.compile(program.statements).unwrap();
+    code::print_instructions(compiler.instructions())
+}
+
+#[test]
+fn comparisons_use_their_own_opcodes() {
+    for (operator, opcode) in [
+        ("<", "OpLessThan"),
+        (">", "OpGreaterThan"),
+        ("<=", "OpLessEqual"),
+        (">=", "OpGreaterEqual"),
+        ("==", "OpEqual"),
+        ("!=", "OpNotEqual"),
+    ] {
+        let listing = disassemble(&format!("1 {} 2", operator));
+        assert_eq!(listing, format!("0000 OpConstant 0\n0003 OpConstant 1\n0006 {}\n0007 OpPop\n", opcode), "{}", operator);
+    }
+}
+
+#[test]
+fn prefix_operators_compile_to_their_own_opcodes() {
+    assert_eq!(disassemble("!true"), "0000 OpTrue\n0001 OpBang\n0002 OpPop\n");
+    assert_eq!(disassemble("-5"), "0000 OpConstant 0\n0003 OpMinus\n0004 OpPop\n");
+}
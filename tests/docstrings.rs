@@ -0,0 +1,86 @@
+// Docstrings: a leading string literal documents a function or a module,
+// builtins carry their own, and doc(), help() and the REPL's :help show them
+#![cfg(feature = "eval")]
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use flux_compiler::{Capture, Io, Runtime};
+
+const SQUARE: &str = "mut square = fn(x) {\n    \"Multiply x by itself.\"\n    x * x\n}\nmut plain = fn(a, b) { a + b }\n";
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+// What help() printed
+fn help_text(source: &str) -> String {
+    let output = Capture::new();
+    let io = Io { input: Box::new(io::empty()), output: Box::new(output.clone()), errors: Box::new(io::sink()) };
+    Runtime::new().with_io(io).run_source(source).unwrap();
+    output.text()
+}
+
+#[test]
+fn a_documented_function() {
+    assert_eq!(run(&format!("{}doc(square)", SQUARE)), "Multiply x by itself.");
+    assert_eq!(help_text(&format!("{}help(square)", SQUARE)), "fn(x)\n    Multiply x by itself.\n");
+    // The docstring is only documentation: the function still returns its last value
+    assert_eq!(run(&format!("{}square(7)", SQUARE)), "49");
+}
+
+#[test]
+fn an_undocumented_function() {
+    assert_eq!(run(&format!("{}doc(plain)", SQUARE)), "null");
+    assert_eq!(help_text(&format!("{}help(plain)", SQUARE)), "fn(a, b)\n    (no documentation)\n");
+    // A string that isn't first doesn't count
+    assert_eq!(run("mut f = fn() { mut x = 1\n\"late\"\n}\ndoc(f)"), "null");
+}
+
+#[test]
+fn a_builtin() {
+    assert_eq!(run("doc(len)"), "len(x): the number of characters in a string or elements in an array");
+    assert_eq!(help_text("help(len)"), "len(x): the number of characters in a string or elements in an array\n");
+}
+
+#[test]
+fn a_memoized_function_keeps_its_documentation() {
+    assert_eq!(run(&format!("{}doc(memoize(square))", SQUARE)), "Multiply x by itself.");
+}
+
+#[test]
+fn a_module() {
+    let path = std::env::temp_dir().join(format!("flux_docstrings_{}_geometry.flux", std::process::id()));
+    fs::write(&path, "\"Shapes and their areas.\"\nmut area = fn(w, h) { w * h }\nmut say = print\n").unwrap();
+    let source = format!("mut geometry = import(\"{}\")\n", path.display());
+    let doc = run(&format!("{}doc(geometry)", source));
+    let help = help_text(&format!("{}help(geometry)", source));
+    fs::remove_file(&path).unwrap();
+    assert_eq!(doc, "Shapes and their areas.");
+    // Re-exported builtins aren't listed as the module's own
+    assert_eq!(help, "module exporting: area\n    Shapes and their areas.\n");
+}
+
+#[test]
+fn values_without_documentation_say_so() {
+    assert_eq!(help_text("help(42)"), "INTEGER (no documentation)\n");
+    assert_eq!(run("doc(\"text\")"), "null");
+}
+
+#[test]
+fn the_repl_help_command_shows_a_binding() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .args(["--plain", "--no-init"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run flux_compiler");
+    let input = "mut square = fn(x) { \"Multiply x by itself.\"; x * x }\n:help square\n:help len\n:help nothing\nexit\n";
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let out = String::from_utf8_lossy(&child.wait_with_output().unwrap().stdout).into_owned();
+    assert!(out.contains("fn(x)\n    Multiply x by itself.\n"), "{}", out);
+    assert!(out.contains("len(x): the number of characters"), "{}", out);
+    assert!(out.contains("No such name: nothing"), "{}", out);
+}
@@ -0,0 +1,85 @@
+// Editions: the compatible one keeps the words added after 1.0 usable as
+// names and reads them as keywords only where the grammar needs one; the
+// strict one reserves them and says how to migrate
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::{Edition, Parser};
+use flux_compiler::{RunError, Runtime};
+
+// Each statement as the parser understood it, or the errors
+fn parsed(source: &str, edition: Edition) -> Result<Vec<String>, Vec<String>> {
+    let program = Parser::with_edition(Lexer::new(source.to_string()), edition).parse_program();
+    if program.has_errors() {
+        return Err(program.errors.iter().map(|e| e.to_string()).collect());
+    }
+    Ok(program.statements.iter().map(|stmt| stmt.to_string()).collect())
+}
+
+fn compatible(source: &str) -> Vec<String> {
+    parsed(source, Edition::Compatible).unwrap_or_else(|errors| panic!("{}: {:?}", source, errors))
+}
+
+fn run(source: &str, edition: Edition) -> Result<String, RunError> {
+    Runtime::new().with_edition(edition).run_source(source).map(|value| value.to_string())
+}
+
+fn migration(word: &str) -> String {
+    format!("'{}' is a keyword in the strict edition and can't be used as a name; rename it (e.g. '{}_') or run with --edition compatible", word, word)
+}
+
+#[test]
+fn match_as_a_variable_runs_only_in_the_compatible_edition() {
+    let source = "mut match = 3\nmatch = match + 1\nmatch * 10";
+    assert_eq!(run(source, Edition::Compatible).unwrap(), "40");
+    match run(source, Edition::Strict) {
+        Err(RunError::Parse(errors, _)) => assert_eq!(errors[0].to_string(), format!("line 1:5: {}", migration("match"))),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn break_and_continue_start_an_expression_when_an_operator_follows() {
+    let source = "mut break = 2\nmut continue = 3\nbreak + 1\ncontinue * 2\nbreak == continue";
+    assert_eq!(compatible(source)[2..], ["(break + 1)", "(continue * 2)", "(break == continue)"]);
+    assert_eq!(run(source, Edition::Compatible).unwrap(), "false");
+    // The same inside a loop, for operators that can't begin break's value
+    let source = "mut break = 0\nmut i = 0\nwhile (i < 3) {\n    i = i + 1\n    break + i\n    break < 10 ? 1 : 0\n}\ni";
+    assert_eq!(run(source, Edition::Compatible).unwrap(), "3");
+}
+
+#[test]
+fn outside_a_loop_anything_that_carries_on_an_expression_makes_a_name() {
+    let source = "mut break = fn(n) { n * 2 }\nmut continue = 10\nbreak(4)\ncontinue - 1";
+    assert_eq!(compatible(source)[2..], ["break(4)", "(continue - 1)"]);
+    assert_eq!(run(source, Edition::Compatible).unwrap(), "9");
+}
+
+#[test]
+fn inside_a_loop_what_can_start_a_value_is_breaks_value() {
+    for (body, value) in [("break -1", "(-1)"), ("break (7)", "7"), ("break i", "i")] {
+        let source = format!("mut i = 0\nwhile (true) {{\n    i = i + 1\n    {}\n}}", body);
+        let statements = compatible(&source);
+        assert_eq!(statements[1], format!("while (true) {{ i = (i + 1); break {} }}", value), "{}", body);
+    }
+}
+
+#[test]
+fn a_bare_break_or_continue_is_always_the_keyword() {
+    assert_eq!(compatible("while (true) { break }\nwhile (true) { continue; }\n")[0], "while (true) { break }");
+    assert_eq!(parsed("mut break = 1\nbreak", Edition::Compatible), Err(vec![
+        "line 2:1: 'break' outside of a loop: it only works inside a while or for body".to_string(),
+    ]));
+    // A value on the same line that doesn't continue an expression is break's value
+    assert_eq!(parsed("mut f = fn() { break 1 }", Edition::Compatible), Err(vec![
+        "line 1:16: 'break' outside of a loop: it only works inside a while or for body".to_string(),
+    ]));
+}
+
+#[test]
+fn the_strict_edition_never_reads_them_as_names() {
+    for source in ["mut break = 1", "break = 2", "mut continue = 1"] {
+        let word = if source.contains("break") { "break" } else { "continue" };
+        let errors = parsed(source, Edition::Strict).unwrap_err();
+        assert!(errors[0].ends_with(&migration(word)), "{}: {:?}", source, errors);
+    }
+    assert!(parsed("break + 1", Edition::Strict).is_err());
+}
@@ -0,0 +1,101 @@
+// Every consumer of equality agrees with object::objects_equal: `==` and `!=`,
+// match patterns, `in`, unique(), count() and deep_equal(), in both engines
+#![cfg(feature = "eval")]
+use flux_compiler::object::{objects_equal, Object};
+#[cfg(feature = "vm")]
+use flux_compiler::Engine;
+use flux_compiler::Runtime;
+
+// Pairs of values that are equal, written differently on each side
+const EQUAL: &[(&str, &str)] = &[
+    ("1", "1.0"),
+    ("[1, 2, 3]", "[1, 2, 3.0]"),
+    ("{\"a\": 1, \"b\": 2}", "{\"b\": 2, \"a\": 1}"),
+    ("{1: \"x\", true: [1], \"k\": 'c'}", "{\"k\": 'c', true: [1.0], 1: \"x\"}"),
+    ("[{\"a\": [1, {\"b\": 2}]}, []]", "[{\"a\": [1.0, {\"b\": 2}]}, []]"),
+    ("{\"in\": {\"x\": [1, 2], \"y\": {}}}", "{\"in\": {\"y\": {}, \"x\": [1, 2]}}"),
+];
+
+// Pairs that differ, however slightly
+const UNEQUAL: &[(&str, &str)] = &[
+    ("1", "2"),
+    ("[1, 2]", "[2, 1]"),
+    ("[1, 2]", "[1, 2, 3]"),
+    ("[[1]]", "[[\"1\"]]"),
+    ("{\"a\": 1}", "{\"a\": 1, \"b\": 2}"),
+    ("{\"a\": 1}", "{\"a\": 2}"),
+    ("{1: 1}", "{\"1\": 1}"),
+    ("{\"a\": [1, 2]}", "{\"a\": [2, 1]}"),
+    ("[{}]", "[[]]"),
+    ("counter()", "{}"),
+];
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+fn value(source: &str) -> Object {
+    Runtime::new().run_source(source).unwrap()
+}
+
+#[test]
+fn the_function_itself() {
+    for (l, r) in EQUAL {
+        assert!(objects_equal(&value(l), &value(r)), "{} == {}", l, r);
+        assert!(objects_equal(&value(r), &value(l)), "{} == {}", r, l);
+    }
+    for (l, r) in UNEQUAL {
+        assert!(!objects_equal(&value(l), &value(r)), "{} != {}", l, r);
+    }
+    let nan = Object::Float(f64::NAN);
+    assert!(!objects_equal(&nan, &nan));
+}
+
+#[test]
+fn the_operators() {
+    for (pairs, equal) in [(EQUAL, true), (UNEQUAL, false)] {
+        for (l, r) in pairs {
+            assert_eq!(run(&format!("{} == {}", l, r)), equal.to_string(), "{} == {}", l, r);
+            assert_eq!(run(&format!("{} != {}", r, l)), (!equal).to_string(), "{} != {}", r, l);
+            assert_eq!(run(&format!("deep_equal({}, {})", l, r)), equal.to_string(), "deep_equal({}, {})", l, r);
+        }
+    }
+}
+
+#[test]
+fn match_patterns() {
+    for (pairs, equal) in [(EQUAL, true), (UNEQUAL, false)] {
+        // A hash pattern matches any hash with those keys, so it isn't an equality test
+        for (l, r) in pairs.iter().filter(|(_, r)| !r.contains(['{', '('])) {
+            let source = format!("match ({}) {{ {} => true, _ => false }}", l, r);
+            assert_eq!(run(&source), equal.to_string(), "{}", source);
+        }
+    }
+}
+
+#[test]
+fn membership_and_counting() {
+    for (pairs, equal) in [(EQUAL, true), (UNEQUAL, false)] {
+        for (l, r) in pairs {
+            assert_eq!(run(&format!("{} in [0, {}]", l, r)), equal.to_string(), "{} in [0, {}]", l, r);
+            assert_eq!(run(&format!("count([{}, 0, {}, {}], {})", l, r, l, r)), if equal { "3" } else { "1" }, "count {} {}", l, r);
+            let kept = if equal { format!("[{}]", l) } else { format!("[{}, {}]", l, r) };
+            assert_eq!(run(&format!("unique([{}, {}, {}])", l, r, l)), run(&kept), "unique {} {}", l, r);
+        }
+    }
+    // Hash membership looks at keys, matched the same way
+    assert_eq!(run("[1.0 in {1: \"a\"}, \"a\" in {1: \"a\"}, 2.5 in {2: 0}]"), "[true, false, false]");
+}
+
+#[cfg(feature = "vm")]
+#[test]
+fn both_engines_agree_on_scalars() {
+    for source in ["1 == 1.0", "2.5 != 2", "1 == 2", "true == true", "\"a\" == \"a\""] {
+        let interpreted = run(source);
+        let compiled = Runtime::new().with_engine(Engine::Vm).run_source(source).unwrap().to_string();
+        assert_eq!(interpreted, compiled, "{}", source);
+    }
+}
@@ -0,0 +1,89 @@
+// Runtime errors from a script point at the operation that failed
+use std::fs;
+use flux_compiler::{Engine, RunError, Runtime};
+use flux_compiler::diagnostics;
+use flux_compiler::token::Span;
+
+fn run_script(name: &str, source: &str, engine: Engine) -> RunError {
+    let path = std::env::temp_dir().join(format!("flux_excerpt_{}_{}.flux", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let result = Runtime::new().with_engine(engine).run_file(&path);
+    let _ = fs::remove_file(&path);
+    result.expect_err("the script should fail")
+}
+
+#[test]
+fn only_the_failing_infix_is_underlined() {
+    let source = "mut a = 4\nmut b = a * 2 + (a - true) * 3 - a / 2\n";
+    for &engine in Engine::ALL {
+        match run_script("infix", source, engine) {
+            RunError::Runtime(msg, Some(excerpt)) => {
+                assert_eq!(msg, "Type mismatch");
+                let lines: Vec<&str> = excerpt.lines().collect();
+                assert_eq!(lines[0], " --> line 2:18");
+                assert_eq!(lines[2], "2 | mut b = a * 2 + (a - true) * 3 - a / 2");
+                assert_eq!(lines[3], "  |                  ^^^^^^^^");
+            },
+            other => panic!("{:?}: expected an excerpt, got {:?}", engine, other),
+        }
+    }
+}
+
+#[test]
+fn source_strings_have_no_excerpt() {
+    match Runtime::new().run_source("1 + true") {
+        Err(RunError::Runtime(_, excerpt)) => assert_eq!(excerpt, None),
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn multi_line_spans_stop_at_the_first_line() {
+    let source = "x = [1,\n  2][\"k\"]";
+    let excerpt = diagnostics::excerpt(source, Span { start: 4, end: source.len() }).unwrap();
+    assert_eq!(excerpt, " --> line 1:5\n  |\n1 | x = [1,\n  |     ^^^");
+}
+
+#[cfg(feature = "eval")]
+#[test]
+fn a_missing_variable_deep_in_nested_functions_is_pinned_to_its_line() {
+    let source = "mut outer = fn(n) {\n  mut inner = fn(m) {\n    mut total = m + 1\n    total * scale\n  }\n  inner(n) + 1\n}\nouter(3)\n";
+    let excerpt = match run_script("nested", source, Engine::Interpreter) {
+        RunError::Runtime(msg, Some(excerpt)) => {
+            assert_eq!(msg, "Variable 'scale' not found");
+            excerpt
+        },
+        other => panic!("expected an excerpt, got {:?}", other),
+    };
+    let lines: Vec<&str> = excerpt.lines().collect();
+    assert_eq!(lines[0], " --> line 4:13");
+    assert_eq!(lines[3], "  |             ^^^^^");
+}
+
+#[cfg(feature = "vm")]
+#[test]
+fn the_compiler_points_at_the_name_it_rejects() {
+    let source = "mut f = fn(x) {\n  x + y\n}\nf(1)\n";
+    match run_script("compile", source, Engine::Vm) {
+        RunError::Compile(msg, Some(excerpt)) => {
+            assert_eq!(msg, "Undefined variable: y");
+            assert!(excerpt.starts_with(" --> line 2:7\n"), "{}", excerpt);
+        },
+        other => panic!("expected a compile error with an excerpt, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "eval")]
+#[test]
+fn loops_assignments_and_matches_are_pinned_too() {
+    for (source, line) in [
+        ("mut n = 5\nfor (x in n) { x }\n", " --> line 2:11"),
+        ("mut grid = [1]\ngrid[3] = 0\n", " --> line 2:1"),
+        ("mut v = 7\nmatch (v) { 1 => 0 }\n", " --> line 2:1"),
+    ] {
+        match run_script("statements", source, Engine::Interpreter) {
+            RunError::Runtime(_, Some(excerpt)) => assert!(excerpt.starts_with(line), "{}\n{}", source, excerpt),
+            other => panic!("{}: expected an excerpt, got {:?}", source, other),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Hashes with defaults, counter(), get(), and index assignment, which changes
+// the variable in the scope where it was declared
+#![cfg(feature = "eval")]
+use flux_compiler::{Engine, RunError, Runtime};
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+fn error_of(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("{} should fail at run time, got {:?}", source, other),
+    }
+}
+
+#[test]
+fn counters_start_every_key_at_zero() {
+    assert_eq!(run("mut c = counter()\nfor (w in [\"a\", \"b\", \"a\"]) { c[w] = c[w] + 1 }\n[c[\"a\"], c[\"b\"], c[\"z\"]]"), "[2, 1, 0]");
+    // Reading a missing key doesn't add it
+    assert_eq!(run("mut c = counter()\nc[\"z\"]\nkeys(c)"), "[]");
+}
+
+#[test]
+fn a_default_is_returned_for_missing_keys() {
+    assert_eq!(run("mut d = hash_with_default(\"?\")\nd[\"k\"] = \"v\"\n[d[\"k\"], d[\"missing\"], len(keys(d))]"), "[v, ?, 1]");
+}
+
+#[test]
+fn get_falls_back() {
+    assert_eq!(run("mut h = {\"a\": 1}\n[get(h, \"a\"), get(h, \"b\"), get(h, \"b\", 0)]"), "[1, null, 0]");
+    // A hash's own default comes before no fallback at all
+    assert_eq!(run("mut c = counter()\n[get(c, \"x\"), get(c, \"x\", 5)]"), "[0, 5]");
+}
+
+#[test]
+fn index_assignment_updates_the_variable_where_it_lives() {
+    assert_eq!(run("mut h = {\"a\": 1}\nmut f = fn() { h[\"a\"] = 5 }\nf()\nh[\"a\"]"), "5");
+    assert_eq!(run("mut xs = [1, [2, 3]]\nmut f = fn() { xs[1][0] = 9 }\nf()\nxs"), "[1, [9, 3]]");
+    assert_eq!(run("mut h = {}\nif (true) { h[\"k\"] = {\"n\": 1} }\nh[\"k\"][\"n\"] = 2\nh"), "{\"k\": {\"n\": 2}}");
+    // A local of the same name hides the outer one
+    assert_eq!(run("mut xs = [1]\nmut f = fn() { mut xs = [0]; xs[0] = 7; xs }\n[f(), xs]"), "[[7], [1]]");
+}
+
+#[test]
+fn index_assignment_errors() {
+    assert_eq!(error_of("mut xs = [1, 2]\nxs[5] = 1"), "Index 5 out of range for array of length 2");
+    assert_eq!(error_of("mut n = 1\nn[0] = 1"), "Index assignment not supported");
+    assert_eq!(error_of("mut f = fn() { q[0] = 1 }\nf()"), "Variable 'q' not found");
+}
+
+// Each engine this build has, the interpreter first
+fn run_all(source: &str) -> Vec<String> {
+    Engine::ALL.iter().map(|&engine| match Runtime::new().with_engine(engine).run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{:?}: {}: {}", engine, source, e),
+    }).collect()
+}
+
+// The VM compiles no hash literals, but indexes and assigns into the hashes builtins return
+#[test]
+fn both_engines_count_letters() {
+    let source = r#"
+        mut word = "mississippi"
+        mut counts = counter()
+        mut i = 0
+        while (i < len(word)) {
+            counts[word[i]] = counts[word[i]] + 1
+            i = i + 1
+        }
+        counts["s"] * 100 + counts["p"] * 10 + counts["z"]
+    "#;
+    for result in run_all(source) { assert_eq!(result, "420"); }
+    let local = "mut tally = fn(w) { mut c = hash_with_default(\"none\"); c[w] = \"one\"; c[w] + \"/\" + c[\"other\"] }\ntally(\"x\")";
+    for result in run_all(local) { assert_eq!(result, "one/none"); }
+}
+
+#[test]
+fn merging_keeps_the_default() {
+    for result in run_all("mut c = counter()\nc[\"a\"] = 2\nmut m = merge(c, hash_with_default(9))\nm[\"a\"] * 10 + m[\"b\"]") {
+        assert_eq!(result, "20");
+    }
+    assert_eq!(run("mut m = merge({\"a\": 1, \"b\": 2}, {\"b\": 3})\n[m[\"a\"], m[\"b\"], m[\"c\"]]"), "[1, 3, null]");
+    // A plain hash merged with a counter takes the counter's default
+    assert_eq!(run("mut m = merge({\"a\": 1}, counter())\n[m[\"a\"], m[\"z\"]]"), "[1, 0]");
+}
@@ -0,0 +1,80 @@
+// Top-level functions are bound before anything runs, in both engines: they
+// may be called above their definition, call each other, and use globals
+use flux_compiler::{Engine, RunError, Runtime};
+
+fn run_in(engine: Engine, source: &str) -> String {
+    match Runtime::new().with_engine(engine).run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{:?}: {}: {}", engine, source, e),
+    }
+}
+
+// The runtime error each engine reports; they word a missing name differently
+fn error_in(engine: Engine, source: &str) -> String {
+    match Runtime::new().with_engine(engine).run_source(source) {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("{:?}: {} should fail at run time, got {:?}", engine, source, other),
+    }
+}
+
+// The interpreter comes first in Engine::ALL when the build has one
+fn missing(engine: Engine, name: &str) -> String {
+    if cfg!(feature = "eval") && engine == Engine::ALL[0] {
+        format!("Variable '{}' not found", name)
+    } else {
+        format!("Undefined variable: {}", name)
+    }
+}
+
+fn assert_both(source: &str, expected: &str) {
+    for &engine in Engine::ALL {
+        assert_eq!(run_in(engine, source), expected, "{:?}: {}", engine, source);
+    }
+}
+
+#[test]
+fn mutual_recursion() {
+    let source = "mut is_even = fn(n) { if (n == 0) { true } else { is_odd(n - 1) } }\n\
+                  mut is_odd = fn(n) { if (n == 0) { false } else { is_even(n - 1) } }\n\
+                  is_even(10)";
+    assert_both(source, "true");
+}
+
+#[test]
+fn a_call_above_both_definitions() {
+    let source = "mut result = double(half(8))\n\
+                  mut half = fn(x) { x / 2 }\n\
+                  mut double = fn(x) { x * 2 }\n\
+                  result";
+    assert_both(source, "8");
+}
+
+#[test]
+fn functions_read_and_assign_globals() {
+    assert_both("mut n = 1\nmut f = fn() { n + 1 }\nf()", "2");
+    assert_both("mut n = 1\nmut f = fn() { n = 4 }\nf()\nn", "4");
+    // Declared after the function, but before the call
+    assert_both("mut f = fn() { limit * 2 }\nmut limit = 21\nf()", "42");
+}
+
+// Only functions are bound early: any other global is missing until its definition runs
+#[test]
+fn a_variable_read_before_its_definition_is_an_error() {
+    for &engine in Engine::ALL {
+        assert_eq!(error_in(engine, "mut y = x + 1\nmut x = 5"), missing(engine, "x"), "{:?}", engine);
+        assert_eq!(error_in(engine, "mut f = fn() { limit * 2 }\nmut y = f()\nmut limit = 21"), missing(engine, "limit"), "{:?}", engine);
+    }
+}
+
+#[test]
+fn a_failed_run_leaves_the_variable_missing_for_the_next() {
+    for &engine in Engine::ALL {
+        let mut runtime = Runtime::new().with_engine(engine);
+        assert!(runtime.run_source("mut y = x + 1\nmut x = 5").is_err());
+        match runtime.run_source("x") {
+            Err(RunError::Runtime(msg, _)) => assert_eq!(msg, missing(engine, "x"), "{:?}", engine),
+            other => panic!("{:?}: expected a runtime error, got {:?}", engine, other),
+        }
+        assert_eq!(runtime.run_source("mut x = 6\nx").unwrap().to_string(), "6", "{:?}", engine);
+    }
+}
@@ -0,0 +1,133 @@
+// The startup script: --init, $FLUX_INIT or ~/.fluxrc.flux is loaded into the
+// REPL session before the first prompt, and --no-init skips it. Only the
+// default file may be missing without a word; a broken one is reported and
+// the session still works.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// A fresh directory to serve as $HOME, unique to the test
+fn home(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("flux_init_{}_{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A REPL session typing `input`, with HOME at `home` and FLUX_INIT as given
+fn session(home: &Path, flux_init: Option<&Path>, args: &[&str], input: &str) -> String {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_flux_compiler"));
+    cmd.arg("--plain").args(args).env("HOME", home).env_remove("FLUX_INIT");
+    if let Some(path) = flux_init {
+        cmd.env("FLUX_INIT", path);
+    }
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().expect("failed to run flux_compiler");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+// Without the interpreter the REPL compiles each line and the VM reports it
+fn missing_greet() -> &'static str {
+    if cfg!(feature = "eval") { "ERROR: Variable 'greet' not found" } else { "Compiler Error: Undefined variable: greet" }
+}
+
+const GREET: &str = "mut greet = fn(name) { \"hi \" + name }\n";
+
+#[test]
+fn flux_init_is_loaded_before_the_first_prompt() {
+    let home = home("env");
+    let init = home.join("helpers.flux");
+    fs::write(&init, GREET).unwrap();
+    let out = session(&home, Some(&init), &[], "greet(\"ada\")\nexit\n");
+    assert!(out.contains("hi ada"), "{}", out);
+    assert!(!out.contains("Init file"), "{}", out);
+}
+
+#[test]
+fn the_file_in_home_is_the_default() {
+    let home = home("default");
+    fs::write(home.join(".fluxrc.flux"), GREET).unwrap();
+    let out = session(&home, None, &[], "greet(\"bob\")\nexit\n");
+    assert!(out.contains("hi bob"), "{}", out);
+}
+
+#[test]
+fn a_missing_default_file_is_not_mentioned() {
+    let home = home("no_default");
+    let out = session(&home, None, &[], "1 + 1\nexit\n");
+    assert!(!out.contains("Init file"), "{}", out);
+    assert!(out.contains("2"), "{}", out);
+}
+
+#[test]
+fn a_missing_chosen_file_is_reported() {
+    let home = home("missing");
+    let missing = home.join("nowhere.flux");
+    let out = session(&home, None, &["--init", &missing.to_string_lossy()], "1 + 1\nexit\n");
+    assert!(out.contains(&format!("Init file {}: no such file", missing.display())), "{}", out);
+    assert!(out.contains("2"), "{}", out);
+
+    let out = session(&home, Some(&missing), &[], "1 + 1\nexit\n");
+    assert!(out.contains(&format!("Init file {}: no such file", missing.display())), "{}", out);
+}
+
+#[test]
+fn init_wins_over_flux_init() {
+    let home = home("both");
+    let (chosen, from_env) = (home.join("chosen.flux"), home.join("env.flux"));
+    fs::write(&chosen, "mut which = \"chosen\"").unwrap();
+    fs::write(&from_env, "mut which = \"env\"").unwrap();
+    let out = session(&home, Some(&from_env), &["--init", &chosen.to_string_lossy()], "which\nexit\n");
+    assert!(out.contains("chosen"), "{}", out);
+    assert!(!out.contains("env"), "{}", out);
+}
+
+#[test]
+fn no_init_skips_every_startup_script() {
+    let home = home("no_init");
+    fs::write(home.join(".fluxrc.flux"), GREET).unwrap();
+    let init = home.join("helpers.flux");
+    fs::write(&init, GREET).unwrap();
+    for flux_init in [None, Some(init.as_path())] {
+        let out = session(&home, flux_init, &["--no-init"], "greet(\"x\")\nexit\n");
+        assert!(!out.contains("hi x"), "{}", out);
+        assert!(out.contains(missing_greet()), "{}", out);
+    }
+}
+
+#[test]
+fn a_broken_init_file_still_leaves_a_working_prompt() {
+    let home = home("broken");
+    let init = home.join("broken.flux");
+    fs::write(&init, "mut ok = 1\nmut = = 2\n").unwrap();
+    let out = session(&home, Some(&init), &[], "40 + 2\nexit\n");
+    assert!(out.contains(&format!("Init file {} has errors, skipping it:", init.display())), "{}", out);
+    assert!(out.contains("42"), "{}", out);
+}
+
+#[test]
+fn an_init_file_that_fails_part_way_keeps_what_ran() {
+    let home = home("fails");
+    let init = home.join("fails.flux");
+    fs::write(&init, format!("{}1 / 0\nmut after = 1\n", GREET)).unwrap();
+    let out = session(&home, Some(&init), &[], "greet(\"ok\")\nexit\n");
+    assert!(out.contains(&format!("Init file {} failed: ERROR: Division by zero", init.display())), "{}", out);
+    assert!(out.contains("hi ok"), "{}", out);
+}
+
+#[test]
+fn scripts_load_only_an_explicit_init_file() {
+    let home = home("script");
+    let init = home.join("helpers.flux");
+    fs::write(&init, GREET).unwrap();
+    let script = home.join("script.flux");
+    fs::write(&script, "greet(\"script\")").unwrap();
+    let script = script.to_string_lossy().into_owned();
+
+    let out = session(&home, Some(&init), &[&script], "");
+    assert!(out.contains(missing_greet()), "FLUX_INIT applies only to the REPL: {}", out);
+    let out = session(&home, None, &["--init", &init.to_string_lossy(), &script], "");
+    assert_eq!(out, "hi script\n");
+}
@@ -0,0 +1,83 @@
+// Integer arithmetic at the edges of i64: both engines give the same answer,
+// and an error rather than a panic where there is no answer
+use flux_compiler::{Engine, RunError, Runtime};
+
+const MIN: &str = "(0 - 9223372036854775807 - 1)";
+
+fn run_in(engine: Engine, source: &str) -> Result<String, String> {
+    match Runtime::new().with_engine(engine).run_source(source) {
+        Ok(value) => Ok(value.to_string()),
+        Err(RunError::Runtime(msg, _)) => Err(msg),
+        Err(e) => panic!("{:?}: {}: {}", engine, source, e),
+    }
+}
+
+fn assert_both(source: &str, expected: Result<&str, &str>) {
+    for &engine in Engine::ALL {
+        let expected = expected.map(str::to_string).map_err(str::to_string);
+        assert_eq!(run_in(engine, source), expected, "{:?}: {}", engine, source);
+    }
+}
+
+#[test]
+fn division_rounds_toward_zero() {
+    assert_both("7 / 2", Ok("3"));
+    assert_both("-7 / 2", Ok("-3"));
+    assert_both("7 / -2", Ok("-3"));
+}
+
+#[test]
+fn division_by_zero_is_an_error() {
+    assert_both("1 / 0", Err("Division by zero"));
+    assert_both("0 / 0", Err("Division by zero"));
+    assert_both("mut zero = 0\n5 / zero", Err("Division by zero"));
+}
+
+#[test]
+fn dividing_the_smallest_integer_by_minus_one_overflows() {
+    assert_both(&format!("{} / -1", MIN), Err("Integer overflow: -9223372036854775808 / -1"));
+    assert_both(&format!("{} / 1", MIN), Ok("-9223372036854775808"));
+}
+
+#[test]
+fn addition_subtraction_and_multiplication_overflow_as_errors() {
+    assert_both("9223372036854775807 + 1", Err("Integer overflow: 9223372036854775807 + 1"));
+    assert_both(&format!("{} - 1", MIN), Err("Integer overflow: -9223372036854775808 - 1"));
+    assert_both("0 - 9223372036854775807 - 2", Err("Integer overflow: -9223372036854775807 - 2"));
+    assert_both("4611686018427387904 * 2", Err("Integer overflow: 4611686018427387904 * 2"));
+    assert_both(&format!("{} * -1", MIN), Err("Integer overflow: -9223372036854775808 * -1"));
+}
+
+#[test]
+fn results_at_the_limits_still_fit() {
+    assert_both("9223372036854775806 + 1", Ok("9223372036854775807"));
+    assert_both(&format!("{} + 0", MIN), Ok("-9223372036854775808"));
+    assert_both("4611686018427387903 * 2", Ok("9223372036854775806"));
+    assert_both("-4611686018427387904 * 2", Ok("-9223372036854775808"));
+}
+
+#[test]
+fn compound_assignment_overflows_the_same_way() {
+    assert_both("mut n = 9223372036854775807\nn += 1\nn", Err("Integer overflow: 9223372036854775807 + 1"));
+    assert_both("mut n = 3037000500\nn *= n\nn", Err("Integer overflow: 3037000500 * 3037000500"));
+}
+
+#[test]
+fn an_overflow_inside_a_function_stops_the_program() {
+    let source = "let grow = fn(n) { n * 1000 }\nmut n = 1\nwhile (n > 0) { n = grow(n) }\nn";
+    assert_both(source, Err("Integer overflow: 1000000000000000000 * 1000"));
+}
+
+#[test]
+fn negating_the_smallest_integer_overflows() {
+    assert_both(&format!("-{}", MIN), Err("Integer overflow: -(-9223372036854775808)"));
+    assert_both(&format!("mut n = {}\n-n", MIN), Err("Integer overflow: -(-9223372036854775808)"));
+}
+
+#[test]
+fn negation_is_exact_everywhere_else() {
+    assert_both("-9223372036854775807", Ok("-9223372036854775807"));
+    assert_both("mut n = -9223372036854775807\n-n", Ok("9223372036854775807"));
+    assert_both("--5", Ok("5"));
+    assert_both("-0", Ok("0"));
+}
@@ -0,0 +1,63 @@
+// Integer literals at the edge of 64 bits: the largest one parses, anything
+// past it is a parse error naming the line, never a silent 0
+use flux_compiler::ast::{Expression, Statement};
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::Parser;
+use flux_compiler::{Engine, RunError, Runtime};
+
+// The value of a program that is a single integer literal
+fn literal(source: &str) -> i64 {
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert!(!program.has_errors(), "{}: {:?}", source, program.errors);
+    match &program.statements[..] {
+        [Statement::Expression(Expression::IntegerLiteral { value, .. })] => *value,
+        other => panic!("{} is not an integer literal: {:?}", source, other),
+    }
+}
+
+// The parse errors of `source`, as the CLI shows them
+fn errors_of(source: &str) -> Vec<String> {
+    Parser::new(Lexer::new(source.to_string())).parse_program().errors.iter().map(|e| e.to_string()).collect()
+}
+
+const FORTY_DIGITS: &str = "1234567890123456789012345678901234567890";
+
+#[test]
+fn the_largest_integer_parses() {
+    assert_eq!(literal("9223372036854775807"), i64::MAX);
+    assert_eq!(literal("9_223_372_036_854_775_807"), i64::MAX);
+    assert_eq!(literal("0"), 0);
+}
+
+#[test]
+fn one_past_the_largest_is_out_of_range() {
+    assert_eq!(errors_of("9223372036854775808"), ["line 1:1: integer literal out of range for 64-bit integer: '9223372036854775808'"]);
+    // The minus is an operator, so the smallest integer can't be written as a literal either
+    assert_eq!(errors_of("-9223372036854775808"), ["line 1:2: integer literal out of range for 64-bit integer: '9223372036854775808'"]);
+}
+
+#[test]
+fn a_forty_digit_literal_is_out_of_range() {
+    let expected = format!("line 1:1: integer literal out of range for 64-bit integer: '{}'", FORTY_DIGITS);
+    assert_eq!(errors_of(FORTY_DIGITS), [expected]);
+    assert_eq!(errors_of("99999999999999999999"), ["line 1:1: integer literal out of range for 64-bit integer: '99999999999999999999'"]);
+}
+
+#[test]
+fn the_error_names_the_line_and_column_of_the_literal() {
+    let source = format!("mut a = 1\nmut b = 2\nmut c = a + {} * b\nmut d = 4", FORTY_DIGITS);
+    assert_eq!(errors_of(&source), [format!("line 3:13: integer literal out of range for 64-bit integer: '{}'", FORTY_DIGITS)]);
+}
+
+#[test]
+fn no_engine_runs_a_program_with_an_oversized_literal() {
+    for &engine in Engine::ALL {
+        for source in ["9223372036854775808", FORTY_DIGITS, "print(\"never\")\nmut x = 9223372036854775808 + 1"] {
+            match Runtime::new().with_engine(engine).run_source(source) {
+                Err(RunError::Parse(errors, _)) => assert!(errors[0].message.starts_with("integer literal out of range"), "{:?}", errors),
+                other => panic!("{:?}: {} should not parse, got {:?}", engine, source, other),
+            }
+        }
+        assert_eq!(Runtime::new().with_engine(engine).run_source("9223372036854775807").unwrap().to_string(), "9223372036854775807");
+    }
+}
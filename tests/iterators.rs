@@ -0,0 +1,62 @@
+// Iterators are lazy: a range is never built as an array, and ends cleanly at
+// the edges of the integers
+#![cfg(feature = "eval")]
+use std::time::{Duration, Instant};
+use flux_compiler::Runtime;
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+#[test]
+fn a_huge_range_is_only_walked_as_far_as_needed() {
+    let start = Instant::now();
+    assert_eq!(run("collect(take(range(10_000_000), 3))"), "[0, 1, 2]");
+    assert_eq!(run("mut r = range(10_000_000)\nnext(r)\nnext(r)"), "1");
+    assert!(start.elapsed() < Duration::from_secs(1), "took {:?}", start.elapsed());
+}
+
+#[test]
+fn ranges_stop_at_the_ends_of_the_integers() {
+    assert_eq!(run("collect(range(9223372036854775806, 9223372036854775807, 10))"), "[9223372036854775806]");
+    assert_eq!(run("collect(range(9223372036854775805, 9223372036854775807))"), "[9223372036854775805, 9223372036854775806]");
+    assert_eq!(run("collect(range(-9223372036854775807, -9223372036854775807 - 1, -5))"), "[-9223372036854775807]");
+}
+
+#[test]
+fn a_for_loop_walks_a_range_without_building_it() {
+    assert_eq!(run("mut total = 0\nfor (x in range(200_000)) { total += x }\ntotal"), "19999900000");
+    // No array could hold this one
+    let start = Instant::now();
+    assert_eq!(run("mut seen = 0\nfor (x in range(9223372036854775807)) { if (x == 3) { break }\nseen += 1 }\nseen"), "3");
+    assert!(start.elapsed() < Duration::from_secs(1), "took {:?}", start.elapsed());
+}
+
+#[test]
+fn a_for_loop_takes_any_iterator() {
+    let source = "mut out = []\nfor (x in take(filter(map(range(100), fn(x) { x * x }), fn(x) { x > 10 }), 3)) { out = push(out, x) }\nout";
+    assert_eq!(run(source), "[16, 25, 36]");
+    assert_eq!(run("mut out = []\nfor (i, x in range(5, 8)) { out = push(out, [i, x]) }\nout"), "[[0, 5], [1, 6], [2, 7]]");
+    assert_eq!(run("mut out = []\nfor (c in \"abc\") { out = push(out, c) }\nout"), "[a, b, c]");
+}
+
+#[test]
+fn an_error_in_a_callback_stops_the_loop() {
+    match Runtime::new().run_source("for (x in map(range(3), fn(x) { 1 / (x - 1) })) { x }") {
+        Err(e) => assert!(e.to_string().contains("Division by zero"), "{}", e),
+        Ok(value) => panic!("expected an error, got {}", value),
+    }
+}
+
+#[test]
+fn hashes_iterate_in_key_order() {
+    let hash = "{\"c\": 3, \"a\": 1, \"e\": 5, \"b\": 2, \"d\": 4}";
+    for _ in 0..20 {
+        assert_eq!(run(&format!("collect(map({}, fn(k) {{ k }}))", hash)), "[a, b, c, d, e]");
+    }
+    assert_eq!(run(&format!("collect({}) == keys({})", hash, hash)), "true");
+    assert_eq!(run(&format!("mut out = []\nfor (k, v in {}) {{ out = push(out, k + v) }}\nout", hash)), "[a1, b2, c3, d4, e5]");
+}
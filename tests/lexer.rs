@@ -0,0 +1,53 @@
+// How the lexer splits source into tokens and which words are keywords
+use flux_compiler::lexer::Lexer;
+use flux_compiler::token::TokenType;
+
+fn tokens(source: &str) -> Vec<(TokenType, String)> {
+    Lexer::tokenize(source).into_iter().map(|tok| (tok.token_type, tok.literal)).collect()
+}
+
+fn ident(name: &str) -> (TokenType, String) {
+    (TokenType::Identifier, name.to_string())
+}
+
+fn dot() -> (TokenType, String) {
+    (TokenType::Dot, ".".to_string())
+}
+
+fn eof() -> (TokenType, String) {
+    (TokenType::EOF, String::new())
+}
+
+#[test]
+fn member_access_is_a_dot_between_names() {
+    assert_eq!(tokens("a.b"), vec![ident("a"), dot(), ident("b"), eof()]);
+    assert_eq!(tokens("arr.len"), vec![ident("arr"), dot(), ident("len"), eof()]);
+}
+
+#[test]
+fn whitespace_around_the_dot_is_allowed() {
+    assert_eq!(tokens("a .b"), vec![ident("a"), dot(), ident("b"), eof()]);
+    assert_eq!(tokens("a . b"), vec![ident("a"), dot(), ident("b"), eof()]);
+}
+
+#[test]
+fn a_digit_after_the_dot_makes_a_float() {
+    assert_eq!(tokens("1.5"), vec![(TokenType::Float, "1.5".to_string()), eof()]);
+    assert_eq!(tokens("1.foo"), vec![(TokenType::Int, "1".to_string()), dot(), ident("foo"), eof()]);
+    assert_eq!(tokens("1.5.x"), vec![(TokenType::Float, "1.5".to_string()), dot(), ident("x"), eof()]);
+    assert_eq!(tokens("1.2.3"), vec![(TokenType::Illegal, "1.2.3".to_string()), eof()]);
+}
+
+#[test]
+fn three_dots_are_still_one_token() {
+    assert_eq!(tokens("xs..."), vec![ident("xs"), (TokenType::DotDotDot, "...".to_string()), eof()]);
+}
+
+#[test]
+fn embeddings_can_add_keywords() {
+    flux_compiler::add_keyword("si", TokenType::If);
+    flux_compiler::add_keyword("var", TokenType::Mut);
+    assert_eq!(tokens("si")[0].0, TokenType::If);
+    let value = flux_compiler::Runtime::new().run_source("var x = 2\nsi (x > 1) { x * 21 }").unwrap();
+    assert_eq!(value.to_string(), "42");
+}
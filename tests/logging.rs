@@ -0,0 +1,101 @@
+// log_debug/log_info/log_warn/log_error: the text and JSON-lines formats, and
+// the minimum level from set_log_level(), FLUX_LOG and --log-level
+#![cfg(feature = "eval")]
+use std::fs;
+use std::process::Command;
+use flux_compiler::{Capture, Io, Runtime};
+
+// What a script logged, one line each, with the timestamps checked and taken out
+fn logged(source: &str) -> Vec<String> {
+    let errors = Capture::new();
+    let io = Io { input: Box::new(std::io::empty()), output: Box::new(std::io::sink()), errors: Box::new(errors.clone()) };
+    Runtime::new().with_io(io).run_source(source).unwrap();
+    errors.text().lines().map(without_timestamp).collect()
+}
+
+// `2026-01-31T09:15:02.047Z WARN  x` is `<ts> WARN  x`, and likewise for "ts" in JSON
+fn without_timestamp(line: &str) -> String {
+    let (before, rest) = match line.strip_prefix("{\"ts\":\"") {
+        Some(rest) => ("{\"ts\":\"", rest),
+        None => ("", line),
+    };
+    let ts = &rest[..24];
+    let shape: String = ts.chars().map(|c| if c.is_ascii_digit() { '0' } else { c }).collect();
+    assert_eq!(shape, "0000-00-00T00:00:00.000Z", "{}", line);
+    format!("{}<ts>{}", before, &rest[24..])
+}
+
+#[test]
+fn text_lines_have_a_timestamp_a_padded_level_and_the_data_as_json() {
+    let source = r#"
+        set_log_level("debug")
+        log_debug("starting")
+        log_info("loaded", {"files": 3})
+        log_warn("disk almost full", {"unit": "GB", "free": 12})
+        log_error("gave up", {"tries": [1, "two"]})
+    "#;
+    let lines = logged(source);
+    assert_eq!(lines[0], "<ts> DEBUG starting");
+    assert_eq!(lines[1], "<ts> INFO  loaded {\"files\":3}");
+    // Keys in order, so the line is the same every run
+    assert_eq!(lines[2], "<ts> WARN  disk almost full {\"free\":12,\"unit\":\"GB\"}");
+    assert_eq!(lines[3], "<ts> ERROR gave up {\"tries\":[1,\"two\"]}");
+    assert_eq!(lines.len(), 4);
+}
+
+#[test]
+fn json_lines_carry_the_same_fields() {
+    let source = r#"
+        set_log_format("json")
+        log_info("loaded", {"files": 3})
+        log_error("say \"hi\"")
+        set_log_format("text")
+        log_info("back to text")
+    "#;
+    assert_eq!(logged(source), [
+        "{\"ts\":\"<ts>\",\"level\":\"info\",\"msg\":\"loaded\",\"data\":{\"files\":3}}",
+        "{\"ts\":\"<ts>\",\"level\":\"error\",\"msg\":\"say \\\"hi\\\"\"}",
+        "<ts> INFO  back to text",
+    ]);
+}
+
+#[test]
+fn messages_below_the_minimum_level_are_dropped() {
+    let source = r#"
+        set_log_level("warn")
+        log_debug("a"); log_info("b"); log_warn("c"); log_error("d")
+        set_log_level("error")
+        log_warn("e"); log_error("f")
+        set_log_level("debug")
+        log_debug("g")
+    "#;
+    assert_eq!(logged(source), ["<ts> WARN  c", "<ts> ERROR d", "<ts> ERROR f", "<ts> DEBUG g"]);
+}
+
+#[test]
+fn an_unknown_level_or_format_is_an_error() {
+    let message = |source: &str| Runtime::new().run_source(source).unwrap_err().to_string();
+    assert!(message("set_log_level(\"loud\")").contains("set_log_level: level must be \"debug\", \"info\", \"warn\" or \"error\", not \"loud\""));
+    assert!(message("set_log_format(\"xml\")").contains("set_log_format"));
+}
+
+// The starting level comes from FLUX_LOG, and --log-level beats it
+#[test]
+fn flux_log_and_the_log_level_flag_set_the_starting_level() {
+    let path = std::env::temp_dir().join(format!("flux_logging_{}.flux", std::process::id()));
+    fs::write(&path, "log_debug(\"d\"); log_info(\"i\"); log_warn(\"w\"); log_error(\"e\")").unwrap();
+    let levels = |flux_log: Option<&str>, flag: Option<&str>| {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_flux_compiler"));
+        match flux_log {
+            Some(level) => cmd.env("FLUX_LOG", level),
+            None => cmd.env_remove("FLUX_LOG"),
+        };
+        if let Some(level) = flag { cmd.args(["--log-level", level]); }
+        let output = cmd.arg(&path).output().expect("failed to run flux_compiler");
+        String::from_utf8_lossy(&output.stderr).lines().map(|line| line.split_whitespace().nth(1).unwrap().to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(levels(None, None), ["INFO", "WARN", "ERROR"]);
+    assert_eq!(levels(Some("error"), None), ["ERROR"]);
+    assert_eq!(levels(Some("error"), Some("debug")), ["DEBUG", "INFO", "WARN", "ERROR"]);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,96 @@
+// Sugar is rewritten into core nodes before either engine sees it
+use flux_compiler::ast::{walk_expression, walk_statement, BlockStatement, Expression, Statement, VisitorMut};
+use flux_compiler::ast_lower;
+use flux_compiler::intern::Symbol;
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::Parser;
+use flux_compiler::token::Span;
+use flux_compiler::{Engine, Runtime};
+
+fn parse(source: &str) -> Vec<Statement> {
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert!(!program.has_errors(), "{:?}", program.errors);
+    program.statements
+}
+
+// Every sugar node left in a tree, by name
+#[derive(Default)]
+struct Sugar(Vec<&'static str>);
+
+impl VisitorMut for Sugar {
+    fn visit_statement(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::CompoundAssign { .. } => self.0.push("compound assignment"),
+            Statement::Function { .. } => self.0.push("fn name"),
+            _ => {},
+        }
+        walk_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        if let Expression::Infix { operator, .. } = expr && (operator == "&&" || operator == "||") {
+            self.0.push("logical operator");
+        }
+        walk_expression(self, expr);
+    }
+}
+
+fn sugar_in(program: &mut [Statement]) -> Vec<&'static str> {
+    let mut sugar = Sugar::default();
+    for stmt in program { sugar.visit_statement(stmt); }
+    sugar.0
+}
+
+#[test]
+fn no_sugar_survives_lowering() {
+    let source = "mut n = 0\nmut f = fn(x) { n += x; x > 0 && (x < 10 || x == 99) }\nwhile (n < 3 || false) { n *= 2 }\nfn g(y) { y -= 1 }";
+    let mut program = parse(source);
+    assert_eq!(sugar_in(&mut program), ["compound assignment", "logical operator", "logical operator", "logical operator", "compound assignment", "fn name", "compound assignment"]);
+    let mut lowered = ast_lower::lower(program);
+    assert!(sugar_in(&mut lowered).is_empty());
+}
+
+#[test]
+fn named_functions_become_bindings() {
+    let function = Statement::Function {
+        name: Symbol::intern("double"),
+        parameters: vec![Symbol::intern("x")],
+        variadic: true,
+        body: BlockStatement { statements: parse("x *= 2\nx") },
+    };
+    match ast_lower::lower_statement(function) {
+        Statement::Let { name, value: Expression::FunctionLiteral { parameters, variadic, body } } => {
+            assert_eq!((name.as_str(), parameters.len(), variadic), ("double", 1, true));
+            let statements: Vec<String> = body.statements.iter().map(|stmt| stmt.to_string()).collect();
+            assert_eq!(statements, ["x = (x * 2)", "x"]);
+        },
+        other => panic!("expected a binding, got {}", other),
+    }
+}
+
+#[test]
+fn compound_assignment_keeps_its_span() {
+    let span = Span { start: 10, end: 16 };
+    let stmt = Statement::CompoundAssign { name: Symbol::intern("x"), operator: "-".to_string(), value: Expression::Boolean(true), span };
+    match ast_lower::lower_statement(stmt) {
+        Statement::Assign { value: Expression::Infix { operator, span: lowered, .. }, .. } => {
+            assert_eq!((operator.as_str(), lowered), ("-", span));
+        },
+        other => panic!("expected an assignment, got {}", other),
+    }
+}
+
+#[test]
+fn both_engines_agree_on_lowered_logic() {
+    // The right side only runs when the left doesn't settle the answer, so 1 / 0 never does
+    let cases = [
+        ("false && 1 / 0", "false"), ("true || 1 / 0", "true"), ("0 && true", "true"),
+        ("false || 2", "true"), ("1 && false", "false"), ("false || false", "false"),
+    ];
+    for &engine in Engine::ALL {
+        for (source, expected) in cases {
+            let value = Runtime::new().with_engine(engine).run_source(source).unwrap();
+            assert_eq!(value.to_string(), expected, "{} with {:?}", source, engine);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// match on structure: array and hash patterns that bind their parts, literal
+// and binding mixes, rest patterns and guards, tried in order
+#![cfg(feature = "eval")]
+use flux_compiler::{RunError, Runtime};
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+fn error_of(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("{} should fail at run time, got {:?}", source, other),
+    }
+}
+
+const HANDLE: &str = r#"
+    mut handle = fn(event) {
+        match (event) {
+            {"type": "click", "x": x, "y": y} if x < 0 || y < 0 => "click off screen",
+            {"type": "click", "x": x, "y": y} => "click at " + x + "," + y,
+            {"type": "key", "key": "q"} => "quit",
+            {"type": "key", "key": k} => "key " + k,
+            {"type": t} => "unknown event " + t,
+            _ => "not an event",
+        }
+    }
+"#;
+
+#[test]
+fn events_of_different_shapes() {
+    let source = format!(r#"{}[
+        handle({{"type": "click", "x": 3, "y": 4}}),
+        handle({{"type": "click", "x": -1, "y": 4}}),
+        handle({{"type": "key", "key": "q"}}),
+        handle({{"type": "key", "key": "a", "shift": true}}),
+        handle({{"type": "scroll"}}),
+        handle({{"x": 1}}),
+        handle("click"),
+    ]"#, HANDLE);
+    assert_eq!(run(&source), "[click at 3,4, click off screen, quit, key a, unknown event scroll, not an event, not an event]");
+}
+
+#[test]
+fn a_recursive_sum_over_head_and_tail() {
+    let source = r#"
+        mut total = fn(xs) {
+            match (xs) {
+                [] => 0,
+                [head, tail...] => head + total(tail),
+            }
+        }
+        [total([]), total([5]), total([1, 2, 3, 4])]
+    "#;
+    assert_eq!(run(source), "[0, 5, 10]");
+}
+
+#[test]
+fn array_patterns_check_the_length() {
+    let source = r#"
+        mut shape = fn(xs) {
+            match (xs) {
+                [] => "empty",
+                [x] => "one: " + x,
+                [x, y] => "two: " + x + y,
+                [x, y, rest...] => "more, rest " + len(rest),
+            }
+        }
+        [shape([]), shape([1]), shape([1, 2]), shape([1, 2, 3]), shape([1, 2, 3, 4, 5])]
+    "#;
+    assert_eq!(run(source), "[empty, one: 1, two: 12, more, rest 1, more, rest 3]");
+}
+
+#[test]
+fn literals_and_bindings_mix_and_nest() {
+    let source = r#"
+        mut route = fn(request) {
+            match (request) {
+                ["GET", "/"] => "home",
+                ["GET", path] => "page " + path,
+                ["POST", path, {"user": {"name": name}}] => name + " posts to " + path,
+                [method, _] => "no " + method,
+            }
+        }
+        [route(["GET", "/"]), route(["GET", "/about"]), route(["POST", "/x", {"user": {"name": "ada"}}]), route(["PUT", "/"])]
+    "#;
+    assert_eq!(run(source), "[home, page /about, ada posts to /x, no PUT]");
+}
+
+#[test]
+fn the_first_arm_that_accepts_wins_and_guards_can_refuse() {
+    let source = r#"
+        mut size = fn(n) {
+            match (n) {
+                0 => "zero",
+                n if n < 0 => "negative",
+                n if n > 100 => "big",
+                1 => "one",
+                _ => "small",
+            }
+        }
+        [size(0), size(-5), size(500), size(1), size(7)]
+    "#;
+    assert_eq!(run(source), "[zero, negative, big, one, small]");
+}
+
+#[test]
+fn bindings_stay_inside_their_arm() {
+    let source = r#"
+        mut x = "outer"
+        mut got = match ([1, 2]) { [x, y] => x + y }
+        [got, x]
+    "#;
+    assert_eq!(run(source), "[3, outer]");
+    assert_eq!(error_of("match (1) { y => y }\ny"), "Variable 'y' not found");
+}
+
+#[test]
+fn a_value_no_arm_accepts_is_an_error() {
+    assert_eq!(error_of("match ([1, 2, 3]) { [a, b] => a, {\"a\": a} => a }"), "No match arm for value: [1, 2, 3]");
+    assert_eq!(error_of("match (5) { n if n > 10 => n }"), "No match arm for value: 5");
+}
@@ -0,0 +1,101 @@
+// memoize(): results cached by argument list, shared by every copy of the
+// wrapper, with an optional limit on the number of entries
+#![cfg(feature = "eval")]
+use std::time::{Duration, Instant};
+use flux_compiler::{RunError, Runtime};
+
+fn run(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+fn error_of(source: &str) -> String {
+    match Runtime::new().run_source(source) {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("{} should fail at run time, got {:?}", source, other),
+    }
+}
+
+// fib through the wrapper, counting the calls that reach the function itself
+const FIB: &str = r#"
+    mut calls = 0
+    let fib = memoize(fn(n) {
+        calls = calls + 1
+        if (n < 2) { n } else { fib(n - 1) + fib(n - 2) }
+    })
+"#;
+
+const PLAIN_FIB: &str = "let slow = fn(n) { if (n < 2) { n } else { slow(n - 1) + slow(n - 2) } }\n";
+
+#[test]
+fn memoized_fib_30_is_quick_and_computes_each_value_once() {
+    let start = Instant::now();
+    assert_eq!(run(&format!("{}[fib(30), calls]", FIB)), "[832040, 31]");
+    // Unmemoized, this is over a million calls
+    assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+}
+
+#[test]
+fn memoized_and_plain_fib_agree() {
+    let source = format!("{}{}mut n = 0\nmut same = true\nwhile (n < 16) {{ same = same && fib(n) == slow(n); n = n + 1 }}\nsame", FIB, PLAIN_FIB);
+    assert_eq!(run(&source), "true");
+}
+
+#[test]
+fn a_repeated_call_is_a_cache_hit() {
+    let source = format!("{}[fib(20), calls, fib(20), calls, fib(21), calls]", FIB);
+    assert_eq!(run(&source), "[6765, 21, 6765, 21, 10946, 22]");
+}
+
+#[test]
+fn every_argument_is_part_of_the_key() {
+    let source = r#"
+        mut calls = 0
+        let add = memoize(fn(a, b) { calls = calls + 1; a + b })
+        [add(1, 2), add(2, 1), add(1, 2), add("1", 2), calls]
+    "#;
+    assert_eq!(run(source), "[3, 3, 3, 12, 3]");
+}
+
+#[test]
+fn copies_of_the_wrapper_share_the_cache() {
+    let source = r#"
+        mut calls = 0
+        let square = memoize(fn(x) { calls = calls + 1; x * x })
+        let copy = square
+        [square(4), copy(4), calls]
+    "#;
+    assert_eq!(run(source), "[16, 16, 1]");
+}
+
+#[test]
+fn unhashable_arguments_call_through() {
+    let source = r#"
+        mut calls = 0
+        let total = memoize(fn(xs) { calls = calls + 1; sum(xs) })
+        [total([1, 2]), total([1, 2]), calls]
+    "#;
+    assert_eq!(run(source), "[3, 3, 2]");
+}
+
+#[test]
+fn the_oldest_entry_is_evicted_past_max_entries() {
+    let source = r#"
+        mut calls = 0
+        let twice = memoize(fn(x) { calls = calls + 1; x * 2 }, 2)
+        twice(1); twice(2); twice(3)
+        let before = calls
+        twice(3); twice(2)
+        let hits = calls - before
+        twice(1)
+        [hits, calls - before]
+    "#;
+    assert_eq!(run(source), "[0, 1]");
+}
+
+#[test]
+fn max_entries_must_be_positive() {
+    assert_eq!(error_of("memoize(fn(x) { x }, 0)"), "memoize: max_entries must be a positive integer, got 0");
+}
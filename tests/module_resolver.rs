@@ -0,0 +1,74 @@
+// Embedding: a host serves import() from its own table through
+// set_module_resolver, falls back to disk for what it doesn't know, and fails
+// the import when it reports an error of its own
+#![cfg(feature = "eval")]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+use flux_compiler::{set_module_resolver, RunError, Runtime};
+
+type Requests = Rc<RefCell<Vec<(String, Option<String>)>>>;
+
+// A resolver serving `modules`, failing on "broken", and recording each request.
+// The resolver belongs to the thread, and each test runs on its own.
+fn install(modules: &[(&str, &str)]) -> Requests {
+    let modules: HashMap<String, String> = modules.iter().map(|(name, source)| (name.to_string(), source.to_string())).collect();
+    let requests: Requests = Rc::new(RefCell::new(Vec::new()));
+    let seen = requests.clone();
+    set_module_resolver(Box::new(move |name, importer| {
+        seen.borrow_mut().push((name.to_string(), importer.map(str::to_string)));
+        if name.ends_with("broken") {
+            return Err("the module store is offline".to_string());
+        }
+        Ok(modules.get(name).cloned())
+    }));
+    requests
+}
+
+fn run(source: &str) -> Result<String, RunError> {
+    Runtime::new().run_source(source).map(|value| value.to_string())
+}
+
+#[test]
+fn modules_come_from_the_host() {
+    let requests = install(&[
+        ("greeting", "mut names = import(\"names\")\nmut hello = fn() { \"hello \" + names.first }"),
+        ("names", "mut first = \"ada\""),
+    ]);
+    assert_eq!(run("mut g = import(\"greeting\")\ng.hello()").unwrap(), "hello ada");
+    // Each request names the module asking for it
+    assert_eq!(*requests.borrow(), [
+        ("greeting".to_string(), None),
+        ("names".to_string(), Some("greeting".to_string())),
+    ]);
+}
+
+#[test]
+fn a_module_the_host_doesnt_have_is_read_from_disk() {
+    install(&[]);
+    let path = std::env::temp_dir().join(format!("flux_resolver_{}_disk.flux", std::process::id()));
+    fs::write(&path, "mut answer = 42").unwrap();
+    let result = run(&format!("import(\"{}\").answer", path.display()));
+    fs::remove_file(&path).unwrap();
+    assert_eq!(result.unwrap(), "42");
+    match run("import(\"nowhere.flux\")") {
+        Err(RunError::Runtime(msg, _)) => assert_eq!(msg, "Module 'nowhere.flux' not found"),
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_host_error_fails_the_import_even_with_the_file_on_disk() {
+    install(&[]);
+    let path = std::env::temp_dir().join(format!("flux_resolver_{}_broken", std::process::id()));
+    fs::write(&path, "mut answer = 42").unwrap();
+    let result = run(&format!("import(\"{}\")", path.display()));
+    fs::remove_file(&path).unwrap();
+    match result {
+        Err(RunError::Runtime(msg, _)) => {
+            assert_eq!(msg, format!("Module '{}' can't be resolved: the module store is offline", path.display()));
+        },
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
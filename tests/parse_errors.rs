@@ -0,0 +1,266 @@
+// Parse errors: one per broken statement, and a bounded report for a file that isn't Flux at all
+use std::fs;
+use std::process::Command;
+use flux_compiler::ast::{Expression, Statement};
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::{ParseError, Parser, DEFAULT_MAX_ERRORS};
+use flux_compiler::{RunError, Runtime};
+
+// Each error as the CLI shows it: "line 3:17: ..."
+fn shown(errors: &[ParseError]) -> Vec<String> {
+    errors.iter().map(|e| e.to_string()).collect()
+}
+
+// 1MB of reproducible noise, decoded the way a binary file would be shown
+fn random_text() -> String {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let bytes: Vec<u8> = (0..1_000_000).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 24) as u8
+    }).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[test]
+fn random_bytes_keep_only_the_first_errors() {
+    let source = random_text();
+    let (errors, summary) = match Runtime::new().parse(&source) {
+        Err(RunError::Parse(errors, Some(summary))) => (errors, summary),
+        other => panic!("expected capped parse errors, got {:?}", other.map(|p| p.len())),
+    };
+    assert_eq!(errors.len(), DEFAULT_MAX_ERRORS);
+    let lines: Vec<&str> = summary.lines().collect();
+    assert!(lines[0].starts_with("... and ") && lines[0].ends_with(" more errors"), "{}", summary);
+    assert!(lines[1].ends_with("is this a Flux source file?"), "{}", summary);
+
+    // Capping only drops the tail: the errors kept are the first ones found
+    let uncapped = Parser::new(Lexer::new(source.clone())).with_max_errors(usize::MAX).parse_program();
+    assert_eq!(uncapped.summary, None);
+    assert_eq!(&uncapped.errors[..DEFAULT_MAX_ERRORS], &errors[..]);
+    let dropped = uncapped.errors.len() - DEFAULT_MAX_ERRORS;
+    assert!(lines[0].contains(&format!(" {} ", dropped)), "{} vs {}", summary, dropped);
+}
+
+#[test]
+fn a_large_error_count_is_grouped_in_thousands() {
+    // One broken statement per line, so one error each
+    let source = "mut = 1\n".repeat(12_345);
+    match Runtime::new().parse(&source) {
+        Err(RunError::Parse(errors, Some(summary))) => {
+            assert_eq!(errors.len(), DEFAULT_MAX_ERRORS);
+            assert_eq!(summary, "... and 12,245 more errors");
+        },
+        other => panic!("expected capped parse errors, got {:?}", other.map(|p| p.len())),
+    }
+}
+
+#[test]
+fn independent_typos_are_each_reported_once() {
+    let source = "mut a = (1 + ;\nmut f = fn(x) {\n    mut y = x * ]\n    y + 1\n}\nmut b = {\"k\": 1, 2}; mut c = 3\nprint(c)";
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert_eq!(shown(&program.errors), vec![
+        "line 1:14: no prefix parse function for ';' found",
+        "line 3:17: no prefix parse function for ']' found",
+        "line 6:19: expected ':' but got '}'",
+    ]);
+    // What parses around them is kept, the rest of the function's body included
+    let kept: Vec<String> = program.statements.iter().map(|stmt| stmt.to_string()).collect();
+    assert_eq!(kept, ["mut f = fn(x) { (y + 1) }", "mut c = 3", "print(c)"]);
+    match &program.statements[0] {
+        Statement::Let { value: Expression::FunctionLiteral { body, .. }, .. } => {
+            assert_eq!(body.statements.len(), 1);
+            assert_eq!(body.statements[0].to_string(), "(y + 1)");
+        },
+        other => panic!("expected a function, got {}", other),
+    }
+}
+
+#[test]
+fn a_bad_statement_in_a_nested_block_keeps_the_braces_matched() {
+    // The broken statement has braces of its own, and so does the one after it
+    let source = "mut f = fn(x) {\n    mut a = 1\n    if (x) { mut c = { ] } }\n    mut b = {\"k\": 3}\n    a + b[\"k\"]\n}\nmut g = fn() { 7 }\ng()";
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert_eq!(shown(&program.errors), vec!["line 3:24: no prefix parse function for ']' found"]);
+    let kept: Vec<String> = program.statements.iter().map(|stmt| stmt.to_string()).collect();
+    assert_eq!(kept[1..], ["mut g = fn() { 7 }", "g()"]);
+    match &program.statements[0] {
+        Statement::Let { value: Expression::FunctionLiteral { body, .. }, .. } => {
+            let statements: Vec<String> = body.statements.iter().map(|stmt| stmt.to_string()).collect();
+            assert_eq!(statements, ["mut a = 1", "if (x) { mut c = { ; } }", "mut b = {\"k\": 3}", "(a + (b[\"k\"]))"]);
+        },
+        other => panic!("expected a function, got {}", other),
+    }
+}
+
+#[test]
+fn check_prints_a_bounded_report() {
+    let path = std::env::temp_dir().join(format!("flux_noise_{}.flux", std::process::id()));
+    fs::write(&path, random_text()).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .args(["--check", "--max-errors", "20"])
+        .arg(&path)
+        .output()
+        .expect("failed to run flux_compiler");
+    let _ = fs::remove_file(&path);
+    let text = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(text.len() < 4096, "{} bytes of output", text.len());
+    assert_eq!(text.lines().filter(|line| line.starts_with("\tline ")).count(), 20);
+    assert!(text.contains(" more errors\n"), "{}", text);
+    assert!(text.contains("is this a Flux source file?"), "{}", text);
+}
+
+#[test]
+fn a_trailing_comma_needs_something_before_it() {
+    let runtime = Runtime::new();
+    for (source, expected) in [
+        ("[,]", "line 1:2: no prefix parse function for ',' found"),
+        ("{,}", "line 1:2: no prefix parse function for ',' found"),
+        ("f(,)", "line 1:3: no prefix parse function for ',' found"),
+        ("[1,,]", "line 1:4: no prefix parse function for ',' found"),
+        ("fn(,) { 1 }", "line 1:4: expected a parameter name but got ','"),
+        ("fn(a,,) { 1 }", "line 1:6: expected a parameter name but got ','"),
+    ] {
+        match runtime.parse(source) {
+            Err(RunError::Parse(errors, _)) => assert_eq!(errors[0].to_string(), expected, "{}", source),
+            other => panic!("{} should not parse, got {:?}", source, other),
+        }
+    }
+    for source in ["[]", "{}", "f()", "fn() { 1 }", "[1,]", "{1: 2,}", "f(1,)", "fn(a,) { a }"] {
+        assert!(runtime.parse(source).is_ok(), "{}", source);
+    }
+}
+
+#[test]
+fn only_the_last_parameter_can_be_a_rest_parameter() {
+    let runtime = Runtime::new();
+    for (source, expected) in [
+        ("fn(...a, b) { a }", "line 1:4: a rest parameter must come last in the parameter list"),
+        ("fn f(x, ...a, ...b) { a }", "line 1:9: a rest parameter must come last in the parameter list"),
+        ("fn(a, ...) { a }", "line 1:10: expected a parameter name but got ')'"),
+    ] {
+        match runtime.parse(source) {
+            Err(RunError::Parse(errors, _)) => assert_eq!(errors[0].to_string(), expected, "{}", source),
+            other => panic!("{} should not parse, got {:?}", source, other),
+        }
+    }
+    for source in ["fn(...a) { a }", "fn(a, ...b) { b }", "fn(a, ...b,) { b }"] {
+        assert!(runtime.parse(source).is_ok(), "{}", source);
+    }
+}
+
+// An unclosed bracket is reported once, naming where it was opened, and the
+// statements after it parse as if it weren't there
+#[test]
+fn an_unclosed_bracket_costs_only_its_own_statement() {
+    for (broken, expected) in [
+        ("print(add(1, 2);", "line 2:16: expected ')' but got ';' (unclosed '(' opened at line 2:6)"),
+        ("print(add(1, 2)", "line 3:1: expected ')' but got 'mut' (unclosed '(' opened at line 2:6)"),
+        ("mut total = (1 + 2", "line 3:1: expected ')' but got 'mut' (unclosed '(' opened at line 2:13)"),
+        ("mut xs = [1, [2, 3]", "line 3:1: expected ']' but got 'mut' (unclosed '[' opened at line 2:10)"),
+        ("xs[0;", "line 2:5: expected ']' but got ';' (unclosed '[' opened at line 2:3)"),
+        ("mut h = {\"a\": (1), \"b\": 2", "line 3:1: expected ',' or '}' but got 'mut' (unclosed '{' opened at line 2:9)"),
+    ] {
+        let source = format!("mut a = 1\n{}\nmut b = [a, 2]\nmut c = b\n", broken);
+        let program = Parser::new(Lexer::new(source.clone())).parse_program();
+        assert_eq!(shown(&program.errors), [expected], "{}", source);
+        let rest: Vec<String> = program.statements.iter().skip(1).map(|stmt| stmt.to_string()).collect();
+        assert_eq!(rest, ["mut b = [a, 2]", "mut c = b"], "{}", source);
+    }
+}
+
+#[test]
+fn an_unclosed_group_or_condition_is_an_error() {
+    for (source, expected) in [
+        ("mut a = (1 + 2", vec!["line 1:15: expected ')' but got end of input (unclosed '(' opened at line 1:9)"]),
+        ("mut a = (1 + 2; mut b = a", vec!["line 1:15: expected ')' but got ';' (unclosed '(' opened at line 1:9)"]),
+        ("print(max((1 + 2, 3))\nmut b = 1", vec!["line 1:17: expected ')' but got ',' (unclosed '(' opened at line 1:11)"]),
+        ("mut a = ((1) * 2\nmut b = a", vec!["line 2:1: expected ')' but got 'mut' (unclosed '(' opened at line 1:9)"]),
+        // The body after a condition missing its ')' is still the body
+        ("if (a > 1 { a } else { 0 }\nmut b = 1", vec!["line 1:11: expected ')' but got '{' (unclosed '(' opened at line 1:4)"]),
+        ("while (a < 3\n{ a += 1 }\nmut b = 1", vec!["line 2:1: expected ')' but got '{' (unclosed '(' opened at line 1:7)"]),
+        ("if (a > 1 2) { a }\nmut b = (1", vec![
+            "line 1:11: expected ')' but got '2' (unclosed '(' opened at line 1:4)",
+            "line 2:11: expected ')' but got end of input (unclosed '(' opened at line 2:9)",
+        ]),
+    ] {
+        let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+        assert_eq!(shown(&program.errors), expected, "{}", source);
+    }
+}
+
+#[test]
+fn errors_come_with_their_position() {
+    let program = Parser::new(Lexer::new("mut a = 1
+mut b = (a +
+".to_string())).parse_program();
+    assert!(program.has_errors());
+    assert_eq!(program.errors, [ParseError {
+        message: "no prefix parse function for end of input found".to_string(),
+        line: 3,
+        column: 1,
+    }]);
+    // Errors past the limit still count, even when none is kept
+    let program = Parser::new(Lexer::new("mut = 1".to_string())).with_max_errors(0).parse_program();
+    assert!(program.errors.is_empty());
+    assert!(program.has_errors());
+    assert_eq!(program.summary.as_deref(), Some("... and 1 more error"));
+}
+
+// A bare `return` ends at ';', '}', a line break or the end of the input; a
+// value that doesn't parse is reported rather than leaving a bare return
+#[test]
+fn return_without_a_value() {
+    for (source, expected) in [
+        ("fn f() { return }", vec!["return"]),
+        ("fn f() { return; }", vec!["return"]),
+        ("fn f() {\n    return\n}", vec!["return"]),
+        ("fn f(x) {\n    return\n    x\n}", vec!["return", "x"]),
+        ("fn f(x) { if (x) { return } x }", vec!["if (x) { return }", "x"]),
+        ("fn f(x) { return x }", vec!["return x"]),
+    ] {
+        let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+        assert!(!program.has_errors(), "{}: {:?}", source, program.errors);
+        match &program.statements[..] {
+            [Statement::Function { body, .. }] => {
+                let statements: Vec<String> = body.statements.iter().map(|stmt| stmt.to_string()).collect();
+                assert_eq!(statements, expected, "{}", source);
+            },
+            other => panic!("{}: expected one function, got {:?}", source, other),
+        }
+    }
+    let program = Parser::new(Lexer::new("fn f() { return ) }\nmut a = 1".to_string())).parse_program();
+    assert_eq!(shown(&program.errors), ["line 1:17: no prefix parse function for ')' found"]);
+    // Only the broken statement goes: the function stays, with an empty body
+    let kept: Vec<String> = program.statements.iter().map(|stmt| stmt.to_string()).collect();
+    assert_eq!(kept, ["fn f() {}", "mut a = 1"]);
+}
+
+// `=` only assigns as a statement of its own; inside an expression it is
+// reported where it stands, with a hint for the comparison that was meant
+#[test]
+fn assignment_is_not_an_expression() {
+    let inside = "'=' can't be used inside an expression: assignment is a statement (to compare, use '==')";
+    let runtime = Runtime::new();
+    for (source, expected) in [
+        ("if (x = 5) { x }", format!("line 1:7: {}", inside)),
+        ("while (x = 0) { }", format!("line 1:10: {}", inside)),
+        ("mut y = x = 3", format!("line 1:11: {}", inside)),
+        ("print(x = 1)", format!("line 1:9: {}", inside)),
+        ("mut h = {\"a\": x = 1}", format!("line 1:17: {}", inside)),
+        ("ok ? x = 1 : 2", format!("line 1:8: {}", inside)),
+        ("h.a = 1", "line 1:5: '=' needs a variable name or an index on its left, got '(h.a)'".to_string()),
+        ("f() = 1", "line 1:5: '=' needs a variable name or an index on its left, got 'f()'".to_string()),
+        ("a + b = 1", "line 1:7: '=' needs a variable name or an index on its left, got '(a + b)'".to_string()),
+    ] {
+        match runtime.parse(source) {
+            Err(RunError::Parse(errors, _)) => assert_eq!(shown(&errors), [expected], "{}", source),
+            other => panic!("{} should not parse, got {:?}", source, other),
+        }
+    }
+    for source in ["mut x = 1\nx = 2", "mut g = [[0]]\ng[0][0] = 1", "for (mut i = 0; i < 2; i = i + 1) { }", "if (x == 5) { x }"] {
+        assert!(runtime.parse(source).is_ok(), "{}", source);
+    }
+}
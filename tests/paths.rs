@@ -0,0 +1,81 @@
+// The path builtins take Unix and Windows paths apart the same way on any host
+#![cfg(feature = "eval")]
+use std::env;
+use flux_compiler::paths;
+use flux_compiler::{RunError, Runtime};
+
+fn run(source: &str) -> String {
+    Runtime::new().run_source(source).unwrap().to_string()
+}
+
+#[test]
+fn both_separators_split_paths() {
+    for (path, dirname, basename, ext) in [
+        ("lib/math.flux", "lib", "math.flux", "flux"),
+        ("lib\\math.flux", "lib", "math.flux", "flux"),
+        ("/usr/share/flux/", "/usr/share", "flux", ""),
+        ("C:\\Users\\ada\\notes.txt", "C:\\Users\\ada", "notes.txt", "txt"),
+        ("C:\\notes", "C:\\", "notes", ""),
+        ("/", "/", "", ""),
+        ("archive.tar.gz", "", "archive.tar.gz", "gz"),
+        ("home/.bashrc", "home", ".bashrc", ""),
+        ("a//b", "a", "b", ""),
+    ] {
+        assert_eq!(paths::dirname(path), dirname, "{}", path);
+        assert_eq!(paths::basename(path), basename, "{}", path);
+        assert_eq!(paths::extension(path), ext, "{}", path);
+    }
+}
+
+#[test]
+fn joining_keeps_the_separator_in_use() {
+    assert_eq!(paths::join(&["lib", "geo", "shapes.flux"]), ["lib", "geo", "shapes.flux"].join(std::path::MAIN_SEPARATOR_STR));
+    assert_eq!(paths::join(&["lib/", "geo", "shapes.flux"]), "lib/geo/shapes.flux");
+    assert_eq!(paths::join(&["C:\\data", "in", "a.csv"]), "C:\\data\\in\\a.csv");
+    assert_eq!(paths::join(&["a", "", "b\\c"]), "a\\b\\c");
+    // An absolute part starts over, as in std::path
+    assert_eq!(paths::join(&["a/b", "/etc", "hosts"]), "/etc/hosts");
+    assert_eq!(paths::join(&["a", "D:\\x"]), "D:\\x");
+}
+
+#[test]
+fn normalizing_folds_dot_steps() {
+    assert_eq!(paths::normalize("a/./b/../c"), "a/c");
+    assert_eq!(paths::normalize("modules/../main.flux"), "main.flux");
+    assert_eq!(paths::normalize("../../x"), "../../x");
+    assert_eq!(paths::normalize("/../etc"), "/etc");
+    assert_eq!(paths::normalize("C:\\a\\..\\b"), "C:\\b");
+    assert_eq!(paths::normalize("a/.."), ".");
+    // Relative to the file naming it, not to the working directory
+    assert_eq!(paths::relative_to("sub/main.flux", "lib/util.flux"), "sub/lib/util.flux");
+    assert_eq!(paths::relative_to("sub/lib/util.flux", "../helpers.flux"), "sub/helpers.flux");
+    assert_eq!(paths::relative_to("main.flux", "/opt/flux/x.flux"), "/opt/flux/x.flux");
+}
+
+#[test]
+fn the_builtins_return_strings() {
+    assert_eq!(run("path_join(\"docs\", \"guide\\\\intro.md\")"), "docs\\guide\\intro.md");
+    assert_eq!(run("path_dirname(path_join(\"a/b\", \"c.flux\"))"), "a/b");
+    assert_eq!(run("path_basename(\"C:\\\\tmp\\\\x.flux\") + \" \" + path_ext(\"x.flux\")"), "x.flux flux");
+    match Runtime::new().run_source("path_join()") {
+        Err(RunError::Runtime(msg, _)) => assert_eq!(msg, "path_join: expected at least 1 argument, got 0"),
+        other => panic!("expected an arity error, got {:?}", other),
+    }
+}
+
+// The only test here that reads or changes the working directory
+#[test]
+fn relative_paths_follow_the_working_directory() {
+    let start = env::current_dir().unwrap();
+    let temp = env::temp_dir().canonicalize().unwrap();
+    let mut runtime = Runtime::new();
+    runtime.run_source(&format!("set_cwd({:?})", temp.to_string_lossy())).unwrap();
+    assert_eq!(runtime.run_source("cwd()").unwrap().to_string(), temp.to_string_lossy());
+    let expected = temp.join("data.txt");
+    assert_eq!(runtime.run_source("path_absolute(\"data.txt\")").unwrap().to_string(), expected.to_string_lossy());
+    match runtime.run_source("set_cwd(\"no/such/dir\")") {
+        Err(RunError::Runtime(msg, _)) => assert!(msg.starts_with("set_cwd: can't change to 'no/such/dir': "), "{}", msg),
+        other => panic!("expected an error, got {:?}", other),
+    }
+    env::set_current_dir(start).unwrap();
+}
@@ -0,0 +1,39 @@
+// Colors only on a terminal that wants them; plain text everywhere else
+use std::io::Write;
+use std::process::{Command, Stdio};
+use flux_compiler::ui::ColorChoice;
+
+#[test]
+fn color_detection() {
+    assert_eq!(ColorChoice::decide(false, true, false, Some("xterm-256color")), ColorChoice::Color);
+    assert_eq!(ColorChoice::decide(false, true, false, None), ColorChoice::Color);
+    assert_eq!(ColorChoice::decide(true, true, false, Some("xterm")), ColorChoice::Plain);
+    assert_eq!(ColorChoice::decide(false, false, false, Some("xterm")), ColorChoice::Plain);
+    assert_eq!(ColorChoice::decide(false, true, true, Some("xterm")), ColorChoice::Plain);
+    assert_eq!(ColorChoice::decide(false, true, false, Some("dumb")), ColorChoice::Plain);
+    assert_eq!(ColorChoice::Plain.error("oops"), "oops");
+    assert!(ColorChoice::Color.error("oops").contains("\x1b["));
+}
+
+#[test]
+fn plain_repl_has_no_escapes() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .args(["--plain", "--no-init"])
+        .env("TERM", "xterm-256color")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run flux_compiler");
+    child.stdin.take().unwrap().write_all(b"\"open\nmissing\n:help len\n1 + 2\nexit\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let text = String::from_utf8_lossy(&output.stdout);
+
+    assert!(!text.contains('\x1b'), "{:?}", text);
+    assert!(text.contains("Whoops! We hit a snag"), "{}", text);
+    // Without the interpreter, `missing` fails to compile and there is no :help
+    if cfg!(feature = "eval") {
+        assert!(text.contains("ERROR: "), "{}", text);
+        assert!(text.contains("len(x)"), "{}", text);
+    }
+    assert!(text.contains("\n3\n") || text.contains(">> 3\n"), "{}", text);
+}
@@ -0,0 +1,48 @@
+// Prefix `-` and `!` bind tighter than any binary operator but looser than
+// calls, indexing and member access: `-a[0]` negates the element
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::Parser;
+
+fn parse(source: &str) -> String {
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert!(!program.has_errors(), "{}: {:?}", source, program.errors);
+    program.statements.iter().map(|stmt| stmt.to_string()).collect::<Vec<_>>().join("; ")
+}
+
+#[test]
+fn prefix_operators_against_postfix_and_binary_ones() {
+    for (source, expected) in [
+        ("-a[0]", "(-(a[0]))"),
+        ("!a[0]", "(!(a[0]))"),
+        ("-f(x)", "(-f(x))"),
+        ("!f(x)", "(!f(x))"),
+        ("-f(x)[1]", "(-(f(x)[1]))"),
+        ("-a.b", "(-(a.b))"),
+        ("!a.b[0]", "(!((a.b)[0]))"),
+        ("-a[0][1]", "(-((a[0])[1]))"),
+        ("-a * b", "((-a) * b)"),
+        ("-a + b * c", "((-a) + (b * c))"),
+        ("a - -b", "(a - (-b))"),
+        ("a * -b[0]", "(a * (-(b[0])))"),
+        ("!a == b", "((!a) == b)"),
+        ("!a && b", "((!a) && b)"),
+        ("--a", "(-(-a))"),
+        ("!-a", "(!(-a))"),
+        ("-2 ** 2", "((-2) ** 2)"),
+        ("-a..b", "((-a)..b)"),
+        ("(-a)[0]", "((-a)[0])"),
+    ] {
+        assert_eq!(parse(source), expected, "{}", source);
+    }
+}
+
+// Arrays and hashes are the interpreter's
+#[cfg(feature = "eval")]
+#[test]
+fn negating_an_element_or_a_result() {
+    let mut runtime = flux_compiler::Runtime::new();
+    runtime.run_source("mut xs = [3, [4]]\nmut f = fn(x) { x * 2 }\nmut h = {\"n\": true}").unwrap();
+    for (source, expected) in [("-xs[0]", "-3"), ("-xs[1][0] + 1", "-3"), ("-f(5)", "-10"), ("!h.n", "false"), ("!f(1) == false", "true")] {
+        assert_eq!(runtime.run_source(source).unwrap().to_string(), expected, "{}", source);
+    }
+}
@@ -0,0 +1,82 @@
+// Embedding: a host collects what a script prints as values rather than text.
+// The hooks belong to the Runtime they were set on, and are only in force while it runs.
+#![cfg(feature = "eval")]
+use std::cell::RefCell;
+use std::rc::Rc;
+use flux_compiler::Runtime;
+use flux_compiler::object::Object;
+
+type Calls = Rc<RefCell<Vec<Vec<Object>>>>;
+
+// A runtime whose print() and write_err()/log_*() calls land in the two returned lists
+fn hooked_runtime() -> (Runtime, Calls, Calls) {
+    let printed: Calls = Rc::new(RefCell::new(Vec::new()));
+    let logged: Calls = Rc::new(RefCell::new(Vec::new()));
+    let mut runtime = Runtime::new();
+    let sink = printed.clone();
+    runtime.set_print_hook(Box::new(move |args| sink.borrow_mut().push(args.to_vec())));
+    let sink = logged.clone();
+    runtime.set_error_hook(Box::new(move |args| sink.borrow_mut().push(args.to_vec())));
+    (runtime, printed, logged)
+}
+
+fn strings(calls: &Calls) -> Vec<String> {
+    calls.borrow().iter().map(|args| args.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ")).collect()
+}
+
+#[test]
+fn hooks_receive_the_values_not_their_text() {
+    let (mut runtime, printed, logged) = hooked_runtime();
+    let source = r#"
+        print(1 + 2, "three");
+        print({"total": 3});
+        log_warn("disk almost full", {"free": 12});
+        write_err("oops");
+    "#;
+    runtime.run_source(source).expect("script failed");
+
+    let printed = printed.borrow();
+    assert_eq!(printed[0], vec![Object::Integer(3), Object::String("three".to_string())]);
+    assert!(matches!(&printed[1][..], [Object::Hash(hash)] if hash.pairs.len() == 1));
+    let logged = logged.borrow();
+    assert_eq!(logged[0][0], Object::String("warn".to_string()));
+    assert_eq!(logged[0][1], Object::String("disk almost full".to_string()));
+    assert!(matches!(&logged[0][2], Object::Hash(hash) if hash.pairs.len() == 1));
+    assert_eq!(logged[1], vec![Object::String("oops".to_string())]);
+}
+
+#[test]
+fn each_runtime_keeps_its_own_hooks() {
+    let (mut first, first_printed, _) = hooked_runtime();
+    let (mut second, second_printed, _) = hooked_runtime();
+    first.run_source("print(1)").unwrap();
+    second.run_source("print(2)").unwrap();
+    first.run_source("print(3)").unwrap();
+    assert_eq!(strings(&first_printed), vec!["1", "3"]);
+    assert_eq!(strings(&second_printed), vec!["2"]);
+}
+
+#[test]
+fn a_hook_is_not_called_once_its_runtime_is_done() {
+    let (mut hooked, printed, _) = hooked_runtime();
+    hooked.run_source("print(\"mine\")").unwrap();
+    // No hook of its own: this one prints to stdout
+    Runtime::new().run_source("print(\"not mine\")").unwrap();
+    assert_eq!(strings(&printed), vec!["mine"]);
+}
+
+#[test]
+fn a_runtime_run_from_inside_a_hook_uses_its_own() {
+    let (inner, inner_printed, _) = hooked_runtime();
+    let inner = Rc::new(RefCell::new(inner));
+    let outer_printed: Calls = Rc::new(RefCell::new(Vec::new()));
+    let mut outer = Runtime::new();
+    let (sink, nested) = (outer_printed.clone(), inner.clone());
+    outer.set_print_hook(Box::new(move |args| {
+        sink.borrow_mut().push(args.to_vec());
+        nested.borrow_mut().run_source("print(\"inner\")").unwrap();
+    }));
+    outer.run_source("print(\"outer\"); print(\"again\")").unwrap();
+    assert_eq!(strings(&outer_printed), vec!["outer", "again"]);
+    assert_eq!(strings(&inner_printed), vec!["inner", "inner"]);
+}
@@ -0,0 +1,76 @@
+// random, shuffle and sample: reproducible after seed_random, and with every
+// outcome about equally likely
+#![cfg(feature = "eval")]
+use std::collections::HashMap;
+use flux_compiler::{RunError, Runtime};
+
+fn run(runtime: &mut Runtime, source: &str) -> String {
+    match runtime.run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+// How often each result of `source` came up in `n` runs after seeding with `seed`
+fn tally(seed: i64, source: &str, n: usize) -> HashMap<String, usize> {
+    let mut runtime = Runtime::new();
+    run(&mut runtime, &format!("seed_random({})", seed));
+    let mut counts = HashMap::new();
+    for _ in 0..n {
+        *counts.entry(run(&mut runtime, source)).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[test]
+fn the_same_seed_gives_the_same_results() {
+    let source = "seed_random(42)\n[shuffle([1, 2, 3, 4, 5, 6, 7, 8]), sample([1, 2, 3, 4, 5, 6, 7, 8], 3), random(1000)]";
+    let first = run(&mut Runtime::new(), source);
+    assert_eq!(run(&mut Runtime::new(), source), first);
+    let other = run(&mut Runtime::new(), &source.replace("42", "43"));
+    assert_ne!(other, first);
+}
+
+#[test]
+fn shuffle_keeps_every_element_once() {
+    let mut runtime = Runtime::new();
+    for seed in 0..20 {
+        let shuffled = run(&mut runtime, &format!("seed_random({})\nsort(shuffle([5, 3, 9, 1, 7, 2]))", seed));
+        assert_eq!(shuffled, "[1, 2, 3, 5, 7, 9]");
+    }
+    assert_eq!(run(&mut runtime, "[shuffle([]), shuffle([\"only\"])]"), "[[], [only]]");
+}
+
+#[test]
+fn sample_picks_distinct_elements() {
+    let mut runtime = Runtime::new();
+    for seed in 0..20 {
+        let picked = run(&mut runtime, &format!("seed_random({})\nmut s = sample([1, 2, 3, 4, 5, 6], 4)\n[len(s), len(unique(s))]", seed));
+        assert_eq!(picked, "[4, 4]");
+    }
+    assert_eq!(run(&mut runtime, "[sample([1, 2], 0), sort(sample([1, 2], 2))]"), "[[], [1, 2]]");
+    match runtime.run_source("sample([1, 2], 3)") {
+        Err(RunError::Runtime(msg, _)) => assert_eq!(msg, "sample count 3 must be between 0 and the array length 2"),
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+// Seeded, so the counts are the same every run and the bounds can't flake.
+// Each bound is about five standard deviations from the expected count.
+#[test]
+fn every_order_of_a_shuffle_is_about_equally_likely() {
+    let counts = tally(7, "shuffle([1, 2, 3])", 6000);
+    assert_eq!(counts.len(), 6, "{:?}", counts);
+    for (order, count) in &counts {
+        assert!((850..=1150).contains(count), "{} came up {} times in 6000: {:?}", order, count, counts);
+    }
+}
+
+#[test]
+fn every_element_is_about_equally_likely_to_be_sampled() {
+    let counts = tally(11, "sample([1, 2, 3, 4, 5], 1)", 5000);
+    assert_eq!(counts.len(), 5, "{:?}", counts);
+    for (picked, count) in &counts {
+        assert!((850..=1150).contains(count), "{} came up {} times in 5000: {:?}", picked, count, counts);
+    }
+}
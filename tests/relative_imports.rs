@@ -0,0 +1,44 @@
+// import() paths are relative to the file that imports, so a script finds its
+// modules whatever directory it is run from
+#![cfg(feature = "eval")]
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use flux_compiler::Runtime;
+
+// sub/main.flux -> sub/lib/util.flux -> sub/helpers.flux
+fn fixture(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("flux_relative_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("sub/lib")).unwrap();
+    fs::write(dir.join("sub/main.flux"), "mut util = import(\"lib/util.flux\")\nprint(util[\"twice\"](21))\n").unwrap();
+    fs::write(dir.join("sub/lib/util.flux"), "mut helpers = import(\"../helpers.flux\")\nmut twice = fn(x) { helpers[\"add\"](x, x) }\n").unwrap();
+    fs::write(dir.join("sub/helpers.flux"), "mut add = fn(a, b) { a + b }\n").unwrap();
+    dir
+}
+
+fn flux(dir: &Path, script: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .current_dir(dir)
+        .args(["--no-init", script])
+        .output()
+        .expect("failed to run flux_compiler");
+    String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr)
+}
+
+#[test]
+fn a_script_imports_from_its_own_directory() {
+    let dir = fixture("cli");
+    assert_eq!(flux(&dir, "sub/main.flux").trim(), "42");
+    assert_eq!(flux(&dir.join("sub/lib"), "../main.flux").trim(), "42");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn run_file_resolves_against_the_script() {
+    let dir = fixture("run_file");
+    fs::write(dir.join("sub/value.flux"), "import(\"lib/util.flux\")[\"twice\"](21)\n").unwrap();
+    let mut runtime = Runtime::new();
+    assert_eq!(runtime.run_file(dir.join("sub/value.flux")).unwrap().to_string(), "42");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,41 @@
+// A scripted REPL session with :watch expressions
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn session(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .args(["--plain", "--no-init"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run flux_compiler");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn watch_lines(output: &str) -> Vec<&str> {
+    output.lines().map(|line| line.trim_start_matches(">> ")).filter(|line| line.starts_with("  [")).collect()
+}
+
+#[test]
+fn watches_follow_a_changing_variable() {
+    let out = session("mut x = 1\n:watch x * 10\n:watch missing\nx = 5\n:unwatch 2\nx = x + 1\n\"open\nexit\n");
+    assert!(out.contains("Watching [1] x * 10"), "{}", out);
+    assert!(out.contains("Removed watch [2] missing"), "{}", out);
+    // Without the interpreter, watches are compiled and the VM reports the error
+    let missing = if cfg!(feature = "eval") {
+        "  [2] missing = ERROR: Variable 'missing' not found"
+    } else {
+        "  [2] missing = Compiler Error: Undefined variable: missing"
+    };
+    assert_eq!(watch_lines(&out), vec!["  [1] x * 10 = 50", missing, "  [1] x * 10 = 60"]);
+}
+
+#[test]
+fn watches_can_be_listed() {
+    let out = session(":watches\n:watch 1 + 1\n:watches\n:unwatch 3\nexit\n");
+    assert!(out.contains("No watches"), "{}", out);
+    assert!(out.contains("  [1] 1 + 1\n"), "{}", out);
+    assert!(out.contains("No watch 3"), "{}", out);
+}
@@ -0,0 +1,82 @@
+// Rendering a tree with Display gives source that parses back to the same tree
+use std::fs;
+use flux_compiler::ast::{self, Statement};
+use flux_compiler::lexer::Lexer;
+use flux_compiler::parser::Parser;
+
+fn parse(source: &str) -> Option<Vec<Statement>> {
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    if program.has_errors() { None } else { Some(program.statements) }
+}
+
+fn render(statements: &[Statement]) -> String {
+    statements.iter().map(|stmt| format!("{}\n", stmt)).collect()
+}
+
+// Trees are compared through --ast json, which leaves out source positions
+fn assert_round_trips(name: &str, statements: &[Statement]) {
+    let rendered = render(statements);
+    let reparsed = parse(&rendered).unwrap_or_else(|| panic!("{}: the rendering doesn't parse:\n{}", name, rendered));
+    // One statement per line of JSON, so a mismatch names the statement
+    let (before, after) = (ast::to_json(statements), ast::to_json(&reparsed));
+    for (expected, got) in before.lines().zip(after.lines()) {
+        assert_eq!(got, expected, "{}: rendered as\n{}", name, rendered);
+    }
+    assert_eq!(after.lines().count(), before.lines().count(), "{}: rendered as\n{}", name, rendered);
+}
+
+#[test]
+fn control_flow_and_functions_render_in_full() {
+    for (source, expected) in [
+        ("if (x > 1) { a } else { b }", "if ((x > 1)) { a } else { b }"),
+        ("if (x) { } else { if (y) { 1; 2 } }", "if (x) {} else { if (y) { 1; 2 } }"),
+        ("while (i < 3) { i += 1 }", "while ((i < 3)) { i += 1 }"),
+        ("fn(a, ...rest) { return a }", "fn(a, ...rest) { return a }"),
+        ("fn add(a, b) { a + b }", "fn add(a, b) { (a + b) }"),
+        ("for (mut i = 0; i < 2; i = i + 1) { print(i) }", "for (mut i = 0; (i < 2); i = (i + 1)) { print(i) }"),
+        ("for (;;) { break }", "for (;;) { break }"),
+        ("for (k, v in h) { continue }", "for (k, v in h) { continue }"),
+        ("match (e) { [a, r...] if (a) => 1, {\"k\": _} => { 2 } }", "match (e) { [a, r...] if a => { 1 }, {\"k\": _} => { 2 } }"),
+        ("mut b = { mut t = 1; t }", "mut b = { mut t = 1; t; }"),
+        ("defer { close(f) }", "defer { close(f) }"),
+        ("material Steel { density: 7850, grade }", "material Steel { density: 7850, grade: grade }"),
+        ("\"say \\\"hi\\\"\\n\"", "\"say \\\"hi\\\"\\n\""),
+    ] {
+        let statements = parse(source).unwrap_or_else(|| panic!("{} should parse", source));
+        assert_eq!(render(&statements).trim_end(), expected, "{}", source);
+    }
+}
+
+#[test]
+fn literals_that_are_easy_to_get_wrong() {
+    for source in [
+        "mut s = \"tab\\tquote\\\" back\\\\slash\"",
+        "mut c = ['\\'', '\\n', '\"']",
+        "mut f = [1.0, 0.5, 10000000000000000.0, 0.0000001]",
+        "mut n = [1_000, -2, 0]",
+        "mut h = {}",
+        "mut e = { ; }",
+        "mut x = { x; }",
+        "mut h = {\"a\": {x}, 1: [2, 3], true: fn() {}, (k): 0}",
+        "mut r = [0..3, 1..=n]",
+        "m = match (v) { -1 => \"neg\", 0.5 => \"half\", 'c' => 1, \"s\" => 2, true => 3, {} => 4, [] => 5, _ => 6 }",
+    ] {
+        assert_round_trips(source, &parse(source).unwrap_or_else(|| panic!("{} should parse", source)));
+    }
+}
+
+// Every corpus program that parses comes back the same
+#[test]
+fn the_corpus_round_trips() {
+    let mut checked = 0;
+    for entry in fs::read_dir("tests/corpus").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "flux") { continue; }
+        // Some programs are there to fail: invalid UTF-8, or a syntax error
+        let Ok(source) = fs::read_to_string(&path) else { continue };
+        let Some(statements) = parse(&source) else { continue };
+        assert_round_trips(&path.display().to_string(), &statements);
+        checked += 1;
+    }
+    assert!(checked > 100, "only {} corpus programs parsed", checked);
+}
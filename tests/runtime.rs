@@ -0,0 +1,185 @@
+// The Runtime builder: engines, I/O, policy and limits in different
+// combinations, each changing what a run does and nothing else
+#![cfg(feature = "eval")]
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::PathBuf;
+use flux_compiler::{Capture, Engine, Io, Policy, RunError, Runtime};
+
+// A runtime writing into two captures, with `input` as its stdin
+fn captured(runtime: Runtime, input: &str) -> (Runtime, Capture, Capture) {
+    let (output, errors) = (Capture::new(), Capture::new());
+    let io = Io {
+        input: Box::new(Cursor::new(input.as_bytes().to_vec())),
+        output: Box::new(output.clone()),
+        errors: Box::new(errors.clone()),
+    };
+    (runtime.with_io(io), output, errors)
+}
+
+fn runtime_error(result: Result<impl std::fmt::Debug, RunError>) -> String {
+    match result {
+        Err(RunError::Runtime(msg, _)) => msg,
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+// A file in the temp directory, unique to the test
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("flux_runtime_{}_{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn builtins_run_under_every_engine() {
+    for &engine in Engine::ALL {
+        let (mut runtime, output, _) = captured(Runtime::new().with_engine(engine), "");
+        let value = runtime.run_source("print(len(\"abc\"), \"x\" + 1); write(\"no newline\"); len(\"four\")").unwrap();
+        assert_eq!(value.to_string(), "4", "{:?}", engine);
+        assert_eq!(output.text(), "3 x1 \nno newline", "{:?}", engine);
+    }
+}
+
+#[test]
+fn a_builtin_error_fails_the_run_under_every_engine() {
+    for &engine in Engine::ALL {
+        let result = Runtime::new().with_engine(engine).run_source("len(1)");
+        assert_eq!(runtime_error(result), "len: expected STRING or ARRAY as argument 1, got INTEGER", "{:?}", engine);
+    }
+}
+
+#[test]
+fn a_variable_shadows_a_builtin_under_every_engine() {
+    for &engine in Engine::ALL {
+        let value = Runtime::new().with_engine(engine).run_source("let len = fn(x) { 42 }; len(\"abc\")").unwrap();
+        assert_eq!(value.to_string(), "42", "{:?}", engine);
+    }
+}
+
+#[test]
+fn io_replaces_the_process_streams() {
+    let (mut runtime, output, errors) = captured(Runtime::new(), "Ada\nLovelace\n");
+    let source = r#"
+        let first = input("first? ");
+        let last = input();
+        print("hello", first, last);
+        write_err("to errors");
+        log_warn("careful");
+    "#;
+    runtime.run_source(source).unwrap();
+    assert_eq!(output.text(), "first? hello Ada Lovelace \n");
+    let errors = errors.text();
+    assert!(errors.starts_with("to errors"), "{}", errors);
+    assert!(errors.trim_end().ends_with("WARN  careful"), "{}", errors);
+}
+
+#[test]
+fn help_writes_to_the_output_stream() {
+    let (mut runtime, output, _) = captured(Runtime::new(), "");
+    runtime.run_source("help(len)").unwrap();
+    assert!(output.text().starts_with("len(x)"), "{}", output.text());
+}
+
+#[test]
+fn pragma_warnings_go_to_the_error_stream() {
+    let path = temp_file("pragma.flux", "#! pragma turbo on\n1");
+    let (mut runtime, output, errors) = captured(Runtime::new(), "");
+    runtime.run_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.text(), "");
+    assert_eq!(errors.text(), "Warning: line 1: unknown pragma 'turbo on' (ignored)\n");
+}
+
+#[test]
+fn a_print_hook_takes_print_but_not_write() {
+    let (mut runtime, output, _) = captured(Runtime::new(), "");
+    runtime.set_print_hook(Box::new(|_| {}));
+    runtime.run_source("print(\"hooked\"); write(\"written\")").unwrap();
+    assert_eq!(output.text(), "written");
+}
+
+#[test]
+fn runtimes_keep_their_own_io() {
+    let (mut first, first_output, _) = captured(Runtime::new(), "");
+    let (mut second, second_output, _) = captured(Runtime::new().with_engine(*Engine::ALL.last().unwrap()), "");
+    first.run_source("print(1)").unwrap();
+    second.run_source("print(2)").unwrap();
+    first.run_source("print(3)").unwrap();
+    assert_eq!(first_output.text(), "1 \n3 \n");
+    assert_eq!(second_output.text(), "2 \n");
+}
+
+#[test]
+fn the_default_policy_allows_files() {
+    let path = temp_file("allowed.txt", "contents");
+    let source = format!("read_file(\"{}\")", path.display());
+    let value = Runtime::new().run_source(&source).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(value.to_string(), "contents");
+}
+
+#[test]
+fn a_sandbox_refuses_files_and_stdin_under_every_engine() {
+    let path = temp_file("refused.txt", "secret");
+    let module = temp_file("refused.flux", "let x = 1");
+    let refused = [
+        (format!("read_file(\"{}\")", path.display()), "read_file: reading files is not allowed by this runtime's policy".to_string()),
+        (format!("write_file(\"{}\", \"new\")", path.display()), "write_file: writing files is not allowed by this runtime's policy".to_string()),
+        ("set_cwd(\"/\")".to_string(), "set_cwd: changing the working directory is not allowed by this runtime's policy".to_string()),
+        ("input()".to_string(), "input: reading stdin is not allowed by this runtime's policy".to_string()),
+        (format!("import(\"{}\")", module.display()), format!("import: reading module '{}' from disk is not allowed by this runtime's policy", module.display())),
+    ];
+    for &engine in Engine::ALL {
+        for (source, message) in &refused {
+            let result = Runtime::new().with_engine(engine).with_policy(Policy::SANDBOX).run_source(source);
+            assert_eq!(runtime_error(result), *message, "{:?}", engine);
+        }
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), "secret");
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&module).unwrap();
+}
+
+#[test]
+fn a_policy_can_allow_reading_but_not_writing() {
+    let path = temp_file("read_only.txt", "readable");
+    let policy = Policy { read_files: true, ..Policy::SANDBOX };
+    let mut runtime = Runtime::new().with_policy(policy);
+    assert_eq!(runtime.run_source(&format!("read_file(\"{}\")", path.display())).unwrap().to_string(), "readable");
+    let result = runtime.run_source(&format!("write_file(\"{}\", \"x\")", path.display()));
+    assert_eq!(runtime_error(result), "write_file: writing files is not allowed by this runtime's policy");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn fuel_stops_every_engine_and_only_with_it_set() {
+    let source = "mut i = 0\nwhile (i < 1000) { i = i + 1 }\ni";
+    for &engine in Engine::ALL {
+        assert_eq!(Runtime::new().with_engine(engine).run_source(source).unwrap().to_string(), "1000");
+        let message = runtime_error(Runtime::new().with_engine(engine).with_fuel(100).run_source(source));
+        assert!(message.starts_with("Out of fuel"), "{:?}: {}", engine, message);
+    }
+}
+
+#[test]
+fn call_depth_limits_every_engine() {
+    let source = "let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } }; down(50)";
+    for &engine in Engine::ALL {
+        assert_eq!(Runtime::new().with_engine(engine).run_source(source).unwrap().to_string(), "0");
+        let message = runtime_error(Runtime::new().with_engine(engine).with_max_call_depth(10).run_source(source));
+        assert!(message.starts_with("Stack overflow"), "{:?}: {}", engine, message);
+    }
+}
+
+// A stdin that fails makes input() fail rather than panic
+#[test]
+fn an_input_error_is_a_runtime_error() {
+    struct Broken;
+    impl io::Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> { Err(io::Error::other("unplugged")) }
+    }
+    let io = Io { input: Box::new(io::BufReader::new(Broken)), output: Box::new(io::sink()), errors: Box::new(io::sink()) };
+    let result = Runtime::new().with_io(io).run_source("input()");
+    assert_eq!(runtime_error(result), "input: can't read stdin: unplugged");
+}
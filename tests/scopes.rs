@@ -0,0 +1,48 @@
+// A call's scope is freed once the call returns, even when a function it
+// bound refers back to it, unless something outside still holds that function
+#![cfg(feature = "eval")]
+use flux_compiler::environment::live_scopes;
+use flux_compiler::Runtime;
+
+fn run(runtime: &mut Runtime, source: &str) -> String {
+    match runtime.run_source(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => panic!("{}: {}", source, e),
+    }
+}
+
+// Live scopes after one call and after `n` more
+fn scopes_after_calls(setup: &str, call: &str, n: usize) -> (usize, usize) {
+    let mut runtime = Runtime::new();
+    run(&mut runtime, setup);
+    run(&mut runtime, call);
+    let before = live_scopes();
+    for _ in 0..n {
+        run(&mut runtime, call);
+    }
+    (before, live_scopes())
+}
+
+#[test]
+fn a_local_recursive_closure_doesnt_keep_its_call_alive() {
+    let setup = "mut count_down = fn(n) { mut go = fn(k) { if (k == 0) { 0 } else { go(k - 1) } }; go(n) }";
+    let (before, after) = scopes_after_calls(setup, "count_down(5)", 100);
+    assert_eq!(after, before);
+    // Nor when the closure sits in an array or hash, or in a nested block
+    let setup = "mut f = fn() { mut go = fn() { go }; mut keep = [{\"go\": go}]; if (true) { mut inner = fn() { keep }; inner() }; 1 }";
+    let (before, after) = scopes_after_calls(setup, "f()", 100);
+    assert_eq!(after, before);
+}
+
+#[test]
+fn a_closure_handed_out_keeps_its_scope() {
+    let mut runtime = Runtime::new();
+    run(&mut runtime, "mut make = fn() { mut n = 0; mut inc = fn() { n = n + 1; n }; inc }\nmut c = make()");
+    assert_eq!(run(&mut runtime, "c()\nc()"), "2");
+    // Returned inside a hash, and recursive
+    run(&mut runtime, "mut wrap = fn() { mut go = fn(k) { if (k == 0) { \"done\" } else { go(k - 1) } }; {\"go\": go} }");
+    assert_eq!(run(&mut runtime, "wrap()[\"go\"](3)"), "done");
+    // Stored in a variable outside the call
+    run(&mut runtime, "mut saved = 0\nmut stash = fn() { mut x = 7; mut get = fn() { x }; saved = get; 0 }\nstash()");
+    assert_eq!(run(&mut runtime, "saved()"), "7");
+}
@@ -0,0 +1,87 @@
+// args() and parse_args(): what follows the script's name belongs to the script
+#![cfg(feature = "eval")]
+use std::fs;
+use std::process::Command;
+use flux_compiler::{RunError, Runtime};
+
+const SPEC: &str = r#"mut spec = {"verbose": {"type": "bool", "short": "v"}, "level": {"type": "int", "short": "l", "default": 2}, "out": {"required": true, "help": "where to write"}}"#;
+
+fn parse(argv: &str) -> Result<String, String> {
+    let mut runtime = Runtime::new();
+    runtime.run_source(SPEC).unwrap();
+    match runtime.run_source(&format!("parse_args(spec, {})", argv)) {
+        Ok(value) => Ok(value.to_string()),
+        Err(RunError::Runtime(msg, _)) => Err(msg),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn flags_after_the_script_go_to_the_script() {
+    let path = std::env::temp_dir().join(format!("flux_args_{}.flux", std::process::id()));
+    fs::write(&path, format!("{}\nprint(args())\nprint(parse_args(spec))", SPEC)).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_flux_compiler"))
+        .arg("--no-init")
+        .arg(&path)
+        .args(["-v", "--out=report.txt", "data.csv", "--", "--vm"])
+        .output()
+        .expect("failed to run flux_compiler");
+    let _ = fs::remove_file(&path);
+    let text = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    assert_eq!(lines, [
+        "[-v, --out=report.txt, data.csv, --, --vm]",
+        "{\"level\": 2, \"out\": report.txt, \"rest\": [data.csv, --vm], \"verbose\": true}",
+    ], "{}", text);
+}
+
+#[test]
+fn boolean_flags_are_switches() {
+    assert_eq!(parse(r#"["--out", "x", "-v"]"#), Ok(r#"{"level": 2, "out": x, "rest": [], "verbose": true}"#.to_string()));
+    assert_eq!(parse(r#"["--out", "x", "--verbose=false"]"#), Ok(r#"{"level": 2, "out": x, "rest": [], "verbose": false}"#.to_string()));
+    // A switch takes no separate value: what follows it is an argument
+    assert_eq!(parse(r#"["--out", "x", "--verbose", "false"]"#), Ok(r#"{"level": 2, "out": x, "rest": [false], "verbose": true}"#.to_string()));
+}
+
+#[test]
+fn short_and_long_names_and_equals_signs_mean_the_same() {
+    for argv in [r#"["--level=7", "--out=a"]"#, r#"["--level", "7", "--out", "a"]"#, r#"["-l", "7", "--out", "a"]"#, r#"["-l=7", "--out=a"]"#] {
+        assert_eq!(parse(argv), Ok(r#"{"level": 7, "out": a, "rest": [], "verbose": false}"#.to_string()), "{}", argv);
+    }
+    // Negative numbers are values, not flags
+    assert_eq!(parse(r#"["-l", "-3", "--out", "a", "-1"]"#), Ok(r#"{"level": -3, "out": a, "rest": [-1], "verbose": false}"#.to_string()));
+}
+
+#[test]
+fn mistakes_fail_with_the_usage_text() {
+    for (argv, problem) in [
+        (r#"["--out", "a", "--colour"]"#, "unknown flag --colour"),
+        (r#"["--out", "a", "-x"]"#, "unknown flag -x"),
+        (r#"["-v"]"#, "missing required flag --out"),
+        (r#"["--out"]"#, "--out needs a value"),
+        (r#"["--out", "a", "-l", "high"]"#, "-l needs an integer, got 'high'"),
+        (r#"["--out", "a", "--verbose=yes"]"#, "--verbose is true or false, got 'yes'"),
+    ] {
+        let msg = parse(argv).unwrap_err();
+        let (first, usage) = msg.split_once('\n').unwrap();
+        assert_eq!(first, format!("parse_args: {}", problem), "{}", argv);
+        assert!(usage.starts_with("usage: script [options] [args...]\n"), "{}", usage);
+        assert!(usage.contains("      --out <string>  where to write (required)"), "{}", usage);
+    }
+}
+
+#[test]
+fn a_bad_spec_is_reported() {
+    let mut runtime = Runtime::new();
+    for (spec, expected) in [
+        (r#"{"n": {"type": "float"}}"#, "usage: --n has type float; use \"bool\", \"int\" or \"string\""),
+        (r#"{"n": {"short": "nn"}}"#, "usage: the short form of --n must be one character, got nn"),
+        (r#"{"n": {"type": "int", "default": "1"}}"#, "usage: the default of --n doesn't fit its type: 1"),
+        (r#"{"rest": {}}"#, "usage: \"rest\" can't be a flag; it holds the other arguments"),
+    ] {
+        match runtime.run_source(&format!("usage({})", spec)) {
+            Err(RunError::Runtime(msg, _)) => assert_eq!(msg, expected, "{}", spec),
+            other => panic!("{} should fail, got {:?}", spec, other),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+// Golden files for the text other tools read: how values print, how AST nodes
+// print, the --tokens dump and the disassembler's listing. Each is compared
+// with tests/snapshots/<name>.snap; FLUX_UPDATE_EXPECT=1 rewrites them instead,
+// as for the corpus.
+//
+// The samples come from constructor functions with one value per variant, and
+// each has an exhaustive `*_variant` match beside it, so a new variant doesn't
+// compile until it has a sample (and so a line in the snapshot).
+// The snapshots list every variant, so they need the build with both engines
+#![cfg(all(feature = "eval", feature = "vm"))]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use flux_compiler::ast::{BlockStatement, Expression, HashLiteral, MatchArm, Pattern, RecordKind, Statement};
+use flux_compiler::builtins::BUILTINS;
+use flux_compiler::code;
+use flux_compiler::compiler::Compiler;
+use flux_compiler::environment::Environment;
+use flux_compiler::intern::Symbol;
+use flux_compiler::lexer::Lexer;
+use flux_compiler::object::{new_iterator, CompiledFunction, HashKey, HashObject, IterState, MemoCache, Object};
+use flux_compiler::parser::Parser;
+use flux_compiler::token::{Span, TokenType};
+
+fn check_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(format!("{}.snap", name));
+    if std::env::var("FLUX_UPDATE_EXPECT").is_ok_and(|v| v == "1") {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no {} (run with FLUX_UPDATE_EXPECT=1 to create it)", path.display()));
+    assert_eq!(expected, actual, "{} is out of date (FLUX_UPDATE_EXPECT=1 rewrites it)", path.display());
+}
+
+// Checks that `samples` include every variant, given each sample's variant number
+fn assert_covers(kind: &str, variants: usize, numbers: impl Iterator<Item = usize>) {
+    let mut seen = vec![false; variants];
+    for n in numbers { seen[n] = true; }
+    let missing: Vec<usize> = (0..variants).filter(|n| !seen[*n]).collect();
+    assert!(missing.is_empty(), "no {} sample for variant(s) {:?}", kind, missing);
+}
+
+// --- OBJECTS ---
+
+const OBJECT_VARIANTS: usize = 19;
+
+fn object_variant(obj: &Object) -> usize {
+    match obj {
+        Object::Integer(_) => 0,
+        Object::Float(_) => 1,
+        Object::Boolean(_) => 2,
+        Object::String(_) => 3,
+        Object::Char(_) => 4,
+        Object::Return(_) => 5,
+        Object::Break(_) => 6,
+        Object::Continue => 7,
+        Object::Error(_) => 8,
+        Object::Null => 9,
+        Object::Function { .. } => 10,
+        Object::Builtin(_) => 11,
+        Object::Array(_) => 12,
+        Object::Hash(_) => 13,
+        Object::Module { .. } => 14,
+        Object::Memoized { .. } => 15,
+        Object::Iterator(_) => 16,
+        Object::CompiledFunction(_) => 17,
+        Object::Record { .. } => 18,
+    }
+}
+
+fn hash(pairs: &[(HashKey, Object)]) -> HashObject {
+    HashObject::new(pairs.iter().cloned().collect::<HashMap<_, _>>())
+}
+
+fn every_object() -> Vec<Object> {
+    let function = Object::Function {
+        parameters: vec![Symbol::intern("x")],
+        variadic: false,
+        body: BlockStatement { statements: vec![] },
+        env: Environment::new(),
+    };
+    vec![
+        Object::Integer(-42),
+        Object::Float(2.5),
+        Object::Float(1e300),
+        Object::Float(f64::NAN),
+        Object::Boolean(true),
+        Object::String("text with \"quotes\"".to_string()),
+        Object::Char('é'),
+        Object::Return(Box::new(Object::Integer(7))),
+        Object::Break(Box::new(Object::Null)),
+        Object::Continue,
+        Object::Error("Type mismatch".to_string()),
+        Object::Null,
+        function.clone(),
+        Object::Builtin(&BUILTINS[0]),
+        Object::Array(vec![Object::Integer(1), Object::String("two".to_string()), Object::Array(vec![])]),
+        Object::Hash(hash(&[
+            (HashKey::String("b".to_string()), Object::Integer(2)),
+            (HashKey::Integer(1), Object::Boolean(false)),
+            (HashKey::Boolean(true), Object::Null),
+        ])),
+        Object::Module {
+            name: "shapes".to_string(),
+            path: "modules/shapes.flux".to_string(),
+            exports: hash(&[(HashKey::String("area".to_string()), Object::Integer(1))]),
+        },
+        Object::Record {
+            kind: RecordKind::Material,
+            name: "Steel".to_string(),
+            fields: hash(&[(HashKey::String("density".to_string()), Object::Integer(7850))]),
+        },
+        Object::Memoized { function: Box::new(function), cache: Rc::new(RefCell::new(MemoCache::new(None))) },
+        new_iterator(IterState::Range { next: 0, end: 3, step: 1 }),
+        Object::CompiledFunction(Rc::new(CompiledFunction {
+            name: "sq".to_string(),
+            instructions: vec![],
+            num_locals: 0,
+            num_parameters: 1,
+            spans: vec![],
+        })),
+    ]
+}
+
+#[test]
+fn object_display() {
+    let samples = every_object();
+    assert_covers("Object", OBJECT_VARIANTS, samples.iter().map(object_variant));
+    let text: String = samples.iter().map(|obj| format!("{:<20} {}\n", obj.type_name(), obj)).collect();
+    check_snapshot("objects", &text);
+}
+
+// --- AST ---
+
+const EXPRESSION_VARIANTS: usize = 21;
+
+fn expression_variant(expr: &Expression) -> usize {
+    match expr {
+        Expression::Identifier { .. } => 0,
+        Expression::IntegerLiteral { .. } => 1,
+        Expression::FloatLiteral(_) => 2,
+        Expression::StringLiteral(_) => 3,
+        Expression::CharLiteral(_) => 4,
+        Expression::Boolean(_) => 5,
+        Expression::Prefix { .. } => 6,
+        Expression::Infix { .. } => 7,
+        Expression::If { .. } => 8,
+        Expression::FunctionLiteral { .. } => 9,
+        Expression::Call { .. } => 10,
+        Expression::ArrayLiteral(_) => 11,
+        Expression::IndexExpression { .. } => 12,
+        Expression::While { .. } => 13,
+        Expression::For { .. } => 14,
+        Expression::ForIn { .. } => 15,
+        Expression::HashLiteral(_) => 16,
+        Expression::Match { .. } => 17,
+        Expression::Member { .. } => 18,
+        Expression::Range { .. } => 19,
+        Expression::Block(_) => 20,
+    }
+}
+
+fn ident(name: &str) -> Expression {
+    Expression::Identifier { name: Symbol::intern(name), span: Span::default() }
+}
+
+fn int(value: i64) -> Expression {
+    Expression::IntegerLiteral { value, raw: value.to_string() }
+}
+
+fn block(statements: Vec<Statement>) -> BlockStatement {
+    BlockStatement { statements }
+}
+
+fn every_expression() -> Vec<Expression> {
+    let sum = Expression::Infix { left: Box::new(ident("a")), operator: "+".to_string(), right: Box::new(int(1)), span: Span::default() };
+    vec![
+        ident("total"),
+        Expression::IntegerLiteral { value: 1000, raw: "1_000".to_string() },
+        Expression::FloatLiteral(0.5),
+        Expression::StringLiteral("hi".to_string()),
+        Expression::CharLiteral('\n'),
+        Expression::Boolean(false),
+        Expression::Prefix { operator: "-".to_string(), right: Box::new(ident("x")), span: Span::default() },
+        sum.clone(),
+        Expression::If { condition: Box::new(ident("ok")), consequence: block(vec![]), alternative: None },
+        Expression::FunctionLiteral { parameters: vec![Symbol::intern("a")], variadic: false, body: block(vec![Statement::Expression(sum.clone())]) },
+        Expression::Call { function: Box::new(ident("len")), arguments: vec![ident("xs")], span: Span::default() },
+        Expression::ArrayLiteral(vec![int(1), sum.clone(), Expression::StringLiteral("s".to_string())]),
+        Expression::IndexExpression { left: Box::new(ident("xs")), index: Box::new(int(0)), span: Span::default() },
+        Expression::Member { object: Box::new(ident("config")), property: Symbol::intern("port"), span: Span::default() },
+        Expression::Range { start: Box::new(int(0)), end: Box::new(sum.clone()), inclusive: true, span: Span::default() },
+        Expression::While { condition: Box::new(Expression::Boolean(true)), body: block(vec![]) },
+        Expression::For {
+            init: Some(Box::new(Statement::Let { name: Symbol::intern("i"), value: int(0) })),
+            condition: None,
+            post: None,
+            body: block(vec![]),
+        },
+        Expression::ForIn { variable: Symbol::intern("k"), value: Some(Symbol::intern("v")), iterable: Box::new(ident("h")), body: block(vec![]), span: Span::default() },
+        Expression::HashLiteral(HashLiteral { pairs: vec![(Expression::StringLiteral("k".to_string()), int(1))] }),
+        Expression::Block(block(vec![Statement::Let { name: Symbol::intern("t"), value: int(2) }, Statement::Expression(ident("t"))])),
+        Expression::Match {
+            subject: Box::new(ident("event")),
+            arms: vec![MatchArm { pattern: Pattern::Wildcard, guard: None, body: block(vec![]) }],
+            span: Span::default(),
+        },
+    ]
+}
+
+const STATEMENT_VARIANTS: usize = 12;
+
+fn statement_variant(stmt: &Statement) -> usize {
+    match stmt {
+        Statement::Let { .. } => 0,
+        Statement::Assign { .. } => 1,
+        Statement::IndexAssign { .. } => 2,
+        Statement::Return(_) => 3,
+        Statement::Break(_) => 4,
+        Statement::Continue => 5,
+        Statement::Defer(_) => 6,
+        Statement::Expression(_) => 7,
+        Statement::Function { .. } => 8,
+        Statement::CompoundAssign { .. } => 9,
+        Statement::LetDestructure { .. } => 10,
+        Statement::Record { .. } => 11,
+    }
+}
+
+fn every_statement() -> Vec<Statement> {
+    vec![
+        Statement::Let { name: Symbol::intern("x"), value: int(1) },
+        Statement::LetDestructure { names: vec![Symbol::intern("x"), Symbol::intern("y")], value: ident("point"), span: Span::default() },
+        Statement::Assign { name: Symbol::intern("x"), value: int(2), span: Span::default() },
+        Statement::CompoundAssign { name: Symbol::intern("x"), operator: "*".to_string(), value: int(3), span: Span::default() },
+        Statement::IndexAssign { left: ident("grid"), index: int(0), value: int(3), span: Span::default() },
+        Statement::Return(Some(ident("x"))),
+        Statement::Break(Some(int(4))),
+        Statement::Break(None),
+        Statement::Continue,
+        Statement::Defer(block(vec![])),
+        Statement::Expression(ident("x")),
+        Statement::Function { name: Symbol::intern("area"), parameters: vec![Symbol::intern("w"), Symbol::intern("h")], variadic: true, body: block(vec![]) },
+        Statement::Record { kind: RecordKind::Context, name: Symbol::intern("Lab"), fields: vec![(Symbol::intern("temperature"), int(20)), (Symbol::intern("humidity"), ident("h"))] },
+    ]
+}
+
+#[test]
+fn ast_display() {
+    let expressions = every_expression();
+    let statements = every_statement();
+    assert_covers("Expression", EXPRESSION_VARIANTS, expressions.iter().map(expression_variant));
+    assert_covers("Statement", STATEMENT_VARIANTS, statements.iter().map(statement_variant));
+    let mut text = String::new();
+    for expr in &expressions { text.push_str(&format!("{}\n", expr)); }
+    for stmt in &statements { text.push_str(&format!("{}\n", stmt)); }
+    check_snapshot("ast", &text);
+}
+
+// --- TOKENS ---
+
+// Every kind of token at least once
+const REFERENCE_PROGRAM: &str = r#"mut total = 1_000 + 2.5 * -x / y ** 2
+total += 1; total -= 1; total *= 2; total /= 2
+if (!(a < b) && a > b || a <= b && a >= b) { a == b } else { a != b }
+fn(xs, rest...) { return xs ? xs[0] : 0 }
+mut h = {"k": 'c', "n": [1, 2]}
+while (true && !false) { break }
+match (e) { _ => continue }
+for (mut i = 0; i < 3; i += 1) {}
+for (j in 0..3) { 1..=j }
+defer { h.k }
+material context @
+"unterminated"#;
+
+const TOKEN_VARIANTS: usize = 57;
+
+fn token_variant(t: TokenType) -> usize {
+    match t {
+        TokenType::Illegal => 0,
+        TokenType::EOF => 1,
+        TokenType::Identifier => 2,
+        TokenType::Int => 3,
+        TokenType::Float => 4,
+        TokenType::String => 5,
+        TokenType::UnterminatedString => 6,
+        TokenType::Char => 7,
+        TokenType::Assign => 8,
+        TokenType::PlusAssign => 9,
+        TokenType::MinusAssign => 10,
+        TokenType::AsteriskAssign => 11,
+        TokenType::SlashAssign => 12,
+        TokenType::Plus => 13,
+        TokenType::Minus => 14,
+        TokenType::Bang => 15,
+        TokenType::Asterisk => 16,
+        TokenType::Slash => 17,
+        TokenType::Power => 18,
+        TokenType::Lt => 19,
+        TokenType::Gt => 20,
+        TokenType::LtEq => 21,
+        TokenType::GtEq => 22,
+        TokenType::Eq => 23,
+        TokenType::NotEq => 24,
+        TokenType::And => 25,
+        TokenType::Or => 26,
+        TokenType::Comma => 27,
+        TokenType::Colon => 28,
+        TokenType::Semicolon => 29,
+        TokenType::LParen => 30,
+        TokenType::RParen => 31,
+        TokenType::LBrace => 32,
+        TokenType::RBrace => 33,
+        TokenType::LBracket => 34,
+        TokenType::RBracket => 35,
+        TokenType::Dot => 36,
+        TokenType::DotDotDot => 37,
+        TokenType::FatArrow => 38,
+        TokenType::Fn => 39,
+        TokenType::Mut => 40,
+        TokenType::True => 41,
+        TokenType::False => 42,
+        TokenType::If => 43,
+        TokenType::Else => 44,
+        TokenType::Return => 45,
+        TokenType::While => 46,
+        TokenType::Break => 47,
+        TokenType::Continue => 48,
+        TokenType::Match => 49,
+        TokenType::Defer => 50,
+        TokenType::For => 51,
+        TokenType::Material => 52,
+        TokenType::Context => 53,
+        TokenType::Question => 54,
+        TokenType::DotDot => 55,
+        TokenType::DotDotEq => 56,
+    }
+}
+
+#[test]
+fn token_dump() {
+    let tokens = Lexer::tokenize(REFERENCE_PROGRAM);
+    // Strict keywords are identifiers to the lexer; the parser promotes them
+    let promoted = [TokenType::Break, TokenType::Continue, TokenType::Match, TokenType::Defer, TokenType::For, TokenType::Material, TokenType::Context];
+    assert_covers("token", TOKEN_VARIANTS,
+        tokens.iter().map(|t| token_variant(t.token_type)).chain(promoted.iter().map(|t| token_variant(*t))));
+    check_snapshot("tokens", &Lexer::dump(REFERENCE_PROGRAM));
+}
+
+// --- DISASSEMBLY ---
+
+#[test]
+fn disassembly_listing() {
+    let source = "mut sq = fn(x) { x * x }\nmut n = 0\nwhile (n < 3) { n += 1 }\nif (!(n >= 3)) { -sq(n) } else { sq(2) <= 4 }";
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert!(!program.has_errors(), "{:?}", program.errors);
+    let mut compiler = Compiler::new();
+    compiler.compile(program.statements).unwrap();
+    let mut text = format!("<main>:\n{}", code::print_instructions(compiler.instructions()));
+    for constant in &compiler.constants {
+        if let Object::CompiledFunction(func) = constant {
+            text.push_str(&format!("{}:\n{}", func.name, code::print_instructions(&func.instructions)));
+        }
+    }
+    check_snapshot("disassembly", &text);
+}
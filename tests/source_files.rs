@@ -0,0 +1,37 @@
+// Reading source files: why a read failed, and byte order marks
+use flux_compiler::{RunError, Runtime};
+use flux_compiler::lexer::Lexer;
+use flux_compiler::source::{self, SourceError};
+
+const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/corpus");
+
+#[test]
+fn a_missing_file_says_so() {
+    let path = format!("{}/no_such_program.flux", CORPUS);
+    assert_eq!(source::read(&path), Err(SourceError::NotFound));
+    match Runtime::new().run_file(&path) {
+        Err(RunError::Io(msg)) => assert_eq!(msg, format!("{}: no such file", path)),
+        other => panic!("expected an I/O error, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_latin1_file_names_the_first_bad_byte() {
+    let path = format!("{}/errors_source_latin1.flux", CORPUS);
+    let offset = "// Saved as Latin-1: the ".len();
+    assert_eq!(source::read(&path), Err(SourceError::InvalidUtf8(offset)));
+    match Runtime::new().run_file(&path) {
+        Err(RunError::Io(msg)) => assert!(msg.contains(&format!("first invalid byte is at offset {}", offset)), "{}", msg),
+        other => panic!("expected an I/O error, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_bom_is_dropped() {
+    let path = format!("{}/source_bom.flux", CORPUS);
+    assert!(source::read(&path).unwrap().starts_with("// A byte order mark"));
+    // Source handed over as a string loses it in the lexer, which still counts its bytes
+    let tokens = Lexer::tokenize("\u{FEFF}mut x = 1");
+    assert_eq!((tokens[0].literal.as_str(), tokens[0].column, tokens[0].span.start), ("mut", 1, 3));
+    assert_eq!(Runtime::new().run_source("\u{FEFF}mut x = 20\nx * 2").unwrap().to_string(), "40");
+}
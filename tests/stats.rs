@@ -0,0 +1,64 @@
+// --stats counts what a run did; without it nothing is counted
+#![cfg(feature = "eval")]
+#[cfg(feature = "vm")]
+use flux_compiler::Engine;
+use flux_compiler::Runtime;
+
+#[test]
+fn a_micro_program_has_exact_counts() {
+    let mut runtime = Runtime::new().with_stats(true);
+    runtime.run_source("mut x = 1\nx + 2").unwrap();
+    let stats = runtime.stats();
+    let nodes: Vec<(&str, u64)> = stats.nodes.iter().map(|(kind, n)| (*kind, *n)).collect();
+    assert_eq!(nodes, vec![("Identifier", 1), ("Infix", 1), ("IntegerLiteral", 2)]);
+    assert_eq!((stats.env_gets, stats.env_sets, stats.clones), (1, 1, 1));
+    assert!(stats.builtins.is_empty());
+    assert!(stats.opcodes.is_empty());
+}
+
+#[test]
+fn a_known_program_touches_every_category() {
+    let source = "mut sq = fn(x) { x * x }\nmut total = 0\nfor (x in [1, 2, 3]) { total = total + sq(x) }\nlen([total])";
+    let mut runtime = Runtime::new().with_stats(true);
+    runtime.run_source(source).unwrap();
+    let stats = runtime.stats();
+    assert_eq!(stats.nodes.get("Call"), Some(&4));
+    assert_eq!(stats.nodes.get("ForIn"), Some(&1));
+    assert!(stats.nodes.get("Identifier").is_some_and(|n| *n > 10));
+    assert_eq!(stats.builtins.get("len"), Some(&1));
+    assert!(stats.env_gets > 0 && stats.env_sets > 0 && stats.clones > 0);
+}
+
+#[cfg(feature = "vm")]
+#[test]
+fn the_vm_counts_opcodes() {
+    let mut vm = Runtime::new().with_engine(Engine::Vm).with_stats(true);
+    vm.run_source("mut a = 1\nwhile (a < 5) { a = a + 2 }\na").unwrap();
+    let stats = vm.stats();
+    assert_eq!(stats.opcodes.get("OpAdd"), Some(&2));
+    assert_eq!(stats.opcodes.get("OpLessThan"), Some(&3));
+    assert!(stats.nodes.is_empty());
+}
+
+#[test]
+fn nothing_is_counted_unless_asked_for() {
+    let mut runtime = Runtime::new();
+    runtime.run_source("mut x = [1, 2]\nlen(x) + x[0]").unwrap();
+    assert_eq!(runtime.stats(), Default::default());
+}
+
+#[test]
+fn the_report_is_sorted_and_available_as_a_hash() {
+    let mut runtime = Runtime::new().with_stats(true);
+    runtime.run_source("mut x = 1\nx + x + 2").unwrap();
+    let stats = runtime.stats();
+    let report = stats.report();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "--- stats ---");
+    assert_eq!(lines[1], "evaluator nodes (6):");
+    assert_eq!(lines[2].split_whitespace().collect::<Vec<_>>(), vec!["2", "Identifier"]);
+    assert_eq!(lines[3].split_whitespace().collect::<Vec<_>>(), vec!["2", "Infix"]);
+    assert_eq!(lines[4].split_whitespace().collect::<Vec<_>>(), vec!["2", "IntegerLiteral"]);
+    let hash = stats.to_object().to_string();
+    assert!(hash.contains("\"env_gets\": 2"), "{}", hash);
+}
@@ -0,0 +1,65 @@
+// Both engines decide conditions with object::is_truthy, and an error used as
+// a condition stops the program instead of choosing a branch. Nothing in the
+// language can hold an error as a value yet (there is no try()), so these
+// tests put one where a caught error would end up: in a variable, and in a
+// constant the VM pushes.
+#[cfg(feature = "vm")]
+use flux_compiler::compiler::Compiler;
+#[cfg(feature = "eval")]
+use flux_compiler::environment::Environment;
+#[cfg(feature = "eval")]
+use flux_compiler::evaluator::eval_program;
+#[cfg(feature = "eval")]
+use flux_compiler::intern::Symbol;
+use flux_compiler::lexer::Lexer;
+use flux_compiler::object::{self, Object};
+use flux_compiler::parser::Parser;
+#[cfg(feature = "vm")]
+use flux_compiler::vm::VM;
+
+fn boom() -> Object {
+    Object::Error("boom".to_string())
+}
+
+#[cfg(feature = "vm")]
+fn compile(source: &str) -> Compiler {
+    let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+    assert!(!program.has_errors(), "{:?}", program.errors);
+    let mut compiler = Compiler::new();
+    compiler.compile(program.statements).unwrap();
+    compiler
+}
+
+#[test]
+fn only_false_and_null_are_falsy_and_an_error_is_neither() {
+    assert_eq!(object::is_truthy(&Object::Boolean(false)), Ok(false));
+    assert_eq!(object::is_truthy(&Object::Null), Ok(false));
+    for value in [Object::Boolean(true), Object::Integer(0), Object::String(String::new()), Object::Array(vec![])] {
+        assert_eq!(object::is_truthy(&value), Ok(true), "{}", value);
+    }
+    assert_eq!(object::is_truthy(&boom()), Err("boom".to_string()));
+}
+
+#[cfg(feature = "vm")]
+#[test]
+fn the_vm_faults_on_an_error_condition() {
+    // `7` is constant 0 in each; it becomes the error
+    for source in ["if (7) { 1 } else { 2 }", "while (7) { 1 }", "!7", "7 && true", "7 || true"] {
+        let mut compiler = compile(source);
+        assert_eq!(compiler.constants[0].to_string(), "7", "{}", source);
+        compiler.constants[0] = boom();
+        let mut machine = VM::new(compiler);
+        assert_eq!(machine.run(), Err("boom".to_string()), "{}", source);
+    }
+}
+
+#[cfg(feature = "eval")]
+#[test]
+fn the_interpreter_faults_on_an_error_condition() {
+    for source in ["if (caught) { 1 } else { 2 }", "while (caught) { 1 }", "!caught", "caught || true", "match (1) { _ if caught => 1 }"] {
+        let program = Parser::new(Lexer::new(source.to_string())).parse_program();
+        let mut env = Environment::new();
+        env.set(Symbol::intern("caught"), boom());
+        assert_eq!(eval_program(&program.statements, &mut env).to_string(), "ERROR: boom", "{}", source);
+    }
+}
@@ -0,0 +1,33 @@
+// What doesn't fit the VM's one-byte operands is a compile error, not a
+// wrong answer
+#![cfg(feature = "vm")]
+use flux_compiler::{Engine, RunError, Runtime};
+
+// A function with `count` locals, v0 = 0 .. v{count-1}, returning v3
+fn many_locals(count: usize) -> String {
+    let locals: String = (0..count).map(|i| format!("mut v{} = {}\n", i, i)).collect();
+    format!("mut f = fn() {{\n{}v3\n}}\nf()", locals)
+}
+
+#[test]
+fn a_function_may_have_255_locals() {
+    let value = Runtime::new().with_engine(Engine::Vm).run_source(&many_locals(255)).unwrap();
+    assert_eq!(value.to_string(), "3");
+}
+
+#[test]
+fn more_locals_than_an_operand_holds_are_rejected() {
+    match Runtime::new().with_engine(Engine::Vm).run_source(&many_locals(300)) {
+        Err(RunError::Compile(msg, _)) => assert_eq!(msg, "Too many local variables in f: 300 (at most 255)"),
+        other => panic!("expected a compile error, got {:?}", other),
+    }
+}
+
+#[test]
+fn more_arguments_than_an_operand_holds_are_rejected() {
+    let arguments = vec!["1"; 300].join(", ");
+    match Runtime::new().with_engine(Engine::Vm).run_source(&format!("mut f = fn() {{ 1 }}\nf({})", arguments)) {
+        Err(RunError::Compile(msg, _)) => assert_eq!(msg, "Too many arguments in call: 300"),
+        other => panic!("expected a compile error, got {:?}", other),
+    }
+}
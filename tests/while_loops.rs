@@ -0,0 +1,152 @@
+// while loops with break and continue: the VM, with and without inlining,
+// gives the interpreter's answer
+use flux_compiler::{Engine, RunError, Runtime};
+
+// Every way this build can run a program
+fn runtimes() -> Vec<(String, Runtime)> {
+    let mut runtimes = Vec::new();
+    for &engine in Engine::ALL {
+        runtimes.push((format!("{:?}", engine), Runtime::new().with_engine(engine)));
+        #[cfg(feature = "vm")]
+        if engine == Engine::Vm {
+            runtimes.push(("Vm, inlining".to_string(), Runtime::new().with_engine(engine).with_inlining(true)));
+        }
+    }
+    runtimes
+}
+
+fn assert_all(source: &str, expected: &str) {
+    for (name, mut runtime) in runtimes() {
+        match runtime.run_source(source) {
+            Ok(value) => assert_eq!(value.to_string(), expected, "{}: {}", name, source),
+            Err(e) => panic!("{}: {}: {}", name, source, e),
+        }
+    }
+}
+
+// The VM compiles no arrays or hashes, so the programs below report
+// through strings and integers only
+
+#[test]
+fn nested_loops_break_at_both_levels() {
+    let source = r#"
+        mut pairs = ""
+        mut i = 0
+        while (true) {
+            i = i + 1
+            if (i > 4) { break }
+            mut j = 0
+            while (true) {
+                j = j + 1
+                if (j > i) { break }
+                if (i * j == 6) { break }
+                pairs = pairs + i + j + " "
+            }
+        }
+        pairs + "/ " + i
+    "#;
+    assert_all(source, "11 21 22 31 41 42 43 44 / 5");
+}
+
+#[test]
+fn continue_skips_half_the_iterations() {
+    let source = r#"
+        mut i = 0
+        mut odd = 0
+        mut bodies = 0
+        while (i < 100) {
+            i = i + 1
+            if (i / 2 * 2 == i) { continue }
+            bodies = bodies + 1
+            odd = odd + i
+        }
+        "" + bodies + " " + odd
+    "#;
+    assert_all(source, "50 2500");
+}
+
+#[test]
+fn continue_in_an_inner_loop_leaves_the_outer_one_going() {
+    let source = r#"
+        mut outer = 0
+        mut inner = 0
+        while (outer < 3) {
+            outer = outer + 1
+            mut j = 0
+            while (j < 4) {
+                j = j + 1
+                if (j <= outer) { continue }
+                inner = inner + 1
+            }
+        }
+        "" + outer + " " + inner
+    "#;
+    assert_all(source, "3 6");
+}
+
+#[test]
+fn break_with_a_value_is_the_loops_value() {
+    let source = r#"
+        mut i = 0
+        mut found = while (i < 10) {
+            i = i + 1
+            if (i * i > 20) { break i }
+        }
+        found
+    "#;
+    assert_all(source, "5");
+    assert_all("mut i = 0\nmut v = while (true) { i = i + 1; if (i == 3) { break \"done at \" + i } }\nv", "done at 3");
+}
+
+#[test]
+fn a_loop_that_ends_without_a_value_is_null() {
+    assert_all("mut i = 0\nmut v = while (i < 3) { i = i + 1 }\nv", "null");
+    assert_all("mut i = 0\nmut v = while (true) { i = i + 1; if (i == 3) { break } }\nv", "null");
+    assert_all("mut v = while (false) { 1 }\nv", "null");
+}
+
+#[test]
+fn a_loop_as_a_statement_leaves_nothing_behind() {
+    // Many iterations, each leaving the stack as it found it
+    let source = r#"
+        mut i = 0
+        mut kept = 0
+        while (i < 10000) {
+            i = i + 1
+            if (i / 3 * 3 == i) { continue }
+            if (i == 9999) { break i }
+            kept = kept + 1
+        }
+        while (false) { }
+        kept
+    "#;
+    assert_all(source, "6667");
+}
+
+#[test]
+fn break_leaves_a_loop_inside_a_function() {
+    let source = r#"
+        let first_square_over = fn(limit) {
+            mut i = 0
+            mut hit = while (i < 10) {
+                i = i + 1
+                if (i * i > limit) { break i * i }
+            }
+            if (hit) { hit } else { 0 - 1 }
+        }
+        "" + first_square_over(30) + " " + first_square_over(1000)
+    "#;
+    assert_all(source, "36 -1");
+}
+
+#[test]
+fn break_and_continue_outside_a_loop_are_rejected_by_every_engine() {
+    for source in ["break", "continue", "while (true) { let f = fn() { break }; f() }"] {
+        for (name, mut runtime) in runtimes() {
+            match runtime.run_source(source) {
+                Err(RunError::Parse(errors, _)) => assert!(errors[0].message.contains("outside of a loop"), "{}: {:?}", name, errors),
+                other => panic!("{}: {} should not parse, got {:?}", name, source, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+// write() and write_err(): their arguments' text and nothing else, flushed at
+// once, so `\r` can redraw a progress line in place
+#![cfg(feature = "eval")]
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::process::Command;
+use std::rc::Rc;
+use flux_compiler::{Capture, Io, Runtime};
+
+const PROGRESS: &str = r#"
+    mut pct = 0
+    while (pct <= 100) {
+        write("\rProgress: ", pct, "%")
+        pct = pct + 25
+    }
+    write("\n")
+"#;
+
+const EXPECTED: &[u8] = b"\rProgress: 0%\rProgress: 25%\rProgress: 50%\rProgress: 75%\rProgress: 100%\n";
+
+// Records what was written, with a '|' wherever it was flushed
+#[derive(Clone, Default)]
+struct Flushes(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Flushes {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().push(b'|');
+        Ok(())
+    }
+}
+
+fn captured() -> (Runtime, Capture, Capture) {
+    let (output, errors) = (Capture::new(), Capture::new());
+    let io = Io { input: Box::new(io::empty()), output: Box::new(output.clone()), errors: Box::new(errors.clone()) };
+    (Runtime::new().with_io(io), output, errors)
+}
+
+#[test]
+fn a_progress_line_is_exactly_its_bytes() {
+    let (mut runtime, output, errors) = captured();
+    runtime.run_source(PROGRESS).unwrap();
+    assert_eq!(output.bytes(), EXPECTED);
+    assert_eq!(errors.bytes(), b"");
+}
+
+#[test]
+fn each_write_is_flushed_when_it_is_done() {
+    let flushes = Flushes::default();
+    let io = Io { input: Box::new(io::empty()), output: Box::new(flushes.clone()), errors: Box::new(flushes.clone()) };
+    Runtime::new().with_io(io).run_source("write(\"\\r1\", \"/\", 3); write(\"\\r2/3\"); write_err(\"!\")").unwrap();
+    assert_eq!(String::from_utf8(flushes.0.borrow().clone()).unwrap(), "\r1/3|\r2/3|!|");
+}
+
+#[test]
+fn arguments_are_joined_with_no_separator() {
+    let (mut runtime, output, errors) = captured();
+    runtime.run_source("write(1, \",\", 2.5, \",\", true, \",\", \"x\", \"\\n\"); write(); write_err(\"a\", 1, \"\\r\")").unwrap();
+    assert_eq!(output.bytes(), b"1,2.5,true,x\n");
+    assert_eq!(errors.bytes(), b"a1\r");
+}
+
+#[test]
+fn the_cli_writes_the_same_bytes_to_stdout() {
+    let path = std::env::temp_dir().join(format!("flux_write_{}.flux", std::process::id()));
+    fs::write(&path, PROGRESS).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_flux_compiler")).arg(&path).output().expect("failed to run flux_compiler");
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.stdout, EXPECTED);
+}